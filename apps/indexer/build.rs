@@ -0,0 +1,48 @@
+use std::process::Command;
+
+fn main() {
+    emit_git_sha();
+
+    // Generated code is only needed for the gRPC server; protox avoids requiring protoc.
+    #[cfg(feature = "grpc")]
+    {
+        println!("cargo:rerun-if-changed=proto");
+
+        let fds = protox::compile(["indexer.proto"], ["proto"]).expect("failed to parse protos");
+        tonic_build::configure()
+            .build_client(false)
+            .compile_fds(fds)
+            .expect("failed to generate gRPC code");
+    }
+}
+
+/// Expose the commit being built as `GIT_SHA` for `/version`. A `GIT_SHA` env var wins,
+/// for builds without a `.git` directory such as the Docker image; otherwise ask git, and
+/// fall back to "unknown".
+fn emit_git_sha() {
+    println!("cargo:rerun-if-env-changed=GIT_SHA");
+
+    let sha = std::env::var("GIT_SHA")
+        .ok()
+        .filter(|sha| !sha.is_empty())
+        .or_else(|| git(&["rev-parse", "--short=12", "HEAD"]))
+        .unwrap_or_else(|| "unknown".into());
+    println!("cargo:rustc-env=GIT_SHA={sha}");
+
+    // Rebuild when HEAD moves, whether by checkout (HEAD) or by commit (the branch ref)
+    if let Some(git_dir) = git(&["rev-parse", "--absolute-git-dir"]) {
+        println!("cargo:rerun-if-changed={git_dir}/HEAD");
+        if let Some(head_ref) = git(&["symbolic-ref", "-q", "HEAD"]) {
+            println!("cargo:rerun-if-changed={git_dir}/{head_ref}");
+        }
+    }
+}
+
+fn git(args: &[&str]) -> Option<String> {
+    let output = Command::new("git").args(args).output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let out = String::from_utf8(output.stdout).ok()?;
+    Some(out.trim().to_string())
+}
@@ -0,0 +1,644 @@
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+use alloy::primitives::B256;
+use axum::{
+    extract::{Path, Query, Request, State},
+    http::{header, StatusCode},
+    middleware::Next,
+    response::Response,
+    Json,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::config::{sanitize_rpc_url, ApiLogLevel, Config, SubscribeMode};
+use crate::db::models::TopicConsistency;
+use crate::db::queries::{delete_from_block, verify_topic};
+use crate::indexer::backfill::{
+    get_chain_head, reprocess_transaction, run_backfill, BackfillStats, ReprocessStats,
+};
+use crate::indexer::processor::{RedecodePass, RedecodeStats};
+use crate::indexer::rpc::http_provider;
+use crate::types::TopicHash;
+
+use super::encoding::parse_bytes32;
+#[cfg(feature = "openapi")]
+use super::error::ErrorBody;
+use super::error::{ApiError, ApiResult};
+use super::health::EventCountsResponse;
+use super::state::AppState;
+
+/// Finished jobs beyond this many are forgotten, oldest first.
+const MAX_RETAINED_JOBS: usize = 100;
+
+/// Rejects requests without `Authorization: Bearer <ADMIN_TOKEN>`.
+pub async fn require_admin(
+    State(state): State<AppState>,
+    request: Request,
+    next: Next,
+) -> ApiResult<Response> {
+    let provided = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "));
+
+    match (&state.config.admin_token, provided) {
+        (Some(expected), Some(provided)) if constant_time_eq(expected, provided) => {
+            Ok(next.run(request).await)
+        }
+        _ => Err(ApiError::unauthorized()),
+    }
+}
+
+fn constant_time_eq(a: &str, b: &str) -> bool {
+    a.len() == b.len()
+        && a.bytes()
+            .zip(b.bytes())
+            .fold(0, |acc, (x, y)| acc | (x ^ y))
+            == 0
+}
+
+#[derive(Clone, Copy, Serialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum JobStatus {
+    Running,
+    Done,
+    Failed,
+}
+
+#[derive(Clone, Serialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct BackfillJobResponse {
+    pub id: u64,
+    pub from_block: u64,
+    pub to_block: u64,
+    pub status: JobStatus,
+    pub stats: Option<BackfillStats>,
+    pub error: Option<String>,
+}
+
+/// On-demand backfill jobs started through the admin API, kept in memory.
+#[derive(Default)]
+pub struct BackfillJobs {
+    next_id: AtomicU64,
+    jobs: Mutex<BTreeMap<u64, BackfillJobResponse>>,
+}
+
+impl BackfillJobs {
+    fn start(&self, from_block: u64, to_block: u64) -> BackfillJobResponse {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed) + 1;
+        let job = BackfillJobResponse {
+            id,
+            from_block,
+            to_block,
+            status: JobStatus::Running,
+            stats: None,
+            error: None,
+        };
+
+        let mut jobs = self.jobs.lock().unwrap();
+        jobs.insert(id, job.clone());
+        while jobs.len() > MAX_RETAINED_JOBS {
+            let oldest_finished = jobs
+                .iter()
+                .find(|(_, j)| !matches!(j.status, JobStatus::Running))
+                .map(|(id, _)| *id);
+            match oldest_finished {
+                Some(id) => jobs.remove(&id),
+                None => break,
+            };
+        }
+
+        job
+    }
+
+    fn finish(&self, id: u64, result: crate::error::Result<BackfillStats>) {
+        if let Some(job) = self.jobs.lock().unwrap().get_mut(&id) {
+            match result {
+                Ok(stats) => {
+                    job.status = JobStatus::Done;
+                    job.stats = Some(stats);
+                }
+                Err(e) => {
+                    job.status = JobStatus::Failed;
+                    job.error = Some(e.to_string());
+                }
+            }
+        }
+    }
+
+    fn get(&self, id: u64) -> Option<BackfillJobResponse> {
+        self.jobs.lock().unwrap().get(&id).cloned()
+    }
+}
+
+#[derive(Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct BackfillRequest {
+    pub from_block: u64,
+    pub to_block: u64,
+}
+
+#[cfg_attr(
+    feature = "openapi",
+    utoipa::path(
+        post,
+        path = "/admin/backfill",
+        tag = "admin",
+        request_body = BackfillRequest,
+        security(("admin_token" = [])),
+        responses(
+            (status = 202, description = "Job started", body = BackfillJobResponse),
+            (status = 400, description = "Malformed parameters", body = ErrorBody),
+            (status = 401, description = "Missing or invalid admin token", body = ErrorBody),
+        )
+    )
+)]
+/// Backfill a block range in the background, e.g. to fill a gap left by an RPC outage.
+pub async fn start_backfill(
+    State(state): State<AppState>,
+    Json(req): Json<BackfillRequest>,
+) -> ApiResult<(StatusCode, Json<BackfillJobResponse>)> {
+    if req.from_block > req.to_block {
+        return Err(ApiError::bad_request("from_block must not exceed to_block"));
+    }
+
+    let job = state.backfill_jobs.start(req.from_block, req.to_block);
+    tracing::info!(
+        job = job.id,
+        "Admin backfill started for blocks {} to {}",
+        req.from_block,
+        req.to_block
+    );
+
+    let id = job.id;
+    tokio::spawn(async move {
+        let result = run_backfill(
+            &state.config,
+            &state.config.http_rpc_url(),
+            req.from_block,
+            req.to_block,
+            &state.writer,
+            &state.timestamps,
+            &state.retry_queue,
+            &state.backfill_progress,
+            &state.rpc_breaker,
+        )
+        .await;
+        if let Err(e) = &result {
+            tracing::error!(job = id, "Admin backfill failed: {e}");
+        }
+        state.backfill_jobs.finish(id, result);
+    });
+
+    Ok((StatusCode::ACCEPTED, Json(job)))
+}
+
+#[cfg_attr(
+    feature = "openapi",
+    utoipa::path(
+        get,
+        path = "/admin/backfill/{id}",
+        tag = "admin",
+        params(("id" = u64, Path, description = "Job id")),
+        security(("admin_token" = [])),
+        responses(
+            (status = 200, body = BackfillJobResponse),
+            (status = 401, description = "Missing or invalid admin token", body = ErrorBody),
+            (status = 404, description = "Not found", body = ErrorBody),
+        )
+    )
+)]
+pub async fn get_backfill(
+    State(state): State<AppState>,
+    Path(id): Path<u64>,
+) -> ApiResult<Json<BackfillJobResponse>> {
+    state
+        .backfill_jobs
+        .get(id)
+        .map(Json)
+        .ok_or_else(|| ApiError::not_found(format!("backfill job {id} not found")))
+}
+
+#[derive(Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct ReprocessRequest {
+    pub tx_hash: String,
+    /// Delete the transaction's indexed rows and insert them again
+    #[serde(default)]
+    pub replace: bool,
+}
+
+#[cfg_attr(
+    feature = "openapi",
+    utoipa::path(
+        post,
+        path = "/admin/reprocess",
+        tag = "admin",
+        request_body = ReprocessRequest,
+        security(("admin_token" = [])),
+        responses(
+            (status = 200, body = ReprocessStats),
+            (status = 400, description = "Malformed parameters", body = ErrorBody),
+            (status = 401, description = "Missing or invalid admin token", body = ErrorBody),
+            (status = 404, description = "Transaction not found", body = ErrorBody),
+        )
+    )
+)]
+/// Index a single transaction from its receipt, e.g. one that a filtered `getLogs` dropped.
+pub async fn reprocess(
+    State(state): State<AppState>,
+    Json(req): Json<ReprocessRequest>,
+) -> ApiResult<Json<ReprocessStats>> {
+    let tx_hash = parse_bytes32(&req.tx_hash)
+        .map(B256::from)
+        .ok_or_else(|| ApiError::bad_request("tx_hash must be 32-byte hex"))?;
+
+    tracing::info!("Admin reprocess of {tx_hash} (replace: {})", req.replace);
+    let stats = reprocess_transaction(
+        &state.config,
+        &state.writer,
+        &state.timestamps,
+        tx_hash,
+        req.replace,
+        &state.rpc_breaker,
+    )
+    .await?
+    .ok_or_else(|| ApiError::not_found(format!("transaction {tx_hash} not found")))?;
+
+    Ok(Json(stats))
+}
+
+#[cfg_attr(
+    feature = "openapi",
+    utoipa::path(
+        post,
+        path = "/admin/reprocess-decoded",
+        tag = "admin",
+        security(("admin_token" = [])),
+        responses(
+            (status = 200, body = RedecodeStats),
+            (status = 401, description = "Missing or invalid admin token", body = ErrorBody),
+        )
+    )
+)]
+/// Decode every row stored with its raw log (`STORE_RAW_LOGS`) again and rewrite the decoded
+/// fields that changed, e.g. after a decoder fix, without refetching anything.
+pub async fn reprocess_decoded(State(state): State<AppState>) -> ApiResult<Json<RedecodeStats>> {
+    let mut pass = RedecodePass::new(state.config.compress_blobs, state.config.enable_fts);
+
+    tracing::info!("Admin re-decode of stored raw logs");
+    loop {
+        let (next, more) = state
+            .writer
+            .run(move |conn| {
+                let more = pass.next_batch(conn)?;
+                Ok((pass, more))
+            })
+            .await?;
+        pass = next;
+        if !more {
+            break;
+        }
+    }
+    let stats = pass.stats;
+    tracing::info!(
+        scanned = stats.scanned,
+        updated = stats.updated,
+        failed = stats.failed,
+        mismatched = stats.mismatched,
+        "Admin re-decode finished"
+    );
+
+    Ok(Json(stats))
+}
+
+#[derive(Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct ResyncRequest {
+    pub from_block: u64,
+    /// Must be `true`; the indexed rows from `from_block` on are deleted
+    #[serde(default)]
+    pub confirm: bool,
+}
+
+#[derive(Serialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct ResyncResponse {
+    pub from_block: u64,
+    pub to_block: u64,
+    pub deleted: EventCountsResponse,
+    pub reindexed: BackfillStats,
+}
+
+#[cfg_attr(
+    feature = "openapi",
+    utoipa::path(
+        post,
+        path = "/admin/resync",
+        tag = "admin",
+        request_body = ResyncRequest,
+        security(("admin_token" = [])),
+        responses(
+            (status = 200, body = ResyncResponse),
+            (status = 400, description = "Malformed parameters or missing confirmation", body = ErrorBody),
+            (status = 401, description = "Missing or invalid admin token", body = ErrorBody),
+        )
+    )
+)]
+/// Delete everything indexed from a block on and backfill it again up to the confirmed head,
+/// e.g. after stored rows were found corrupt.
+pub async fn resync(
+    State(state): State<AppState>,
+    Json(req): Json<ResyncRequest>,
+) -> ApiResult<Json<ResyncResponse>> {
+    if !req.confirm {
+        return Err(ApiError::bad_request(
+            "resync deletes indexed events; set \"confirm\": true to proceed",
+        ));
+    }
+
+    let config = &state.config;
+    let provider = http_provider(
+        &config.http_rpc_url(),
+        config.rpc_auth_header.as_deref(),
+        config.rpc_limits.timeout,
+    )?;
+    let to_block = get_chain_head(&provider)
+        .await?
+        .saturating_sub(config.confirmations);
+    if req.from_block > to_block {
+        return Err(ApiError::bad_request(format!(
+            "from_block must not exceed the confirmed head ({to_block})"
+        )));
+    }
+
+    let from_block = req.from_block as i64;
+    let deleted = state
+        .writer
+        .run(move |conn| delete_from_block(conn, from_block))
+        .await?;
+    tracing::warn!(
+        messages = deleted.messages,
+        handshakes = deleted.handshakes,
+        handshake_responses = deleted.handshake_responses,
+        "Admin resync deleted events from block {}, re-indexing up to {to_block}",
+        req.from_block
+    );
+
+    let reindexed = run_backfill(
+        config,
+        &config.http_rpc_url(),
+        req.from_block,
+        to_block,
+        &state.writer,
+        &state.timestamps,
+        &state.retry_queue,
+        &state.backfill_progress,
+        &state.rpc_breaker,
+    )
+    .await?;
+
+    Ok(Json(ResyncResponse {
+        from_block: req.from_block,
+        to_block,
+        deleted: deleted.into(),
+        reindexed,
+    }))
+}
+
+/// The configuration the running instance loaded. RPC and webhook URLs are masked like in
+/// the logs, and secrets are only reported as set or not.
+#[derive(Serialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct ConfigResponse {
+    /// Unset when polling over `RPC_HTTP_URL` alone
+    pub rpc_ws_url: Option<String>,
+    /// Explicit or derived from `rpc_ws_url`
+    pub rpc_http_url: String,
+    pub rpc_auth_header_set: bool,
+    pub contract_address: String,
+    /// After resolving `CREATION_TX_HASH` or detection, if either applied
+    pub creation_block: u64,
+    pub subscribe_mode: &'static str,
+    pub poll_interval_secs: u64,
+    pub confirmations: u64,
+    pub verify_finality: Option<u64>,
+    pub index_events: Vec<&'static str>,
+    /// Number of listed hashes; `None` when every recipient is indexed
+    pub recipient_allowlist_entries: Option<usize>,
+    pub backfill_days: u32,
+    pub max_backfill_blocks: u64,
+    pub retention_days: u32,
+    pub rpc_chunk_size: u64,
+    pub checkpoint_every_blocks: u64,
+    pub rpc_requests_per_second: u32,
+    pub rpc_max_retries: u32,
+    pub rpc_backoff_ms: u64,
+    pub rpc_timeout_secs: u64,
+    pub rpc_breaker_threshold: u32,
+    pub rpc_breaker_window_secs: u64,
+    pub rpc_breaker_cooldown_secs: u64,
+    pub rescan_interval_secs: Option<u64>,
+    pub rescan_blocks: u64,
+    pub ws_idle_timeout_secs: Option<u64>,
+    pub data_dir: String,
+    pub database_path: String,
+    pub sqlite_sync_mode: String,
+    pub read_pool_size: u32,
+    pub compress_blobs: bool,
+    pub store_raw_logs: bool,
+    pub enable_fts: bool,
+    pub server_port: u16,
+    pub listen_uds: Option<String>,
+    pub tls: bool,
+    pub grpc_port: Option<u16>,
+    /// `None` allows any origin
+    pub allowed_origins: Option<Vec<String>>,
+    pub rate_limit_rps: u64,
+    pub trust_proxy_headers: bool,
+    pub max_request_bytes: usize,
+    pub max_concurrent_requests: usize,
+    pub max_stream_clients: usize,
+    pub api_log_level: &'static str,
+    pub webhook_url: Option<String>,
+    pub webhook_secret_set: bool,
+    pub webhook_alert_on_dead_letter: bool,
+}
+
+impl ConfigResponse {
+    fn new(config: &Config) -> Self {
+        let kinds = config.index_events;
+        let index_events = [
+            (kinds.message, "message"),
+            (kinds.handshake, "handshake"),
+            (kinds.hsr, "hsr"),
+        ]
+        .into_iter()
+        .filter_map(|(on, name)| on.then_some(name))
+        .collect();
+
+        Self {
+            rpc_ws_url: (!config.rpc_ws_url.is_empty())
+                .then(|| sanitize_rpc_url(&config.rpc_ws_url)),
+            rpc_http_url: sanitize_rpc_url(&config.http_rpc_url()),
+            rpc_auth_header_set: config.rpc_auth_header.is_some(),
+            contract_address: config.contract_address.to_string(),
+            creation_block: config.creation_block,
+            subscribe_mode: match config.subscribe_mode {
+                SubscribeMode::Ws => "ws",
+                SubscribeMode::Poll => "poll",
+            },
+            poll_interval_secs: config.poll_interval.as_secs(),
+            confirmations: config.confirmations,
+            verify_finality: config.verify_finality,
+            index_events,
+            recipient_allowlist_entries: config.recipient_allowlist.entries(),
+            backfill_days: config.backfill_days,
+            max_backfill_blocks: config.max_backfill_blocks,
+            retention_days: config.retention_days,
+            rpc_chunk_size: config.rpc_chunk_size,
+            checkpoint_every_blocks: config.checkpoint_every_blocks,
+            rpc_requests_per_second: config.rpc_limits.requests_per_second.get(),
+            rpc_max_retries: config.rpc_limits.max_retries,
+            rpc_backoff_ms: config.rpc_limits.backoff_ms,
+            rpc_timeout_secs: config.rpc_limits.timeout.as_secs(),
+            rpc_breaker_threshold: config.rpc_limits.breaker_threshold,
+            rpc_breaker_window_secs: config.rpc_limits.breaker_window.as_secs(),
+            rpc_breaker_cooldown_secs: config.rpc_limits.breaker_cooldown.as_secs(),
+            rescan_interval_secs: config.rescan_interval.map(|d| d.as_secs()),
+            rescan_blocks: config.rescan_blocks,
+            ws_idle_timeout_secs: config.ws_idle_timeout.map(|d| d.as_secs()),
+            data_dir: config.data_dir.clone(),
+            database_path: config.database_path.clone(),
+            sqlite_sync_mode: config.sqlite_sync_mode.clone(),
+            read_pool_size: config.read_pool_size,
+            compress_blobs: config.compress_blobs,
+            store_raw_logs: config.store_raw_logs,
+            enable_fts: config.enable_fts,
+            server_port: config.server_port,
+            listen_uds: config.listen_uds.clone(),
+            tls: config.tls.is_some(),
+            grpc_port: config.grpc_port,
+            allowed_origins: config.allowed_origins.clone(),
+            rate_limit_rps: config.rate_limit_rps,
+            trust_proxy_headers: config.trust_proxy_headers,
+            max_request_bytes: config.max_request_bytes,
+            max_concurrent_requests: config.max_concurrent_requests,
+            max_stream_clients: config.max_stream_clients,
+            api_log_level: match config.api_log_level {
+                ApiLogLevel::Off => "off",
+                ApiLogLevel::Info => "info",
+                ApiLogLevel::Verbose => "verbose",
+            },
+            webhook_url: config.webhook_url.as_deref().map(sanitize_rpc_url),
+            webhook_secret_set: config.webhook_secret.is_some(),
+            webhook_alert_on_dead_letter: config.webhook_alert_on_dead_letter,
+        }
+    }
+}
+
+#[cfg_attr(
+    feature = "openapi",
+    utoipa::path(
+        get,
+        path = "/admin/config",
+        tag = "admin",
+        security(("admin_token" = [])),
+        responses(
+            (status = 200, body = ConfigResponse),
+            (status = 401, description = "Missing or invalid admin token", body = ErrorBody),
+        )
+    )
+)]
+/// The effective configuration, to check whether an env override took effect.
+pub async fn config(State(state): State<AppState>) -> Json<ConfigResponse> {
+    Json(ConfigResponse::new(&state.config))
+}
+
+#[derive(Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::IntoParams))]
+#[cfg_attr(feature = "openapi", into_params(parameter_in = Query))]
+pub struct VerifyQuery {
+    pub topic: String,
+}
+
+#[derive(Serialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct SeqRange {
+    pub from: i64,
+    pub to: i64,
+}
+
+#[derive(Serialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct SeqInversion {
+    pub previous_seq: i64,
+    pub seq: i64,
+}
+
+#[derive(Serialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct VerifyResponse {
+    pub topic: String,
+    pub consistent: bool,
+    pub message_count: i64,
+    pub max_seq: Option<i64>,
+    pub missing_seqs: Vec<SeqRange>,
+    pub ordering_inversions: Vec<SeqInversion>,
+}
+
+impl VerifyResponse {
+    fn new(topic: &TopicHash, report: TopicConsistency) -> Self {
+        Self {
+            topic: topic.to_string(),
+            consistent: report.missing_seqs.is_empty() && report.ordering_inversions.is_empty(),
+            message_count: report.message_count,
+            max_seq: report.max_seq,
+            missing_seqs: report
+                .missing_seqs
+                .into_iter()
+                .map(|(from, to)| SeqRange { from, to })
+                .collect(),
+            ordering_inversions: report
+                .ordering_inversions
+                .into_iter()
+                .map(|(previous_seq, seq)| SeqInversion { previous_seq, seq })
+                .collect(),
+        }
+    }
+}
+
+#[cfg_attr(
+    feature = "openapi",
+    utoipa::path(
+        get,
+        path = "/admin/verify",
+        tag = "admin",
+        params(VerifyQuery),
+        security(("admin_token" = [])),
+        responses(
+            (status = 200, body = VerifyResponse),
+            (status = 400, description = "Malformed parameters", body = ErrorBody),
+            (status = 401, description = "Missing or invalid admin token", body = ErrorBody),
+        )
+    )
+)]
+/// Check a topic's messages for seq gaps and for seqs out of on-chain order.
+pub async fn verify(
+    State(state): State<AppState>,
+    Query(query): Query<VerifyQuery>,
+) -> ApiResult<Json<VerifyResponse>> {
+    let topic = query
+        .topic
+        .parse::<TopicHash>()
+        .map_err(|_| ApiError::bad_request("topic must be 32-byte hex"))?;
+
+    let conn = state.read_pool.get()?;
+    let report = verify_topic(&conn, &topic)?;
+
+    Ok(Json(VerifyResponse::new(&topic, report)))
+}
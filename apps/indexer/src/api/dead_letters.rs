@@ -0,0 +1,117 @@
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    Json,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::db::models::DeadLetterRow;
+use crate::db::queries::{delete_dead_letter, get_dead_letter, list_dead_letters};
+use crate::error::IndexerError;
+use crate::indexer::processor::LogWithMeta;
+
+#[cfg(feature = "openapi")]
+use super::error::ErrorBody;
+use super::error::{ApiError, ApiResult};
+use super::state::AppState;
+
+const DEFAULT_LIMIT: i64 = 100;
+const MAX_LIMIT: i64 = 1000;
+
+#[derive(Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::IntoParams))]
+#[cfg_attr(feature = "openapi", into_params(parameter_in = Query))]
+pub struct DeadLettersQuery {
+    pub limit: Option<i64>,
+}
+
+#[derive(Serialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct DeadLetterResponse {
+    pub id: i64,
+    pub block_number: i64,
+    pub log_index: i64,
+    pub retry_count: i64,
+    pub last_error: String,
+    pub created_at: i64,
+}
+
+impl From<DeadLetterRow> for DeadLetterResponse {
+    fn from(r: DeadLetterRow) -> Self {
+        Self {
+            id: r.id,
+            block_number: r.block_number,
+            log_index: r.log_index,
+            retry_count: r.retry_count,
+            last_error: r.last_error,
+            created_at: r.created_at,
+        }
+    }
+}
+
+#[cfg_attr(
+    feature = "openapi",
+    utoipa::path(
+        get,
+        path = "/admin/dead-letters",
+        tag = "dead-letters",
+        params(DeadLettersQuery),
+        security(("admin_token" = [])),
+        responses(
+            (status = 200, body = [DeadLetterResponse]),
+            (status = 401, description = "Missing or invalid admin token", body = ErrorBody),
+            (status = 503, description = "DB connection pool exhausted", body = ErrorBody),
+        )
+    )
+)]
+pub async fn list(
+    State(state): State<AppState>,
+    Query(query): Query<DeadLettersQuery>,
+) -> ApiResult<Json<Vec<DeadLetterResponse>>> {
+    let limit = query.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+
+    let conn = state.read_pool.get()?;
+    let rows = list_dead_letters(&conn, limit)?;
+
+    Ok(Json(rows.into_iter().map(Into::into).collect()))
+}
+
+#[cfg_attr(
+    feature = "openapi",
+    utoipa::path(
+        post,
+        path = "/admin/dead-letters/{id}/requeue",
+        tag = "dead-letters",
+        params(("id" = i64, Path, description = "Dead-letter id")),
+        security(("admin_token" = [])),
+        responses(
+            (status = 202, description = "Requeued"),
+            (status = 401, description = "Missing or invalid admin token", body = ErrorBody),
+            (status = 404, description = "Not found", body = ErrorBody),
+            (status = 503, description = "DB connection pool exhausted", body = ErrorBody),
+        )
+    )
+)]
+/// Push a dead-lettered event back into the live retry queue with a fresh retry budget.
+pub async fn requeue(State(state): State<AppState>, Path(id): Path<i64>) -> ApiResult<StatusCode> {
+    let row = get_dead_letter(&*state.pool.get()?, id)?
+        .ok_or_else(|| ApiError::not_found(format!("dead-letter {id} not found")))?;
+
+    let log: LogWithMeta = serde_json::from_str(&row.payload)
+        .map_err(|e| IndexerError::Decode(format!("corrupt dead-letter payload for {id}: {e}")))?;
+
+    state.retry_queue.requeue(log, row.last_error).await;
+    state
+        .writer
+        .run(move |conn| delete_dead_letter(conn, id))
+        .await?;
+
+    tracing::info!(
+        id,
+        block = row.block_number,
+        log_index = row.log_index,
+        "Dead-letter requeued"
+    );
+
+    Ok(StatusCode::ACCEPTED)
+}
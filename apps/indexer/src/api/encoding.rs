@@ -0,0 +1,51 @@
+use base64::{engine::general_purpose::STANDARD, Engine};
+use serde::Deserialize;
+
+/// Wire encoding for payload blobs (ciphertexts, keys, plaintext payloads), chosen per
+/// request with `?encoding=`. Topics, hashes and addresses are always hex, since
+/// clients pass them back as query parameters.
+#[derive(Deserialize, Clone, Copy, Default)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[serde(rename_all = "lowercase")]
+pub enum BinaryEncoding {
+    /// 0x-prefixed lowercase hex
+    #[default]
+    Hex,
+    /// Standard alphabet with padding
+    Base64,
+}
+
+impl BinaryEncoding {
+    pub fn encode(self, bytes: &[u8]) -> String {
+        match self {
+            Self::Hex => to_hex(bytes),
+            Self::Base64 => STANDARD.encode(bytes),
+        }
+    }
+}
+
+/// `?encoding=` on its own, for endpoints with no other query parameters.
+#[derive(Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::IntoParams))]
+#[cfg_attr(feature = "openapi", into_params(parameter_in = Query))]
+pub struct EncodingQuery {
+    #[serde(default)]
+    pub encoding: BinaryEncoding,
+}
+
+/// Encode bytes as a 0x-prefixed lowercase hex string.
+pub fn to_hex(bytes: &[u8]) -> String {
+    format!("0x{}", hex::encode(bytes))
+}
+
+/// Parse a fixed-size hex value, with or without a 0x prefix.
+pub fn parse_hex_array<const N: usize>(s: &str) -> Option<[u8; N]> {
+    let s = s.strip_prefix("0x").unwrap_or(s);
+    let mut out = [0u8; N];
+    hex::decode_to_slice(s, &mut out).ok()?;
+    Some(out)
+}
+
+pub fn parse_bytes32(s: &str) -> Option<[u8; 32]> {
+    parse_hex_array(s)
+}
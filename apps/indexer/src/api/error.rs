@@ -0,0 +1,111 @@
+use axum::{
+    http::{header, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::Serialize;
+
+use crate::error::IndexerError;
+
+/// `Retry-After` sent while the startup backfill runs, in seconds.
+const SYNCING_RETRY_AFTER_SECS: &str = "10";
+/// `Retry-After` sent while every stream slot is taken, in seconds.
+const STREAMS_RETRY_AFTER_SECS: &str = "30";
+
+#[derive(Debug)]
+pub enum ApiError {
+    BadRequest(String),
+    NotFound(String),
+    Unauthorized,
+    PayloadTooLarge,
+    Overloaded,
+    /// The startup backfill is still running
+    Syncing,
+    /// `MAX_STREAM_CLIENTS` streams are already open
+    TooManyStreams,
+    Indexer(IndexerError),
+}
+
+#[derive(Serialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct ErrorBody {
+    pub error: String,
+    pub code: &'static str,
+}
+
+impl ApiError {
+    pub fn bad_request(msg: impl Into<String>) -> Self {
+        Self::BadRequest(msg.into())
+    }
+
+    pub fn not_found(msg: impl Into<String>) -> Self {
+        Self::NotFound(msg.into())
+    }
+
+    pub fn unauthorized() -> Self {
+        Self::Unauthorized
+    }
+
+    fn status_and_code(&self) -> (StatusCode, &'static str) {
+        match self {
+            Self::BadRequest(_) => (StatusCode::BAD_REQUEST, "bad_request"),
+            Self::NotFound(_) => (StatusCode::NOT_FOUND, "not_found"),
+            Self::Unauthorized => (StatusCode::UNAUTHORIZED, "unauthorized"),
+            Self::PayloadTooLarge => (StatusCode::PAYLOAD_TOO_LARGE, "payload_too_large"),
+            Self::Overloaded => (StatusCode::SERVICE_UNAVAILABLE, "unavailable"),
+            Self::Syncing => (StatusCode::SERVICE_UNAVAILABLE, "syncing"),
+            Self::TooManyStreams => (StatusCode::SERVICE_UNAVAILABLE, "too_many_streams"),
+            Self::Indexer(IndexerError::Pool(_)) => {
+                (StatusCode::SERVICE_UNAVAILABLE, "unavailable")
+            }
+            Self::Indexer(IndexerError::RpcCircuitOpen(_)) => {
+                (StatusCode::SERVICE_UNAVAILABLE, "rpc_unavailable")
+            }
+            Self::Indexer(IndexerError::Database(_)) => {
+                (StatusCode::INTERNAL_SERVER_ERROR, "database_error")
+            }
+            Self::Indexer(_) => (StatusCode::INTERNAL_SERVER_ERROR, "internal_error"),
+        }
+    }
+}
+
+impl<E: Into<IndexerError>> From<E> for ApiError {
+    fn from(e: E) -> Self {
+        Self::Indexer(e.into())
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let (status, code) = self.status_and_code();
+        let retry_after = match self {
+            Self::Syncing => Some(SYNCING_RETRY_AFTER_SECS),
+            Self::TooManyStreams => Some(STREAMS_RETRY_AFTER_SECS),
+            _ => None,
+        };
+        let error = match self {
+            Self::BadRequest(msg) | Self::NotFound(msg) => msg,
+            Self::Unauthorized => "missing or invalid admin token".into(),
+            Self::PayloadTooLarge => "request body too large".into(),
+            Self::Overloaded => "too many concurrent requests".into(),
+            Self::Syncing => "initial backfill in progress".into(),
+            Self::TooManyStreams => "too many open streams".into(),
+            Self::Indexer(e) => {
+                if status.is_server_error() {
+                    tracing::error!(code, "API request failed: {e}");
+                }
+                e.to_string()
+            }
+        };
+
+        let mut response = (status, Json(ErrorBody { error, code })).into_response();
+        if let Some(secs) = retry_after {
+            response
+                .headers_mut()
+                .insert(header::RETRY_AFTER, HeaderValue::from_static(secs));
+        }
+        response
+    }
+}
+
+pub type ApiResult<T> = std::result::Result<T, ApiError>;
@@ -0,0 +1,42 @@
+use axum::{
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::Serialize;
+
+/// Build a weak ETag from the cursor of the last row in a page.
+///
+/// Pages are ordered by an append-only sequence, so the same request returns the
+/// same body until a row past the current cursor is indexed.
+pub fn weak_etag(cursor: Option<impl std::fmt::Display>, len: usize) -> String {
+    match cursor {
+        Some(c) => format!("W/\"{c}-{len}\""),
+        None => "W/\"empty\"".to_string(),
+    }
+}
+
+fn matches(headers: &HeaderMap, etag: &str) -> bool {
+    let Some(value) = headers
+        .get(header::IF_NONE_MATCH)
+        .and_then(|v| v.to_str().ok())
+    else {
+        return false;
+    };
+    let strip = |t: &str| t.trim().trim_start_matches("W/").to_string();
+    let etag = strip(etag);
+    value
+        .split(',')
+        .any(|t| t.trim() == "*" || strip(t) == etag)
+}
+
+/// Serialize `body` with an `ETag` header, or return 304 if the client already has it.
+pub fn json_with_etag<T: Serialize>(headers: &HeaderMap, etag: String, body: T) -> Response {
+    let value = HeaderValue::from_str(&etag).expect("etag is valid header value");
+
+    if matches(headers, &etag) {
+        return (StatusCode::NOT_MODIFIED, [(header::ETAG, value)]).into_response();
+    }
+
+    ([(header::ETAG, value)], Json(body)).into_response()
+}
@@ -0,0 +1,102 @@
+use std::convert::Infallible;
+
+use axum::{
+    body::{Body, Bytes},
+    extract::{Query, State},
+    http::header,
+    response::{IntoResponse, Response},
+};
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc;
+
+use crate::db::queries::{for_each_handshake, for_each_hsr, for_each_message};
+
+use super::encoding::BinaryEncoding;
+use super::error::ApiResult;
+use super::handshakes::{HandshakeJson, HsrJson};
+use super::messages::MessageJson;
+use super::state::AppState;
+
+/// Rows buffered between the DB reader and the HTTP body.
+const CHANNEL_CAPACITY: usize = 256;
+
+#[derive(Deserialize, Clone, Copy)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum ExportTable {
+    Messages,
+    Handshakes,
+    HandshakeResponses,
+}
+
+#[derive(Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::IntoParams))]
+#[cfg_attr(feature = "openapi", into_params(parameter_in = Query))]
+pub struct ExportQuery {
+    pub table: ExportTable,
+    pub from_block: Option<i64>,
+    #[serde(default)]
+    pub encoding: BinaryEncoding,
+}
+
+fn to_line<T: Serialize>(value: &T) -> Bytes {
+    let mut line = serde_json::to_vec(value).unwrap_or_default();
+    line.push(b'\n');
+    Bytes::from(line)
+}
+
+#[cfg_attr(
+    feature = "openapi",
+    utoipa::path(
+        get,
+        path = "/export",
+        tag = "export",
+        params(ExportQuery),
+        responses(
+            (status = 200, description = "One JSON row per line", content_type = "application/x-ndjson"),
+            (status = 503, description = "DB connection pool exhausted", body = super::error::ErrorBody),
+        )
+    )
+)]
+/// Stream a whole table as NDJSON in on-chain order.
+///
+/// Rows are read on a blocking thread holding a dedicated pooled connection and handed
+/// to the body through a bounded channel. When the client disconnects the receiver is
+/// dropped, the next send fails, and the reader returns its connection to the pool.
+pub async fn export(
+    State(state): State<AppState>,
+    Query(query): Query<ExportQuery>,
+) -> ApiResult<Response> {
+    let conn = state.read_pool.get()?;
+    let from_block = query.from_block.unwrap_or(0);
+    let encoding = query.encoding;
+    let (tx, rx) = mpsc::channel::<Bytes>(CHANNEL_CAPACITY);
+
+    tokio::task::spawn_blocking(move || {
+        let send = |line: Bytes| tx.blocking_send(line).is_ok();
+        let result = match query.table {
+            ExportTable::Messages => for_each_message(&conn, from_block, |r| {
+                send(to_line(&MessageJson::encoded(r, encoding)))
+            }),
+            ExportTable::Handshakes => for_each_handshake(&conn, from_block, |r| {
+                send(to_line(&HandshakeJson::encoded(r, encoding)))
+            }),
+            ExportTable::HandshakeResponses => for_each_hsr(&conn, from_block, |r| {
+                send(to_line(&HsrJson::encoded(r, encoding)))
+            }),
+        };
+        if let Err(e) = result {
+            tracing::warn!("Export aborted: {e}");
+        }
+    });
+
+    let stream = futures_lite::stream::unfold(rx, |mut rx| async move {
+        rx.recv().await.map(|line| (Ok::<_, Infallible>(line), rx))
+    });
+
+    Ok((
+        [(header::CONTENT_TYPE, "application/x-ndjson")],
+        Body::from_stream(stream),
+    )
+        .into_response())
+}
@@ -0,0 +1,102 @@
+use axum::{
+    extract::{Query, State},
+    http::HeaderMap,
+    response::Response,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::db::models::FeedEvent;
+use crate::db::queries::feed_events;
+
+use super::encoding::BinaryEncoding;
+use super::error::ApiResult;
+#[cfg(feature = "openapi")]
+use super::error::ErrorBody;
+use super::etag::{json_with_etag, weak_etag};
+use super::handshakes::{HandshakeJson, HsrJson};
+use super::messages::MessageJson;
+use super::state::AppState;
+
+const DEFAULT_LIMIT: i64 = 100;
+const MAX_LIMIT: i64 = 1000;
+
+#[derive(Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::IntoParams))]
+#[cfg_attr(feature = "openapi", into_params(parameter_in = Query))]
+pub struct FeedQuery {
+    /// Cursor: only events after this block, or after `after_log_index` within it
+    pub after_block: Option<i64>,
+    pub after_log_index: Option<i64>,
+    pub limit: Option<i64>,
+    #[serde(default)]
+    pub encoding: BinaryEncoding,
+}
+
+/// An event of any type, tagged with `type`.
+#[derive(Serialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum FeedItemJson {
+    Message(MessageJson),
+    Handshake(HandshakeJson),
+    HandshakeResponse(HsrJson),
+}
+
+impl FeedItemJson {
+    fn encoded(event: FeedEvent, encoding: BinaryEncoding) -> Self {
+        match event {
+            FeedEvent::Message(r) => Self::Message(MessageJson::encoded(r, encoding)),
+            FeedEvent::Handshake(r) => Self::Handshake(HandshakeJson::encoded(r, encoding)),
+            FeedEvent::HandshakeResponse(r) => {
+                Self::HandshakeResponse(HsrJson::encoded(r, encoding))
+            }
+        }
+    }
+}
+
+#[cfg_attr(
+    feature = "openapi",
+    utoipa::path(
+        get,
+        path = "/feed",
+        tag = "messages",
+        params(FeedQuery),
+        responses(
+            (status = 200, description = "Events in on-chain order, with a weak ETag", body = [FeedItemJson]),
+            (status = 304, description = "Unchanged since If-None-Match"),
+            (status = 503, description = "DB connection pool exhausted", body = ErrorBody),
+        )
+    )
+)]
+/// Messages, handshakes and handshake responses merged into one timeline, ordered by
+/// `(block_number, log_index)`. Page by passing the last event's pair as the cursor.
+pub async fn feed(
+    State(state): State<AppState>,
+    Query(query): Query<FeedQuery>,
+    headers: HeaderMap,
+) -> ApiResult<Response> {
+    let limit = query.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+    let after = query
+        .after_block
+        .map(|b| (b, query.after_log_index.unwrap_or(i64::MAX)));
+
+    let conn = state.read_pool.get()?;
+    let events = feed_events(&conn, after, limit)?;
+
+    let last = events.last().map(|event| {
+        let (block_number, log_index) = match event {
+            FeedEvent::Message(r) => (r.block_number, r.log_index),
+            FeedEvent::Handshake(r) => (r.block_number, r.log_index),
+            FeedEvent::HandshakeResponse(r) => (r.block_number, r.log_index),
+        };
+        format!("{block_number}:{log_index}")
+    });
+    let etag = weak_etag(last, events.len());
+
+    let body: Vec<FeedItemJson> = events
+        .into_iter()
+        .map(|event| FeedItemJson::encoded(event, query.encoding))
+        .collect();
+
+    Ok(json_with_etag(&headers, etag, body))
+}
@@ -0,0 +1,344 @@
+use axum::{
+    extract::{Path, Query, State},
+    Json,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::db::models::{HandshakeRow, HsrRow};
+use crate::db::queries::{
+    get_handshake_with_responses, hsrs_by_responder, messages_by_topic, search_handshakes,
+};
+use crate::types::{RecipientHash, SenderAddress, TopicHash};
+
+use super::encoding::{parse_bytes32, to_hex, BinaryEncoding};
+#[cfg(feature = "openapi")]
+use super::error::ErrorBody;
+use super::error::{ApiError, ApiResult};
+use super::messages::MessageJson;
+use super::state::AppState;
+
+const DEFAULT_LIMIT: i64 = 100;
+const MAX_LIMIT: i64 = 1000;
+
+#[derive(Serialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct HandshakeJson {
+    pub recipient_hash: String,
+    pub seq: i64,
+    pub sender: String,
+    pub pub_keys: String,
+    pub ephemeral_pub_key: String,
+    pub plaintext_payload: String,
+    pub block_number: i64,
+    pub log_index: i64,
+    pub block_timestamp: i64,
+    pub tx_hash: Option<String>,
+    /// Unix time the indexer stored the event; 0 if unknown
+    pub indexed_at: i64,
+}
+
+impl From<HandshakeRow> for HandshakeJson {
+    fn from(r: HandshakeRow) -> Self {
+        Self::encoded(r, BinaryEncoding::Hex)
+    }
+}
+
+impl HandshakeJson {
+    pub fn encoded(r: HandshakeRow, encoding: BinaryEncoding) -> Self {
+        Self {
+            recipient_hash: r.recipient_hash.to_string(),
+            seq: r.seq,
+            sender: r.sender.to_string(),
+            pub_keys: encoding.encode(&r.pub_keys),
+            ephemeral_pub_key: encoding.encode(&r.ephemeral_pub_key),
+            plaintext_payload: encoding.encode(&r.plaintext_payload),
+            block_number: r.block_number,
+            log_index: r.log_index,
+            block_timestamp: r.block_timestamp,
+            tx_hash: r.tx_hash.map(|h| to_hex(&h)),
+            indexed_at: r.indexed_at,
+        }
+    }
+}
+
+#[derive(Serialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct HsrJson {
+    pub global_seq: i64,
+    pub in_response_to: String,
+    pub responder: String,
+    pub responder_ephemeral_r: String,
+    pub ciphertext: String,
+    pub block_number: i64,
+    pub log_index: i64,
+    pub block_timestamp: i64,
+    pub tx_hash: Option<String>,
+    /// Unix time the indexer stored the event; 0 if unknown
+    pub indexed_at: i64,
+}
+
+impl From<HsrRow> for HsrJson {
+    fn from(r: HsrRow) -> Self {
+        Self::encoded(r, BinaryEncoding::Hex)
+    }
+}
+
+impl HsrJson {
+    pub fn encoded(r: HsrRow, encoding: BinaryEncoding) -> Self {
+        Self {
+            global_seq: r.global_seq,
+            in_response_to: to_hex(&r.in_response_to),
+            responder: r.responder.to_string(),
+            responder_ephemeral_r: r.responder_ephemeral_r.to_string(),
+            ciphertext: encoding.encode(&r.ciphertext),
+            block_number: r.block_number,
+            log_index: r.log_index,
+            block_timestamp: r.block_timestamp,
+            tx_hash: r.tx_hash.map(|h| to_hex(&h)),
+            indexed_at: r.indexed_at,
+        }
+    }
+}
+
+#[derive(Serialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct HandshakeDetailResponse {
+    pub handshake: HandshakeJson,
+    pub responses: Vec<HsrJson>,
+}
+
+#[derive(Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::IntoParams))]
+#[cfg_attr(feature = "openapi", into_params(parameter_in = Query))]
+pub struct HandshakeDetailQuery {
+    /// `inResponseTo` tag the client matched for this handshake.
+    pub tag: Option<String>,
+    #[serde(default)]
+    pub encoding: BinaryEncoding,
+}
+
+#[cfg_attr(
+    feature = "openapi",
+    utoipa::path(
+        get,
+        path = "/handshakes/{recipient_hash}/{seq}",
+        tag = "handshakes",
+        params(
+            ("recipient_hash" = String, Path, description = "32-byte recipient hash hex"),
+            ("seq" = i64, Path, description = "Handshake seq for the recipient"),
+            HandshakeDetailQuery,
+        ),
+        responses(
+            (status = 200, body = HandshakeDetailResponse),
+            (status = 400, description = "Malformed parameters", body = ErrorBody),
+            (status = 404, description = "Not found", body = ErrorBody),
+            (status = 503, description = "DB connection pool exhausted", body = ErrorBody),
+        )
+    )
+)]
+pub async fn get_handshake(
+    State(state): State<AppState>,
+    Path((recipient_hash, seq)): Path<(String, i64)>,
+    Query(query): Query<HandshakeDetailQuery>,
+) -> ApiResult<Json<HandshakeDetailResponse>> {
+    let recipient_hash = recipient_hash
+        .parse::<RecipientHash>()
+        .map_err(|_| ApiError::bad_request("recipient_hash must be 32-byte hex"))?;
+    let tag = query
+        .tag
+        .as_deref()
+        .map(|t| parse_bytes32(t).ok_or_else(|| ApiError::bad_request("tag must be 32-byte hex")))
+        .transpose()?;
+
+    let conn = state.read_pool.get()?;
+    let (handshake, responses) =
+        get_handshake_with_responses(&conn, &recipient_hash, seq, tag.as_ref())?
+            .ok_or_else(|| ApiError::not_found("handshake not found"))?;
+
+    Ok(Json(HandshakeDetailResponse {
+        handshake: HandshakeJson::encoded(handshake, query.encoding),
+        responses: responses
+            .into_iter()
+            .map(|r| HsrJson::encoded(r, query.encoding))
+            .collect(),
+    }))
+}
+
+#[derive(Serialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct HandshakeThreadResponse {
+    pub handshake: HandshakeJson,
+    pub responses: Vec<HsrJson>,
+    /// Messages on `topic` in seq order
+    pub messages: Vec<MessageJson>,
+}
+
+#[derive(Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::IntoParams))]
+#[cfg_attr(feature = "openapi", into_params(parameter_in = Query))]
+pub struct HandshakeThreadQuery {
+    /// Conversation topic the client derived from this handshake's session
+    pub topic: String,
+    /// `inResponseTo` tag the client matched for this handshake.
+    pub tag: Option<String>,
+    /// Cursor: only messages with a seq above this
+    pub after_seq: Option<i64>,
+    pub limit: Option<i64>,
+    #[serde(default)]
+    pub encoding: BinaryEncoding,
+}
+
+#[cfg_attr(
+    feature = "openapi",
+    utoipa::path(
+        get,
+        path = "/handshakes/{recipient_hash}/{seq}/thread",
+        tag = "handshakes",
+        params(
+            ("recipient_hash" = String, Path, description = "32-byte recipient hash hex"),
+            ("seq" = i64, Path, description = "Handshake seq for the recipient"),
+            HandshakeThreadQuery,
+        ),
+        responses(
+            (status = 200, body = HandshakeThreadResponse),
+            (status = 400, description = "Malformed parameters", body = ErrorBody),
+            (status = 404, description = "Not found", body = ErrorBody),
+            (status = 503, description = "DB connection pool exhausted", body = ErrorBody),
+        )
+    )
+)]
+/// A handshake, its responses, and one page of the message thread on a client-derived topic.
+pub async fn get_thread(
+    State(state): State<AppState>,
+    Path((recipient_hash, seq)): Path<(String, i64)>,
+    Query(query): Query<HandshakeThreadQuery>,
+) -> ApiResult<Json<HandshakeThreadResponse>> {
+    let recipient_hash = recipient_hash
+        .parse::<RecipientHash>()
+        .map_err(|_| ApiError::bad_request("recipient_hash must be 32-byte hex"))?;
+    let topic = query
+        .topic
+        .parse::<TopicHash>()
+        .map_err(|_| ApiError::bad_request("topic must be 32-byte hex"))?;
+    let tag = query
+        .tag
+        .as_deref()
+        .map(|t| parse_bytes32(t).ok_or_else(|| ApiError::bad_request("tag must be 32-byte hex")))
+        .transpose()?;
+    let limit = query.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+
+    let conn = state.read_pool.get()?;
+    let (handshake, responses) =
+        get_handshake_with_responses(&conn, &recipient_hash, seq, tag.as_ref())?
+            .ok_or_else(|| ApiError::not_found("handshake not found"))?;
+    let messages = messages_by_topic(&conn, &topic, None, query.after_seq, None, false, limit)?;
+
+    Ok(Json(HandshakeThreadResponse {
+        handshake: HandshakeJson::encoded(handshake, query.encoding),
+        responses: responses
+            .into_iter()
+            .map(|r| HsrJson::encoded(r, query.encoding))
+            .collect(),
+        messages: messages
+            .into_iter()
+            .map(|r| MessageJson::encoded(r, query.encoding))
+            .collect(),
+    }))
+}
+
+#[derive(Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::IntoParams))]
+#[cfg_attr(feature = "openapi", into_params(parameter_in = Query))]
+pub struct HandshakeSearchQuery {
+    /// Terms that must all appear in the UTF-8 payload
+    pub q: String,
+    pub limit: Option<i64>,
+    #[serde(default)]
+    pub encoding: BinaryEncoding,
+}
+
+#[cfg_attr(
+    feature = "openapi",
+    utoipa::path(
+        get,
+        path = "/handshakes/search",
+        tag = "handshakes",
+        params(HandshakeSearchQuery),
+        responses(
+            (status = 200, description = "Handshakes, best match first", body = [HandshakeJson]),
+            (status = 400, description = "Missing search terms", body = ErrorBody),
+            (status = 404, description = "Full-text search is not enabled", body = ErrorBody),
+            (status = 503, description = "DB connection pool exhausted", body = ErrorBody),
+        )
+    )
+)]
+/// Full-text search over handshake payloads that are valid UTF-8 (`ENABLE_FTS`).
+pub async fn search(
+    State(state): State<AppState>,
+    Query(query): Query<HandshakeSearchQuery>,
+) -> ApiResult<Json<Vec<HandshakeJson>>> {
+    if !state.config.enable_fts {
+        return Err(ApiError::not_found("full-text search is not enabled"));
+    }
+    if query.q.trim().is_empty() {
+        return Err(ApiError::bad_request("q must contain a search term"));
+    }
+    let limit = query.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+
+    let conn = state.read_pool.get()?;
+    let rows = search_handshakes(&conn, &query.q, limit)?;
+
+    Ok(Json(
+        rows.into_iter()
+            .map(|r| HandshakeJson::encoded(r, query.encoding))
+            .collect(),
+    ))
+}
+
+#[derive(Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::IntoParams))]
+#[cfg_attr(feature = "openapi", into_params(parameter_in = Query))]
+pub struct ResponderQuery {
+    /// 20-byte responder address hex
+    pub responder: String,
+    /// Cursor: return responses with `global_seq` greater than this
+    pub after_seq: Option<i64>,
+    pub limit: Option<i64>,
+    #[serde(default)]
+    pub encoding: BinaryEncoding,
+}
+
+#[cfg_attr(
+    feature = "openapi",
+    utoipa::path(
+        get,
+        path = "/handshake-responses/by-responder",
+        tag = "handshakes",
+        params(ResponderQuery),
+        responses(
+            (status = 200, description = "Responses in global_seq order", body = [HsrJson]),
+            (status = 400, description = "Malformed parameters", body = ErrorBody),
+            (status = 503, description = "DB connection pool exhausted", body = ErrorBody),
+        )
+    )
+)]
+/// Handshake responses sent by one address, paginated by `global_seq`.
+pub async fn by_responder(
+    State(state): State<AppState>,
+    Query(query): Query<ResponderQuery>,
+) -> ApiResult<Json<Vec<HsrJson>>> {
+    let responder = query
+        .responder
+        .parse::<SenderAddress>()
+        .map_err(|_| ApiError::bad_request("responder must be 20-byte hex"))?;
+    let limit = query.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+
+    let conn = state.read_pool.get()?;
+    let rows = hsrs_by_responder(&conn, &responder, query.after_seq, limit)?;
+
+    Ok(Json(
+        rows.into_iter()
+            .map(|r| HsrJson::encoded(r, query.encoding))
+            .collect(),
+    ))
+}
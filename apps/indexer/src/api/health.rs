@@ -1,20 +1,72 @@
 use axum::{extract::State, http::StatusCode, Json};
 use serde::Serialize;
 
+use crate::config::SubscribeMode;
 use crate::db::models::EventCounts;
-use crate::db::queries::{get_event_counts, get_last_processed_block};
+use crate::db::queries::{get_event_counts, get_history_start_block, get_last_processed_block};
 
+use super::error::ApiResult;
+#[cfg(feature = "openapi")]
+use super::error::ErrorBody;
 use super::state::AppState;
 
 #[derive(Serialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
 pub struct HealthResponse {
+    /// `ok`, `syncing` before the first block is indexed, or `degraded` while the disk is
+    /// full
     pub status: &'static str,
+    /// Event writes are failing with `SQLITE_FULL`; nothing new is stored until space is
+    /// freed
+    pub disk_full: bool,
     pub last_block: Option<i64>,
+    /// Set when `MAX_BACKFILL_BLOCKS` capped the first sync: earlier events are not indexed
+    pub history_start_block: Option<i64>,
     pub uptime_seconds: u64,
     pub counts: EventCountsResponse,
+    /// Unset until the subscriber has timed its first RPC call
+    pub rpc_latency: Option<RpcLatencyResponse>,
+    /// How new logs are followed: `ws` or `poll`, including after a fallback
+    pub subscribe_mode: &'static str,
+    /// Set when the WebSocket endpoint rejected `eth_subscribe` and the subscriber fell
+    /// back to polling
+    pub subscription_error: Option<String>,
+    pub reorgs: ReorgsResponse,
+    pub rpc_breaker: RpcBreakerResponse,
 }
 
 #[derive(Serialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct RpcBreakerResponse {
+    /// `closed`, `open` (HTTP RPC calls fail fast) or `half_open` (the next call probes
+    /// the provider)
+    pub state: &'static str,
+    /// Failed calls since the last success, towards `RPC_BREAKER_THRESHOLD`
+    pub consecutive_failures: u32,
+    /// Times the breaker opened since startup
+    pub times_opened: u64,
+}
+
+#[derive(Serialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct ReorgsResponse {
+    /// Chain reorgs detected since startup
+    pub detected: u64,
+    /// Blocks replaced by the deepest one, 0 if none
+    pub max_depth: u64,
+}
+
+#[derive(Serialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct RpcLatencyResponse {
+    /// Latest `eth_blockNumber` round trip
+    pub last_ms: u64,
+    /// Moving average, weighting each new sample by 1/8
+    pub avg_ms: u64,
+}
+
+#[derive(Serialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
 pub struct EventCountsResponse {
     pub messages: i64,
     pub handshakes: i64,
@@ -31,18 +83,146 @@ impl From<EventCounts> for EventCountsResponse {
     }
 }
 
-pub async fn health(State(state): State<AppState>) -> Result<Json<HealthResponse>, StatusCode> {
-    let conn = state.pool.get().map_err(|_| StatusCode::SERVICE_UNAVAILABLE)?;
+#[cfg_attr(
+    feature = "openapi",
+    utoipa::path(
+        get,
+        path = "/health",
+        tag = "health",
+        responses(
+            (status = 200, body = HealthResponse),
+            (status = 503, description = "DB connection pool exhausted", body = ErrorBody),
+        )
+    )
+)]
+pub async fn health(State(state): State<AppState>) -> ApiResult<Json<HealthResponse>> {
+    let conn = state.read_pool.get()?;
 
-    let last_block = get_last_processed_block(&conn).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-    let counts = get_event_counts(&conn).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let last_block = get_last_processed_block(&conn)?;
+    let history_start_block = get_history_start_block(&conn)?;
+    let counts = get_event_counts(&conn)?;
 
-    let status = if last_block.is_some() { "ok" } else { "syncing" };
+    let subscription_error = state.chain_state.subscription_error();
+    let subscribe_mode = match state.config.subscribe_mode {
+        SubscribeMode::Ws if subscription_error.is_none() => "ws",
+        _ => "poll",
+    };
+
+    let disk_full = state.writer.disk_full();
+    let status = if disk_full {
+        "degraded"
+    } else if last_block.is_some() {
+        "ok"
+    } else {
+        "syncing"
+    };
 
     Ok(Json(HealthResponse {
         status,
+        disk_full,
         last_block,
+        history_start_block,
         uptime_seconds: state.uptime_seconds(),
         counts: counts.into(),
+        rpc_latency: state
+            .chain_state
+            .rpc_latency()
+            .map(|(last, avg)| RpcLatencyResponse {
+                last_ms: last.as_millis() as u64,
+                avg_ms: avg.as_millis() as u64,
+            }),
+        subscribe_mode,
+        subscription_error,
+        reorgs: ReorgsResponse {
+            detected: state.chain_state.reorgs_detected(),
+            max_depth: state.chain_state.max_reorg_depth(),
+        },
+        rpc_breaker: RpcBreakerResponse {
+            state: state.rpc_breaker.status().as_str(),
+            consecutive_failures: state.rpc_breaker.consecutive_failures(),
+            times_opened: state.rpc_breaker.times_opened(),
+        },
     }))
 }
+
+#[cfg_attr(
+    feature = "openapi",
+    utoipa::path(
+        get,
+        path = "/health/live",
+        tag = "health",
+        responses(
+            (status = 200, description = "Process is up and the DB answers"),
+            (status = 503, description = "DB unavailable"),
+        )
+    )
+)]
+/// Liveness: the process is up and the DB pool hands out working connections.
+pub async fn live(State(state): State<AppState>) -> StatusCode {
+    let ok = state
+        .pool
+        .get()
+        .ok()
+        .and_then(|conn| conn.query_row("SELECT 1", [], |_| Ok(())).ok())
+        .is_some();
+
+    if ok {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    }
+}
+
+#[derive(Serialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct ReadyResponse {
+    pub ready: bool,
+    pub indexed_block: u64,
+    pub chain_head: u64,
+    pub lag_blocks: u64,
+}
+
+#[cfg_attr(
+    feature = "openapi",
+    utoipa::path(
+        get,
+        path = "/health/ready",
+        tag = "health",
+        responses(
+            (status = 200, description = "Within READY_LAG_BLOCKS of the chain head", body = ReadyResponse),
+            (status = 503, description = "Still catching up", body = ReadyResponse),
+        )
+    )
+)]
+/// Readiness: the indexer is within `READY_LAG_BLOCKS` of the cached chain head.
+pub async fn ready(State(state): State<AppState>) -> ApiResult<(StatusCode, Json<ReadyResponse>)> {
+    let conn = state.read_pool.get()?;
+    let last_block = get_last_processed_block(&conn)?;
+
+    // The live subscription covers blocks without Verbeth events, which never move last_block
+    let indexed_block = last_block
+        .map(|b| b as u64)
+        .unwrap_or(0)
+        .max(state.chain_state.highest_seen_block());
+    let chain_head = state.chain_state.chain_head();
+    let lag_blocks = chain_head.saturating_sub(indexed_block);
+    let ready = state.initial_sync_complete()
+        && last_block.is_some()
+        && lag_blocks <= state.config.ready_lag_blocks;
+
+    let status = if ready {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+
+    Ok((
+        status,
+        Json(ReadyResponse {
+            ready,
+            indexed_block,
+            chain_head,
+            lag_blocks,
+        }),
+    ))
+}
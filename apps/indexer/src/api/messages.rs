@@ -0,0 +1,629 @@
+use std::collections::BTreeMap;
+
+use axum::{
+    extract::{Query, State},
+    http::HeaderMap,
+    response::Response,
+    Json,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::db::models::MessageRow;
+use crate::db::queries::{
+    count_messages_for_topic, first_message_for_topic, get_messages_by_coords, latest_per_topic,
+    messages_by_sender, messages_by_topic, messages_in_seq_range, messages_in_time_range,
+    seq_range_complete,
+};
+use crate::types::{SenderAddress, TopicHash};
+
+use super::encoding::{to_hex, BinaryEncoding, EncodingQuery};
+#[cfg(feature = "openapi")]
+use super::error::ErrorBody;
+use super::error::{ApiError, ApiResult};
+use super::etag::{json_with_etag, weak_etag};
+use super::state::AppState;
+
+const DEFAULT_LIMIT: i64 = 100;
+const MAX_LIMIT: i64 = 1000;
+/// Max topics per `POST /messages/latest` request.
+const MAX_LATEST_TOPICS: usize = 200;
+/// Max coordinates per `POST /messages/batch` request.
+const MAX_BATCH_COORDS: usize = 500;
+
+#[derive(Serialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct MessageJson {
+    pub topic: String,
+    pub seq: i64,
+    pub sender: String,
+    pub ciphertext: String,
+    pub timestamp: i64,
+    pub nonce: i64,
+    pub block_number: i64,
+    pub log_index: i64,
+    pub block_timestamp: i64,
+    pub tx_hash: Option<String>,
+    /// Unix time the indexer stored the event; 0 if unknown
+    pub indexed_at: i64,
+}
+
+impl From<MessageRow> for MessageJson {
+    fn from(r: MessageRow) -> Self {
+        Self::encoded(r, BinaryEncoding::Hex)
+    }
+}
+
+impl MessageJson {
+    pub fn encoded(r: MessageRow, encoding: BinaryEncoding) -> Self {
+        Self {
+            topic: r.topic.to_string(),
+            seq: r.seq,
+            sender: r.sender.to_string(),
+            ciphertext: encoding.encode(&r.ciphertext),
+            timestamp: r.timestamp,
+            nonce: r.nonce,
+            block_number: r.block_number,
+            log_index: r.log_index,
+            block_timestamp: r.block_timestamp,
+            tx_hash: r.tx_hash.map(|h| to_hex(&h)),
+            indexed_at: r.indexed_at,
+        }
+    }
+}
+
+/// Page direction for topic queries.
+#[derive(Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[serde(rename_all = "lowercase")]
+pub enum MessageOrder {
+    /// Oldest first, paged with `after_seq`
+    #[default]
+    Asc,
+    /// Newest first, paged with `before_seq`
+    Desc,
+}
+
+#[derive(Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::IntoParams))]
+#[cfg_attr(feature = "openapi", into_params(parameter_in = Query))]
+pub struct MessagesQuery {
+    pub topic: Option<String>,
+    pub sender: Option<String>,
+    /// Cursor for topic queries: only seqs above this
+    pub after_seq: Option<i64>,
+    /// Cursor for topic queries: only seqs below this, e.g. the last seq of an `order=desc` page
+    pub before_seq: Option<i64>,
+    /// Topic queries only
+    #[serde(default)]
+    pub order: MessageOrder,
+    /// Cursor for sender-only queries, which page in on-chain order
+    pub after_block: Option<i64>,
+    pub after_log_index: Option<i64>,
+    pub limit: Option<i64>,
+    #[serde(default)]
+    pub encoding: BinaryEncoding,
+}
+
+#[cfg_attr(
+    feature = "openapi",
+    utoipa::path(
+        get,
+        path = "/messages",
+        tag = "messages",
+        params(MessagesQuery),
+        responses(
+            (status = 200, description = "Messages, with a weak ETag", body = [MessageJson]),
+            (status = 304, description = "Unchanged since If-None-Match"),
+            (status = 400, description = "Malformed parameters", body = ErrorBody),
+            (status = 503, description = "DB connection pool exhausted", body = ErrorBody),
+        )
+    )
+)]
+pub async fn list(
+    State(state): State<AppState>,
+    Query(query): Query<MessagesQuery>,
+    headers: HeaderMap,
+) -> ApiResult<Response> {
+    let topic = query
+        .topic
+        .as_deref()
+        .map(|t| t.parse::<TopicHash>())
+        .transpose()
+        .map_err(|_| ApiError::bad_request("topic must be 32-byte hex"))?;
+    let sender = query
+        .sender
+        .as_deref()
+        .map(|s| s.parse::<SenderAddress>())
+        .transpose()
+        .map_err(|_| ApiError::bad_request("sender must be 20-byte hex"))?;
+    let limit = query.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+
+    let conn = state.read_pool.get()?;
+
+    let (rows, etag) = match (topic, sender) {
+        (Some(topic), sender) => {
+            let rows = messages_by_topic(
+                &conn,
+                &topic,
+                sender.as_ref(),
+                query.after_seq,
+                query.before_seq,
+                query.order == MessageOrder::Desc,
+                limit,
+            )?;
+            let etag = weak_etag(rows.last().map(|r| r.seq), rows.len());
+            (rows, etag)
+        }
+        (None, Some(_)) if query.order == MessageOrder::Desc => {
+            return Err(ApiError::bad_request("order=desc requires topic"))
+        }
+        (None, Some(sender)) => {
+            let after = query
+                .after_block
+                .map(|b| (b, query.after_log_index.unwrap_or(i64::MAX)));
+            let rows = messages_by_sender(&conn, &sender, after, limit)?;
+            let etag = weak_etag(
+                rows.last()
+                    .map(|r| format!("{}:{}", r.block_number, r.log_index)),
+                rows.len(),
+            );
+            (rows, etag)
+        }
+        (None, None) => return Err(ApiError::bad_request("topic or sender is required")),
+    };
+
+    let body: Vec<MessageJson> = rows
+        .into_iter()
+        .map(|r| MessageJson::encoded(r, query.encoding))
+        .collect();
+
+    Ok(json_with_etag(&headers, etag, body))
+}
+
+#[derive(Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::IntoParams))]
+#[cfg_attr(feature = "openapi", into_params(parameter_in = Query))]
+pub struct FirstQuery {
+    pub topic: String,
+    #[serde(default)]
+    pub encoding: BinaryEncoding,
+}
+
+#[cfg_attr(
+    feature = "openapi",
+    utoipa::path(
+        get,
+        path = "/messages/first",
+        tag = "messages",
+        params(FirstQuery),
+        responses(
+            (status = 200, body = MessageJson),
+            (status = 400, description = "Malformed parameters", body = ErrorBody),
+            (status = 404, description = "Topic has no messages", body = ErrorBody),
+            (status = 503, description = "DB connection pool exhausted", body = ErrorBody),
+        )
+    )
+)]
+/// The oldest indexed message of a topic, for showing how a conversation started.
+pub async fn first(
+    State(state): State<AppState>,
+    Query(query): Query<FirstQuery>,
+) -> ApiResult<Json<MessageJson>> {
+    let topic = query
+        .topic
+        .parse::<TopicHash>()
+        .map_err(|_| ApiError::bad_request("topic must be 32-byte hex"))?;
+
+    let conn = state.read_pool.get()?;
+    let row = first_message_for_topic(&conn, &topic)?
+        .ok_or_else(|| ApiError::not_found(format!("no messages for topic {topic}")))?;
+
+    Ok(Json(MessageJson::encoded(row, query.encoding)))
+}
+
+#[derive(Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::IntoParams))]
+#[cfg_attr(feature = "openapi", into_params(parameter_in = Query))]
+pub struct CountQuery {
+    pub topic: String,
+}
+
+#[derive(Serialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct MessageCountResponse {
+    pub topic: String,
+    pub count: i64,
+    /// Highest indexed seq; null if the topic has no messages
+    pub latest_seq: Option<i64>,
+}
+
+#[cfg_attr(
+    feature = "openapi",
+    utoipa::path(
+        get,
+        path = "/messages/count",
+        tag = "messages",
+        params(CountQuery),
+        responses(
+            (status = 200, body = MessageCountResponse),
+            (status = 400, description = "Malformed parameters", body = ErrorBody),
+            (status = 503, description = "DB connection pool exhausted", body = ErrorBody),
+        )
+    )
+)]
+/// How many messages a topic has, so clients can show a total or pick a sync strategy
+/// before paging. Unknown topics report a count of 0.
+pub async fn count(
+    State(state): State<AppState>,
+    Query(query): Query<CountQuery>,
+) -> ApiResult<Json<MessageCountResponse>> {
+    let topic = query
+        .topic
+        .parse::<TopicHash>()
+        .map_err(|_| ApiError::bad_request("topic must be 32-byte hex"))?;
+
+    let conn = state.read_pool.get()?;
+    let (count, latest_seq) = count_messages_for_topic(&conn, &topic)?;
+
+    Ok(Json(MessageCountResponse {
+        topic: topic.to_string(),
+        count,
+        latest_seq,
+    }))
+}
+
+#[derive(Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::IntoParams))]
+#[cfg_attr(feature = "openapi", into_params(parameter_in = Query))]
+pub struct RangeQuery {
+    pub topic: String,
+    pub from_seq: i64,
+    /// Inclusive; the range may span at most 1000 seqs
+    pub to_seq: i64,
+    #[serde(default)]
+    pub encoding: BinaryEncoding,
+}
+
+#[derive(Serialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct RangeResponse {
+    /// False if any seq in the range has not been indexed
+    pub complete: bool,
+    pub messages: Vec<MessageJson>,
+}
+
+#[cfg_attr(
+    feature = "openapi",
+    utoipa::path(
+        get,
+        path = "/messages/range",
+        tag = "messages",
+        params(RangeQuery),
+        responses(
+            (status = 200, description = "Messages in the seq range", body = RangeResponse),
+            (status = 400, description = "Malformed parameters", body = ErrorBody),
+            (status = 503, description = "DB connection pool exhausted", body = ErrorBody),
+        )
+    )
+)]
+/// Exactly the messages of a topic with `from_seq <= seq <= to_seq`, with a `complete`
+/// flag so syncing clients can tell a fully indexed slice from one with gaps.
+pub async fn range(
+    State(state): State<AppState>,
+    Query(query): Query<RangeQuery>,
+) -> ApiResult<Json<RangeResponse>> {
+    let topic = query
+        .topic
+        .parse::<TopicHash>()
+        .map_err(|_| ApiError::bad_request("topic must be 32-byte hex"))?;
+    if query.from_seq < 0 || query.to_seq < query.from_seq {
+        return Err(ApiError::bad_request("expected 0 <= from_seq <= to_seq"));
+    }
+    if query.to_seq - query.from_seq >= MAX_LIMIT {
+        return Err(ApiError::bad_request(format!(
+            "range may span at most {MAX_LIMIT} seqs"
+        )));
+    }
+
+    let conn = state.read_pool.get()?;
+    let complete = seq_range_complete(&conn, &topic, query.from_seq, query.to_seq)?;
+    let rows = messages_in_seq_range(&conn, &topic, query.from_seq, query.to_seq)?;
+
+    Ok(Json(RangeResponse {
+        complete,
+        messages: rows
+            .into_iter()
+            .map(|r| MessageJson::encoded(r, query.encoding))
+            .collect(),
+    }))
+}
+
+#[derive(Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::IntoParams))]
+#[cfg_attr(feature = "openapi", into_params(parameter_in = Query))]
+pub struct ByTimeQuery {
+    /// Inclusive lower `block_timestamp` bound (unix seconds)
+    pub from: i64,
+    /// Inclusive upper `block_timestamp` bound (unix seconds)
+    pub to: i64,
+    /// Max results (default 100, max 1000)
+    pub limit: Option<i64>,
+    #[serde(default)]
+    pub encoding: BinaryEncoding,
+}
+
+#[cfg_attr(
+    feature = "openapi",
+    utoipa::path(
+        get,
+        path = "/messages/by-time",
+        tag = "messages",
+        params(ByTimeQuery),
+        responses(
+            (status = 200, description = "Messages in the time window, oldest first", body = Vec<MessageJson>),
+            (status = 400, description = "Malformed parameters", body = ErrorBody),
+            (status = 503, description = "DB connection pool exhausted", body = ErrorBody),
+        )
+    )
+)]
+/// Messages of every topic whose block falls in `[from, to]`, oldest first.
+pub async fn by_time(
+    State(state): State<AppState>,
+    Query(query): Query<ByTimeQuery>,
+) -> ApiResult<Json<Vec<MessageJson>>> {
+    if query.from > query.to {
+        return Err(ApiError::bad_request("from must not exceed to"));
+    }
+    let limit = query.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+
+    let conn = state.read_pool.get()?;
+    let rows = messages_in_time_range(&conn, query.from, query.to, limit)?;
+
+    Ok(Json(
+        rows.into_iter()
+            .map(|r| MessageJson::encoded(r, query.encoding))
+            .collect(),
+    ))
+}
+
+#[cfg_attr(
+    feature = "openapi",
+    utoipa::path(
+        post,
+        path = "/messages/latest",
+        tag = "messages",
+        params(EncodingQuery),
+        request_body(content = Vec<String>, description = "32-byte topic hexes"),
+        responses(
+            (status = 200, description = "Latest message keyed by topic hex", body = BTreeMap<String, MessageJson>),
+            (status = 400, description = "Malformed parameters", body = ErrorBody),
+            (status = 503, description = "DB connection pool exhausted", body = ErrorBody),
+        )
+    )
+)]
+/// The most recent message of each requested topic, keyed by topic hex, for inbox views.
+/// Topics with no messages are left out of the response.
+pub async fn latest(
+    State(state): State<AppState>,
+    Query(query): Query<EncodingQuery>,
+    Json(topics): Json<Vec<String>>,
+) -> ApiResult<Json<BTreeMap<String, MessageJson>>> {
+    if topics.len() > MAX_LATEST_TOPICS {
+        return Err(ApiError::bad_request(format!(
+            "at most {MAX_LATEST_TOPICS} topics per request"
+        )));
+    }
+    let topics = topics
+        .iter()
+        .map(|t| t.parse::<TopicHash>())
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|_| ApiError::bad_request("topic must be 32-byte hex"))?;
+
+    let conn = state.read_pool.get()?;
+    let rows = latest_per_topic(&conn, &topics)?;
+
+    Ok(Json(
+        rows.into_iter()
+            .map(|r| {
+                let json = MessageJson::encoded(r, query.encoding);
+                (json.topic.clone(), json)
+            })
+            .collect(),
+    ))
+}
+
+#[derive(Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct MessageCoord {
+    /// 32-byte topic hex
+    pub topic: String,
+    pub seq: i64,
+}
+
+#[cfg_attr(
+    feature = "openapi",
+    utoipa::path(
+        post,
+        path = "/messages/batch",
+        tag = "messages",
+        params(EncodingQuery),
+        request_body = Vec<MessageCoord>,
+        responses(
+            (status = 200, description = "Matching messages by topic, then seq", body = [MessageJson]),
+            (status = 400, description = "Malformed parameters", body = ErrorBody),
+            (status = 503, description = "DB connection pool exhausted", body = ErrorBody),
+        )
+    )
+)]
+/// Specific messages by `(topic, seq)`, so a client catching up can fetch the ones it is
+/// missing in one round trip. Coordinates with no message are left out of the response.
+pub async fn batch(
+    State(state): State<AppState>,
+    Query(query): Query<EncodingQuery>,
+    Json(coords): Json<Vec<MessageCoord>>,
+) -> ApiResult<Json<Vec<MessageJson>>> {
+    if coords.len() > MAX_BATCH_COORDS {
+        return Err(ApiError::bad_request(format!(
+            "at most {MAX_BATCH_COORDS} coordinates per request"
+        )));
+    }
+    let coords = coords
+        .iter()
+        .map(|c| c.topic.parse::<TopicHash>().map(|topic| (topic, c.seq)))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|_| ApiError::bad_request("topic must be 32-byte hex"))?;
+
+    let conn = state.read_pool.get()?;
+    let rows = get_messages_by_coords(&conn, &coords)?;
+
+    Ok(Json(
+        rows.into_iter()
+            .map(|r| MessageJson::encoded(r, query.encoding))
+            .collect(),
+    ))
+}
+
+/// Ratchet v1 framing, as written by the SDK: version byte, Ed25519 signature, sender DH
+/// key, previous chain length `pn` and message number `n` (both u32 BE), then the
+/// secretbox nonce and sealed body.
+const RATCHET_VERSION: u8 = 0x01;
+const SIGNATURE_LEN: usize = 64;
+const DH_KEY_LEN: usize = 32;
+const RATCHET_HEADER_LEN: usize = 1 + SIGNATURE_LEN + DH_KEY_LEN + 4 + 4;
+const SECRETBOX_NONCE_LEN: usize = 24;
+const SECRETBOX_MAC_LEN: usize = 16;
+
+#[derive(Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct DecodeRequest {
+    /// Ciphertext hex, with or without a 0x prefix
+    pub ciphertext: String,
+}
+
+#[derive(Serialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[serde(rename_all = "lowercase")]
+pub enum CiphertextFormat {
+    /// Binary ratchet payload (leading version byte)
+    Ratchet,
+    /// Legacy JSON envelope
+    Json,
+    Unknown,
+}
+
+#[derive(Serialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct RatchetHeaderJson {
+    /// Sender's 32-byte DH ratchet key, hex
+    pub dh: String,
+    pub pn: u32,
+    pub n: u32,
+    /// Bytes after the header: secretbox nonce and sealed body
+    pub body_length: usize,
+}
+
+#[derive(Serialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct FramingJson {
+    pub length: usize,
+    /// `MAX_CIPHERTEXT_SIZE`; longer ciphertexts are dead-lettered instead of indexed
+    pub max_length: usize,
+    pub within_limit: bool,
+    pub format: CiphertextFormat,
+    pub version: Option<u64>,
+    pub ratchet: Option<RatchetHeaderJson>,
+    /// Why the framing is invalid, if it is
+    pub problem: Option<String>,
+}
+
+#[cfg_attr(
+    feature = "openapi",
+    utoipa::path(
+        post,
+        path = "/messages/decode",
+        tag = "messages",
+        request_body = DecodeRequest,
+        responses(
+            (status = 200, description = "Structural metadata of the ciphertext", body = FramingJson),
+            (status = 400, description = "Ciphertext is not hex", body = ErrorBody),
+        )
+    )
+)]
+/// Inspect a ciphertext's framing so clients can check a blob without implementing the
+/// decoder. Nothing is decrypted, and the signature is not verified.
+pub async fn decode(
+    State(state): State<AppState>,
+    Json(request): Json<DecodeRequest>,
+) -> ApiResult<Json<FramingJson>> {
+    let hex_str = request.ciphertext.trim();
+    let bytes = hex::decode(hex_str.strip_prefix("0x").unwrap_or(hex_str))
+        .map_err(|_| ApiError::bad_request("ciphertext must be hex"))?;
+    let max_length = state.config.payload_limits.ciphertext;
+
+    let mut framing = inspect_framing(&bytes);
+    framing.max_length = max_length;
+    framing.within_limit = bytes.len() <= max_length;
+    Ok(Json(framing))
+}
+
+fn inspect_framing(bytes: &[u8]) -> FramingJson {
+    let mut framing = FramingJson {
+        length: bytes.len(),
+        max_length: 0,
+        within_limit: false,
+        format: CiphertextFormat::Unknown,
+        version: None,
+        ratchet: None,
+        problem: None,
+    };
+
+    match bytes.first() {
+        None => framing.problem = Some("ciphertext is empty".into()),
+        Some(b'{') => {
+            framing.format = CiphertextFormat::Json;
+            match serde_json::from_slice::<serde_json::Value>(bytes) {
+                Ok(envelope) => {
+                    framing.version = envelope.get("v").and_then(|v| v.as_u64());
+                    let missing: Vec<&str> = ["v", "epk", "n", "ct"]
+                        .into_iter()
+                        .filter(|field| envelope.get(field).is_none())
+                        .collect();
+                    if !missing.is_empty() {
+                        framing.problem =
+                            Some(format!("envelope is missing {}", missing.join(", ")));
+                    }
+                }
+                Err(e) => framing.problem = Some(format!("envelope is not valid JSON: {e}")),
+            }
+        }
+        Some(&version) => {
+            framing.version = Some(version.into());
+            if version != RATCHET_VERSION {
+                framing.problem = Some(format!("unknown version byte 0x{version:02x}"));
+            } else if bytes.len() < RATCHET_HEADER_LEN {
+                framing.format = CiphertextFormat::Ratchet;
+                framing.problem = Some(format!(
+                    "shorter than the {RATCHET_HEADER_LEN}-byte ratchet header"
+                ));
+            } else {
+                framing.format = CiphertextFormat::Ratchet;
+                let dh_start = 1 + SIGNATURE_LEN;
+                let pn_start = dh_start + DH_KEY_LEN;
+                let u32_at = |i: usize| u32::from_be_bytes(bytes[i..i + 4].try_into().unwrap());
+                let body_length = bytes.len() - RATCHET_HEADER_LEN;
+                framing.ratchet = Some(RatchetHeaderJson {
+                    dh: to_hex(&bytes[dh_start..pn_start]),
+                    pn: u32_at(pn_start),
+                    n: u32_at(pn_start + 4),
+                    body_length,
+                });
+                if body_length < SECRETBOX_NONCE_LEN + SECRETBOX_MAC_LEN {
+                    framing.problem = Some(format!(
+                        "body is {body_length} bytes, shorter than a secretbox nonce and MAC"
+                    ));
+                }
+            }
+        }
+    }
+    framing
+}
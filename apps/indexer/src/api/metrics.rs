@@ -0,0 +1,161 @@
+use std::fmt::Write;
+use std::sync::atomic::Ordering;
+
+use axum::{extract::State, http::header, response::IntoResponse};
+
+use crate::indexer::contract_metrics::ContractCounts;
+
+use super::state::AppState;
+
+const CONTENT_TYPE: &str = "text/plain; version=0.0.4";
+
+/// Prometheus text exposition of in-process counters and gauges.
+pub async fn metrics(State(state): State<AppState>) -> impl IntoResponse {
+    let mut out = String::new();
+
+    gauge(
+        &mut out,
+        "indexer_uptime_seconds",
+        "Seconds since the indexer started",
+        state.uptime_seconds(),
+    );
+    gauge(
+        &mut out,
+        "indexer_chain_head",
+        "Latest block number seen from the RPC provider",
+        state.chain_state.chain_head(),
+    );
+    gauge(
+        &mut out,
+        "indexer_stream_clients",
+        "Open /stream/* connections",
+        state.stream_clients.load(Ordering::Relaxed) as u64,
+    );
+    if let Some((last, avg)) = state.chain_state.rpc_latency() {
+        gauge(
+            &mut out,
+            "indexer_rpc_latency_milliseconds",
+            "Round-trip time of the latest eth_blockNumber probe to the RPC provider",
+            last.as_millis() as u64,
+        );
+        gauge(
+            &mut out,
+            "indexer_rpc_latency_avg_milliseconds",
+            "Moving average of eth_blockNumber round-trip times",
+            avg.as_millis() as u64,
+        );
+    }
+    counter(
+        &mut out,
+        "indexer_block_timestamp_cache_hits_total",
+        "Block timestamp lookups served from the in-memory cache",
+        state.timestamps.hits(),
+    );
+    counter(
+        &mut out,
+        "indexer_block_timestamp_cache_misses_total",
+        "Block timestamp lookups that required an RPC call",
+        state.timestamps.misses(),
+    );
+
+    let contracts = state.writer.contract_metrics().snapshot();
+    let by_contract = |kind: Option<&str>, count: fn(&ContractCounts) -> u64| {
+        contracts
+            .iter()
+            .map(|(contract, counts)| {
+                let labels = match kind {
+                    Some(kind) => format!("contract=\"{contract}\",kind=\"{kind}\""),
+                    None => format!("contract=\"{contract}\""),
+                };
+                (labels, count(counts))
+            })
+            .collect::<Vec<_>>()
+    };
+    let stored = [
+        by_contract(Some("message"), |c| c.messages),
+        by_contract(Some("handshake"), |c| c.handshakes),
+        by_contract(Some("hsr"), |c| c.handshake_responses),
+    ]
+    .concat();
+    counter_series(
+        &mut out,
+        "indexer_events_processed_total",
+        "Events stored since start, by contract and kind",
+        &stored,
+    );
+    counter_series(
+        &mut out,
+        "indexer_events_skipped_total",
+        "Events already indexed or filtered out, by contract",
+        &by_contract(None, |c| c.skipped),
+    );
+    counter_series(
+        &mut out,
+        "indexer_events_failed_total",
+        "Failed attempts to store an event, by contract",
+        &by_contract(None, |c| c.failed),
+    );
+
+    labeled_counter(
+        &mut out,
+        "indexer_reorgs_detected_total",
+        "Chain reorgs detected, by number of blocks replaced",
+        "depth",
+        &state.chain_state.reorgs_by_depth(),
+    );
+    gauge(
+        &mut out,
+        "indexer_reorg_max_depth",
+        "Blocks replaced by the deepest reorg detected",
+        state.chain_state.max_reorg_depth(),
+    );
+
+    if state.config.verify_finality.is_some() {
+        labeled_counter(
+            &mut out,
+            "indexer_finality_discrepancies_total",
+            "Differences between indexed events and the finalized chain, by kind",
+            "kind",
+            &state.chain_state.finality_discrepancies(),
+        );
+        gauge(
+            &mut out,
+            "indexer_finality_verified_block",
+            "Last block checked against the finalized chain",
+            state.chain_state.verified_block(),
+        );
+    }
+
+    ([(header::CONTENT_TYPE, CONTENT_TYPE)], out)
+}
+
+fn gauge(out: &mut String, name: &str, help: &str, value: u64) {
+    metric(out, name, help, "gauge", value);
+}
+
+fn counter(out: &mut String, name: &str, help: &str, value: u64) {
+    metric(out, name, help, "counter", value);
+}
+
+fn labeled_counter(out: &mut String, name: &str, help: &str, label: &str, values: &[(&str, u64)]) {
+    let series: Vec<_> = values
+        .iter()
+        .map(|(label_value, value)| (format!("{label}=\"{label_value}\""), *value))
+        .collect();
+    counter_series(out, name, help, &series);
+}
+
+/// Counter with one series per label set, each given as `name="value",...`.
+fn counter_series(out: &mut String, name: &str, help: &str, series: &[(String, u64)]) {
+    let _ = writeln!(out, "# HELP {name} {help}");
+    let _ = writeln!(out, "# TYPE {name} counter");
+    for (labels, value) in series {
+        let _ = writeln!(out, "{name}{{{labels}}} {value}");
+    }
+}
+
+fn metric(out: &mut String, name: &str, help: &str, kind: &str, value: u64) {
+    let _ = writeln!(out, "# HELP {name} {help}");
+    let _ = writeln!(out, "# TYPE {name} {kind}");
+    let _ = writeln!(out, "{name} {value}");
+}
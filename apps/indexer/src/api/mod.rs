@@ -1,21 +1,284 @@
-use axum::{routing::get, Router};
-use tower_http::cors::{Any, CorsLayer};
-use tower_http::trace::TraceLayer;
+use std::net::{IpAddr, Ipv4Addr};
+use std::sync::Arc;
+use std::time::Duration;
 
+use axum::{
+    body::Body,
+    error_handling::HandleErrorLayer,
+    extract::{DefaultBodyLimit, State},
+    http::{HeaderValue, Request, Response, StatusCode},
+    middleware::{self, Next},
+    response::IntoResponse,
+    routing::{get, post},
+    BoxError, Router,
+};
+use tower::limit::GlobalConcurrencyLimitLayer;
+use tower::ServiceBuilder;
+use tower_governor::{
+    governor::GovernorConfigBuilder,
+    key_extractor::{KeyExtractor, PeerIpKeyExtractor, SmartIpKeyExtractor},
+    GovernorError, GovernorLayer,
+};
+use tower_http::classify::{ServerErrorsAsFailures, SharedClassifier};
+use tower_http::cors::{AllowOrigin, Any, CorsLayer};
+use tower_http::limit::RequestBodyLimitLayer;
+use tower_http::trace::{DefaultOnRequest, MakeSpan, OnResponse, TraceLayer};
+use tracing::{field, Span};
+
+use crate::config::{ApiLogLevel, Config};
+
+use self::error::ApiError;
+
+pub mod admin;
+pub mod dead_letters;
+pub mod encoding;
+pub mod error;
+pub mod etag;
+pub mod export;
+pub mod feed;
+pub mod handshakes;
 pub mod health;
+pub mod messages;
+pub mod metrics;
+#[cfg(feature = "openapi")]
+pub mod openapi;
+pub mod search;
 pub mod state;
+pub mod stats;
+pub mod stream;
+pub mod topics;
+pub mod version;
 
 pub use state::AppState;
 
-pub fn create_router(state: AppState) -> Router {
-    let cors = CorsLayer::new()
-        .allow_origin(Any)
+const RATE_LIMIT_CLEANUP_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Peer address of the connection, or with `trust_proxy_headers` the client IP a proxy put
+/// in `X-Forwarded-For` and friends. Those headers are client-controlled unless a proxy
+/// overwrites them, so trusting them by default would let anyone pick their own bucket.
+/// Requests that carry no client IP at all count as localhost.
+#[derive(Clone)]
+struct ClientIpKeyExtractor {
+    trust_proxy_headers: bool,
+}
+
+impl KeyExtractor for ClientIpKeyExtractor {
+    type Key = IpAddr;
+
+    fn extract<T>(&self, req: &Request<T>) -> Result<IpAddr, GovernorError> {
+        let ip = if self.trust_proxy_headers {
+            SmartIpKeyExtractor.extract(req)
+        } else {
+            PeerIpKeyExtractor.extract(req)
+        };
+        Ok(ip.unwrap_or(IpAddr::V4(Ipv4Addr::LOCALHOST)))
+    }
+}
+
+fn cors_layer(config: &Config) -> CorsLayer {
+    let origins = match &config.allowed_origins {
+        Some(origins) => {
+            AllowOrigin::list(origins.iter().filter_map(|o| HeaderValue::from_str(o).ok()))
+        }
+        None => AllowOrigin::from(Any),
+    };
+
+    CorsLayer::new()
+        .allow_origin(origins)
         .allow_methods(Any)
-        .allow_headers(Any);
+        .allow_headers(Any)
+}
+
+/// Trace layer whose span carries the request line at info level, so the per-response
+/// line shows which endpoint (and with `verbose`, which query) it was for.
+fn request_log_layer(
+    with_query: bool,
+) -> TraceLayer<
+    SharedClassifier<ServerErrorsAsFailures>,
+    impl MakeSpan<Body> + Clone,
+    DefaultOnRequest,
+    impl OnResponse<Body> + Clone,
+> {
+    TraceLayer::new_for_http()
+        .make_span_with(move |request: &Request<Body>| {
+            let span = tracing::info_span!(
+                "request",
+                method = %request.method(),
+                path = %request.uri().path(),
+                query = field::Empty,
+            );
+            if let Some(query) = request.uri().query().filter(|_| with_query) {
+                span.record("query", query);
+            }
+            span
+        })
+        .on_response(|response: &Response<Body>, latency: Duration, _: &Span| {
+            tracing::info!(
+                status = response.status().as_u16(),
+                latency_ms = latency.as_millis() as u64,
+                "response"
+            );
+        })
+}
+
+/// The body limit layer and axum's extractors reject oversized bodies in plain text;
+/// answer with the usual JSON error instead.
+async fn payload_too_large_as_json(response: Response<Body>) -> Response<Body> {
+    if response.status() == StatusCode::PAYLOAD_TOO_LARGE {
+        ApiError::PayloadTooLarge.into_response()
+    } else {
+        response
+    }
+}
 
+/// Answers 503 with `Retry-After` until the startup backfill completes, so clients never
+/// mistake a partially indexed history for the full one.
+async fn require_initial_sync(
+    State(state): State<AppState>,
+    request: Request<Body>,
+    next: Next,
+) -> Result<Response<Body>, ApiError> {
+    if !state.initial_sync_complete() {
+        return Err(ApiError::Syncing);
+    }
+    Ok(next.run(request).await)
+}
+
+/// Routes serving indexed events, gated on the startup backfill.
+fn data_router(state: AppState) -> Router<AppState> {
+    Router::new()
+        .route("/stats", get(stats::stats))
+        .route("/messages", get(messages::list))
+        .route("/messages/latest", post(messages::latest))
+        .route("/messages/first", get(messages::first))
+        .route("/messages/batch", post(messages::batch))
+        .route("/messages/count", get(messages::count))
+        .route("/messages/range", get(messages::range))
+        .route("/messages/by-time", get(messages::by_time))
+        .route("/search", get(search::search))
+        .route("/feed", get(feed::feed))
+        .route("/stream/messages", get(stream::messages))
+        .route("/topics", get(topics::list))
+        .route("/handshakes/search", get(handshakes::search))
+        .route(
+            "/handshakes/{recipient_hash}/{seq}",
+            get(handshakes::get_handshake),
+        )
+        .route(
+            "/handshakes/{recipient_hash}/{seq}/thread",
+            get(handshakes::get_thread),
+        )
+        .route(
+            "/handshake-responses/by-responder",
+            get(handshakes::by_responder),
+        )
+        .route("/export", get(export::export))
+        .route_layer(middleware::from_fn_with_state(state, require_initial_sync))
+}
+
+fn admin_router(state: AppState) -> Router<AppState> {
     Router::new()
+        .route("/config", get(admin::config))
+        .route("/backfill", post(admin::start_backfill))
+        .route("/backfill/{id}", get(admin::get_backfill))
+        .route("/reprocess", post(admin::reprocess))
+        .route("/reprocess-decoded", post(admin::reprocess_decoded))
+        .route("/resync", post(admin::resync))
+        .route("/verify", get(admin::verify))
+        .route("/dead-letters", get(dead_letters::list))
+        .route("/dead-letters/{id}/requeue", post(dead_letters::requeue))
+        .route_layer(middleware::from_fn_with_state(state, admin::require_admin))
+}
+
+pub fn create_router(state: AppState) -> Router {
+    let cors = cors_layer(&state.config);
+    let trust_proxy_headers = state.config.trust_proxy_headers;
+    // Socket clients have no IP, so without forwarded headers one bucket would throttle
+    // them all together; leave them unlimited instead
+    let rate_limit_rps = if state.config.listen_uds.is_some() && !trust_proxy_headers {
+        0
+    } else {
+        state.config.rate_limit_rps
+    };
+    let api_log_level = state.config.api_log_level;
+    let max_request_bytes = state.config.max_request_bytes;
+    let max_concurrent_requests = state.config.max_concurrent_requests;
+
+    let router = Router::new()
         .route("/health", get(health::health))
-        .layer(cors)
-        .layer(TraceLayer::new_for_http())
-        .with_state(state)
+        .route("/health/live", get(health::live))
+        .route("/health/ready", get(health::ready))
+        .route("/metrics", get(metrics::metrics))
+        .route("/version", get(version::version))
+        .route("/messages/decode", post(messages::decode))
+        .merge(data_router(state.clone()))
+        .route("/stream/backfill", get(stream::backfill))
+        .nest("/admin", admin_router(state.clone()));
+
+    #[cfg(feature = "openapi")]
+    let router = router.route("/openapi.json", get(openapi::openapi_json));
+
+    #[cfg(feature = "swagger-ui")]
+    let router = router.merge(
+        utoipa_swagger_ui::SwaggerUi::new("/docs")
+            .config(utoipa_swagger_ui::Config::from("/openapi.json")),
+    );
+
+    // The JSON extractors would otherwise apply axum's own 2 MB default on top
+    let router = router
+        .layer(DefaultBodyLimit::disable())
+        .layer(RequestBodyLimitLayer::new(max_request_bytes))
+        .layer(middleware::map_response(payload_too_large_as_json));
+
+    // One semaphore shared by every route. Requests past the cap are shed with 503 rather
+    // than queued, so a burst can't pile up behind the DB pool.
+    // MAX_CONCURRENT_REQUESTS=0 disables the cap
+    let router = if max_concurrent_requests > 0 {
+        router.layer(
+            ServiceBuilder::new()
+                .layer(HandleErrorLayer::new(|_: BoxError| async {
+                    ApiError::Overloaded
+                }))
+                .load_shed()
+                .layer(GlobalConcurrencyLimitLayer::new(max_concurrent_requests)),
+        )
+    } else {
+        router
+    };
+
+    // Per-IP token bucket: sustained `rate_limit_rps` with bursts of twice that.
+    // Behind a proxy, set TRUST_PROXY_HEADERS or every client shares the proxy's bucket.
+    // On LISTEN_UDS there is no peer IP; requests without forwarded headers share one bucket.
+    // RATE_LIMIT_RPS=0 disables limiting
+    let router = if let Some(interval_ns) = 1_000_000_000u64.checked_div(rate_limit_rps) {
+        let governor = GovernorConfigBuilder::default()
+            .key_extractor(ClientIpKeyExtractor {
+                trust_proxy_headers,
+            })
+            .per_nanosecond(interval_ns.max(1))
+            .burst_size((rate_limit_rps * 2).min(u32::MAX as u64) as u32)
+            .finish()
+            .expect("rate limit config is non-zero");
+        let governor = Arc::new(governor);
+
+        let limiter = governor.limiter().clone();
+        tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(RATE_LIMIT_CLEANUP_INTERVAL).await;
+                limiter.retain_recent();
+            }
+        });
+
+        router.layer(GovernorLayer { config: governor })
+    } else {
+        router
+    };
+
+    let router = router.layer(cors);
+    let router = match api_log_level {
+        ApiLogLevel::Off => router.layer(TraceLayer::new_for_http()),
+        ApiLogLevel::Info => router.layer(request_log_layer(false)),
+        ApiLogLevel::Verbose => router.layer(request_log_layer(true)),
+    };
+    router.with_state(state)
 }
@@ -0,0 +1,73 @@
+use axum::Json;
+use utoipa::openapi::security::{HttpAuthScheme, HttpBuilder, SecurityScheme};
+use utoipa::{Modify, OpenApi};
+
+use super::encoding::BinaryEncoding;
+use super::export::ExportTable;
+use super::messages::MessageOrder;
+use super::{
+    admin, dead_letters, export, feed, handshakes, health, messages, search, stats, stream, topics,
+    version,
+};
+use crate::indexer::backfill::BackfillProgress;
+
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "Verbeth Indexer API"),
+    paths(
+        health::health,
+        health::live,
+        health::ready,
+        version::version,
+        stats::stats,
+        messages::list,
+        messages::latest,
+        messages::first,
+        messages::range,
+        messages::by_time,
+        messages::batch,
+        messages::count,
+        messages::decode,
+        search::search,
+        feed::feed,
+        topics::list,
+        handshakes::search,
+        handshakes::get_handshake,
+        handshakes::get_thread,
+        handshakes::by_responder,
+        export::export,
+        dead_letters::list,
+        dead_letters::requeue,
+        stream::backfill,
+        stream::messages,
+        admin::config,
+        admin::start_backfill,
+        admin::get_backfill,
+        admin::reprocess,
+        admin::reprocess_decoded,
+        admin::resync,
+        admin::verify,
+    ),
+    // Enums used only as query parameters, and event-stream payloads, are not collected
+    // from `paths`
+    components(schemas(BinaryEncoding, ExportTable, MessageOrder, BackfillProgress)),
+    modifiers(&AdminTokenScheme)
+)]
+pub struct ApiDoc;
+
+/// Declares the bearer scheme that the `/admin` paths reference.
+struct AdminTokenScheme;
+
+impl Modify for AdminTokenScheme {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+        components.add_security_scheme(
+            "admin_token",
+            SecurityScheme::Http(HttpBuilder::new().scheme(HttpAuthScheme::Bearer).build()),
+        );
+    }
+}
+
+pub async fn openapi_json() -> Json<utoipa::openapi::OpenApi> {
+    Json(ApiDoc::openapi())
+}
@@ -0,0 +1,94 @@
+use axum::{
+    extract::{Query, State},
+    Json,
+};
+use serde::Deserialize;
+
+use crate::db::models::MessageSearch;
+use crate::db::queries::search_messages;
+use crate::types::{SenderAddress, TopicHash};
+
+use super::encoding::BinaryEncoding;
+#[cfg(feature = "openapi")]
+use super::error::ErrorBody;
+use super::error::{ApiError, ApiResult};
+use super::messages::MessageJson;
+use super::state::AppState;
+
+const DEFAULT_LIMIT: i64 = 100;
+const MAX_LIMIT: i64 = 500;
+
+#[derive(Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::IntoParams))]
+#[cfg_attr(feature = "openapi", into_params(parameter_in = Query))]
+pub struct SearchQuery {
+    pub topic: Option<String>,
+    pub sender: Option<String>,
+    pub min_timestamp: Option<i64>,
+    pub max_timestamp: Option<i64>,
+    pub min_nonce: Option<i64>,
+    pub max_nonce: Option<i64>,
+    pub limit: Option<i64>,
+    #[serde(default)]
+    pub encoding: BinaryEncoding,
+}
+
+/// Search messages by any combination of topic, sender, block time and nonce range.
+///
+/// Nonce bounds alone would scan the whole table, so at least one of `topic`, `sender`
+/// or a full `min_timestamp`/`max_timestamp` window is required.
+#[cfg_attr(
+    feature = "openapi",
+    utoipa::path(
+        get,
+        path = "/search",
+        tag = "messages",
+        params(SearchQuery),
+        responses(
+            (status = 200, body = [MessageJson]),
+            (status = 400, description = "Malformed parameters", body = ErrorBody),
+            (status = 503, description = "DB connection pool exhausted", body = ErrorBody),
+        )
+    )
+)]
+pub async fn search(
+    State(state): State<AppState>,
+    Query(query): Query<SearchQuery>,
+) -> ApiResult<Json<Vec<MessageJson>>> {
+    let search = MessageSearch {
+        topic: query
+            .topic
+            .as_deref()
+            .map(|t| t.parse::<TopicHash>())
+            .transpose()
+            .map_err(|_| ApiError::bad_request("topic must be 32-byte hex"))?,
+        sender: query
+            .sender
+            .as_deref()
+            .map(|s| s.parse::<SenderAddress>())
+            .transpose()
+            .map_err(|_| ApiError::bad_request("sender must be 20-byte hex"))?,
+        min_timestamp: query.min_timestamp,
+        max_timestamp: query.max_timestamp,
+        min_nonce: query.min_nonce,
+        max_nonce: query.max_nonce,
+    };
+
+    let has_window = search.min_timestamp.is_some() && search.max_timestamp.is_some();
+    if search.topic.is_none() && search.sender.is_none() && !has_window {
+        return Err(ApiError::bad_request(
+            "one of topic, sender, or min_timestamp+max_timestamp is required",
+        ));
+    }
+
+    let limit = query.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+
+    let conn = state.read_pool.get()?;
+    let rows = search_messages(&conn, &search, limit)?;
+
+    Ok(Json(
+        rows.into_iter()
+            .map(|r| MessageJson::encoded(r, query.encoding))
+            .collect(),
+    ))
+}
@@ -1,25 +1,82 @@
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use std::sync::Arc;
 use std::time::Instant;
 
+use tokio::sync::broadcast;
+
 use crate::config::Config;
 use crate::db::DbPool;
+use crate::indexer::backfill::ProgressTracker;
+use crate::indexer::chain_state::ChainState;
+use crate::indexer::circuit_breaker::CircuitBreaker;
+use crate::indexer::processor::IndexedEvent;
+use crate::indexer::retry_queue::RetryQueue;
+use crate::indexer::timestamp_cache::BlockTimestampCache;
+use crate::indexer::writer::EventWriter;
+
+use super::admin::BackfillJobs;
 
 #[derive(Clone)]
 pub struct AppState {
+    /// Read-write pool shared with the indexer, for the few handlers that write
     pub pool: DbPool,
+    /// Pool for read-only queries; the write pool itself if `READ_POOL_SIZE=0`
+    pub read_pool: DbPool,
+    /// Where admin backfills and reprocessing store events, alongside the indexer
+    pub writer: EventWriter,
     pub config: Arc<Config>,
+    pub retry_queue: Arc<RetryQueue>,
+    pub chain_state: Arc<ChainState>,
+    pub events: broadcast::Sender<IndexedEvent>,
+    pub timestamps: Arc<BlockTimestampCache>,
+    pub backfill_jobs: Arc<BackfillJobs>,
+    /// Backfills in flight, for `/stream/backfill`
+    pub backfill_progress: ProgressTracker,
+    /// Shared by every task calling the RPC provider over HTTP, reported by `/health`
+    pub rpc_breaker: Arc<CircuitBreaker>,
+    /// Set once the startup backfill has finished; data routes answer 503 until then
+    pub initial_sync_complete: Arc<AtomicBool>,
+    /// Open `/stream/*` connections, capped by `MAX_STREAM_CLIENTS`
+    pub stream_clients: Arc<AtomicUsize>,
     pub start_time: Instant,
 }
 
 impl AppState {
-    pub fn new(pool: DbPool, config: Config) -> Self {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        pool: DbPool,
+        read_pool: DbPool,
+        writer: EventWriter,
+        config: Config,
+        retry_queue: Arc<RetryQueue>,
+        chain_state: Arc<ChainState>,
+        events: broadcast::Sender<IndexedEvent>,
+        timestamps: Arc<BlockTimestampCache>,
+        backfill_progress: ProgressTracker,
+        rpc_breaker: Arc<CircuitBreaker>,
+    ) -> Self {
         Self {
             pool,
+            read_pool,
+            writer,
             config: Arc::new(config),
+            retry_queue,
+            chain_state,
+            events,
+            timestamps,
+            backfill_jobs: Arc::default(),
+            backfill_progress,
+            rpc_breaker,
+            initial_sync_complete: Arc::default(),
+            stream_clients: Arc::default(),
             start_time: Instant::now(),
         }
     }
 
+    pub fn initial_sync_complete(&self) -> bool {
+        self.initial_sync_complete.load(Ordering::Relaxed)
+    }
+
     pub fn uptime_seconds(&self) -> u64 {
         self.start_time.elapsed().as_secs()
     }
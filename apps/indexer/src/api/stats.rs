@@ -0,0 +1,147 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use axum::{
+    extract::{Query, State},
+    Json,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::db::queries;
+
+use super::error::ApiResult;
+#[cfg(feature = "openapi")]
+use super::error::ErrorBody;
+use super::health::EventCountsResponse;
+use super::state::AppState;
+
+const DEFAULT_HOT_TOPICS: i64 = 10;
+const MAX_HOT_TOPICS: i64 = 100;
+const DEFAULT_WINDOW_SECS: i64 = 3600;
+const MIN_WINDOW_SECS: i64 = 60;
+const MAX_WINDOW_SECS: i64 = 7 * 24 * 3600;
+
+#[derive(Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::IntoParams))]
+#[cfg_attr(feature = "openapi", into_params(parameter_in = Query))]
+pub struct StatsQuery {
+    /// Topics in `hot_topics` (default 10, max 100, 0 to skip the query)
+    pub hot_topics: Option<i64>,
+    /// Window the rates are measured over, in seconds (default 3600, 60 to 604800)
+    pub window_secs: Option<i64>,
+}
+
+#[derive(Serialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct StatsResponse {
+    pub events: EventCountsResponse,
+    pub distinct: DistinctCountsResponse,
+    /// Busiest topics over the window, busiest first
+    pub hot_topics: Vec<HotTopicJson>,
+    /// Events handled since start, per configured contract
+    pub contracts: Vec<ContractStatsJson>,
+}
+
+#[derive(Serialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct ContractStatsJson {
+    pub address: String,
+    pub messages: u64,
+    pub handshakes: u64,
+    pub handshake_responses: u64,
+    /// Already indexed, or left out by `INDEX_EVENTS` or `RECIPIENT_ALLOWLIST`
+    pub skipped: u64,
+    /// Failed store attempts; a retried event counts once per attempt
+    pub failed: u64,
+}
+
+#[derive(Serialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct DistinctCountsResponse {
+    /// Addresses that have sent at least one message
+    pub senders: i64,
+    /// Recipient hashes that have received at least one handshake
+    pub recipients: i64,
+    /// Addresses that have sent at least one handshake response
+    pub responders: i64,
+}
+
+#[derive(Serialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct HotTopicJson {
+    pub topic: String,
+    /// Messages in the window per minute of window
+    pub msgs_per_min: f64,
+}
+
+#[cfg_attr(
+    feature = "openapi",
+    utoipa::path(
+        get,
+        path = "/stats",
+        tag = "health",
+        params(StatsQuery),
+        responses(
+            (status = 200, body = StatsResponse),
+            (status = 503, description = "DB connection pool exhausted", body = ErrorBody),
+        )
+    )
+)]
+/// Event totals, distinct participant counts and the busiest topics' message rates.
+///
+/// Each distinct count scans a whole index, so this is meant for dashboards polling
+/// every few minutes rather than per-request use.
+pub async fn stats(
+    State(state): State<AppState>,
+    Query(query): Query<StatsQuery>,
+) -> ApiResult<Json<StatsResponse>> {
+    let hot_topics = query
+        .hot_topics
+        .unwrap_or(DEFAULT_HOT_TOPICS)
+        .clamp(0, MAX_HOT_TOPICS);
+    let window_secs = query
+        .window_secs
+        .unwrap_or(DEFAULT_WINDOW_SECS)
+        .clamp(MIN_WINDOW_SECS, MAX_WINDOW_SECS);
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs() as i64);
+
+    let conn = state.read_pool.get()?;
+
+    let hot_topics = if hot_topics > 0 {
+        let window_mins = window_secs as f64 / 60.0;
+        queries::busiest_topics_since(&conn, now - window_secs, hot_topics)?
+            .into_iter()
+            .map(|(topic, count)| HotTopicJson {
+                topic: topic.to_string(),
+                msgs_per_min: count as f64 / window_mins,
+            })
+            .collect()
+    } else {
+        Vec::new()
+    };
+
+    Ok(Json(StatsResponse {
+        events: queries::get_event_counts(&conn)?.into(),
+        distinct: DistinctCountsResponse {
+            senders: queries::distinct_sender_count(&conn)?,
+            recipients: queries::distinct_recipient_count(&conn)?,
+            responders: queries::distinct_responder_count(&conn)?,
+        },
+        hot_topics,
+        contracts: state
+            .writer
+            .contract_metrics()
+            .snapshot()
+            .into_iter()
+            .map(|(address, counts)| ContractStatsJson {
+                address: address.to_string(),
+                messages: counts.messages,
+                handshakes: counts.handshakes,
+                handshake_responses: counts.handshake_responses,
+                skipped: counts.skipped,
+                failed: counts.failed,
+            })
+            .collect(),
+    }))
+}
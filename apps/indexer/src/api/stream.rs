@@ -0,0 +1,232 @@
+use std::collections::VecDeque;
+use std::convert::Infallible;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use axum::{
+    extract::{Query, State},
+    http::HeaderMap,
+    response::sse::{Event, KeepAlive, Sse},
+};
+use futures_lite::{stream, Stream};
+use serde::Deserialize;
+use tokio::sync::broadcast;
+
+use crate::db::models::MessageRow;
+use crate::db::queries::messages_by_topic;
+use crate::indexer::processor::IndexedEvent;
+use crate::types::TopicHash;
+
+use super::encoding::BinaryEncoding;
+#[cfg(feature = "openapi")]
+use super::error::ErrorBody;
+use super::error::{ApiError, ApiResult};
+use super::messages::MessageJson;
+use super::state::AppState;
+
+/// Missed messages replayed per connection. A longer gap ends the stream after this many,
+/// and the client's reconnect picks up from the last one.
+const MAX_REPLAY: i64 = 500;
+
+/// One of the `MAX_STREAM_CLIENTS` stream slots, given back when the stream is dropped,
+/// i.e. when the client disconnects.
+struct StreamSlot(Arc<AtomicUsize>);
+
+impl StreamSlot {
+    fn acquire(state: &AppState) -> ApiResult<Self> {
+        let limit = state.config.max_stream_clients;
+        state
+            .stream_clients
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |open| {
+                (limit == 0 || open < limit).then_some(open + 1)
+            })
+            .map_err(|_| ApiError::TooManyStreams)?;
+        Ok(Self(state.stream_clients.clone()))
+    }
+}
+
+impl Drop for StreamSlot {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::AcqRel);
+    }
+}
+
+#[cfg_attr(
+    feature = "openapi",
+    utoipa::path(
+        get,
+        path = "/stream/backfill",
+        tag = "stream",
+        responses(
+            (status = 200, content_type = "text/event-stream", description = "A `progress` \
+             event with a `BackfillProgress` per running backfill on every update, or an \
+             `idle` event once none is running"),
+            (status = 503, description = "MAX_STREAM_CLIENTS streams already open", body = ErrorBody),
+        )
+    )
+)]
+/// Server-sent backfill progress: the current state on connect, then every change.
+pub async fn backfill(
+    State(state): State<AppState>,
+) -> ApiResult<Sse<impl Stream<Item = Result<Event, Infallible>>>> {
+    let slot = StreamSlot::acquire(&state)?;
+    let mut running = state.backfill_progress.subscribe();
+    // Send the current state first, even if nothing changes for a while
+    running.mark_changed();
+
+    let events = stream::unfold(
+        (running, VecDeque::new(), slot),
+        |(mut running, mut pending, slot)| async move {
+            loop {
+                if let Some(event) = pending.pop_front() {
+                    return Some((Ok(event), (running, pending, slot)));
+                }
+                running.changed().await.ok()?;
+                let snapshot = running.borrow_and_update();
+                if snapshot.is_empty() {
+                    pending.push_back(Event::default().event("idle").data("{}"));
+                }
+                for progress in snapshot.values() {
+                    if let Ok(event) = Event::default().event("progress").json_data(progress) {
+                        pending.push_back(event);
+                    }
+                }
+            }
+        },
+    );
+
+    Ok(Sse::new(events).keep_alive(KeepAlive::default()))
+}
+
+#[derive(Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::IntoParams))]
+#[cfg_attr(feature = "openapi", into_params(parameter_in = Query))]
+pub struct MessageStreamQuery {
+    pub topic: String,
+    #[serde(default)]
+    pub encoding: BinaryEncoding,
+}
+
+struct MessageStream {
+    topic: TopicHash,
+    encoding: BinaryEncoding,
+    live: broadcast::Receiver<IndexedEvent>,
+    replay: VecDeque<MessageRow>,
+    /// More rows were missed than one replay sends
+    truncated: bool,
+    /// Highest seq sent; live rows at or below it already went out in the replay
+    last_seq: Option<i64>,
+    _slot: StreamSlot,
+}
+
+impl MessageStream {
+    fn event(&mut self, row: MessageRow) -> Event {
+        self.last_seq = Some(row.seq);
+        let id = row.seq.to_string();
+        Event::default()
+            .event("message")
+            .id(id)
+            .json_data(MessageJson::encoded(row, self.encoding))
+            .unwrap_or_default()
+    }
+}
+
+#[cfg_attr(
+    feature = "openapi",
+    utoipa::path(
+        get,
+        path = "/stream/messages",
+        tag = "stream",
+        params(
+            MessageStreamQuery,
+            ("Last-Event-ID" = Option<i64>, Header, description = "Seq of the last message received; missed ones are replayed first"),
+        ),
+        responses(
+            (status = 200, content_type = "text/event-stream", description = "A `message` \
+             event with a `MessageJson` per message, its `seq` as the event id"),
+            (status = 400, description = "Malformed parameters", body = ErrorBody),
+            (status = 503, description = "DB connection pool exhausted or MAX_STREAM_CLIENTS streams already open", body = ErrorBody),
+        )
+    )
+)]
+/// Server-sent messages of one topic as they are indexed. A reconnect with `Last-Event-ID`
+/// first replays what was missed from the DB.
+pub async fn messages(
+    State(state): State<AppState>,
+    Query(query): Query<MessageStreamQuery>,
+    headers: HeaderMap,
+) -> ApiResult<Sse<impl Stream<Item = Result<Event, Infallible>>>> {
+    let topic = query
+        .topic
+        .parse::<TopicHash>()
+        .map_err(|_| ApiError::bad_request("topic must be 32-byte hex"))?;
+    let cursor = headers
+        .get("last-event-id")
+        .map(|v| {
+            v.to_str()
+                .ok()
+                .and_then(|v| v.trim().parse::<i64>().ok())
+                .ok_or_else(|| ApiError::bad_request("Last-Event-ID must be a message seq"))
+        })
+        .transpose()?;
+    let slot = StreamSlot::acquire(&state)?;
+
+    // Subscribe before reading the DB, so nothing stored in between is missed; the overlap
+    // is dropped by seq
+    let live = state.events.subscribe();
+    let (replay, truncated) = match cursor {
+        Some(after_seq) => {
+            let conn = state.read_pool.get()?;
+            let mut rows = messages_by_topic(
+                &conn,
+                &topic,
+                None,
+                Some(after_seq),
+                None,
+                false,
+                MAX_REPLAY + 1,
+            )?;
+            let truncated = rows.len() as i64 > MAX_REPLAY;
+            rows.truncate(MAX_REPLAY as usize);
+            (rows.into(), truncated)
+        }
+        None => (VecDeque::new(), false),
+    };
+
+    let state = MessageStream {
+        topic,
+        encoding: query.encoding,
+        live,
+        replay,
+        truncated,
+        last_seq: cursor,
+        _slot: slot,
+    };
+    let events = stream::unfold(state, |mut state| async move {
+        if let Some(row) = state.replay.pop_front() {
+            let event = state.event(row);
+            return Some((Ok(event), state));
+        }
+        // Ending here makes the client reconnect with the last replayed seq
+        if state.truncated {
+            return None;
+        }
+        loop {
+            match state.live.recv().await {
+                Ok(IndexedEvent::Message(row))
+                    if row.topic == state.topic
+                        && state.last_seq.is_none_or(|seq| row.seq > seq) =>
+                {
+                    let event = state.event(row);
+                    return Some((Ok(event), state));
+                }
+                Ok(_) => {}
+                // Lagging behind the broadcast loses rows; the reconnect replays them
+                Err(broadcast::error::RecvError::Lagged(_)) => return None,
+                Err(broadcast::error::RecvError::Closed) => return None,
+            }
+        }
+    });
+
+    Ok(Sse::new(events).keep_alive(KeepAlive::default()))
+}
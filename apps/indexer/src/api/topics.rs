@@ -0,0 +1,69 @@
+use axum::{
+    extract::{Query, State},
+    Json,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::db::models::TopicSummary;
+use crate::db::queries::distinct_topics;
+
+use super::error::ApiResult;
+#[cfg(feature = "openapi")]
+use super::error::ErrorBody;
+use super::state::AppState;
+
+const DEFAULT_LIMIT: i64 = 100;
+const MAX_LIMIT: i64 = 1000;
+
+#[derive(Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::IntoParams))]
+#[cfg_attr(feature = "openapi", into_params(parameter_in = Query))]
+pub struct TopicsQuery {
+    pub limit: Option<i64>,
+    pub offset: Option<i64>,
+}
+
+#[derive(Serialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct TopicJson {
+    pub topic: String,
+    pub message_count: i64,
+    pub last_block_timestamp: i64,
+}
+
+impl From<TopicSummary> for TopicJson {
+    fn from(t: TopicSummary) -> Self {
+        Self {
+            topic: t.topic.to_string(),
+            message_count: t.message_count,
+            last_block_timestamp: t.last_block_timestamp,
+        }
+    }
+}
+
+#[cfg_attr(
+    feature = "openapi",
+    utoipa::path(
+        get,
+        path = "/topics",
+        tag = "messages",
+        params(TopicsQuery),
+        responses(
+            (status = 200, body = [TopicJson]),
+            (status = 503, description = "DB connection pool exhausted", body = ErrorBody),
+        )
+    )
+)]
+/// Known topics, most recently active first.
+pub async fn list(
+    State(state): State<AppState>,
+    Query(query): Query<TopicsQuery>,
+) -> ApiResult<Json<Vec<TopicJson>>> {
+    let limit = query.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+    let offset = query.offset.unwrap_or(0).max(0);
+
+    let conn = state.read_pool.get()?;
+    let rows = distinct_topics(&conn, limit, offset)?;
+
+    Ok(Json(rows.into_iter().map(Into::into).collect()))
+}
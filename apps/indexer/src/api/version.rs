@@ -0,0 +1,44 @@
+use axum::{extract::State, Json};
+use serde::Serialize;
+
+use crate::db::schema::current_version;
+
+use super::error::ApiResult;
+#[cfg(feature = "openapi")]
+use super::error::ErrorBody;
+use super::state::AppState;
+
+#[derive(Serialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct VersionResponse {
+    pub version: &'static str,
+    /// Commit the binary was built from, or "unknown"
+    pub git_sha: &'static str,
+    /// Latest migration applied to the database
+    pub schema_version: i64,
+    pub contract_address: String,
+}
+
+#[cfg_attr(
+    feature = "openapi",
+    utoipa::path(
+        get,
+        path = "/version",
+        tag = "health",
+        responses(
+            (status = 200, body = VersionResponse),
+            (status = 503, description = "DB connection pool exhausted", body = ErrorBody),
+        )
+    )
+)]
+/// Build and schema info, so operators can tell which build each instance is running.
+pub async fn version(State(state): State<AppState>) -> ApiResult<Json<VersionResponse>> {
+    let conn = state.read_pool.get()?;
+
+    Ok(Json(VersionResponse {
+        version: env!("CARGO_PKG_VERSION"),
+        git_sha: env!("GIT_SHA"),
+        schema_version: current_version(&conn)?,
+        contract_address: state.config.contract_address.to_string(),
+    }))
+}
@@ -1,98 +1,742 @@
-use alloy::primitives::Address;
+use alloy::primitives::{Address, B256};
+use std::fmt::Display;
+use std::num::{NonZeroU32, NonZeroUsize};
+use std::path::Path;
 use std::str::FromStr;
+use std::time::Duration;
 
 use crate::error::{IndexerError, Result};
+use crate::indexer::events::{EventKinds, RecipientAllowlist};
+
+/// `DATABASE_PATH`, or `indexer.db` under `DATA_DIR`. Read on its own by `--stats`, which
+/// needs nothing else from the environment.
+pub fn database_path_from_env() -> String {
+    database_path(&|name| std::env::var(name).ok())
+}
+
+fn data_dir(var: &dyn Fn(&str) -> Option<String>) -> String {
+    var("DATA_DIR").unwrap_or_else(|| "./data".into())
+}
+
+fn database_path(var: &dyn Fn(&str) -> Option<String>) -> String {
+    var("DATABASE_PATH").unwrap_or_else(|| {
+        Path::new(&data_dir(var))
+            .join("indexer.db")
+            .to_string_lossy()
+            .into_owned()
+    })
+}
 
 /// Sanitize RPC URL by masking API keys.
 /// Common patterns: .../v2/abc123 (Alchemy), .../v3/abc123 (Infura)
 pub fn sanitize_rpc_url(url: &str) -> String {
-    if let Some(idx) = url.rfind('/') {
+    // Query parameters (`?apikey=...`) are redacted wholesale: names are kept, values are not
+    let (url, query) = match url.split_once('?') {
+        Some((url, query)) => (url, Some(query)),
+        None => (url, None),
+    };
+
+    let mut sanitized = url.to_string();
+    // Only path segments can hold a key, not the host
+    let path_start = url.find("://").map_or(0, |i| i + 3);
+    if let Some(idx) = url.rfind('/').filter(|&idx| idx >= path_start) {
         let (base, key) = url.split_at(idx + 1);
         // API keys are typically long alphanumeric strings without dots or slashes
         if key.len() > 8 && !key.contains('.') && !key.contains('/') {
-            return format!("{}***", base);
+            sanitized = format!("{}***", base);
+        }
+    }
+
+    if let Some(query) = query {
+        let params: Vec<String> = query
+            .split('&')
+            .map(|param| match param.split_once('=') {
+                Some((name, _)) => format!("{name}=***"),
+                None => param.to_string(),
+            })
+            .collect();
+        sanitized = format!("{sanitized}?{}", params.join("&"));
+    }
+
+    sanitized
+}
+
+/// Variables looked up by name, and the problems found in them. Each problem is recorded
+/// and parsing carries on with the default, so a single error can list every variable that
+/// needs fixing.
+struct ConfigReader<'a> {
+    var: &'a dyn Fn(&str) -> Option<String>,
+    errors: Vec<String>,
+}
+
+impl<'a> ConfigReader<'a> {
+    fn new(var: &'a dyn Fn(&str) -> Option<String>) -> Self {
+        Self {
+            var,
+            errors: Vec::new(),
+        }
+    }
+
+    fn var(&self, name: &str) -> Option<String> {
+        (self.var)(name)
+    }
+
+    fn push(&mut self, error: impl Into<String>) {
+        self.errors.push(error.into());
+    }
+
+    /// `name` parsed as `T`, or `default` if it is unset or invalid.
+    fn parse<T>(&mut self, name: &str, default: T) -> T
+    where
+        T: FromStr,
+        T::Err: Display,
+    {
+        match self.var(name) {
+            Some(value) => value.parse().unwrap_or_else(|e| {
+                self.push(format!("Invalid {name}: {e}"));
+                default
+            }),
+            None => default,
+        }
+    }
+
+    /// `name` parsed as `T`, or `None` if it is unset, empty or invalid.
+    fn parse_optional<T>(&mut self, name: &str) -> Option<T>
+    where
+        T: FromStr,
+        T::Err: Display,
+    {
+        let value = self.var(name).filter(|v| !v.is_empty())?;
+        value
+            .parse()
+            .map_err(|e| self.push(format!("Invalid {name}: {e}")))
+            .ok()
+    }
+
+    fn check_scheme(&mut self, name: &str, url: &str, schemes: &[&str]) {
+        let scheme = url.split_once("://").map(|(s, _)| s.to_ascii_lowercase());
+        if !scheme.is_some_and(|s| schemes.contains(&s.as_str())) {
+            self.push(format!(
+                "Invalid {name}: {} (expected {}://)",
+                sanitize_rpc_url(url),
+                schemes.join(":// or ")
+            ));
+        }
+    }
+
+    fn into_result(self) -> Result<()> {
+        match self.errors.len() {
+            0 => Ok(()),
+            1 => Err(IndexerError::Config(self.errors.concat())),
+            n => Err(IndexerError::Config(format!(
+                "{n} problems: {}",
+                self.errors.join("; ")
+            ))),
+        }
+    }
+}
+
+/// Max byte sizes of event payload fields. Larger events are rejected with `PayloadTooLarge`.
+/// The defaults are the current protocol's sizes; a later protocol version may need more.
+#[derive(Clone, Copy)]
+pub struct PayloadLimits {
+    pub ciphertext: usize,
+    pub pub_keys: usize,
+    pub ephemeral_key: usize,
+    pub plaintext_payload: usize,
+    pub hsr_ciphertext: usize,
+}
+
+impl Default for PayloadLimits {
+    fn default() -> Self {
+        Self {
+            ciphertext: 64 * 1024,    // 64 KB per message
+            pub_keys: 65,             // version + X25519 + Ed25519
+            ephemeral_key: 1216,      // X25519 32 + ML-KEM 1184
+            plaintext_payload: 1024,  // 1 KB metadata
+            hsr_ciphertext: 4 * 1024, // 4 KB handshake response
+        }
+    }
+}
+
+impl PayloadLimits {
+    fn from_env(env: &mut ConfigReader) -> Self {
+        let defaults = Self::default();
+
+        Self {
+            ciphertext: env.parse("MAX_CIPHERTEXT_SIZE", defaults.ciphertext),
+            pub_keys: env.parse("MAX_PUB_KEYS_SIZE", defaults.pub_keys),
+            ephemeral_key: env.parse("MAX_EPHEMERAL_KEY_SIZE", defaults.ephemeral_key),
+            plaintext_payload: env.parse("MAX_PLAINTEXT_PAYLOAD_SIZE", defaults.plaintext_payload),
+            hsr_ciphertext: env.parse("MAX_HSR_CIPHERTEXT_SIZE", defaults.hsr_ciphertext),
         }
     }
-    url.to_string()
+}
+
+/// RPC throughput and retry limits for HTTP backfill requests.
+#[derive(Clone, Copy)]
+pub struct RpcLimits {
+    pub requests_per_second: NonZeroU32,
+    pub max_retries: u32,
+    pub backoff_ms: u64,
+    /// Per-request limit, after which the request counts as failed and is retried
+    pub timeout: Duration,
+    /// Consecutive failed RPC calls, each within `breaker_window` of the last, that open
+    /// the circuit breaker; 0 disables it
+    pub breaker_threshold: u32,
+    pub breaker_window: Duration,
+    /// How long an open breaker fails calls before letting one through to probe
+    pub breaker_cooldown: Duration,
+}
+
+/// PEM certificate chain and private key for serving the API over HTTPS.
+#[derive(Clone)]
+pub struct TlsPaths {
+    pub cert_path: String,
+    pub key_path: String,
+}
+
+/// Per-request API logging (`API_LOG_LEVEL`).
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum ApiLogLevel {
+    /// Only tower-http's default debug-level trace and server errors
+    Off,
+    /// One info line per response: method, path, status and latency
+    Info,
+    /// `Info` plus the query string, e.g. to spot which topic a slow list scanned
+    Verbose,
+}
+
+/// How the live indexer learns about new logs.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum SubscribeMode {
+    /// `eth_subscribe` over `RPC_WS_URL`
+    Ws,
+    /// Incremental `eth_getLogs` over HTTP every `POLL_INTERVAL`
+    Poll,
 }
 
 #[allow(dead_code)]
 pub struct Config {
+    /// Empty in poll mode when only `RPC_HTTP_URL` is set
     pub rpc_ws_url: String,
     pub rpc_http_url: Option<String>,
     pub contract_address: Address,
     pub creation_block: u64,
+    /// Deployment tx; when set, its block replaces `creation_block` at startup
+    pub creation_tx_hash: Option<B256>,
+    /// Neither creation setting is given for a custom contract: an empty DB starts from
+    /// the contract's first log, found by searching the chain
+    pub detect_creation_block: bool,
+    /// Directory for the database and any other files the indexer writes
+    pub data_dir: String,
     pub database_path: String,
+    /// Delay between `wal_checkpoint(TRUNCATE)` runs; `None` leaves it to SQLite
+    pub wal_checkpoint_interval: Option<Duration>,
+    /// How often recent blocks are re-read for missed events; `None` disables rescans
+    pub rescan_interval: Option<Duration>,
+    /// Blocks behind the resume point covered by each rescan
+    pub rescan_blocks: u64,
     pub server_port: u16,
+    /// Unix socket path to serve the HTTP API on instead of `server_port`
+    pub listen_uds: Option<String>,
+    /// Serve the API over HTTPS with this cert/key; `None` serves plain HTTP
+    pub tls: Option<TlsPaths>,
     pub backfill_days: u32,
+    /// Max blocks covered by the initial backfill of an empty DB (0 = no cap)
+    pub max_backfill_blocks: u64,
     pub retention_days: u32,
     pub rpc_chunk_size: u64,
+    /// Min blocks between backfill checkpoints of `last_block` (0 = after every chunk)
+    pub checkpoint_every_blocks: u64,
+    pub rpc_limits: RpcLimits,
+    pub payload_limits: PayloadLimits,
     /// SQLite synchronous mode: "OFF", "NORMAL", or "FULL" (default: NORMAL)
     pub sqlite_sync_mode: String,
+    /// Integrity check run on startup: "OFF", "QUICK", or "FULL" (default: OFF)
+    pub db_integrity_check: String,
+    /// Run the backfill to chain head and exit without serving (`--backfill-only` or `MODE=backfill`)
+    pub backfill_only: bool,
+    /// Max blocks behind chain head before /health/ready reports not ready
+    pub ready_lag_blocks: u64,
+    /// Blocks a log must be buried under before it is indexed (0 = index immediately)
+    pub confirmations: u64,
+    /// Blocks on top before indexed events are re-checked against `getLogs` (`None` = off)
+    pub verify_finality: Option<u64>,
+    /// CORS origin allowlist; `None` allows any origin
+    pub allowed_origins: Option<Vec<String>>,
+    /// Per-IP API request rate (0 = unlimited)
+    pub rate_limit_rps: u64,
+    /// Key the rate limit on `X-Forwarded-For`/`X-Real-IP`/`Forwarded` instead of the peer address
+    pub trust_proxy_headers: bool,
+    /// Largest accepted API request body
+    pub max_request_bytes: usize,
+    /// API requests handled at once before new ones get 503 (0 = unlimited)
+    pub max_concurrent_requests: usize,
+    /// Open `/stream/*` connections before new ones get 503 (0 = unlimited)
+    pub max_stream_clients: usize,
+    pub api_log_level: ApiLogLevel,
+    /// gRPC server port; `None` disables it (requires the `grpc` feature)
+    pub grpc_port: Option<u16>,
+    /// URL that receives a POST for every newly indexed event; `None` disables webhooks
+    pub webhook_url: Option<String>,
+    /// HMAC-SHA256 key for the webhook signature header
+    pub webhook_secret: Option<String>,
+    /// Delivery retries before a webhook event is dropped
+    pub webhook_max_retries: u32,
+    /// Also POST a `dead_letter` alert when an event is dead-lettered, at most once a minute
+    pub webhook_alert_on_dead_letter: bool,
+    /// Block timestamps kept in the shared LRU cache
+    pub block_timestamp_cache_size: NonZeroUsize,
+    /// `Authorization` header value sent to the RPC provider over HTTP and WS
+    pub rpc_auth_header: Option<String>,
+    /// Bearer token for `/admin` routes; `None` rejects every admin request
+    pub admin_token: Option<String>,
+    /// Reconnect the WS subscription after this long without a log or new head; `None` disables
+    pub ws_idle_timeout: Option<Duration>,
+    pub subscribe_mode: SubscribeMode,
+    /// Delay between `getLogs` polls in poll mode
+    pub poll_interval: Duration,
+    /// zstd-compress message and handshake payload blobs on insert
+    pub compress_blobs: bool,
+    /// Keep each event's serialized log next to its row, for `/admin/reprocess-decoded`
+    pub store_raw_logs: bool,
+    /// Maintain a full-text index of handshake payloads for `/handshakes/search`
+    pub enable_fts: bool,
+    /// Read-only connections reserved for API queries (0 = share the write pool)
+    pub read_pool_size: u32,
+    /// Event types to fetch and store (default: all)
+    pub index_events: EventKinds,
+    /// Topics and recipient hashes to store events for (default: all)
+    pub recipient_allowlist: RecipientAllowlist,
 }
 
 impl Config {
+    /// Read the config from the environment. Every invalid or missing variable is reported
+    /// in one error rather than stopping at the first.
     pub fn from_env() -> Result<Self> {
         dotenvy::dotenv().ok();
+        Self::from_lookup(&|name| std::env::var(name).ok())
+    }
+
+    /// `from_env` with `var` in place of the environment.
+    fn from_lookup(var: &dyn Fn(&str) -> Option<String>) -> Result<Self> {
+        let mut env = ConfigReader::new(var);
+
+        let subscribe_mode = match env.var("SUBSCRIBE_MODE").as_deref() {
+            Some("ws") | None => SubscribeMode::Ws,
+            Some("poll") => SubscribeMode::Poll,
+            Some(other) => {
+                env.push(format!(
+                    "Invalid SUBSCRIBE_MODE: {other} (expected ws or poll)"
+                ));
+                SubscribeMode::Ws
+            }
+        };
+
+        let poll_interval = env.parse("POLL_INTERVAL", 5u64);
+        if poll_interval == 0 {
+            env.push("POLL_INTERVAL must be at least 1");
+        }
+        let poll_interval = Duration::from_secs(poll_interval);
+
+        let rpc_http_url = env.var("RPC_HTTP_URL");
+        if let Some(url) = &rpc_http_url {
+            env.check_scheme("RPC_HTTP_URL", url, &["http", "https"]);
+        }
+
+        // Poll mode only talks HTTP, so either URL will do
+        let rpc_ws_url = match env.var("RPC_WS_URL") {
+            Some(url) => {
+                env.check_scheme("RPC_WS_URL", &url, &["ws", "wss"]);
+                url
+            }
+            None if subscribe_mode == SubscribeMode::Poll && rpc_http_url.is_some() => {
+                String::new()
+            }
+            None if subscribe_mode == SubscribeMode::Poll => {
+                env.push("RPC_HTTP_URL or RPC_WS_URL is required");
+                String::new()
+            }
+            None => {
+                env.push("RPC_WS_URL is required");
+                String::new()
+            }
+        };
 
-        let rpc_ws_url = std::env::var("RPC_WS_URL")
-            .map_err(|_| IndexerError::Config("RPC_WS_URL is required".into()))?;
+        let custom_contract = env.var("CONTRACT_ADDRESS").is_some();
+        let contract_address = env.parse(
+            "CONTRACT_ADDRESS",
+            Address::from_str("0x82C9c5475D63e4C9e959280e9066aBb24973a663").unwrap(),
+        );
 
-        let rpc_http_url = std::env::var("RPC_HTTP_URL").ok();
+        // The default only fits the default contract
+        let creation_block_set = env.var("CREATION_BLOCK").is_some();
+        let creation_block = env.parse("CREATION_BLOCK", 37097547u64);
 
-        let contract_address = std::env::var("CONTRACT_ADDRESS")
-            .unwrap_or_else(|_| "0x82C9c5475D63e4C9e959280e9066aBb24973a663".into());
-        let contract_address = Address::from_str(&contract_address)
-            .map_err(|e| IndexerError::Config(format!("Invalid CONTRACT_ADDRESS: {e}")))?;
+        let creation_tx_hash = env.parse_optional::<B256>("CREATION_TX_HASH");
+        let detect_creation_block =
+            custom_contract && !creation_block_set && creation_tx_hash.is_none();
 
-        let creation_block = std::env::var("CREATION_BLOCK")
-            .unwrap_or_else(|_| "37097547".into())
-            .parse::<u64>()
-            .map_err(|e| IndexerError::Config(format!("Invalid CREATION_BLOCK: {e}")))?;
+        let data_dir = data_dir(var);
+        let database_path = database_path(var);
 
-        let database_path = std::env::var("DATABASE_PATH")
-            .unwrap_or_else(|_| "./data/indexer.db".into());
+        let wal_checkpoint_interval = env.parse("WAL_CHECKPOINT_INTERVAL", 300u64);
+        let wal_checkpoint_interval =
+            (wal_checkpoint_interval > 0).then(|| Duration::from_secs(wal_checkpoint_interval));
 
-        let server_port = std::env::var("SERVER_PORT")
-            .unwrap_or_else(|_| "3002".into())
-            .parse::<u16>()
-            .map_err(|e| IndexerError::Config(format!("Invalid SERVER_PORT: {e}")))?;
+        let rescan_interval = env.parse("RESCAN_INTERVAL", 3600u64);
+        let rescan_interval = (rescan_interval > 0).then(|| Duration::from_secs(rescan_interval));
 
-        let backfill_days = std::env::var("BACKFILL_DAYS")
-            .unwrap_or_else(|_| "7".into())
-            .parse::<u32>()
-            .map_err(|e| IndexerError::Config(format!("Invalid BACKFILL_DAYS: {e}")))?;
+        let rescan_blocks = env.parse("RESCAN_BLOCKS", 2000u64);
 
-        let retention_days = std::env::var("RETENTION_DAYS")
-            .unwrap_or_else(|_| "7".into())
-            .parse::<u32>()
-            .map_err(|e| IndexerError::Config(format!("Invalid RETENTION_DAYS: {e}")))?;
+        let server_port = env.parse("SERVER_PORT", 3002u16);
+        let listen_uds = env.var("LISTEN_UDS").filter(|v| !v.is_empty());
+
+        let backfill_days = env.parse("BACKFILL_DAYS", 7u32);
+
+        let max_backfill_blocks = env.parse("MAX_BACKFILL_BLOCKS", 1_000_000u64);
+
+        let retention_days = env.parse("RETENTION_DAYS", 7u32);
 
         // Default to 10 for Alchemy free tier compatibility
-        let rpc_chunk_size = std::env::var("RPC_CHUNK_SIZE")
-            .unwrap_or_else(|_| "10".into())
-            .parse::<u64>()
-            .map_err(|e| IndexerError::Config(format!("Invalid RPC_CHUNK_SIZE: {e}")))?;
+        let rpc_chunk_size = env.parse("RPC_CHUNK_SIZE", 10u64);
+
+        let checkpoint_every_blocks = env.parse("CHECKPOINT_EVERY_BLOCKS", 0u64);
+
+        // Defaults target Alchemy free tier: 500 CU/s, eth_getLogs = 75 CU → max ~6 req/s
+        let requests_per_second = env.parse("RPC_REQUESTS_PER_SECOND", NonZeroU32::new(5).unwrap());
+
+        let max_retries = env.parse("RPC_MAX_RETRIES", 5u32);
+
+        let backoff_ms = env.parse("RPC_BACKOFF_MS", 1000u64);
+
+        let rpc_timeout = env.parse("RPC_TIMEOUT_SECS", 30u64);
+        if rpc_timeout == 0 {
+            env.push("RPC_TIMEOUT_SECS must be at least 1");
+        }
+
+        let breaker_threshold = env.parse("RPC_BREAKER_THRESHOLD", 10u32);
+        let breaker_window = env.parse("RPC_BREAKER_WINDOW_SECS", 60u64);
+        if breaker_window == 0 {
+            env.push("RPC_BREAKER_WINDOW_SECS must be at least 1");
+        }
+        let breaker_cooldown = env.parse("RPC_BREAKER_COOLDOWN_SECS", 30u64);
+        if breaker_cooldown == 0 {
+            env.push("RPC_BREAKER_COOLDOWN_SECS must be at least 1");
+        }
+
+        let payload_limits = PayloadLimits::from_env(&mut env);
 
         // SQLite sync mode: NORMAL (default), FULL for extra local durability
-        let sqlite_sync_mode = std::env::var("SQLITE_SYNC_MODE").unwrap_or_else(|_| "NORMAL".into());
+        let sqlite_sync_mode = env
+            .var("SQLITE_SYNC_MODE")
+            .unwrap_or_else(|| "NORMAL".into());
         if !["OFF", "NORMAL", "FULL"].contains(&sqlite_sync_mode.as_str()) {
-            return Err(IndexerError::Config(format!(
+            env.push(format!(
                 "Invalid SQLITE_SYNC_MODE: {sqlite_sync_mode} (expected OFF, NORMAL, or FULL)"
-            )));
+            ));
+        }
+
+        let db_integrity_check = env
+            .var("DB_INTEGRITY_CHECK")
+            .unwrap_or_else(|| "OFF".into());
+        if !["OFF", "QUICK", "FULL"].contains(&db_integrity_check.as_str()) {
+            env.push(format!(
+                "Invalid DB_INTEGRITY_CHECK: {db_integrity_check} (expected OFF, QUICK, or FULL)"
+            ));
+        }
+
+        let mode = env.var("MODE").unwrap_or_else(|| "serve".into());
+        if !["serve", "backfill"].contains(&mode.as_str()) {
+            env.push(format!("Invalid MODE: {mode} (expected serve or backfill)"));
+        }
+        let backfill_only = mode == "backfill" || std::env::args().any(|a| a == "--backfill-only");
+
+        let ready_lag_blocks = env.parse("READY_LAG_BLOCKS", 10u64);
+
+        let confirmations = env.parse("CONFIRMATIONS", 0u64);
+
+        let verify_finality = env.parse("VERIFY_FINALITY", 0u64);
+        if verify_finality > 0 && verify_finality <= confirmations {
+            env.push("VERIFY_FINALITY must exceed CONFIRMATIONS");
+        }
+        let verify_finality = (verify_finality > 0).then_some(verify_finality);
+
+        let allowed_origins = env.var("ALLOWED_ORIGINS").map(|v| {
+            v.split(',')
+                .map(|o| o.trim().to_string())
+                .filter(|o| !o.is_empty())
+                .collect::<Vec<_>>()
+        });
+
+        let rate_limit_rps = env.parse("RATE_LIMIT_RPS", 0u64);
+        let trust_proxy_headers = env.parse("TRUST_PROXY_HEADERS", false);
+
+        let max_request_bytes = env.parse("MAX_REQUEST_BYTES", 262_144usize);
+        if max_request_bytes == 0 {
+            env.push("MAX_REQUEST_BYTES must be at least 1");
         }
 
+        let max_concurrent_requests = env.parse("MAX_CONCURRENT_REQUESTS", 256usize);
+
+        let max_stream_clients = env.parse("MAX_STREAM_CLIENTS", 128usize);
+
+        let api_log_level = match env.var("API_LOG_LEVEL").as_deref() {
+            Some("off") | None => ApiLogLevel::Off,
+            Some("info") => ApiLogLevel::Info,
+            Some("verbose") => ApiLogLevel::Verbose,
+            Some(other) => {
+                env.push(format!(
+                    "Invalid API_LOG_LEVEL: {other} (expected off, info or verbose)"
+                ));
+                ApiLogLevel::Off
+            }
+        };
+
+        let grpc_port = env.parse_optional::<u16>("GRPC_PORT");
+
+        let webhook_url = env.var("WEBHOOK_URL").filter(|v| !v.is_empty());
+        let webhook_secret = env.var("WEBHOOK_SECRET").filter(|v| !v.is_empty());
+
+        let webhook_max_retries = env.parse("WEBHOOK_MAX_RETRIES", 5u32);
+
+        let webhook_alert_on_dead_letter = env.parse("WEBHOOK_ALERT_ON_DEAD_LETTER", false);
+
+        let rpc_auth_header = env.var("RPC_AUTH_HEADER").filter(|v| !v.is_empty());
+
+        let admin_token = env.var("ADMIN_TOKEN").filter(|v| !v.is_empty());
+
+        let tls_cert_path = env.var("TLS_CERT_PATH").filter(|v| !v.is_empty());
+        let tls_key_path = env.var("TLS_KEY_PATH").filter(|v| !v.is_empty());
+        let tls = match (tls_cert_path, tls_key_path) {
+            (Some(cert_path), Some(key_path)) => Some(TlsPaths {
+                cert_path,
+                key_path,
+            }),
+            (None, None) => None,
+            (Some(_), None) => {
+                env.push("TLS_CERT_PATH is set but TLS_KEY_PATH is not");
+                None
+            }
+            (None, Some(_)) => {
+                env.push("TLS_KEY_PATH is set but TLS_CERT_PATH is not");
+                None
+            }
+        };
+        if tls.is_some() && listen_uds.is_some() {
+            env.push("TLS_CERT_PATH/TLS_KEY_PATH can't be combined with LISTEN_UDS");
+        }
+
+        let block_timestamp_cache_size = env.parse(
+            "BLOCK_TIMESTAMP_CACHE_SIZE",
+            NonZeroUsize::new(10_000).unwrap(),
+        );
+
+        let ws_idle_timeout = env.parse("WS_IDLE_TIMEOUT", 0u64);
+        let ws_idle_timeout = (ws_idle_timeout > 0).then(|| Duration::from_secs(ws_idle_timeout));
+
+        let compress_blobs = env.parse("COMPRESS_BLOBS", false);
+
+        let store_raw_logs = env.parse("STORE_RAW_LOGS", false);
+
+        let enable_fts = env.parse("ENABLE_FTS", false);
+
+        let read_pool_size = env.parse("READ_POOL_SIZE", 4u32);
+
+        let index_events = match env.var("INDEX_EVENTS") {
+            Some(v) => EventKinds::parse(&v).unwrap_or_else(|e| {
+                env.push(format!("Invalid INDEX_EVENTS: {e}"));
+                EventKinds::ALL
+            }),
+            None => EventKinds::ALL,
+        };
+
+        let recipient_allowlist = env
+            .var("RECIPIENT_ALLOWLIST")
+            .map(|v| {
+                RecipientAllowlist::parse(&v).unwrap_or_else(|e| {
+                    env.push(format!("Invalid RECIPIENT_ALLOWLIST: {e}"));
+                    RecipientAllowlist::default()
+                })
+            })
+            .unwrap_or_default();
+
+        env.into_result()?;
+
         Ok(Self {
             rpc_ws_url,
             rpc_http_url,
             contract_address,
             creation_block,
+            creation_tx_hash,
+            detect_creation_block,
+            data_dir,
             database_path,
+            wal_checkpoint_interval,
+            rescan_interval,
+            rescan_blocks,
             server_port,
+            listen_uds,
+            tls,
             backfill_days,
+            max_backfill_blocks,
             retention_days,
             rpc_chunk_size,
+            checkpoint_every_blocks,
+            rpc_limits: RpcLimits {
+                requests_per_second,
+                max_retries,
+                backoff_ms,
+                timeout: Duration::from_secs(rpc_timeout),
+                breaker_threshold,
+                breaker_window: Duration::from_secs(breaker_window),
+                breaker_cooldown: Duration::from_secs(breaker_cooldown),
+            },
+            payload_limits,
             sqlite_sync_mode,
+            db_integrity_check,
+            backfill_only,
+            ready_lag_blocks,
+            confirmations,
+            verify_finality,
+            allowed_origins,
+            rate_limit_rps,
+            trust_proxy_headers,
+            max_request_bytes,
+            max_concurrent_requests,
+            max_stream_clients,
+            api_log_level,
+            grpc_port,
+            webhook_url,
+            webhook_secret,
+            webhook_max_retries,
+            webhook_alert_on_dead_letter,
+            block_timestamp_cache_size,
+            rpc_auth_header,
+            admin_token,
+            ws_idle_timeout,
+            subscribe_mode,
+            poll_interval,
+            compress_blobs,
+            store_raw_logs,
+            enable_fts,
+            read_pool_size,
+            index_events,
+            recipient_allowlist,
         })
     }
+
+    /// Config built from `vars` alone, ignoring the environment.
+    #[cfg(test)]
+    pub fn from_vars(vars: &[(&str, &str)]) -> Result<Self> {
+        Self::from_lookup(&|name| {
+            vars.iter()
+                .find(|(n, _)| *n == name)
+                .map(|(_, v)| v.to_string())
+        })
+    }
+
+    /// HTTP RPC endpoint, derived from the WS URL if `RPC_HTTP_URL` is not set.
+    pub fn http_rpc_url(&self) -> String {
+        self.rpc_http_url.clone().unwrap_or_else(|| {
+            self.rpc_ws_url
+                .replace("wss://", "https://")
+                .replace("ws://", "http://")
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config_error(vars: &[(&str, &str)]) -> String {
+        match Config::from_vars(vars) {
+            Err(IndexerError::Config(error)) => error,
+            Err(e) => panic!("not a config error: {e}"),
+            Ok(_) => panic!("config accepted"),
+        }
+    }
+
+    #[test]
+    fn minimal_config_is_accepted() {
+        let config = Config::from_vars(&[("RPC_WS_URL", "wss://rpc.example.com")]).unwrap();
+        assert_eq!(config.http_rpc_url(), "https://rpc.example.com");
+        assert_eq!(config.database_path, "./data/indexer.db");
+    }
+
+    #[test]
+    fn every_problem_is_reported_at_once() {
+        let error = config_error(&[
+            ("RPC_WS_URL", "https://rpc.example.com"),
+            ("RPC_HTTP_URL", "wss://rpc.example.com"),
+            ("CONTRACT_ADDRESS", "0xnothex"),
+            ("RPC_CHUNK_SIZE", "ten"),
+            ("SQLITE_SYNC_MODE", "SOMETIMES"),
+        ]);
+
+        assert!(error.starts_with("5 problems: "), "{error}");
+        for problem in [
+            "Invalid RPC_HTTP_URL: wss://rpc.example.com (expected http:// or https://)",
+            "Invalid RPC_WS_URL: https://rpc.example.com (expected ws:// or wss://)",
+            "Invalid CONTRACT_ADDRESS",
+            "Invalid RPC_CHUNK_SIZE",
+            "Invalid SQLITE_SYNC_MODE: SOMETIMES",
+        ] {
+            assert!(error.contains(problem), "{problem:?} missing from {error}");
+        }
+    }
+
+    #[test]
+    fn missing_url_is_reported_with_the_other_problems() {
+        let error = config_error(&[
+            ("RPC_TIMEOUT_SECS", "0"),
+            ("TLS_CERT_PATH", "/etc/indexer/cert.pem"),
+        ]);
+
+        assert!(error.starts_with("3 problems: "), "{error}");
+        assert!(error.contains("RPC_WS_URL is required"));
+        assert!(error.contains("RPC_TIMEOUT_SECS must be at least 1"));
+        assert!(error.contains("TLS_CERT_PATH is set but TLS_KEY_PATH is not"));
+    }
+
+    #[test]
+    fn single_problem_is_reported_as_is() {
+        assert_eq!(
+            config_error(&[("SUBSCRIBE_MODE", "poll")]),
+            "RPC_HTTP_URL or RPC_WS_URL is required"
+        );
+    }
+
+    #[test]
+    fn sanitizer_strips_path_keys() {
+        assert_eq!(
+            sanitize_rpc_url("wss://base-mainnet.g.alchemy.com/v2/Xk3pQ9zLmN7vR2tY"),
+            "wss://base-mainnet.g.alchemy.com/v2/***"
+        );
+        assert_eq!(
+            sanitize_rpc_url("https://mainnet.infura.io/v3/0123456789abcdef0123456789abcdef"),
+            "https://mainnet.infura.io/v3/***"
+        );
+    }
+
+    #[test]
+    fn sanitizer_strips_query_params() {
+        assert_eq!(
+            sanitize_rpc_url("https://rpc.example.com/?apikey=s3cr3t&chain=base"),
+            "https://rpc.example.com/?apikey=***&chain=***"
+        );
+        assert_eq!(
+            sanitize_rpc_url("wss://rpc.example.com/v2/Xk3pQ9zLmN7vR2tY?apikey=s3cr3t"),
+            "wss://rpc.example.com/v2/***?apikey=***"
+        );
+    }
+
+    #[test]
+    fn sanitizer_keeps_urls_without_secrets() {
+        for url in ["http://localhost:8545", "https://mainnet.base.org"] {
+            assert_eq!(sanitize_rpc_url(url), url);
+        }
+    }
 }
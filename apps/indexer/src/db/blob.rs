@@ -0,0 +1,89 @@
+//! Optional zstd compression of large event blobs at rest.
+//!
+//! Every stored blob starts with a one-byte format header: `FORMAT_RAW` followed by the
+//! bytes as they are, or `FORMAT_ZSTD` followed by a zstd frame. The format is never
+//! guessed from the content, which is attacker-chosen on-chain data. Rows written before
+//! the header existed were given one by schema migration 14.
+
+use std::io;
+
+const FORMAT_RAW: u8 = 0x00;
+const FORMAT_ZSTD: u8 = 0x01;
+const ZSTD_LEVEL: i32 = 3;
+
+/// Blob as it should be written. Compressed only if `compress` is set and that makes it smaller.
+pub fn encode(data: &[u8], compress: bool) -> Vec<u8> {
+    if compress {
+        let mut stored = vec![FORMAT_ZSTD];
+        if zstd::stream::copy_encode(data, &mut stored, ZSTD_LEVEL).is_ok()
+            && stored.len() <= data.len()
+        {
+            return stored;
+        }
+    }
+
+    let mut stored = Vec::with_capacity(data.len() + 1);
+    stored.push(FORMAT_RAW);
+    stored.extend_from_slice(data);
+    stored
+}
+
+/// Original bytes of a stored blob, decompressing it if its header says so.
+pub fn decode(mut stored: Vec<u8>) -> io::Result<Vec<u8>> {
+    match stored.first() {
+        Some(&FORMAT_RAW) => {
+            stored.remove(0);
+            Ok(stored)
+        }
+        Some(&FORMAT_ZSTD) => zstd::stream::decode_all(&stored[1..]),
+        Some(other) => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("unknown blob format {other:#04x}"),
+        )),
+        None => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "blob has no format header",
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ZSTD_MAGIC: [u8; 4] = [0x28, 0xB5, 0x2F, 0xFD];
+
+    #[test]
+    fn round_trips_either_way() {
+        let data = b"{\"v\":1,\"ct\":\"aGVsbG8gaGVsbG8gaGVsbG8gaGVsbG8gaGVsbG8=\"}".repeat(20);
+        for compress in [false, true] {
+            assert_eq!(decode(encode(&data, compress)).unwrap(), data);
+        }
+        assert_eq!(encode(&data, true)[0], FORMAT_ZSTD);
+        assert_eq!(encode(&data, false)[0], FORMAT_RAW);
+    }
+
+    #[test]
+    fn incompressible_data_is_stored_raw() {
+        let data = [0x42u8; 3];
+        assert_eq!(encode(&data, true), [FORMAT_RAW, 0x42, 0x42, 0x42]);
+    }
+
+    #[test]
+    fn payload_shaped_like_a_zstd_frame_stays_raw() {
+        // A ciphertext crafted to look like what compressed blobs used to be sniffed as
+        let mut data = vec![FORMAT_ZSTD];
+        data.extend_from_slice(&ZSTD_MAGIC);
+        data.extend_from_slice(&[0xff; 8]);
+
+        for compress in [false, true] {
+            assert_eq!(decode(encode(&data, compress)).unwrap(), data);
+        }
+    }
+
+    #[test]
+    fn empty_blob_round_trips() {
+        assert_eq!(decode(encode(&[], false)).unwrap(), Vec::<u8>::new());
+        assert!(decode(Vec::new()).is_err());
+    }
+}
@@ -1,33 +1,154 @@
-use r2d2::Pool;
+use r2d2::{CustomizeConnection, Pool};
 use r2d2_sqlite::SqliteConnectionManager;
+use rusqlite::{Connection, OpenFlags};
 use std::path::Path;
+use std::time::{Duration, Instant};
 
-use crate::error::Result;
+use crate::error::{IndexerError, Result};
 
+pub mod blob;
 pub mod models;
 pub mod queries;
+pub mod report;
 pub mod schema;
+pub mod wal;
 
 pub type DbPool = Pool<SqliteConnectionManager>;
 
-pub fn create_pool(database_path: &str, sync_mode: &str) -> Result<DbPool> {
+/// Max problems reported by a failed integrity check.
+const INTEGRITY_CHECK_MAX_ERRORS: u32 = 10;
+/// How long a statement waits on another connection's lock before failing with SQLITE_BUSY.
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Connection-level pragmas. SQLite keeps these per connection, so they are applied to
+/// every connection the pool opens, not just the one that runs the migrations.
+#[derive(Debug)]
+struct ConnectionPragmas {
+    /// `None` for read-only connections, which never write
+    synchronous: Option<String>,
+}
+
+impl CustomizeConnection<Connection, rusqlite::Error> for ConnectionPragmas {
+    fn on_acquire(&self, conn: &mut Connection) -> std::result::Result<(), rusqlite::Error> {
+        conn.busy_timeout(BUSY_TIMEOUT)?;
+        conn.execute_batch("PRAGMA foreign_keys=ON;")?;
+        if let Some(synchronous) = &self.synchronous {
+            conn.execute_batch(&format!("PRAGMA synchronous={synchronous};"))?;
+        }
+        Ok(())
+    }
+}
+
+pub fn create_pool(database_path: &str, sync_mode: &str, integrity_check: &str) -> Result<DbPool> {
     if let Some(parent) = Path::new(database_path).parent() {
         std::fs::create_dir_all(parent).ok();
     }
 
     let manager = SqliteConnectionManager::file(database_path);
-    let pool = Pool::builder().max_size(4).build(manager)?;
+    // One connection is held for good by the event writer
+    let pool = Pool::builder()
+        .max_size(5)
+        .connection_customizer(Box::new(ConnectionPragmas {
+            synchronous: Some(sync_mode.to_string()),
+        }))
+        .build(manager)?;
 
+    // Unlike the per-connection pragmas, WAL mode is stored in the file and only needs setting once
     let conn = pool.get()?;
-    let pragmas = format!(
-        "PRAGMA journal_mode=WAL; PRAGMA synchronous={}; PRAGMA foreign_keys=ON;",
-        sync_mode
-    );
-    conn.execute_batch(&pragmas)?;
+    conn.execute_batch("PRAGMA journal_mode=WAL;")?;
 
     tracing::info!("SQLite initialized with synchronous={}", sync_mode);
 
+    match integrity_check {
+        "QUICK" => check_integrity(&conn, "quick_check")?,
+        "FULL" => check_integrity(&conn, "integrity_check")?,
+        _ => {}
+    }
+
     schema::run_migrations(&conn)?;
 
     Ok(pool)
 }
+
+/// Run `PRAGMA quick_check` or `integrity_check`, which is the thorough but slower one.
+/// A file corrupted by a power loss fails here with the problems SQLite found, instead of
+/// as confusing insert errors later.
+fn check_integrity(conn: &Connection, pragma: &str) -> Result<()> {
+    let started = Instant::now();
+    let problems = conn
+        .prepare(&format!("PRAGMA {pragma}({INTEGRITY_CHECK_MAX_ERRORS})"))?
+        .query_map([], |row| row.get::<_, String>(0))?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+
+    if problems != ["ok"] {
+        return Err(IndexerError::Integrity(problems.join("; ")));
+    }
+
+    tracing::info!("SQLite {pragma} passed in {:?}", started.elapsed());
+    Ok(())
+}
+
+/// Read-only pool over the same file for API queries, so reads never queue behind the
+/// writer's connections. WAL lets these readers run alongside an open write transaction.
+/// Call after `create_pool`, which creates and migrates the file.
+pub fn create_read_pool(database_path: &str, size: u32) -> Result<DbPool> {
+    let manager = SqliteConnectionManager::file(database_path).with_flags(
+        OpenFlags::SQLITE_OPEN_READ_ONLY
+            | OpenFlags::SQLITE_OPEN_URI
+            | OpenFlags::SQLITE_OPEN_NO_MUTEX,
+    );
+    let pool = Pool::builder()
+        .max_size(size)
+        .connection_customizer(Box::new(ConnectionPragmas { synchronous: None }))
+        .build(manager)?;
+
+    tracing::info!("SQLite read pool initialized with {} connections", size);
+
+    Ok(pool)
+}
+
+/// Migrated in-memory database for unit tests.
+#[cfg(test)]
+pub fn memory_db() -> Connection {
+    let conn = Connection::open_in_memory().expect("open in-memory database");
+    schema::run_migrations(&conn).expect("migrate in-memory database");
+    conn
+}
+
+/// Pool over a migrated in-memory database of its own, for tests that go through the
+/// event writer. Every connection shares the database through SQLite's shared cache.
+#[cfg(test)]
+pub fn memory_pool() -> DbPool {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    static NEXT_DB: AtomicUsize = AtomicUsize::new(0);
+    let name = format!(
+        "file:test-{}?mode=memory&cache=shared",
+        NEXT_DB.fetch_add(1, Ordering::Relaxed)
+    );
+    create_pool(&name, "NORMAL", "OFF").expect("create in-memory pool")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pragma(conn: &Connection, name: &str) -> i64 {
+        conn.query_row(&format!("PRAGMA {name}"), [], |row| row.get(0))
+            .unwrap()
+    }
+
+    #[test]
+    fn every_pooled_connection_gets_the_pragmas() {
+        let pool = memory_pool();
+        // Held at once, so each is a separate connection rather than the same one reused
+        let conns: Vec<_> = (0..pool.max_size()).map(|_| pool.get().unwrap()).collect();
+        for conn in &conns {
+            assert_eq!(pragma(conn, "foreign_keys"), 1);
+            assert_eq!(
+                pragma(conn, "busy_timeout"),
+                BUSY_TIMEOUT.as_millis() as i64
+            );
+        }
+    }
+}
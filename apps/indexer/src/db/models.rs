@@ -1,36 +1,66 @@
+use crate::types::{EphemeralKey, RecipientHash, SenderAddress, TopicHash};
+
+#[derive(Clone)]
 pub struct MessageRow {
-    pub topic: [u8; 32],
+    pub topic: TopicHash,
     pub seq: i64,
-    pub sender: [u8; 20],
+    pub sender: SenderAddress,
     pub ciphertext: Vec<u8>,
     pub timestamp: i64,
     pub nonce: i64,
     pub block_number: i64,
     pub log_index: i64,
     pub block_timestamp: i64,
+    /// `None` for logs delivered without a transaction hash (pending logs)
+    pub tx_hash: Option<[u8; 32]>,
+    /// `None` for pending logs and rows indexed before block hashes were stored
+    pub block_hash: Option<[u8; 32]>,
+    /// Unix time the row was inserted; 0 for rows indexed before this was recorded
+    pub indexed_at: i64,
 }
 
+#[derive(Clone)]
 pub struct HandshakeRow {
-    pub recipient_hash: [u8; 32],
+    pub recipient_hash: RecipientHash,
     pub seq: i64,
-    pub sender: [u8; 20],
+    pub sender: SenderAddress,
     pub pub_keys: Vec<u8>,
     pub ephemeral_pub_key: Vec<u8>,
     pub plaintext_payload: Vec<u8>,
     pub block_number: i64,
     pub log_index: i64,
     pub block_timestamp: i64,
+    /// `None` for logs delivered without a transaction hash (pending logs)
+    pub tx_hash: Option<[u8; 32]>,
+    /// `None` for pending logs and rows indexed before block hashes were stored
+    pub block_hash: Option<[u8; 32]>,
+    /// Unix time the row was inserted; 0 for rows indexed before this was recorded
+    pub indexed_at: i64,
 }
 
+#[derive(Clone)]
 pub struct HsrRow {
     pub global_seq: i64,
     pub in_response_to: [u8; 32],
-    pub responder: [u8; 20],
-    pub responder_ephemeral_r: [u8; 32],
+    pub responder: SenderAddress,
+    pub responder_ephemeral_r: EphemeralKey,
     pub ciphertext: Vec<u8>,
     pub block_number: i64,
     pub log_index: i64,
     pub block_timestamp: i64,
+    /// `None` for logs delivered without a transaction hash (pending logs)
+    pub tx_hash: Option<[u8; 32]>,
+    /// `None` for pending logs and rows indexed before block hashes were stored
+    pub block_hash: Option<[u8; 32]>,
+    /// Unix time the row was inserted; 0 for rows indexed before this was recorded
+    pub indexed_at: i64,
+}
+
+/// One event of the global feed, from whichever table stores it.
+pub enum FeedEvent {
+    Message(MessageRow),
+    Handshake(HandshakeRow),
+    HandshakeResponse(HsrRow),
 }
 
 pub struct EventCounts {
@@ -38,3 +68,43 @@ pub struct EventCounts {
     pub handshakes: i64,
     pub handshake_responses: i64,
 }
+
+pub struct DeadLetterRow {
+    pub id: i64,
+    pub block_number: i64,
+    pub log_index: i64,
+    pub retry_count: i64,
+    pub last_error: String,
+    /// JSON-serialized `LogWithMeta`, used to requeue the event.
+    pub payload: String,
+    pub created_at: i64,
+}
+
+/// Optional filters for `search_messages`; `None` fields are not constrained.
+#[derive(Default)]
+pub struct MessageSearch {
+    pub topic: Option<TopicHash>,
+    pub sender: Option<SenderAddress>,
+    pub min_timestamp: Option<i64>,
+    pub max_timestamp: Option<i64>,
+    pub min_nonce: Option<i64>,
+    pub max_nonce: Option<i64>,
+}
+
+/// One row of the `topics` summary table.
+pub struct TopicSummary {
+    pub topic: TopicHash,
+    pub message_count: i64,
+    pub last_block_timestamp: i64,
+}
+
+/// Integrity report for one topic's `seq` counter, from `verify_topic`.
+#[derive(Default)]
+pub struct TopicConsistency {
+    pub message_count: i64,
+    pub max_seq: Option<i64>,
+    /// Inclusive `(first, last)` ranges of seq values below `max_seq` with no row
+    pub missing_seqs: Vec<(i64, i64)>,
+    /// `(previous_seq, seq)` pairs where the higher seq comes earlier on chain
+    pub ordering_inversions: Vec<(i64, i64)>,
+}
@@ -1,36 +1,228 @@
-use rusqlite::{params, Connection, OptionalExtension};
+use std::collections::BTreeSet;
+
+use rusqlite::types::Value;
+use rusqlite::{params, params_from_iter, Connection, OptionalExtension};
 
 use crate::error::Result;
+use crate::types::{RecipientHash, SenderAddress, TopicHash};
+
+use super::blob;
+use super::models::{
+    DeadLetterRow, EventCounts, FeedEvent, HandshakeRow, HsrRow, MessageRow, MessageSearch,
+    TopicConsistency, TopicSummary,
+};
 
-use super::models::{EventCounts, HandshakeRow, HsrRow, MessageRow};
+/// `key_hash` of counters that are not kept per key, like the global handshake-response
+/// seq. SQLite treats NULLs in a primary key as distinct, so a NULL key would never hit
+/// the upsert's conflict and every allocation would add another row.
+const GLOBAL_KEY: &[u8] = &[];
 
 pub fn get_and_increment_seq(
     conn: &Connection,
     key_type: &str,
     key_hash: Option<&[u8; 32]>,
 ) -> Result<i64> {
-    // BEGIN IMMEDIATE acquires write lock immediately, preventing race conditions
-    conn.execute("BEGIN IMMEDIATE", [])?;
+    // Inside a caller's transaction (a backfill chunk) the counter commits or rolls back
+    // with the rows that use it
+    let own_transaction = conn.is_autocommit();
+    if own_transaction {
+        // BEGIN IMMEDIATE acquires write lock immediately, preventing race conditions
+        conn.execute("BEGIN IMMEDIATE", [])?;
+    }
 
+    let key_hash = key_hash.map_or(GLOBAL_KEY, |h| h.as_slice());
     let result = (|| -> Result<i64> {
         let seq: i64 = conn
-            .query_row(
-                "SELECT next_seq FROM seq_counters WHERE key_type = ?1 AND key_hash IS ?2",
-                params![key_type, key_hash.map(|h| h.as_slice())],
-                |row| row.get(0),
-            )
+            .prepare_cached(
+                "SELECT next_seq FROM seq_counters WHERE key_type = ?1 AND key_hash = ?2",
+            )?
+            .query_row(params![key_type, key_hash], |row| row.get(0))
             .optional()?
             .unwrap_or(0);
 
-        conn.execute(
+        conn.prepare_cached(
             "INSERT INTO seq_counters (key_type, key_hash, next_seq) VALUES (?1, ?2, ?3)
              ON CONFLICT(key_type, key_hash) DO UPDATE SET next_seq = ?3",
-            params![key_type, key_hash.map(|h| h.as_slice()), seq + 1],
-        )?;
+        )?
+        .execute(params![key_type, key_hash, seq + 1])?;
 
         Ok(seq)
     })();
 
+    if !own_transaction {
+        return result;
+    }
+    match &result {
+        Ok(_) => {
+            conn.execute("COMMIT", [])?;
+        }
+        Err(_) => {
+            let _ = conn.execute("ROLLBACK", []);
+        }
+    }
+
+    result
+}
+
+/// Whether a log at `(block_number, log_index)` is already stored in any event table.
+pub fn is_log_indexed(conn: &Connection, block_number: i64, log_index: i64) -> Result<bool> {
+    let exists = conn
+        .prepare_cached(
+            "SELECT EXISTS(
+                 SELECT 1 FROM messages WHERE block_number = ?1 AND log_index = ?2
+                 UNION ALL
+                 SELECT 1 FROM handshakes WHERE block_number = ?1 AND log_index = ?2
+                 UNION ALL
+                 SELECT 1 FROM handshake_responses WHERE block_number = ?1 AND log_index = ?2
+             )",
+        )?
+        .query_row(params![block_number, log_index], |row| row.get(0))?;
+    Ok(exists)
+}
+
+/// Delete the event stored at `(block_number, log_index)`, e.g. after a reorg retracted it.
+///
+/// If the row held the latest seq for its key, the counter is rolled back so the
+/// re-mined log reuses it. Otherwise later seqs are already handed out and the
+/// deleted seq is left as a gap rather than renumbering published rows.
+pub fn delete_log(conn: &Connection, block_number: i64, log_index: i64) -> Result<bool> {
+    conn.execute("BEGIN IMMEDIATE", [])?;
+
+    let result = (|| -> Result<bool> {
+        let message: Option<(Vec<u8>, i64)> = conn
+            .query_row(
+                "DELETE FROM messages WHERE block_number = ?1 AND log_index = ?2
+                 RETURNING topic, seq",
+                params![block_number, log_index],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .optional()?;
+        if let Some((topic, seq)) = message {
+            rollback_seq(conn, "message", Some(&topic), seq)?;
+            conn.execute(
+                "UPDATE topics SET
+                     message_count = message_count - 1,
+                     last_block_timestamp = COALESCE(
+                         (SELECT MAX(block_timestamp) FROM messages WHERE topic = ?1), 0)
+                 WHERE topic = ?1",
+                params![topic],
+            )?;
+            conn.execute(
+                "DELETE FROM topics WHERE topic = ?1 AND message_count <= 0",
+                params![topic],
+            )?;
+            return Ok(true);
+        }
+
+        let handshake: Option<(Vec<u8>, i64)> = conn
+            .query_row(
+                "DELETE FROM handshakes WHERE block_number = ?1 AND log_index = ?2
+                 RETURNING recipient_hash, seq",
+                params![block_number, log_index],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .optional()?;
+        if let Some((recipient_hash, seq)) = handshake {
+            rollback_seq(conn, "handshake", Some(&recipient_hash), seq)?;
+            return Ok(true);
+        }
+
+        let hsr: Option<i64> = conn
+            .query_row(
+                "DELETE FROM handshake_responses WHERE block_number = ?1 AND log_index = ?2
+                 RETURNING global_seq",
+                params![block_number, log_index],
+                |row| row.get(0),
+            )
+            .optional()?;
+        if let Some(global_seq) = hsr {
+            rollback_seq(conn, "hsr", None, global_seq)?;
+            return Ok(true);
+        }
+
+        Ok(false)
+    })();
+
+    match &result {
+        Ok(_) => {
+            conn.execute("COMMIT", [])?;
+        }
+        Err(_) => {
+            let _ = conn.execute("ROLLBACK", []);
+        }
+    }
+
+    result
+}
+
+/// Delete every event indexed at or above `from_block` and rewind the resume point to the
+/// block before it, in one transaction.
+///
+/// Unlike `delete_log`, which only rolls back a counter whose latest seq was deleted, each
+/// affected seq counter is reset to follow the highest seq still stored for its key. This
+/// way re-indexing the range assigns seqs from there instead of leaving a gap per event.
+pub fn delete_from_block(conn: &Connection, from_block: i64) -> Result<EventCounts> {
+    conn.execute("BEGIN IMMEDIATE", [])?;
+
+    let result = (|| -> Result<EventCounts> {
+        let topics = conn
+            .prepare("DELETE FROM messages WHERE block_number >= ?1 RETURNING topic")?
+            .query_map(params![from_block], |row| row.get::<_, Vec<u8>>(0))?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        let recipients = conn
+            .prepare("DELETE FROM handshakes WHERE block_number >= ?1 RETURNING recipient_hash")?
+            .query_map(params![from_block], |row| row.get::<_, Vec<u8>>(0))?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        let handshake_responses = conn.execute(
+            "DELETE FROM handshake_responses WHERE block_number >= ?1",
+            params![from_block],
+        )?;
+
+        let mut reset_topic = conn.prepare(
+            "UPDATE seq_counters SET next_seq =
+                 COALESCE((SELECT MAX(seq) + 1 FROM messages WHERE topic = ?1), 0)
+             WHERE key_type = 'message' AND key_hash = ?1",
+        )?;
+        let mut update_topic = conn.prepare(
+            "UPDATE topics SET
+                 message_count = (SELECT COUNT(*) FROM messages WHERE topic = ?1),
+                 last_block_timestamp = COALESCE(
+                     (SELECT MAX(block_timestamp) FROM messages WHERE topic = ?1), 0)
+             WHERE topic = ?1",
+        )?;
+        for topic in topics.iter().collect::<BTreeSet<_>>() {
+            reset_topic.execute(params![topic])?;
+            update_topic.execute(params![topic])?;
+        }
+        conn.execute("DELETE FROM topics WHERE message_count <= 0", [])?;
+
+        let mut reset_recipient = conn.prepare(
+            "UPDATE seq_counters SET next_seq =
+                 COALESCE((SELECT MAX(seq) + 1 FROM handshakes WHERE recipient_hash = ?1), 0)
+             WHERE key_type = 'handshake' AND key_hash = ?1",
+        )?;
+        for recipient_hash in recipients.iter().collect::<BTreeSet<_>>() {
+            reset_recipient.execute(params![recipient_hash])?;
+        }
+
+        if handshake_responses > 0 {
+            conn.execute(
+                "UPDATE seq_counters SET next_seq =
+                     COALESCE((SELECT MAX(global_seq) + 1 FROM handshake_responses), 0)
+                 WHERE key_type = 'hsr' AND key_hash = ?1",
+                params![GLOBAL_KEY],
+            )?;
+        }
+
+        set_last_processed_block(conn, (from_block - 1).max(0))?;
+
+        Ok(EventCounts {
+            messages: topics.len() as i64,
+            handshakes: recipients.len() as i64,
+            handshake_responses: handshake_responses as i64,
+        })
+    })();
+
     match &result {
         Ok(_) => {
             conn.execute("COMMIT", [])?;
@@ -43,65 +235,952 @@ pub fn get_and_increment_seq(
     result
 }
 
-pub fn insert_message(conn: &Connection, row: &MessageRow) -> Result<bool> {
-    let inserted = conn.execute(
-        "INSERT OR IGNORE INTO messages
-         (topic, seq, sender, ciphertext, timestamp, nonce, block_number, log_index, block_timestamp)
-         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
-        params![
-            row.topic.as_slice(),
+fn rollback_seq(
+    conn: &Connection,
+    key_type: &str,
+    key_hash: Option<&[u8]>,
+    seq: i64,
+) -> Result<()> {
+    conn.execute(
+        "UPDATE seq_counters SET next_seq = ?3
+         WHERE key_type = ?1 AND key_hash = ?2 AND next_seq = ?3 + 1",
+        params![key_type, key_hash.unwrap_or(GLOBAL_KEY), seq],
+    )?;
+    Ok(())
+}
+
+// The per-event write path uses prepare_cached. rusqlite keeps the statement cache on
+// each Connection, so every pooled connection compiles these once and reuses them.
+// `raw_log` is the serialized log the row was decoded from, if `STORE_RAW_LOGS` is on.
+pub fn insert_message(
+    conn: &Connection,
+    row: &MessageRow,
+    raw_log: Option<&[u8]>,
+    compress: bool,
+) -> Result<bool> {
+    let inserted = conn
+        .prepare_cached(
+            "INSERT OR IGNORE INTO messages
+             (topic, seq, sender, ciphertext, timestamp, nonce, block_number, log_index, block_timestamp, tx_hash, block_hash, indexed_at, raw_log)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13)",
+        )?
+        .execute(params![
+            row.topic,
             row.seq,
-            row.sender.as_slice(),
-            &row.ciphertext,
+            row.sender,
+            blob::encode(&row.ciphertext, compress),
             row.timestamp,
             row.nonce,
             row.block_number,
             row.log_index,
             row.block_timestamp,
-        ],
-    )?;
-    Ok(inserted > 0)
+            row.tx_hash.as_ref().map(|h| h.as_slice()),
+            row.block_hash.as_ref().map(|h| h.as_slice()),
+            row.indexed_at,
+            raw_log.map(|raw| blob::encode(raw, compress)),
+        ])?;
+    if inserted == 0 {
+        return Ok(false);
+    }
+
+    conn.prepare_cached(
+        "INSERT INTO topics (topic, message_count, last_block_timestamp) VALUES (?1, 1, ?2)
+         ON CONFLICT(topic) DO UPDATE SET
+             message_count = message_count + 1,
+             last_block_timestamp = MAX(last_block_timestamp, excluded.last_block_timestamp)",
+    )?
+    .execute(params![row.topic, row.block_timestamp])?;
+    Ok(true)
 }
 
-pub fn insert_handshake(conn: &Connection, row: &HandshakeRow) -> Result<bool> {
-    let inserted = conn.execute(
-        "INSERT OR IGNORE INTO handshakes
-         (recipient_hash, seq, sender, pub_keys, ephemeral_pub_key, plaintext_payload, block_number, log_index, block_timestamp)
-         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
-        params![
-            row.recipient_hash.as_slice(),
+pub fn insert_handshake(
+    conn: &Connection,
+    row: &HandshakeRow,
+    raw_log: Option<&[u8]>,
+    compress: bool,
+) -> Result<bool> {
+    let inserted = conn
+        .prepare_cached(
+            "INSERT OR IGNORE INTO handshakes
+             (recipient_hash, seq, sender, pub_keys, ephemeral_pub_key, plaintext_payload, block_number, log_index, block_timestamp, tx_hash, block_hash, indexed_at, raw_log)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13)",
+        )?
+        .execute(params![
+            row.recipient_hash,
             row.seq,
-            row.sender.as_slice(),
-            &row.pub_keys,
+            row.sender,
+            blob::encode(&row.pub_keys, compress),
             &row.ephemeral_pub_key,
-            &row.plaintext_payload,
+            blob::encode(&row.plaintext_payload, compress),
             row.block_number,
             row.log_index,
             row.block_timestamp,
-        ],
-    )?;
+            row.tx_hash.as_ref().map(|h| h.as_slice()),
+            row.block_hash.as_ref().map(|h| h.as_slice()),
+            row.indexed_at,
+            raw_log.map(|raw| blob::encode(raw, compress)),
+        ])?;
     Ok(inserted > 0)
 }
 
-pub fn insert_hsr(conn: &Connection, row: &HsrRow) -> Result<bool> {
-    let inserted = conn.execute(
-        "INSERT OR IGNORE INTO handshake_responses
-         (global_seq, in_response_to, responder, responder_ephemeral_r, ciphertext, block_number, log_index, block_timestamp)
-         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
-        params![
+pub fn insert_hsr(
+    conn: &Connection,
+    row: &HsrRow,
+    raw_log: Option<&[u8]>,
+    compress: bool,
+) -> Result<bool> {
+    let inserted = conn
+        .prepare_cached(
+            "INSERT OR IGNORE INTO handshake_responses
+             (global_seq, in_response_to, responder, responder_ephemeral_r, ciphertext, block_number, log_index, block_timestamp, tx_hash, block_hash, indexed_at, raw_log)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12)",
+        )?
+        .execute(params![
             row.global_seq,
             row.in_response_to.as_slice(),
-            row.responder.as_slice(),
-            row.responder_ephemeral_r.as_slice(),
-            &row.ciphertext,
+            row.responder,
+            row.responder_ephemeral_r,
+            blob::encode(&row.ciphertext, compress),
             row.block_number,
             row.log_index,
             row.block_timestamp,
-        ],
-    )?;
+            row.tx_hash.as_ref().map(|h| h.as_slice()),
+            row.block_hash.as_ref().map(|h| h.as_slice()),
+            row.indexed_at,
+            raw_log.map(|raw| blob::encode(raw, compress)),
+        ])?;
     Ok(inserted > 0)
 }
 
+/// Messages stored with their raw log and an id above `after_id`, in id order, as
+/// `(id, row, raw_log)`.
+pub fn messages_with_raw_log(
+    conn: &Connection,
+    after_id: i64,
+    limit: i64,
+) -> Result<Vec<(i64, MessageRow, Vec<u8>)>> {
+    let mut stmt = conn.prepare(
+        "SELECT topic, seq, sender, ciphertext, timestamp, nonce, block_number, log_index, block_timestamp, tx_hash, block_hash, indexed_at, id, raw_log
+         FROM messages WHERE id > ?1 AND raw_log IS NOT NULL ORDER BY id LIMIT ?2",
+    )?;
+    let rows = stmt
+        .query_map(params![after_id, limit], |row| {
+            Ok((row.get(12)?, map_message(row)?, get_blob(row, 13)?))
+        })?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+    Ok(rows)
+}
+
+pub fn handshakes_with_raw_log(
+    conn: &Connection,
+    after_id: i64,
+    limit: i64,
+) -> Result<Vec<(i64, HandshakeRow, Vec<u8>)>> {
+    let mut stmt = conn.prepare(
+        "SELECT recipient_hash, seq, sender, pub_keys, ephemeral_pub_key, plaintext_payload,
+                block_number, log_index, block_timestamp, tx_hash, block_hash, indexed_at, id, raw_log
+         FROM handshakes WHERE id > ?1 AND raw_log IS NOT NULL ORDER BY id LIMIT ?2",
+    )?;
+    let rows = stmt
+        .query_map(params![after_id, limit], |row| {
+            Ok((row.get(12)?, map_handshake(row)?, get_blob(row, 13)?))
+        })?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+    Ok(rows)
+}
+
+pub fn hsrs_with_raw_log(
+    conn: &Connection,
+    after_id: i64,
+    limit: i64,
+) -> Result<Vec<(i64, HsrRow, Vec<u8>)>> {
+    let mut stmt = conn.prepare(
+        "SELECT global_seq, in_response_to, responder, responder_ephemeral_r, ciphertext,
+                block_number, log_index, block_timestamp, tx_hash, block_hash, indexed_at, id, raw_log
+         FROM handshake_responses WHERE id > ?1 AND raw_log IS NOT NULL ORDER BY id LIMIT ?2",
+    )?;
+    let rows = stmt
+        .query_map(params![after_id, limit], |row| {
+            Ok((row.get(11)?, map_hsr(row)?, get_blob(row, 12)?))
+        })?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+    Ok(rows)
+}
+
+/// Overwrite a message's decoded fields. Its topic, seq and chain position stay as they are.
+pub fn update_message_fields(
+    conn: &Connection,
+    id: i64,
+    row: &MessageRow,
+    compress: bool,
+) -> Result<()> {
+    conn.prepare_cached(
+        "UPDATE messages SET sender = ?2, ciphertext = ?3, timestamp = ?4, nonce = ?5 WHERE id = ?1",
+    )?
+    .execute(params![
+        id,
+        row.sender,
+        blob::encode(&row.ciphertext, compress),
+        row.timestamp,
+        row.nonce,
+    ])?;
+    Ok(())
+}
+
+/// Overwrite a handshake's decoded fields, keeping its recipient hash and seq.
+pub fn update_handshake_fields(
+    conn: &Connection,
+    id: i64,
+    row: &HandshakeRow,
+    compress: bool,
+) -> Result<()> {
+    conn.prepare_cached(
+        "UPDATE handshakes SET sender = ?2, pub_keys = ?3, ephemeral_pub_key = ?4, plaintext_payload = ?5
+         WHERE id = ?1",
+    )?
+    .execute(params![
+        id,
+        row.sender,
+        blob::encode(&row.pub_keys, compress),
+        &row.ephemeral_pub_key,
+        blob::encode(&row.plaintext_payload, compress),
+    ])?;
+    Ok(())
+}
+
+/// Overwrite a handshake response's decoded fields, keeping its global seq.
+pub fn update_hsr_fields(conn: &Connection, id: i64, row: &HsrRow, compress: bool) -> Result<()> {
+    conn.prepare_cached(
+        "UPDATE handshake_responses SET in_response_to = ?2, responder = ?3, responder_ephemeral_r = ?4, ciphertext = ?5
+         WHERE id = ?1",
+    )?
+    .execute(params![
+        id,
+        row.in_response_to.as_slice(),
+        row.responder,
+        row.responder_ephemeral_r,
+        blob::encode(&row.ciphertext, compress),
+    ])?;
+    Ok(())
+}
+
+/// Read a blob column written through `blob::encode`.
+fn get_blob(row: &rusqlite::Row, idx: usize) -> rusqlite::Result<Vec<u8>> {
+    blob::decode(row.get(idx)?).map_err(|e| {
+        rusqlite::Error::FromSqlConversionFailure(idx, rusqlite::types::Type::Blob, Box::new(e))
+    })
+}
+
+fn map_message(row: &rusqlite::Row) -> rusqlite::Result<MessageRow> {
+    Ok(MessageRow {
+        topic: row.get(0)?,
+        seq: row.get(1)?,
+        sender: row.get(2)?,
+        ciphertext: get_blob(row, 3)?,
+        timestamp: row.get(4)?,
+        nonce: row.get(5)?,
+        block_number: row.get(6)?,
+        log_index: row.get(7)?,
+        block_timestamp: row.get(8)?,
+        tx_hash: row.get(9)?,
+        block_hash: row.get(10)?,
+        indexed_at: row.get(11)?,
+    })
+}
+
+/// Messages for a topic with `after_seq < seq < before_seq` (either bound optional), in
+/// ascending `seq` order or, if `descending`, newest first. `idx_msg_topic_seq` is walked
+/// in either direction without a sort, and seqs are unique per topic, so pages are stable.
+/// `sender` optionally narrows the result to a single sender within the topic.
+pub fn messages_by_topic(
+    conn: &Connection,
+    topic: &TopicHash,
+    sender: Option<&SenderAddress>,
+    after_seq: Option<i64>,
+    before_seq: Option<i64>,
+    descending: bool,
+    limit: i64,
+) -> Result<Vec<MessageRow>> {
+    let direction = if descending { "DESC" } else { "ASC" };
+    let mut stmt = conn.prepare(&format!(
+        "SELECT topic, seq, sender, ciphertext, timestamp, nonce, block_number, log_index, block_timestamp, tx_hash, block_hash, indexed_at
+         FROM messages WHERE topic = ?1 AND seq > ?2 AND seq < ?3 AND (?4 IS NULL OR sender = ?4)
+         ORDER BY seq {direction} LIMIT ?5"
+    ))?;
+    let rows = stmt
+        .query_map(
+            params![
+                topic,
+                after_seq.unwrap_or(-1),
+                before_seq.unwrap_or(i64::MAX),
+                sender,
+                limit
+            ],
+            map_message,
+        )?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+    Ok(rows)
+}
+
+/// Messages of a topic with `from_seq <= seq <= to_seq`, in seq order.
+pub fn messages_in_seq_range(
+    conn: &Connection,
+    topic: &TopicHash,
+    from_seq: i64,
+    to_seq: i64,
+) -> Result<Vec<MessageRow>> {
+    let mut stmt = conn.prepare(
+        "SELECT topic, seq, sender, ciphertext, timestamp, nonce, block_number, log_index, block_timestamp, tx_hash, block_hash, indexed_at
+         FROM messages WHERE topic = ?1 AND seq BETWEEN ?2 AND ?3
+         ORDER BY seq",
+    )?;
+    let rows = stmt
+        .query_map(params![topic, from_seq, to_seq], map_message)?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+    Ok(rows)
+}
+
+/// Messages of every topic with `from_ts <= block_timestamp <= to_ts`, oldest first and
+/// in on-chain order within a timestamp, read through `idx_msg_block_timestamp`.
+pub fn messages_in_time_range(
+    conn: &Connection,
+    from_ts: i64,
+    to_ts: i64,
+    limit: i64,
+) -> Result<Vec<MessageRow>> {
+    let mut stmt = conn.prepare(
+        "SELECT topic, seq, sender, ciphertext, timestamp, nonce, block_number, log_index, block_timestamp, tx_hash, block_hash, indexed_at
+         FROM messages WHERE block_timestamp BETWEEN ?1 AND ?2
+         ORDER BY block_timestamp, block_number, log_index LIMIT ?3",
+    )?;
+    let rows = stmt
+        .query_map(params![from_ts, to_ts, limit], map_message)?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+    Ok(rows)
+}
+
+/// The `limit` topics with the most messages at or after `since` (a `block_timestamp`),
+/// busiest first, with their counts. Only the window is read from `idx_msg_block_timestamp`.
+pub fn busiest_topics_since(
+    conn: &Connection,
+    since: i64,
+    limit: i64,
+) -> Result<Vec<(TopicHash, i64)>> {
+    let mut stmt = conn.prepare(
+        "SELECT topic, COUNT(*) AS recent FROM messages WHERE block_timestamp >= ?1
+         GROUP BY topic ORDER BY recent DESC, topic LIMIT ?2",
+    )?;
+    let rows = stmt
+        .query_map(params![since, limit], |row| Ok((row.get(0)?, row.get(1)?)))?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+    Ok(rows)
+}
+
+/// The lowest-seq message of a topic: seq 0 unless the start of the conversation has not
+/// been indexed.
+pub fn first_message_for_topic(conn: &Connection, topic: &TopicHash) -> Result<Option<MessageRow>> {
+    let row = conn
+        .prepare_cached(
+            "SELECT topic, seq, sender, ciphertext, timestamp, nonce, block_number, log_index, block_timestamp, tx_hash, block_hash, indexed_at
+             FROM messages WHERE topic = ?1 ORDER BY seq LIMIT 1",
+        )?
+        .query_row(params![topic], map_message)
+        .optional()?;
+    Ok(row)
+}
+
+/// Whether every seq in `[from_seq, to_seq]` is indexed for the topic. `(topic, seq)` is
+/// unique, so a row count equal to the span means there are no gaps.
+pub fn seq_range_complete(
+    conn: &Connection,
+    topic: &TopicHash,
+    from_seq: i64,
+    to_seq: i64,
+) -> Result<bool> {
+    let count: i64 = conn
+        .prepare_cached("SELECT COUNT(*) FROM messages WHERE topic = ?1 AND seq BETWEEN ?2 AND ?3")?
+        .query_row(params![topic, from_seq, to_seq], |row| row.get(0))?;
+    Ok(count == to_seq - from_seq + 1)
+}
+
+/// Number of messages indexed for a topic and its highest seq, both read from
+/// `idx_msg_topic_seq`. An unknown topic gives `(0, None)`.
+pub fn count_messages_for_topic(
+    conn: &Connection,
+    topic: &TopicHash,
+) -> Result<(i64, Option<i64>)> {
+    Ok(conn
+        .prepare_cached("SELECT COUNT(*), MAX(seq) FROM messages WHERE topic = ?1")?
+        .query_row(params![topic], |row| Ok((row.get(0)?, row.get(1)?)))?)
+}
+
+/// Messages from a sender across all topics in on-chain order, starting after the
+/// `(block_number, log_index)` cursor if given.
+pub fn messages_by_sender(
+    conn: &Connection,
+    sender: &SenderAddress,
+    after: Option<(i64, i64)>,
+    limit: i64,
+) -> Result<Vec<MessageRow>> {
+    let (after_block, after_log_index) = after.unwrap_or((-1, -1));
+    let mut stmt = conn.prepare(
+        "SELECT topic, seq, sender, ciphertext, timestamp, nonce, block_number, log_index, block_timestamp, tx_hash, block_hash, indexed_at
+         FROM messages WHERE sender = ?1 AND (block_number, log_index) > (?2, ?3)
+         ORDER BY block_number, log_index LIMIT ?4",
+    )?;
+    let rows = stmt
+        .query_map(
+            params![sender, after_block, after_log_index, limit],
+            map_message,
+        )?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+    Ok(rows)
+}
+
+/// Messages matching every set filter in `search`, in on-chain order.
+/// Timestamp bounds apply to `block_timestamp`; all bounds are inclusive.
+pub fn search_messages(
+    conn: &Connection,
+    search: &MessageSearch,
+    limit: i64,
+) -> Result<Vec<MessageRow>> {
+    let mut clauses: Vec<&str> = Vec::new();
+    let mut values: Vec<Value> = Vec::new();
+
+    if let Some(topic) = search.topic {
+        clauses.push("topic = ?");
+        values.push(Value::Blob(topic.0.to_vec()));
+    }
+    if let Some(sender) = search.sender {
+        clauses.push("sender = ?");
+        values.push(Value::Blob(sender.0.to_vec()));
+    }
+    if let Some(ts) = search.min_timestamp {
+        clauses.push("block_timestamp >= ?");
+        values.push(Value::Integer(ts));
+    }
+    if let Some(ts) = search.max_timestamp {
+        clauses.push("block_timestamp <= ?");
+        values.push(Value::Integer(ts));
+    }
+    if let Some(nonce) = search.min_nonce {
+        clauses.push("nonce >= ?");
+        values.push(Value::Integer(nonce));
+    }
+    if let Some(nonce) = search.max_nonce {
+        clauses.push("nonce <= ?");
+        values.push(Value::Integer(nonce));
+    }
+    values.push(Value::Integer(limit));
+
+    let where_clause = if clauses.is_empty() {
+        String::new()
+    } else {
+        format!("WHERE {}", clauses.join(" AND "))
+    };
+    let sql = format!(
+        "SELECT topic, seq, sender, ciphertext, timestamp, nonce, block_number, log_index, block_timestamp, tx_hash, block_hash, indexed_at
+         FROM messages {where_clause} ORDER BY block_number, log_index LIMIT ?"
+    );
+
+    let mut stmt = conn.prepare(&sql)?;
+    let rows = stmt
+        .query_map(params_from_iter(values), map_message)?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+    Ok(rows)
+}
+
+/// The highest-`seq` message of each topic, for inbox views. Topics without messages are omitted.
+pub fn latest_per_topic(conn: &Connection, topics: &[TopicHash]) -> Result<Vec<MessageRow>> {
+    if topics.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    // The subquery is a single seek on the (topic, seq) primary key per topic
+    let placeholders = vec!["?"; topics.len()].join(", ");
+    let sql = format!(
+        "SELECT topic, seq, sender, ciphertext, timestamp, nonce, block_number, log_index, block_timestamp, tx_hash, block_hash, indexed_at
+         FROM messages m WHERE topic IN ({placeholders})
+           AND seq = (SELECT MAX(seq) FROM messages WHERE topic = m.topic)"
+    );
+
+    let mut stmt = conn.prepare(&sql)?;
+    let rows = stmt
+        .query_map(params_from_iter(topics), map_message)?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+    Ok(rows)
+}
+
+/// Messages at the given `(topic, seq)` coordinates, ordered by topic then seq. Coordinates
+/// with no message are omitted and duplicates are returned once.
+pub fn get_messages_by_coords(
+    conn: &Connection,
+    coords: &[(TopicHash, i64)],
+) -> Result<Vec<MessageRow>> {
+    if coords.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    // A row-value IN list is one seek on the unique (topic, seq) index per coordinate
+    let placeholders = vec!["(?, ?)"; coords.len()].join(", ");
+    let sql = format!(
+        "SELECT topic, seq, sender, ciphertext, timestamp, nonce, block_number, log_index, block_timestamp, tx_hash, block_hash, indexed_at
+         FROM messages WHERE (topic, seq) IN (VALUES {placeholders})
+         ORDER BY topic, seq"
+    );
+
+    let values = coords
+        .iter()
+        .flat_map(|(topic, seq)| [Value::Blob(topic.0.to_vec()), Value::Integer(*seq)]);
+    let mut stmt = conn.prepare(&sql)?;
+    let rows = stmt
+        .query_map(params_from_iter(values), map_message)?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+    Ok(rows)
+}
+
+/// Known topics with their message counts, most recently active first.
+pub fn distinct_topics(conn: &Connection, limit: i64, offset: i64) -> Result<Vec<TopicSummary>> {
+    let mut stmt = conn.prepare(
+        "SELECT topic, message_count, last_block_timestamp FROM topics
+         ORDER BY last_block_timestamp DESC, topic LIMIT ?1 OFFSET ?2",
+    )?;
+    let rows = stmt
+        .query_map(params![limit, offset], |row| {
+            Ok(TopicSummary {
+                topic: row.get(0)?,
+                message_count: row.get(1)?,
+                last_block_timestamp: row.get(2)?,
+            })
+        })?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+    Ok(rows)
+}
+
+/// Scan a topic in seq order for missing seq values and for seqs that were assigned
+/// out of on-chain order, e.g. after a reorg or an interrupted reprocess.
+pub fn verify_topic(conn: &Connection, topic: &TopicHash) -> Result<TopicConsistency> {
+    let mut stmt = conn.prepare(
+        "SELECT seq, block_number, log_index FROM messages WHERE topic = ?1 ORDER BY seq",
+    )?;
+    let mut rows = stmt.query(params![topic])?;
+
+    let mut report = TopicConsistency::default();
+    let mut previous: Option<(i64, (i64, i64))> = None;
+    while let Some(row) = rows.next()? {
+        let seq: i64 = row.get(0)?;
+        let position: (i64, i64) = (row.get(1)?, row.get(2)?);
+
+        let expected = previous.map_or(0, |(prev_seq, _)| prev_seq + 1);
+        if seq > expected {
+            report.missing_seqs.push((expected, seq - 1));
+        }
+        if let Some((prev_seq, prev_position)) = previous {
+            if position < prev_position {
+                report.ordering_inversions.push((prev_seq, seq));
+            }
+        }
+
+        report.message_count += 1;
+        report.max_seq = Some(seq);
+        previous = Some((seq, position));
+    }
+
+    Ok(report)
+}
+
+fn map_handshake(row: &rusqlite::Row) -> rusqlite::Result<HandshakeRow> {
+    Ok(HandshakeRow {
+        recipient_hash: row.get(0)?,
+        seq: row.get(1)?,
+        sender: row.get(2)?,
+        pub_keys: get_blob(row, 3)?,
+        ephemeral_pub_key: row.get(4)?,
+        plaintext_payload: get_blob(row, 5)?,
+        block_number: row.get(6)?,
+        log_index: row.get(7)?,
+        block_timestamp: row.get(8)?,
+        tx_hash: row.get(9)?,
+        block_hash: row.get(10)?,
+        indexed_at: row.get(11)?,
+    })
+}
+
+fn map_hsr(row: &rusqlite::Row) -> rusqlite::Result<HsrRow> {
+    Ok(HsrRow {
+        global_seq: row.get(0)?,
+        in_response_to: row.get(1)?,
+        responder: row.get(2)?,
+        responder_ephemeral_r: row.get(3)?,
+        ciphertext: get_blob(row, 4)?,
+        block_number: row.get(5)?,
+        log_index: row.get(6)?,
+        block_timestamp: row.get(7)?,
+        tx_hash: row.get(8)?,
+        block_hash: row.get(9)?,
+        indexed_at: row.get(10)?,
+    })
+}
+
+pub fn get_handshake(
+    conn: &Connection,
+    recipient_hash: &RecipientHash,
+    seq: i64,
+) -> Result<Option<HandshakeRow>> {
+    let row = conn
+        .query_row(
+            "SELECT recipient_hash, seq, sender, pub_keys, ephemeral_pub_key, plaintext_payload,
+                    block_number, log_index, block_timestamp, tx_hash, block_hash, indexed_at
+             FROM handshakes WHERE recipient_hash = ?1 AND seq = ?2",
+            params![recipient_hash, seq],
+            map_handshake,
+        )
+        .optional()?;
+    Ok(row)
+}
+
+/// Add a handshake's payload to `handshakes_fts` under its row id. Payloads are opaque
+/// bytes to the protocol; only valid, non-blank UTF-8 is indexed. Returns whether it was.
+pub fn index_handshake_payload(conn: &Connection, id: i64, payload: &[u8]) -> Result<bool> {
+    let Some(text) = std::str::from_utf8(payload)
+        .ok()
+        .filter(|t| !t.trim().is_empty())
+    else {
+        return Ok(false);
+    };
+    conn.prepare_cached("INSERT OR REPLACE INTO handshakes_fts (rowid, payload) VALUES (?1, ?2)")?
+        .execute(params![id, text])?;
+    Ok(true)
+}
+
+/// Drop a handshake's payload from `handshakes_fts`, e.g. before indexing a rewritten one.
+pub fn unindex_handshake_payload(conn: &Connection, id: i64) -> Result<()> {
+    conn.prepare_cached("DELETE FROM handshakes_fts WHERE rowid = ?1")?
+        .execute(params![id])?;
+    Ok(())
+}
+
+/// Index handshakes stored since the FTS table last saw a row, e.g. while `ENABLE_FTS`
+/// was off. Ids only grow, so everything above the highest indexed id is new.
+pub fn index_missing_handshake_payloads(conn: &Connection) -> Result<u64> {
+    let mut stmt = conn.prepare(
+        "SELECT id, plaintext_payload FROM handshakes
+         WHERE id > (SELECT COALESCE(MAX(rowid), 0) FROM handshakes_fts)
+         ORDER BY id",
+    )?;
+    let rows = stmt
+        .query_map([], |row| Ok((row.get::<_, i64>(0)?, get_blob(row, 1)?)))?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+
+    let mut indexed = 0;
+    for (id, payload) in rows {
+        if index_handshake_payload(conn, id, &payload)? {
+            indexed += 1;
+        }
+    }
+    Ok(indexed)
+}
+
+/// Handshakes whose payload contains every whitespace-separated term of `terms`, best
+/// match first. Terms are quoted, so FTS5 operators in user input match literally.
+pub fn search_handshakes(conn: &Connection, terms: &str, limit: i64) -> Result<Vec<HandshakeRow>> {
+    let query = terms
+        .split_whitespace()
+        .map(|term| format!("\"{}\"", term.replace('"', "\"\"")))
+        .collect::<Vec<_>>()
+        .join(" ");
+    let mut stmt = conn.prepare(
+        "SELECT h.recipient_hash, h.seq, h.sender, h.pub_keys, h.ephemeral_pub_key, h.plaintext_payload,
+                h.block_number, h.log_index, h.block_timestamp, h.tx_hash, h.block_hash, h.indexed_at
+         FROM handshakes_fts JOIN handshakes h ON h.id = handshakes_fts.rowid
+         WHERE handshakes_fts MATCH ?1
+         ORDER BY rank LIMIT ?2",
+    )?;
+    let rows = stmt
+        .query_map(params![query, limit], map_handshake)?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+    Ok(rows)
+}
+
+#[cfg_attr(not(feature = "grpc"), allow(dead_code))]
+pub fn handshakes_by_recipient(
+    conn: &Connection,
+    recipient_hash: &RecipientHash,
+    after_seq: Option<i64>,
+    limit: i64,
+) -> Result<Vec<HandshakeRow>> {
+    let mut stmt = conn.prepare(
+        "SELECT recipient_hash, seq, sender, pub_keys, ephemeral_pub_key, plaintext_payload,
+                block_number, log_index, block_timestamp, tx_hash, block_hash, indexed_at
+         FROM handshakes WHERE recipient_hash = ?1 AND seq > ?2
+         ORDER BY seq LIMIT ?3",
+    )?;
+    let rows = stmt
+        .query_map(
+            params![recipient_hash, after_seq.unwrap_or(-1), limit],
+            map_handshake,
+        )?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+    Ok(rows)
+}
+
+pub fn hsrs_in_response_to(conn: &Connection, tag: &[u8; 32]) -> Result<Vec<HsrRow>> {
+    let mut stmt = conn.prepare(
+        "SELECT global_seq, in_response_to, responder, responder_ephemeral_r, ciphertext,
+                block_number, log_index, block_timestamp, tx_hash, block_hash, indexed_at
+         FROM handshake_responses WHERE in_response_to = ?1 ORDER BY global_seq",
+    )?;
+    let rows = stmt
+        .query_map(params![tag.as_slice()], map_hsr)?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+    Ok(rows)
+}
+
+/// Responses sent by `responder` in ascending `global_seq` order, starting after
+/// `after_seq` if given.
+pub fn hsrs_by_responder(
+    conn: &Connection,
+    responder: &SenderAddress,
+    after_seq: Option<i64>,
+    limit: i64,
+) -> Result<Vec<HsrRow>> {
+    let mut stmt = conn.prepare(
+        "SELECT global_seq, in_response_to, responder, responder_ephemeral_r, ciphertext,
+                block_number, log_index, block_timestamp, tx_hash, block_hash, indexed_at
+         FROM handshake_responses WHERE responder = ?1 AND global_seq > ?2
+         ORDER BY global_seq LIMIT ?3",
+    )?;
+    let rows = stmt
+        .query_map(params![responder, after_seq.unwrap_or(-1), limit], map_hsr)?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+    Ok(rows)
+}
+
+/// Fetch a handshake together with the responses carrying `tag` as `in_response_to`.
+///
+/// The `inResponseTo` tag is derived from ECDH + KEM secrets, so it cannot be computed
+/// from the handshake row; callers supply the tag they matched client-side. The join is
+/// evaluated at query time, so responses indexed before their handshake are still returned.
+pub fn get_handshake_with_responses(
+    conn: &Connection,
+    recipient_hash: &RecipientHash,
+    seq: i64,
+    tag: Option<&[u8; 32]>,
+) -> Result<Option<(HandshakeRow, Vec<HsrRow>)>> {
+    let Some(handshake) = get_handshake(conn, recipient_hash, seq)? else {
+        return Ok(None);
+    };
+
+    let responses = match tag {
+        Some(tag) => hsrs_in_response_to(conn, tag)?,
+        None => Vec::new(),
+    };
+
+    Ok(Some((handshake, responses)))
+}
+
+/// Stream every message at or after `from_block` in on-chain order into `f`,
+/// stopping early when `f` returns false.
+pub fn for_each_message(
+    conn: &Connection,
+    from_block: i64,
+    mut f: impl FnMut(MessageRow) -> bool,
+) -> Result<()> {
+    let mut stmt = conn.prepare(
+        "SELECT topic, seq, sender, ciphertext, timestamp, nonce, block_number, log_index, block_timestamp, tx_hash, block_hash, indexed_at
+         FROM messages WHERE block_number >= ?1 ORDER BY block_number, log_index",
+    )?;
+    for row in stmt.query_map(params![from_block], map_message)? {
+        if !f(row?) {
+            break;
+        }
+    }
+    Ok(())
+}
+
+pub fn for_each_handshake(
+    conn: &Connection,
+    from_block: i64,
+    mut f: impl FnMut(HandshakeRow) -> bool,
+) -> Result<()> {
+    let mut stmt = conn.prepare(
+        "SELECT recipient_hash, seq, sender, pub_keys, ephemeral_pub_key, plaintext_payload,
+                block_number, log_index, block_timestamp, tx_hash, block_hash, indexed_at
+         FROM handshakes WHERE block_number >= ?1 ORDER BY block_number, log_index",
+    )?;
+    for row in stmt.query_map(params![from_block], map_handshake)? {
+        if !f(row?) {
+            break;
+        }
+    }
+    Ok(())
+}
+
+pub fn for_each_hsr(
+    conn: &Connection,
+    from_block: i64,
+    mut f: impl FnMut(HsrRow) -> bool,
+) -> Result<()> {
+    let mut stmt = conn.prepare(
+        "SELECT global_seq, in_response_to, responder, responder_ephemeral_r, ciphertext,
+                block_number, log_index, block_timestamp, tx_hash, block_hash, indexed_at
+         FROM handshake_responses WHERE block_number >= ?1 ORDER BY block_number, log_index",
+    )?;
+    for row in stmt.query_map(params![from_block], map_hsr)? {
+        if !f(row?) {
+            break;
+        }
+    }
+    Ok(())
+}
+
+/// Events of every type after the `(block_number, log_index)` cursor, merged in on-chain
+/// order. The key is unique per chain, so it orders events across tables without ties.
+pub fn feed_events(
+    conn: &Connection,
+    after: Option<(i64, i64)>,
+    limit: i64,
+) -> Result<Vec<FeedEvent>> {
+    let (after_block, after_log_index) = after.unwrap_or((-1, -1));
+    // Both reads below must see the same snapshot for the rows to line up with the keys
+    let tx = conn.unchecked_transaction()?;
+
+    // The page's keys across all tables; each arm walks its unique (block_number, log_index)
+    // index, so SQLite merges the arms instead of sorting them
+    let keys = tx
+        .prepare(
+            "SELECT 0 AS kind, block_number, log_index FROM messages
+                 WHERE (block_number, log_index) > (?1, ?2)
+             UNION ALL
+             SELECT 1, block_number, log_index FROM handshakes
+                 WHERE (block_number, log_index) > (?1, ?2)
+             UNION ALL
+             SELECT 2, block_number, log_index FROM handshake_responses
+                 WHERE (block_number, log_index) > (?1, ?2)
+             ORDER BY block_number, log_index LIMIT ?3",
+        )?
+        .query_map(params![after_block, after_log_index, limit], |row| {
+            Ok((
+                row.get::<_, u8>(0)?,
+                row.get::<_, i64>(1)?,
+                row.get::<_, i64>(2)?,
+            ))
+        })?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+    let Some(&(_, last_block, last_log_index)) = keys.last() else {
+        return Ok(Vec::new());
+    };
+
+    // Then each table's rows within the page, which come back in key order
+    let page = params![after_block, after_log_index, last_block, last_log_index];
+    let mut messages = tx
+        .prepare(
+            "SELECT topic, seq, sender, ciphertext, timestamp, nonce, block_number, log_index, block_timestamp, tx_hash, block_hash, indexed_at
+             FROM messages WHERE (block_number, log_index) > (?1, ?2)
+                 AND (block_number, log_index) <= (?3, ?4)
+             ORDER BY block_number, log_index",
+        )?
+        .query_map(page, map_message)?
+        .collect::<rusqlite::Result<Vec<_>>>()?
+        .into_iter();
+    let mut handshakes = tx
+        .prepare(
+            "SELECT recipient_hash, seq, sender, pub_keys, ephemeral_pub_key, plaintext_payload,
+                    block_number, log_index, block_timestamp, tx_hash, block_hash, indexed_at
+             FROM handshakes WHERE (block_number, log_index) > (?1, ?2)
+                 AND (block_number, log_index) <= (?3, ?4)
+             ORDER BY block_number, log_index",
+        )?
+        .query_map(page, map_handshake)?
+        .collect::<rusqlite::Result<Vec<_>>>()?
+        .into_iter();
+    let mut hsrs = tx
+        .prepare(
+            "SELECT global_seq, in_response_to, responder, responder_ephemeral_r, ciphertext,
+                    block_number, log_index, block_timestamp, tx_hash, block_hash, indexed_at
+             FROM handshake_responses WHERE (block_number, log_index) > (?1, ?2)
+                 AND (block_number, log_index) <= (?3, ?4)
+             ORDER BY block_number, log_index",
+        )?
+        .query_map(page, map_hsr)?
+        .collect::<rusqlite::Result<Vec<_>>>()?
+        .into_iter();
+
+    let events = keys
+        .into_iter()
+        .filter_map(|(kind, _, _)| match kind {
+            0 => messages.next().map(FeedEvent::Message),
+            1 => handshakes.next().map(FeedEvent::Handshake),
+            _ => hsrs.next().map(FeedEvent::HandshakeResponse),
+        })
+        .collect();
+    Ok(events)
+}
+
+/// Distinct `(block_number, block_hash)` of events indexed at or above `from_block`,
+/// in block order. Rows stored without a block hash are skipped.
+pub fn indexed_block_hashes(conn: &Connection, from_block: i64) -> Result<Vec<(i64, [u8; 32])>> {
+    let mut stmt = conn.prepare(
+        "SELECT DISTINCT block_number, block_hash FROM (
+             SELECT block_number, block_hash FROM messages WHERE block_number >= ?1
+             UNION ALL
+             SELECT block_number, block_hash FROM handshakes WHERE block_number >= ?1
+             UNION ALL
+             SELECT block_number, block_hash FROM handshake_responses WHERE block_number >= ?1
+         ) WHERE block_hash IS NOT NULL ORDER BY block_number",
+    )?;
+    let rows = stmt
+        .query_map(params![from_block], |row| Ok((row.get(0)?, row.get(1)?)))?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+    Ok(rows)
+}
+
+/// `(block_number, log_index, block_hash)` of an indexed event.
+pub type IndexedLog = (i64, i64, Option<[u8; 32]>);
+
+/// Every event indexed in `from_block..=to_block`, newest first, which is the order
+/// `delete_log` needs to roll seq counters back cleanly.
+pub fn indexed_logs_in_range(
+    conn: &Connection,
+    from_block: i64,
+    to_block: i64,
+) -> Result<Vec<IndexedLog>> {
+    let mut stmt = conn.prepare(
+        "SELECT block_number, log_index, block_hash FROM messages
+         WHERE block_number BETWEEN ?1 AND ?2
+         UNION ALL
+         SELECT block_number, log_index, block_hash FROM handshakes
+         WHERE block_number BETWEEN ?1 AND ?2
+         UNION ALL
+         SELECT block_number, log_index, block_hash FROM handshake_responses
+         WHERE block_number BETWEEN ?1 AND ?2
+         ORDER BY 1 DESC, 2 DESC",
+    )?;
+    let rows = stmt
+        .query_map(params![from_block, to_block], |row| {
+            Ok((row.get(0)?, row.get(1)?, row.get(2)?))
+        })?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+    Ok(rows)
+}
+
+/// `(block_number, log_index)` of the dead letters in `from_block..=to_block`.
+pub fn dead_lettered_logs_in_range(
+    conn: &Connection,
+    from_block: i64,
+    to_block: i64,
+) -> Result<Vec<(i64, i64)>> {
+    let mut stmt = conn.prepare(
+        "SELECT block_number, log_index FROM dead_letters WHERE block_number BETWEEN ?1 AND ?2",
+    )?;
+    let rows = stmt
+        .query_map(params![from_block, to_block], |row| {
+            Ok((row.get(0)?, row.get(1)?))
+        })?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+    Ok(rows)
+}
+
 pub fn get_last_processed_block(conn: &Connection) -> Result<Option<i64>> {
     let value = conn
         .query_row(
@@ -123,13 +1202,100 @@ pub fn set_last_processed_block(conn: &Connection, block: i64) -> Result<()> {
     Ok(())
 }
 
+/// Creation block resolved on an earlier start. `key` is the `CREATION_TX_HASH`, or the
+/// contract address when the block was detected, so changing either resolves it afresh.
+pub fn get_cached_creation_block(conn: &Connection, key: &str) -> Result<Option<u64>> {
+    let value = conn
+        .query_row(
+            "SELECT value FROM indexer_state WHERE key = ?1",
+            params![format!("creation_block:{key}")],
+            |row| row.get::<_, String>(0),
+        )
+        .optional()?
+        .and_then(|v| v.parse::<u64>().ok());
+
+    Ok(value)
+}
+
+pub fn cache_creation_block(conn: &Connection, key: &str, block: u64) -> Result<()> {
+    conn.execute(
+        "INSERT OR REPLACE INTO indexer_state (key, value) VALUES (?1, ?2)",
+        params![format!("creation_block:{key}"), block.to_string()],
+    )?;
+    Ok(())
+}
+
+/// First block of the initial backfill when `MAX_BACKFILL_BLOCKS` cut it short, if it did.
+pub fn get_history_start_block(conn: &Connection) -> Result<Option<i64>> {
+    let value = conn
+        .query_row(
+            "SELECT value FROM indexer_state WHERE key = 'history_start_block'",
+            [],
+            |row| row.get::<_, String>(0),
+        )
+        .optional()?
+        .and_then(|v| v.parse::<i64>().ok());
+
+    Ok(value)
+}
+
+/// `None` clears it, for an empty DB resynced with the full range.
+pub fn set_history_start_block(conn: &Connection, block: Option<i64>) -> Result<()> {
+    match block {
+        Some(block) => conn.execute(
+            "INSERT OR REPLACE INTO indexer_state (key, value) VALUES ('history_start_block', ?1)",
+            params![block.to_string()],
+        )?,
+        None => conn.execute(
+            "DELETE FROM indexer_state WHERE key = 'history_start_block'",
+            [],
+        )?,
+    };
+    Ok(())
+}
+
+/// Last block checked by finality verification, if it has run.
+pub fn get_verified_block(conn: &Connection) -> Result<Option<i64>> {
+    let value = conn
+        .query_row(
+            "SELECT value FROM indexer_state WHERE key = 'verified_block'",
+            [],
+            |row| row.get::<_, String>(0),
+        )
+        .optional()?
+        .and_then(|v| v.parse::<i64>().ok());
+
+    Ok(value)
+}
+
+pub fn set_verified_block(conn: &Connection, block: i64) -> Result<()> {
+    conn.execute(
+        "INSERT OR REPLACE INTO indexer_state (key, value) VALUES ('verified_block', ?1)",
+        params![block.to_string()],
+    )?;
+    Ok(())
+}
+
+/// Like `set_last_processed_block`, but never moves the cursor backwards, so
+/// re-backfilling an older range leaves the live resume point alone.
+pub fn advance_last_processed_block(conn: &Connection, block: i64) -> Result<()> {
+    conn.execute(
+        "INSERT INTO indexer_state (key, value) VALUES ('last_block', ?1)
+         ON CONFLICT(key) DO UPDATE SET value = excluded.value
+         WHERE CAST(excluded.value AS INTEGER) > CAST(indexer_state.value AS INTEGER)",
+        params![block.to_string()],
+    )?;
+    Ok(())
+}
+
 pub fn get_event_counts(conn: &Connection) -> Result<EventCounts> {
-    let messages: i64 =
-        conn.query_row("SELECT COUNT(*) FROM messages", [], |row| row.get(0))?;
+    let messages: i64 = conn.query_row("SELECT COUNT(*) FROM messages", [], |row| row.get(0))?;
     let handshakes: i64 =
         conn.query_row("SELECT COUNT(*) FROM handshakes", [], |row| row.get(0))?;
     let handshake_responses: i64 =
-        conn.query_row("SELECT COUNT(*) FROM handshake_responses", [], |row| row.get(0))?;
+        conn.query_row("SELECT COUNT(*) FROM handshake_responses", [], |row| {
+            row.get(0)
+        })?;
 
     Ok(EventCounts {
         messages,
@@ -138,7 +1304,236 @@ pub fn get_event_counts(conn: &Connection) -> Result<EventCounts> {
     })
 }
 
+/// Each of the distinct counts below is a full scan of a covering index, so cost
+/// grows with the table rather than with the number of distinct values.
+pub fn distinct_sender_count(conn: &Connection) -> Result<i64> {
+    Ok(
+        conn.query_row("SELECT COUNT(DISTINCT sender) FROM messages", [], |row| {
+            row.get(0)
+        })?,
+    )
+}
+
+pub fn distinct_recipient_count(conn: &Connection) -> Result<i64> {
+    Ok(conn.query_row(
+        "SELECT COUNT(DISTINCT recipient_hash) FROM handshakes",
+        [],
+        |row| row.get(0),
+    )?)
+}
+
+pub fn distinct_responder_count(conn: &Connection) -> Result<i64> {
+    Ok(conn.query_row(
+        "SELECT COUNT(DISTINCT responder) FROM handshake_responses",
+        [],
+        |row| row.get(0),
+    )?)
+}
+
 pub fn is_db_empty(conn: &Connection) -> Result<bool> {
     let counts = get_event_counts(conn)?;
     Ok(counts.messages == 0 && counts.handshakes == 0 && counts.handshake_responses == 0)
 }
+
+pub fn insert_dead_letter(
+    conn: &Connection,
+    block_number: i64,
+    log_index: i64,
+    retry_count: i64,
+    last_error: &str,
+    payload: &str,
+) -> Result<i64> {
+    conn.execute(
+        "INSERT INTO dead_letters (block_number, log_index, retry_count, last_error, payload, created_at)
+         VALUES (?1, ?2, ?3, ?4, ?5, strftime('%s', 'now'))",
+        params![block_number, log_index, retry_count, last_error, payload],
+    )?;
+    Ok(conn.last_insert_rowid())
+}
+
+fn map_dead_letter(row: &rusqlite::Row) -> rusqlite::Result<DeadLetterRow> {
+    Ok(DeadLetterRow {
+        id: row.get(0)?,
+        block_number: row.get(1)?,
+        log_index: row.get(2)?,
+        retry_count: row.get(3)?,
+        last_error: row.get(4)?,
+        payload: row.get(5)?,
+        created_at: row.get(6)?,
+    })
+}
+
+pub fn list_dead_letters(conn: &Connection, limit: i64) -> Result<Vec<DeadLetterRow>> {
+    let mut stmt = conn.prepare(
+        "SELECT id, block_number, log_index, retry_count, last_error, payload, created_at
+         FROM dead_letters ORDER BY id LIMIT ?1",
+    )?;
+    let rows = stmt
+        .query_map(params![limit], map_dead_letter)?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+    Ok(rows)
+}
+
+pub fn get_dead_letter(conn: &Connection, id: i64) -> Result<Option<DeadLetterRow>> {
+    let row = conn
+        .query_row(
+            "SELECT id, block_number, log_index, retry_count, last_error, payload, created_at
+             FROM dead_letters WHERE id = ?1",
+            params![id],
+            map_dead_letter,
+        )
+        .optional()?;
+    Ok(row)
+}
+
+pub fn delete_dead_letter(conn: &Connection, id: i64) -> Result<bool> {
+    let deleted = conn.execute("DELETE FROM dead_letters WHERE id = ?1", params![id])?;
+    Ok(deleted > 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::memory_db;
+
+    #[test]
+    fn global_seq_counts_up_from_zero() {
+        let conn = memory_db();
+        let seqs = (0..4)
+            .map(|_| get_and_increment_seq(&conn, "hsr", None).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(seqs, [0, 1, 2, 3]);
+
+        let rows: i64 = conn
+            .query_row(
+                "SELECT COUNT(*) FROM seq_counters WHERE key_type = 'hsr'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(rows, 1);
+    }
+
+    #[test]
+    fn keyed_seqs_count_independently() {
+        let conn = memory_db();
+        let (a, b) = ([1u8; 32], [2u8; 32]);
+        assert_eq!(
+            get_and_increment_seq(&conn, "message", Some(&a)).unwrap(),
+            0
+        );
+        assert_eq!(
+            get_and_increment_seq(&conn, "message", Some(&a)).unwrap(),
+            1
+        );
+        assert_eq!(
+            get_and_increment_seq(&conn, "message", Some(&b)).unwrap(),
+            0
+        );
+        assert_eq!(
+            get_and_increment_seq(&conn, "handshake", Some(&a)).unwrap(),
+            0
+        );
+    }
+
+    #[test]
+    fn rollback_returns_the_latest_global_seq() {
+        let conn = memory_db();
+        for _ in 0..3 {
+            get_and_increment_seq(&conn, "hsr", None).unwrap();
+        }
+        rollback_seq(&conn, "hsr", None, 2).unwrap();
+        assert_eq!(get_and_increment_seq(&conn, "hsr", None).unwrap(), 2);
+    }
+
+    const TOPIC: TopicHash = TopicHash([7; 32]);
+
+    fn insert_messages(conn: &Connection, seqs: std::ops::Range<i64>) {
+        for seq in seqs {
+            let row = MessageRow {
+                topic: TOPIC,
+                seq,
+                sender: SenderAddress([1; 20]),
+                ciphertext: vec![0xab; 8],
+                timestamp: 1_700_000_000,
+                nonce: seq,
+                block_number: 100 + seq,
+                log_index: 0,
+                block_timestamp: 1_700_000_000 + seq,
+                tx_hash: None,
+                block_hash: None,
+                indexed_at: 0,
+            };
+            assert!(insert_message(conn, &row, None, false).unwrap());
+        }
+    }
+
+    /// Seqs of every page of `page_size`, each cursor taken from the previous page's last row.
+    fn pages(conn: &Connection, descending: bool, page_size: i64) -> Vec<Vec<i64>> {
+        let mut pages = Vec::new();
+        let mut cursor = None;
+        loop {
+            let (after, before) = if descending {
+                (None, cursor)
+            } else {
+                (cursor, None)
+            };
+            let page: Vec<i64> =
+                messages_by_topic(conn, &TOPIC, None, after, before, descending, page_size)
+                    .unwrap()
+                    .iter()
+                    .map(|m| m.seq)
+                    .collect();
+            if page.is_empty() {
+                return pages;
+            }
+            cursor = page.last().copied();
+            pages.push(page);
+        }
+    }
+
+    #[test]
+    fn ascending_pages_cover_every_message_once() {
+        let conn = memory_db();
+        insert_messages(&conn, 0..7);
+        assert_eq!(
+            pages(&conn, false, 3),
+            [vec![0, 1, 2], vec![3, 4, 5], vec![6]]
+        );
+
+        // A full last page is followed by an empty one, not a repeat
+        insert_messages(&conn, 7..8);
+        assert_eq!(pages(&conn, false, 4), [vec![0, 1, 2, 3], vec![4, 5, 6, 7]]);
+    }
+
+    #[test]
+    fn descending_pages_cover_every_message_once() {
+        let conn = memory_db();
+        insert_messages(&conn, 0..7);
+        assert_eq!(
+            pages(&conn, true, 3),
+            [vec![6, 5, 4], vec![3, 2, 1], vec![0]]
+        );
+        assert_eq!(pages(&conn, true, 7), [vec![6, 5, 4, 3, 2, 1, 0]]);
+    }
+
+    #[test]
+    fn topic_pages_use_the_index_in_both_directions() {
+        let conn = memory_db();
+        for direction in ["ASC", "DESC"] {
+            let plan: Vec<String> = conn
+                .prepare(&format!(
+                    "EXPLAIN QUERY PLAN SELECT seq FROM messages
+                     WHERE topic = ?1 AND seq > ?2 AND seq < ?3 ORDER BY seq {direction} LIMIT 10"
+                ))
+                .unwrap()
+                .query_map(params![TOPIC, -1, i64::MAX], |row| row.get(3))
+                .unwrap()
+                .collect::<rusqlite::Result<_>>()
+                .unwrap();
+            let plan = plan.join("\n");
+            assert!(plan.contains("idx_msg_topic_seq"), "{direction}: {plan}");
+            assert!(!plan.contains("TEMP B-TREE"), "{direction}: {plan}");
+        }
+    }
+}
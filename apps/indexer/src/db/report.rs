@@ -0,0 +1,193 @@
+//! `--stats`: a summary of an indexer database for operators, without starting the indexer.
+
+use std::path::Path;
+
+use rusqlite::{Connection, OpenFlags, OptionalExtension};
+use serde::Serialize;
+
+use crate::error::{IndexerError, Result};
+
+use super::queries::{get_event_counts, get_last_processed_block};
+use super::schema::current_version;
+
+/// Tables whose rows carry a block position, reported with their ranges.
+const EVENT_TABLES: [&str; 3] = ["messages", "handshakes", "handshake_responses"];
+/// Other tables worth a row count.
+const OTHER_TABLES: [&str; 2] = ["topics", "dead_letters"];
+
+#[derive(Serialize)]
+pub struct DbReport {
+    pub database_path: String,
+    pub schema_version: i64,
+    pub last_block: Option<i64>,
+    pub file_bytes: u64,
+    /// 0 if there is no `-wal` file
+    pub wal_bytes: u64,
+    pub page_size: i64,
+    pub page_count: i64,
+    /// Pages a `VACUUM` would give back
+    pub free_pages: i64,
+    pub tables: Vec<TableReport>,
+}
+
+#[derive(Serialize)]
+pub struct TableReport {
+    pub name: &'static str,
+    pub rows: i64,
+    /// The table and its indexes; `None` if SQLite was built without `dbstat`
+    pub bytes: Option<i64>,
+    pub min_block: Option<i64>,
+    pub max_block: Option<i64>,
+    pub oldest_block_timestamp: Option<i64>,
+    pub newest_block_timestamp: Option<i64>,
+}
+
+/// Open the database read-only and print its report, as JSON if `json` is set.
+pub fn print(database_path: &str, json: bool) -> Result<()> {
+    if !Path::new(database_path).exists() {
+        return Err(IndexerError::Config(format!(
+            "database {database_path} does not exist"
+        )));
+    }
+    let conn = Connection::open_with_flags(
+        database_path,
+        OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX,
+    )?;
+    let report = build(&conn, database_path)?;
+
+    if json {
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&report).expect("report serializes")
+        );
+    } else {
+        print_human(&report);
+    }
+    Ok(())
+}
+
+fn build(conn: &Connection, database_path: &str) -> Result<DbReport> {
+    let counts = get_event_counts(conn)?;
+    let event_rows = [
+        counts.messages,
+        counts.handshakes,
+        counts.handshake_responses,
+    ];
+
+    let mut tables = Vec::new();
+    for (name, rows) in EVENT_TABLES.into_iter().zip(event_rows) {
+        let (min_block, max_block, oldest, newest) = conn.query_row(
+            &format!(
+                "SELECT MIN(block_number), MAX(block_number), MIN(block_timestamp), MAX(block_timestamp)
+                 FROM {name}"
+            ),
+            [],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
+        )?;
+        tables.push(TableReport {
+            name,
+            rows,
+            bytes: table_bytes(conn, name),
+            min_block,
+            max_block,
+            oldest_block_timestamp: oldest,
+            newest_block_timestamp: newest,
+        });
+    }
+    for name in OTHER_TABLES {
+        let rows = conn.query_row(&format!("SELECT COUNT(*) FROM {name}"), [], |row| {
+            row.get(0)
+        })?;
+        tables.push(TableReport {
+            name,
+            rows,
+            bytes: table_bytes(conn, name),
+            min_block: None,
+            max_block: None,
+            oldest_block_timestamp: None,
+            newest_block_timestamp: None,
+        });
+    }
+
+    let pragma = |name: &str| -> Result<i64> {
+        Ok(conn.query_row(&format!("PRAGMA {name}"), [], |row| row.get(0))?)
+    };
+    let file_size = |path: &str| std::fs::metadata(path).map(|m| m.len()).unwrap_or(0);
+
+    Ok(DbReport {
+        database_path: database_path.to_string(),
+        schema_version: current_version(conn)?,
+        last_block: get_last_processed_block(conn)?,
+        file_bytes: file_size(database_path),
+        wal_bytes: file_size(&format!("{database_path}-wal")),
+        page_size: pragma("page_size")?,
+        page_count: pragma("page_count")?,
+        free_pages: pragma("freelist_count")?,
+        tables,
+    })
+}
+
+/// Bytes held by a table and its indexes, from the `dbstat` virtual table.
+fn table_bytes(conn: &Connection, table: &str) -> Option<i64> {
+    conn.query_row(
+        "SELECT SUM(pgsize) FROM dbstat
+         WHERE name IN (SELECT name FROM sqlite_master WHERE tbl_name = ?1)",
+        [table],
+        |row| row.get(0),
+    )
+    .optional()
+    .ok()
+    .flatten()
+}
+
+fn print_human(report: &DbReport) {
+    println!("Database        {}", report.database_path);
+    println!("Schema version  {}", report.schema_version);
+    println!(
+        "Last block      {}",
+        report
+            .last_block
+            .map_or_else(|| "-".to_string(), |b| b.to_string())
+    );
+    println!(
+        "File size       {} (WAL {}, {} of {} pages free)",
+        human_bytes(report.file_bytes as i64),
+        human_bytes(report.wal_bytes as i64),
+        report.free_pages,
+        report.page_count
+    );
+    println!();
+    println!(
+        "{:<20} {:>10} {:>10}  {:<21}  block timestamps",
+        "table", "rows", "size", "blocks"
+    );
+    for table in &report.tables {
+        let range = |min: Option<i64>, max: Option<i64>| match (min, max) {
+            (Some(min), Some(max)) => format!("{min}-{max}"),
+            _ => "-".to_string(),
+        };
+        println!(
+            "{:<20} {:>10} {:>10}  {:<21}  {}",
+            table.name,
+            table.rows,
+            table.bytes.map_or_else(|| "-".to_string(), human_bytes),
+            range(table.min_block, table.max_block),
+            range(table.oldest_block_timestamp, table.newest_block_timestamp),
+        );
+    }
+}
+
+fn human_bytes(bytes: i64) -> String {
+    const UNITS: [&str; 4] = ["B", "KiB", "MiB", "GiB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{bytes} B")
+    } else {
+        format!("{value:.1} {}", UNITS[unit])
+    }
+}
@@ -2,7 +2,156 @@ use rusqlite::Connection;
 
 use crate::error::Result;
 
-const SCHEMA_VERSION: i64 = 1;
+const SCHEMA_VERSION: i64 = 14;
+
+/// Incremental migrations applied on top of the base schema, in order.
+const MIGRATIONS: &[(i64, &str)] = &[
+    (
+        2,
+        r#"
+        CREATE TABLE IF NOT EXISTS dead_letters (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            block_number INTEGER NOT NULL,
+            log_index INTEGER NOT NULL,
+            retry_count INTEGER NOT NULL,
+            last_error TEXT NOT NULL,
+            payload TEXT NOT NULL,
+            created_at INTEGER NOT NULL
+        );
+        "#,
+    ),
+    (
+        3,
+        r#"
+        CREATE INDEX IF NOT EXISTS idx_hsr_in_response_to ON handshake_responses(in_response_to);
+        "#,
+    ),
+    (
+        4,
+        r#"
+        -- (block_number, log_index) is the on-chain identity of a log; seq is assigned locally
+        DELETE FROM messages WHERE id NOT IN
+            (SELECT MIN(id) FROM messages GROUP BY block_number, log_index);
+        DELETE FROM handshakes WHERE id NOT IN
+            (SELECT MIN(id) FROM handshakes GROUP BY block_number, log_index);
+        DELETE FROM handshake_responses WHERE id NOT IN
+            (SELECT MIN(id) FROM handshake_responses GROUP BY block_number, log_index);
+
+        DROP INDEX IF EXISTS idx_msg_block;
+        CREATE UNIQUE INDEX IF NOT EXISTS idx_msg_block_log ON messages(block_number, log_index);
+        CREATE UNIQUE INDEX IF NOT EXISTS idx_hs_block_log ON handshakes(block_number, log_index);
+        CREATE UNIQUE INDEX IF NOT EXISTS idx_hsr_block_log ON handshake_responses(block_number, log_index);
+        "#,
+    ),
+    (
+        5,
+        r#"
+        CREATE INDEX IF NOT EXISTS idx_msg_sender ON messages(sender, block_number, log_index);
+        "#,
+    ),
+    (
+        6,
+        r#"
+        -- NULL for rows indexed before this migration or from logs without a tx hash
+        ALTER TABLE messages ADD COLUMN tx_hash BLOB;
+        ALTER TABLE handshakes ADD COLUMN tx_hash BLOB;
+        ALTER TABLE handshake_responses ADD COLUMN tx_hash BLOB;
+        "#,
+    ),
+    (
+        7,
+        r#"
+        -- Per-topic summary maintained on insert/delete, so listing topics never scans messages
+        CREATE TABLE IF NOT EXISTS topics (
+            topic BLOB PRIMARY KEY,
+            message_count INTEGER NOT NULL,
+            last_block_timestamp INTEGER NOT NULL
+        );
+        INSERT OR IGNORE INTO topics (topic, message_count, last_block_timestamp)
+            SELECT topic, COUNT(*), MAX(block_timestamp) FROM messages GROUP BY topic;
+        CREATE INDEX IF NOT EXISTS idx_topics_activity ON topics(last_block_timestamp DESC, topic);
+        "#,
+    ),
+    (
+        8,
+        r#"
+        -- NULL for rows indexed before this migration; recovery skips them when checking for reorgs
+        ALTER TABLE messages ADD COLUMN block_hash BLOB;
+        ALTER TABLE handshakes ADD COLUMN block_hash BLOB;
+        ALTER TABLE handshake_responses ADD COLUMN block_hash BLOB;
+        "#,
+    ),
+    (
+        9,
+        r#"
+        CREATE INDEX IF NOT EXISTS idx_hsr_responder ON handshake_responses(responder, global_seq);
+        "#,
+    ),
+    (
+        10,
+        r#"
+        -- Insert time is unknown for rows indexed before this migration; they read as 0
+        ALTER TABLE messages ADD COLUMN indexed_at INTEGER NOT NULL DEFAULT 0;
+        ALTER TABLE handshakes ADD COLUMN indexed_at INTEGER NOT NULL DEFAULT 0;
+        ALTER TABLE handshake_responses ADD COLUMN indexed_at INTEGER NOT NULL DEFAULT 0;
+        "#,
+    ),
+    (
+        11,
+        r#"
+        -- Time windows: /messages/by-time, timestamp searches and retention
+        CREATE INDEX IF NOT EXISTS idx_msg_block_timestamp ON messages(block_timestamp);
+        CREATE INDEX IF NOT EXISTS idx_hs_block_timestamp ON handshakes(block_timestamp);
+        CREATE INDEX IF NOT EXISTS idx_hsr_block_timestamp ON handshake_responses(block_timestamp);
+        "#,
+    ),
+    (
+        12,
+        r#"
+        -- Serialized log the row was decoded from; only written with STORE_RAW_LOGS
+        ALTER TABLE messages ADD COLUMN raw_log BLOB;
+        ALTER TABLE handshakes ADD COLUMN raw_log BLOB;
+        ALTER TABLE handshake_responses ADD COLUMN raw_log BLOB;
+        "#,
+    ),
+    (
+        13,
+        r#"
+        -- The global hsr counter was keyed on NULL, which never conflicts in the primary key,
+        -- so every allocation added a row. Fold them into one row keyed on an empty blob,
+        -- resuming past every seq handed out or stored.
+        INSERT INTO seq_counters (key_type, key_hash, next_seq)
+            SELECT 'hsr', X'', MAX(
+                COALESCE((SELECT MAX(next_seq) FROM seq_counters WHERE key_type = 'hsr'), 0),
+                COALESCE((SELECT MAX(global_seq) + 1 FROM handshake_responses), 0))
+            WHERE EXISTS (SELECT 1 FROM seq_counters WHERE key_type = 'hsr')
+        ON CONFLICT(key_type, key_hash) DO UPDATE SET next_seq = MAX(next_seq, excluded.next_seq);
+        DELETE FROM seq_counters WHERE key_hash IS NULL;
+        "#,
+    ),
+    (
+        14,
+        r#"
+        -- Blobs written through db::blob now always start with a format byte. Older rows
+        -- stored verbatim get the raw marker (00). Those that already start with the old
+        -- compressed header (01 and the zstd magic) were read as compressed and stay so.
+        UPDATE messages SET ciphertext = unhex('00' || hex(ciphertext))
+            WHERE substr(ciphertext, 1, 5) IS NOT X'0128B52FFD';
+        UPDATE messages SET raw_log = unhex('00' || hex(raw_log))
+            WHERE raw_log IS NOT NULL AND substr(raw_log, 1, 5) IS NOT X'0128B52FFD';
+        UPDATE handshakes SET pub_keys = unhex('00' || hex(pub_keys))
+            WHERE substr(pub_keys, 1, 5) IS NOT X'0128B52FFD';
+        UPDATE handshakes SET plaintext_payload = unhex('00' || hex(plaintext_payload))
+            WHERE substr(plaintext_payload, 1, 5) IS NOT X'0128B52FFD';
+        UPDATE handshakes SET raw_log = unhex('00' || hex(raw_log))
+            WHERE raw_log IS NOT NULL AND substr(raw_log, 1, 5) IS NOT X'0128B52FFD';
+        UPDATE handshake_responses SET ciphertext = unhex('00' || hex(ciphertext))
+            WHERE substr(ciphertext, 1, 5) IS NOT X'0128B52FFD';
+        UPDATE handshake_responses SET raw_log = unhex('00' || hex(raw_log))
+            WHERE raw_log IS NOT NULL AND substr(raw_log, 1, 5) IS NOT X'0128B52FFD';
+        "#,
+    ),
+];
 
 pub fn run_migrations(conn: &Connection) -> Result<()> {
     conn.execute_batch(
@@ -24,7 +173,6 @@ pub fn run_migrations(conn: &Connection) -> Result<()> {
             UNIQUE(topic, seq)
         );
         CREATE INDEX IF NOT EXISTS idx_msg_topic_seq ON messages(topic, seq);
-        CREATE INDEX IF NOT EXISTS idx_msg_block ON messages(block_number, log_index);
 
         CREATE TABLE IF NOT EXISTS handshakes (
             id INTEGER PRIMARY KEY AUTOINCREMENT,
@@ -68,14 +216,112 @@ pub fn run_migrations(conn: &Connection) -> Result<()> {
         "#,
     )?;
 
-    let version: i64 = conn.query_row(
-        "SELECT version FROM schema_version ORDER BY version DESC LIMIT 1",
-        [],
-        |row| row.get(0),
-    )?;
+    for (target, sql) in MIGRATIONS {
+        if current_version(conn)? < *target {
+            conn.execute_batch(&format!(
+                "BEGIN; {sql} INSERT INTO schema_version VALUES ({target}); COMMIT;"
+            ))?;
+            tracing::info!(schema_version = target, "Applied migration");
+        }
+    }
+
+    let version = current_version(conn)?;
 
     tracing::info!(schema_version = version, "Database initialized");
     assert_eq!(version, SCHEMA_VERSION, "Schema version mismatch");
 
     Ok(())
 }
+
+/// Create the optional full-text index over handshake payloads (`ENABLE_FTS`). It lives
+/// outside the versioned migrations so databases that never enable it don't pay for it.
+/// Rowids mirror `handshakes.id`, and the trigger keeps it in step with reorg deletes even
+/// while the setting is off.
+pub fn enable_handshake_fts(conn: &Connection) -> Result<()> {
+    conn.execute_batch(
+        r#"
+        CREATE VIRTUAL TABLE IF NOT EXISTS handshakes_fts USING fts5(payload);
+        CREATE TRIGGER IF NOT EXISTS handshakes_fts_delete AFTER DELETE ON handshakes BEGIN
+            DELETE FROM handshakes_fts WHERE rowid = old.id;
+        END;
+        "#,
+    )?;
+    Ok(())
+}
+
+pub fn current_version(conn: &Connection) -> Result<i64> {
+    Ok(conn.query_row(
+        "SELECT version FROM schema_version ORDER BY version DESC LIMIT 1",
+        [],
+        |row| row.get(0),
+    )?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn migration_folds_null_keyed_hsr_counters() {
+        let conn = Connection::open_in_memory().unwrap();
+        run_migrations(&conn).unwrap();
+
+        // What the NULL-keyed upsert left behind: one row per allocation after the first
+        conn.execute_batch(
+            "INSERT INTO seq_counters (key_type, key_hash, next_seq) VALUES
+                 ('hsr', NULL, 1), ('hsr', NULL, 2), ('hsr', NULL, 2);
+             INSERT INTO handshake_responses (global_seq, in_response_to, responder,
+                 responder_ephemeral_r, ciphertext, block_number, log_index, block_timestamp)
+                 VALUES (4, X'00', X'00', X'00', X'00', 1, 0, 1);
+             DELETE FROM schema_version WHERE version >= 13;",
+        )
+        .unwrap();
+        run_migrations(&conn).unwrap();
+
+        let counters = conn
+            .prepare("SELECT key_hash, next_seq FROM seq_counters WHERE key_type = 'hsr'")
+            .unwrap()
+            .query_map([], |row| {
+                Ok((row.get::<_, Vec<u8>>(0)?, row.get::<_, i64>(1)?))
+            })
+            .unwrap()
+            .collect::<rusqlite::Result<Vec<_>>>()
+            .unwrap();
+        assert_eq!(counters, [(Vec::new(), 5)]);
+    }
+
+    #[test]
+    fn migration_marks_verbatim_blobs_raw() {
+        let conn = Connection::open_in_memory().unwrap();
+        run_migrations(&conn).unwrap();
+
+        // Rows as stored before the format byte: a verbatim ciphertext, one compressed
+        // under the old header, and an empty one
+        conn.execute_batch(
+            "INSERT INTO messages (topic, seq, sender, ciphertext, timestamp, nonce,
+                 block_number, log_index, block_timestamp) VALUES
+                 (X'01', 0, X'00', X'deadbeef', 0, 0, 1, 0, 1),
+                 (X'01', 1, X'00', X'0128B52FFD00', 0, 0, 1, 1, 1),
+                 (X'01', 2, X'00', X'', 0, 0, 1, 2, 1);
+             DELETE FROM schema_version WHERE version = 14;",
+        )
+        .unwrap();
+        run_migrations(&conn).unwrap();
+
+        let blobs = conn
+            .prepare("SELECT ciphertext FROM messages ORDER BY seq")
+            .unwrap()
+            .query_map([], |row| row.get::<_, Vec<u8>>(0))
+            .unwrap()
+            .collect::<rusqlite::Result<Vec<_>>>()
+            .unwrap();
+        assert_eq!(
+            blobs,
+            [
+                vec![0x00, 0xde, 0xad, 0xbe, 0xef],
+                vec![0x01, 0x28, 0xb5, 0x2f, 0xfd, 0x00],
+                vec![0x00],
+            ]
+        );
+    }
+}
@@ -0,0 +1,81 @@
+use std::time::Duration;
+
+use rusqlite::Connection;
+use tokio::sync::watch;
+
+use crate::error::Result;
+
+use super::BUSY_TIMEOUT;
+
+/// Periodically run `PRAGMA wal_checkpoint(TRUNCATE)` on a dedicated connection.
+///
+/// SQLite's automatic checkpoints are PASSIVE: they never wait for readers and never shrink
+/// the `-wal` file, so a busy API can keep it growing indefinitely. TRUNCATE waits up to the
+/// busy timeout for readers to finish, copies every frame back and resets the file to zero.
+pub async fn run_checkpoints(
+    database_path: String,
+    interval: Duration,
+    mut shutdown: watch::Receiver<bool>,
+) {
+    let opened = Connection::open(&database_path).and_then(|conn| {
+        conn.busy_timeout(BUSY_TIMEOUT)?;
+        Ok(conn)
+    });
+    let mut conn = match opened {
+        Ok(conn) => conn,
+        Err(e) => {
+            tracing::error!("WAL checkpoints disabled, failed to open database: {e}");
+            return;
+        }
+    };
+
+    loop {
+        tokio::select! {
+            _ = tokio::time::sleep(interval) => {}
+            _ = shutdown.changed() => {
+                if *shutdown.borrow() {
+                    return;
+                }
+            }
+        }
+
+        // Waiting out readers blocks, so keep it off the runtime's worker threads
+        let result = match tokio::task::spawn_blocking(move || {
+            let result = checkpoint(&conn);
+            (conn, result)
+        })
+        .await
+        {
+            Ok((returned, result)) => {
+                conn = returned;
+                result
+            }
+            Err(e) => {
+                tracing::error!("WAL checkpoint task failed: {e}");
+                return;
+            }
+        };
+
+        match result {
+            Ok((false, 0, _)) => tracing::debug!("WAL checkpoint: nothing to do"),
+            Ok((false, log_frames, checkpointed)) => {
+                tracing::info!("WAL checkpoint: {checkpointed}/{log_frames} frames, file truncated")
+            }
+            Ok((true, log_frames, checkpointed)) => tracing::warn!(
+                "WAL checkpoint incomplete, readers still active: {checkpointed}/{log_frames} frames"
+            ),
+            Err(e) => tracing::warn!("WAL checkpoint failed: {e}"),
+        }
+    }
+}
+
+/// Returns `(busy, log_frames, checkpointed_frames)`. TRUNCATE reports zero frames once it
+/// has reset the log, so a PASSIVE pass copies the frames first and supplies the counts.
+fn checkpoint(conn: &Connection) -> Result<(bool, i64, i64)> {
+    let (log_frames, checkpointed) =
+        conn.query_row("PRAGMA wal_checkpoint(PASSIVE)", [], |row| {
+            Ok((row.get(1)?, row.get(2)?))
+        })?;
+    let busy: i64 = conn.query_row("PRAGMA wal_checkpoint(TRUNCATE)", [], |row| row.get(0))?;
+    Ok((busy != 0, log_frames, checkpointed))
+}
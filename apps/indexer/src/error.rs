@@ -12,20 +12,53 @@ pub enum IndexerError {
     #[error("RPC error: {0}")]
     Rpc(#[from] alloy::transports::TransportError),
 
+    #[error("RPC endpoint does not support eth_subscribe: {0}")]
+    SubscriptionUnsupported(String),
+
+    #[error("WebSocket connection lost: {0}")]
+    ConnectionLost(&'static str),
+
     #[error("config error: {0}")]
     Config(String),
 
+    #[error("database integrity check failed: {0}")]
+    Integrity(String),
+
     #[error("event decode error: {0}")]
     Decode(String),
 
+    #[error("RPC circuit breaker open after repeated failures, retrying in {0:?}")]
+    RpcCircuitOpen(std::time::Duration),
+
     #[error("block not found: {0}")]
     BlockNotFound(u64),
 
+    #[error("missing block timestamp for block {0}")]
+    MissingTimestamp(u64),
+
+    #[error("event writer stopped")]
+    WriterStopped,
+
     #[error("task join error: {0}")]
     Join(#[from] tokio::task::JoinError),
 
     #[error("payload too large: {field} is {size} bytes (max: {max})")]
-    PayloadTooLarge { field: &'static str, size: usize, max: usize },
+    PayloadTooLarge {
+        field: &'static str,
+        size: usize,
+        max: usize,
+    },
+}
+
+impl IndexerError {
+    /// SQLite ran out of space for a write (`SQLITE_FULL`).
+    pub fn is_disk_full(&self) -> bool {
+        matches!(
+            self,
+            Self::Database(rusqlite::Error::SqliteFailure(e, _))
+                if e.code == rusqlite::ErrorCode::DiskFull
+        )
+    }
 }
 
 pub type Result<T> = std::result::Result<T, IndexerError>;
@@ -0,0 +1,198 @@
+// tonic::Status is large, but it is the error type every RPC returns anyway
+#![allow(clippy::result_large_err)]
+
+use std::net::SocketAddr;
+use std::pin::Pin;
+
+use futures_lite::{stream, Stream};
+use tokio::sync::{broadcast, watch};
+use tonic::{Request, Response, Status};
+
+use crate::db::models::{HandshakeRow, MessageRow};
+use crate::db::queries::{handshakes_by_recipient, messages_by_topic};
+use crate::db::DbPool;
+use crate::error::IndexerError;
+use crate::indexer::processor::IndexedEvent;
+use crate::types::{RecipientHash, SenderAddress, TopicHash};
+
+mod proto {
+    tonic::include_proto!("verbeth.indexer.v1");
+}
+
+use proto::indexer_server::{Indexer, IndexerServer};
+use proto::{
+    GetHandshakesRequest, GetHandshakesResponse, GetMessagesRequest, GetMessagesResponse,
+    Handshake, Message, StreamMessagesRequest,
+};
+
+const DEFAULT_LIMIT: i64 = 100;
+const MAX_LIMIT: i64 = 1000;
+
+pub struct IndexerService {
+    pool: DbPool,
+    events: broadcast::Sender<IndexedEvent>,
+}
+
+impl IndexerService {
+    pub fn new(pool: DbPool, events: broadcast::Sender<IndexedEvent>) -> Self {
+        Self { pool, events }
+    }
+}
+
+pub async fn serve(
+    addr: SocketAddr,
+    service: IndexerService,
+    mut shutdown: watch::Receiver<bool>,
+) -> Result<(), tonic::transport::Error> {
+    tonic::transport::Server::builder()
+        .add_service(IndexerServer::new(service))
+        .serve_with_shutdown(addr, async move {
+            let _ = shutdown.wait_for(|stop| *stop).await;
+        })
+        .await
+}
+
+impl From<MessageRow> for Message {
+    fn from(r: MessageRow) -> Self {
+        Self {
+            topic: r.topic.0.to_vec(),
+            seq: r.seq,
+            sender: r.sender.0.to_vec(),
+            ciphertext: r.ciphertext,
+            timestamp: r.timestamp,
+            nonce: r.nonce,
+            block_number: r.block_number,
+            log_index: r.log_index,
+            block_timestamp: r.block_timestamp,
+            tx_hash: r.tx_hash.map(|h| h.to_vec()).unwrap_or_default(),
+            indexed_at: r.indexed_at,
+        }
+    }
+}
+
+impl From<HandshakeRow> for Handshake {
+    fn from(r: HandshakeRow) -> Self {
+        Self {
+            recipient_hash: r.recipient_hash.0.to_vec(),
+            seq: r.seq,
+            sender: r.sender.0.to_vec(),
+            pub_keys: r.pub_keys,
+            ephemeral_pub_key: r.ephemeral_pub_key,
+            plaintext_payload: r.plaintext_payload,
+            block_number: r.block_number,
+            log_index: r.log_index,
+            block_timestamp: r.block_timestamp,
+            tx_hash: r.tx_hash.map(|h| h.to_vec()).unwrap_or_default(),
+            indexed_at: r.indexed_at,
+        }
+    }
+}
+
+fn internal(e: impl Into<IndexerError>) -> Status {
+    match e.into() {
+        e @ IndexerError::Pool(_) => Status::unavailable(e.to_string()),
+        e => {
+            tracing::error!("gRPC request failed: {e}");
+            Status::internal("internal error")
+        }
+    }
+}
+
+fn bytes_arg<const N: usize>(bytes: &[u8], field: &str) -> Result<[u8; N], Status> {
+    bytes
+        .try_into()
+        .map_err(|_| Status::invalid_argument(format!("{field} must be {N} bytes")))
+}
+
+fn limit_arg(limit: u32) -> i64 {
+    match limit {
+        0 => DEFAULT_LIMIT,
+        n => (n as i64).min(MAX_LIMIT),
+    }
+}
+
+type MessageStream = Pin<Box<dyn Stream<Item = Result<Message, Status>> + Send>>;
+
+#[tonic::async_trait]
+impl Indexer for IndexerService {
+    async fn get_messages(
+        &self,
+        request: Request<GetMessagesRequest>,
+    ) -> Result<Response<GetMessagesResponse>, Status> {
+        let req = request.into_inner();
+        let topic = TopicHash(bytes_arg(&req.topic, "topic")?);
+        let sender = (!req.sender.is_empty())
+            .then(|| bytes_arg(&req.sender, "sender").map(SenderAddress))
+            .transpose()?;
+
+        let conn = self.pool.get().map_err(internal)?;
+        let rows = messages_by_topic(
+            &conn,
+            &topic,
+            sender.as_ref(),
+            req.after_seq,
+            None,
+            false,
+            limit_arg(req.limit),
+        )
+        .map_err(internal)?;
+
+        Ok(Response::new(GetMessagesResponse {
+            messages: rows.into_iter().map(Into::into).collect(),
+        }))
+    }
+
+    type StreamMessagesStream = MessageStream;
+
+    async fn stream_messages(
+        &self,
+        request: Request<StreamMessagesRequest>,
+    ) -> Result<Response<Self::StreamMessagesStream>, Status> {
+        let req = request.into_inner();
+        let topic = (!req.topic.is_empty())
+            .then(|| bytes_arg(&req.topic, "topic").map(TopicHash))
+            .transpose()?;
+
+        // Live events only; clients catch up with GetMessages and resume from the last seq
+        let rx = self.events.subscribe();
+        let stream = stream::unfold(Some(rx), move |rx| async move {
+            let mut rx = rx?;
+            loop {
+                match rx.recv().await {
+                    Ok(IndexedEvent::Message(row)) => {
+                        if topic.is_none_or(|t| t == row.topic) {
+                            return Some((Ok(row.into()), Some(rx)));
+                        }
+                    }
+                    Ok(_) => {}
+                    Err(broadcast::error::RecvError::Lagged(n)) => {
+                        let status = Status::data_loss(format!(
+                            "stream lagged by {n} events; resync with GetMessages"
+                        ));
+                        return Some((Err(status), None));
+                    }
+                    Err(broadcast::error::RecvError::Closed) => return None,
+                }
+            }
+        });
+
+        Ok(Response::new(Box::pin(stream)))
+    }
+
+    async fn get_handshakes(
+        &self,
+        request: Request<GetHandshakesRequest>,
+    ) -> Result<Response<GetHandshakesResponse>, Status> {
+        let req = request.into_inner();
+        let recipient_hash = RecipientHash(bytes_arg(&req.recipient_hash, "recipient_hash")?);
+
+        let conn = self.pool.get().map_err(internal)?;
+        let rows =
+            handshakes_by_recipient(&conn, &recipient_hash, req.after_seq, limit_arg(req.limit))
+                .map_err(internal)?;
+
+        Ok(Response::new(GetHandshakesResponse {
+            handshakes: rows.into_iter().map(Into::into).collect(),
+        }))
+    }
+}
@@ -1,61 +1,231 @@
-use std::collections::HashMap;
-use std::num::NonZeroU32;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
 use std::time::Duration;
 
 use alloy::eips::BlockNumberOrTag;
-use alloy::primitives::Address;
-use alloy::providers::{Provider, ProviderBuilder, RootProvider};
+use alloy::primitives::B256;
+use alloy::providers::{Provider, RootProvider};
 use alloy::rpc::types::{BlockTransactionsKind, Filter, Log};
 use alloy::transports::http::{Client, Http};
+use alloy::transports::{Transport, TransportError};
 use governor::{Jitter, Quota, RateLimiter};
+use serde::Serialize;
+use tokio::sync::watch;
 
-use crate::db::queries::set_last_processed_block;
-use crate::db::DbPool;
+use crate::config::{Config, RpcLimits};
+use crate::db::queries::delete_log;
 use crate::error::{IndexerError, Result};
 
-use super::processor::{decode_log, EventProcessor, LogWithMeta};
-
-// Alchemy Free tier: 500 CU/s, eth_getLogs = 75 CU → max ~6 req/s
-const REQUESTS_PER_SECOND: u32 = 5;
-const MAX_RETRIES: u32 = 5;
-const INITIAL_BACKOFF_MS: u64 = 1000;
+use super::circuit_breaker::CircuitBreaker;
+use super::events::verbeth_filter;
+use super::processor::{chain_position, decode_log, EventProcessor, LogWithMeta};
+use super::retry_queue::RetryQueue;
+use super::rpc::{http_provider, is_timeout};
+use super::timestamp_cache::BlockTimestampCache;
+use super::writer::EventWriter;
 
+#[derive(Clone, Default, Serialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
 pub struct BackfillStats {
     pub blocks_processed: u64,
     pub events_processed: u64,
     pub events_skipped: u64,
+    pub events_failed: u64,
+}
+
+/// Progress of one running backfill, as streamed by `/stream/backfill`.
+#[derive(Clone, Serialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct BackfillProgress {
+    pub from_block: u64,
+    pub to_block: u64,
+    pub percent: f64,
+    #[serde(flatten)]
+    pub stats: BackfillStats,
+}
+
+/// Backfills in flight, keyed by an id unique to each run. Shared by every path that
+/// backfills, so the API can follow admin jobs, rescans and recoveries alike.
+#[derive(Clone)]
+pub struct ProgressTracker {
+    running: watch::Sender<BTreeMap<u64, BackfillProgress>>,
+    next_id: Arc<AtomicU64>,
+}
+
+impl Default for ProgressTracker {
+    fn default() -> Self {
+        Self {
+            running: watch::Sender::new(BTreeMap::new()),
+            next_id: Arc::default(),
+        }
+    }
 }
 
+impl ProgressTracker {
+    pub fn subscribe(&self) -> watch::Receiver<BTreeMap<u64, BackfillProgress>> {
+        self.running.subscribe()
+    }
+
+    fn start(&self, from_block: u64, to_block: u64) -> ProgressEntry {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let entry = ProgressEntry {
+            running: self.running.clone(),
+            id,
+        };
+        entry.update(from_block, to_block, 0.0, &BackfillStats::default());
+        entry
+    }
+}
+
+/// One backfill's slot in a `ProgressTracker`, removed when dropped so that a failed or
+/// cancelled backfill doesn't linger as running.
+struct ProgressEntry {
+    running: watch::Sender<BTreeMap<u64, BackfillProgress>>,
+    id: u64,
+}
+
+impl ProgressEntry {
+    fn update(&self, from_block: u64, to_block: u64, percent: f64, stats: &BackfillStats) {
+        let progress = BackfillProgress {
+            from_block,
+            to_block,
+            percent: (percent * 10.0).round() / 10.0,
+            stats: stats.clone(),
+        };
+        self.running.send_modify(|running| {
+            running.insert(self.id, progress);
+        });
+    }
+}
+
+impl Drop for ProgressEntry {
+    fn drop(&mut self) {
+        self.running.send_modify(|running| {
+            running.remove(&self.id);
+        });
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
 pub async fn run_backfill(
+    config: &Config,
     rpc_url: &str,
-    contract_address: Address,
     from_block: u64,
     to_block: u64,
-    chunk_size: u64,
-    pool: DbPool,
+    writer: &EventWriter,
+    timestamps: &BlockTimestampCache,
+    retry_queue: &RetryQueue,
+    progress: &ProgressTracker,
+    breaker: &CircuitBreaker,
 ) -> Result<BackfillStats> {
+    let provider = http_provider(
+        rpc_url,
+        config.rpc_auth_header.as_deref(),
+        config.rpc_limits.timeout,
+    )?;
+    backfill_with_provider(
+        config,
+        &provider,
+        from_block,
+        to_block,
+        writer,
+        timestamps,
+        retry_queue,
+        progress,
+        breaker,
+    )
+    .await
+}
+
+/// Backfill through an already-built provider, so any transport (or a mock) can be used.
+#[allow(clippy::too_many_arguments)]
+pub async fn backfill_with_provider<T, P>(
+    config: &Config,
+    provider: &P,
+    from_block: u64,
+    to_block: u64,
+    writer: &EventWriter,
+    timestamps: &BlockTimestampCache,
+    retry_queue: &RetryQueue,
+    progress: &ProgressTracker,
+    breaker: &CircuitBreaker,
+) -> Result<BackfillStats>
+where
+    T: Transport + Clone,
+    P: Provider<T>,
+{
     tracing::info!(
         "Starting backfill from block {} to {}",
         from_block,
         to_block
     );
 
-    let provider = ProviderBuilder::new().on_http(rpc_url.parse().map_err(|e| {
-        IndexerError::Config(format!("Invalid RPC URL: {e}"))
-    })?);
+    let processor = EventProcessor::new(writer.clone())
+        .with_contract(config.contract_address)
+        .with_compression(config.compress_blobs)
+        .with_raw_logs(config.store_raw_logs)
+        .with_fts(config.enable_fts)
+        .with_event_kinds(config.index_events)
+        .with_recipient_allowlist(config.recipient_allowlist.clone())
+        .with_payload_limits(config.payload_limits);
 
-    let processor = EventProcessor::new(pool.clone());
+    let stats = index_blocks(
+        config,
+        provider,
+        &processor,
+        from_block,
+        to_block,
+        timestamps,
+        retry_queue,
+        progress,
+        breaker,
+    )
+    .await?;
 
-    let limiter = RateLimiter::direct(Quota::per_second(
-        NonZeroU32::new(REQUESTS_PER_SECOND).unwrap(),
-    ));
+    tracing::info!(
+        "Backfill complete: {} blocks, {} events processed, {} skipped, {} failed",
+        stats.blocks_processed,
+        stats.events_processed,
+        stats.events_skipped,
+        stats.events_failed
+    );
 
-    let mut block_timestamps: HashMap<u64, u64> = HashMap::new();
-    let mut stats = BackfillStats {
-        blocks_processed: 0,
-        events_processed: 0,
-        events_skipped: 0,
-    };
+    Ok(stats)
+}
+
+/// Fetch, decode and process every Verbeth log in `from_block..=to_block`, checkpointing
+/// `last_block` as chunks complete. Shared by backfill and the poll-mode subscriber, which
+/// passes a processor that publishes to live consumers.
+#[allow(clippy::too_many_arguments)]
+pub(super) async fn index_blocks<T, P>(
+    config: &Config,
+    provider: &P,
+    processor: &EventProcessor,
+    from_block: u64,
+    to_block: u64,
+    timestamps: &BlockTimestampCache,
+    retry_queue: &RetryQueue,
+    progress: &ProgressTracker,
+    breaker: &CircuitBreaker,
+) -> Result<BackfillStats>
+where
+    T: Transport + Clone,
+    P: Provider<T>,
+{
+    let contract_address = config.contract_address;
+    let chunk_size = config.rpc_chunk_size;
+    let limits = config.rpc_limits;
+
+    let limiter = RateLimiter::direct(Quota::per_second(limits.requests_per_second));
+
+    let mut stats = BackfillStats::default();
+    // Cleared if the provider rejects the topic0 set; decode_log filters in code either way
+    let mut use_topic_filter = true;
+    let mut checkpointed = from_block.saturating_sub(1);
+    // Single-chunk ranges, like most polls, finish before progress means anything
+    let progress =
+        (to_block - from_block >= chunk_size).then(|| progress.start(from_block, to_block));
 
     for chunk_start in (from_block..=to_block).step_by(chunk_size as usize) {
         let chunk_end = (chunk_start + chunk_size - 1).min(to_block);
@@ -64,105 +234,389 @@ pub async fn run_backfill(
             .until_ready_with_jitter(Jitter::up_to(Duration::from_millis(100)))
             .await;
 
-        // Note: Don't use .events() for multiple signatures - it doesn't work as OR filter
-        // Filter in code via decode_log() instead
-        let filter = Filter::new()
-            .address(contract_address)
-            .from_block(chunk_start)
-            .to_block(chunk_end);
+        let logs = if use_topic_filter {
+            let filter = verbeth_filter(contract_address, config.index_events)
+                .from_block(chunk_start)
+                .to_block(chunk_end);
+            match get_logs_with_retry(provider, &filter, &limits, breaker).await {
+                Ok(logs) => Some(logs),
+                // Says nothing about whether the provider accepts topic filters
+                Err(e @ IndexerError::RpcCircuitOpen(_)) => return Err(e),
+                Err(e) => {
+                    tracing::warn!(
+                        "Provider rejected topic-filtered getLogs ({e}), falling back to \
+                         address-only filter"
+                    );
+                    use_topic_filter = false;
+                    None
+                }
+            }
+        } else {
+            None
+        };
 
-        let logs = get_logs_with_retry(&provider, &filter).await?;
+        let logs = match logs {
+            Some(logs) => logs,
+            None => {
+                let filter = Filter::new()
+                    .address(contract_address)
+                    .from_block(chunk_start)
+                    .to_block(chunk_end);
+                get_logs_with_retry(provider, &filter, &limits, breaker).await?
+            }
+        };
 
         let mut logs: Vec<_> = logs.into_iter().collect();
-        logs.sort_by_key(|l| (l.block_number, l.log_index));
+        logs.sort_by_key(chain_position);
 
-        let unique_blocks: Vec<u64> = logs
-            .iter()
-            .filter_map(|l| l.block_number)
-            .filter(|b| !block_timestamps.contains_key(b))
-            .collect::<std::collections::HashSet<_>>()
-            .into_iter()
-            .collect();
+        let unique_blocks: BTreeSet<u64> = logs.iter().filter_map(|l| l.block_number).collect();
 
+        // This chunk's blocks only. Timestamps outlive a chunk through the LRU `timestamps`,
+        // so a backfill holds at most `BLOCK_TIMESTAMP_CACHE_SIZE` of them however long it is
+        let mut block_timestamps: HashMap<u64, u64> = HashMap::new();
         for block_num in unique_blocks {
-            limiter.until_ready().await;
-            let timestamp = fetch_block_timestamp_with_retry(&provider, block_num).await?;
+            let timestamp = match timestamps.get(block_num) {
+                Some(timestamp) => timestamp,
+                None => {
+                    limiter.until_ready().await;
+                    match fetch_block_timestamp_with_retry(provider, block_num, &limits, breaker)
+                        .await
+                    {
+                        Ok(timestamp) => {
+                            timestamps.insert(block_num, timestamp);
+                            timestamp
+                        }
+                        Err(e) => {
+                            // The block's events are deferred to the retry queue below
+                            tracing::warn!("Failed to fetch timestamp for block {block_num}: {e}");
+                            continue;
+                        }
+                    }
+                }
+            };
             block_timestamps.insert(block_num, timestamp);
         }
 
+        let mut chunk = Vec::new();
         for log in logs {
             tracing::debug!("Got log with topic: {:?}", log.topics().first());
-            let Some(event) = decode_log(&log) else {
-                // Non-Verbeth events (Upgraded, OwnershipTransferred, etc.) - skip silently
-                continue;
-            };
-
             let block_number = log.block_number.unwrap_or(0);
             let log_index = log.log_index.unwrap_or(0);
-            let block_timestamp = *block_timestamps.get(&block_number).unwrap_or(&0);
+
+            let event = match decode_log(&log) {
+                Ok(Some(event)) => event,
+                // Non-Verbeth events (Upgraded, OwnershipTransferred, etc.) - skip silently
+                Ok(None) => continue,
+                Err(e) => {
+                    stats.events_failed += 1;
+                    tracing::warn!(
+                        block = block_number,
+                        log_index = log_index,
+                        tx = ?log.transaction_hash,
+                        "{e}, skipping"
+                    );
+                    continue;
+                }
+            };
+            let block_timestamp = block_timestamps.get(&block_number).copied();
 
             let log_with_meta = LogWithMeta {
                 event,
                 block_number,
                 log_index,
-                block_timestamp,
+                block_timestamp: block_timestamp.unwrap_or(0),
+                tx_hash: log.transaction_hash,
+                block_hash: log.block_hash,
+                raw_log: processor
+                    .stores_raw_logs()
+                    .then(|| Box::new(log.inner.clone())),
             };
 
-            match processor.process(log_with_meta) {
-                Ok(true) => stats.events_processed += 1,
-                Ok(false) => stats.events_skipped += 1,
-                Err(e) => {
-                    tracing::error!("Failed to process event: {e}");
+            if block_timestamp.is_none() {
+                // Never persist a zero timestamp; the retry loop fetches it again
+                stats.events_failed += 1;
+                let error = IndexerError::MissingTimestamp(block_number);
+                tracing::warn!(
+                    block = block_number,
+                    log_index = log_index,
+                    "{error}, queuing for retry"
+                );
+                retry_queue.push(log_with_meta, &error).await;
+                continue;
+            }
+
+            chunk.push(log_with_meta);
+        }
+
+        // Events past the last checkpoint are replayed after a crash and deduplicated on
+        // (block_number, log_index), so skipping writes only costs replay time. Nothing is
+        // checkpointed while events held by a full disk wait in the retry queue
+        let checkpoint = (chunk_end == to_block
+            || chunk_end - checkpointed >= config.checkpoint_every_blocks)
+            .then_some(chunk_end);
+        let checkpoint = match checkpoint {
+            Some(_) if retry_queue.holds_disk_full().await => None,
+            checkpoint => checkpoint,
+        };
+
+        // The chunk's events and its checkpoint commit together
+        let mut disk_full = false;
+        match processor.process_chunk(chunk.clone(), checkpoint).await {
+            Ok(results) => {
+                for (log_with_meta, result) in chunk.into_iter().zip(results) {
+                    match result {
+                        Ok(true) => stats.events_processed += 1,
+                        Ok(false) => stats.events_skipped += 1,
+                        Err(e) => {
+                            // The checkpoint moves past this block unless the disk is
+                            // full, so only the retry queue gets another go at it
+                            stats.events_failed += 1;
+                            disk_full |= e.is_disk_full();
+                            tracing::error!("Failed to process event: {e}");
+                            retry_queue.push(log_with_meta, &e).await;
+                        }
+                    }
                 }
             }
+            // Nothing in the chunk was stored. Its events wait in the retry queue instead
+            // of failing the backfill, so a full disk degrades the indexer, not kills it
+            Err(e) if e.is_disk_full() => {
+                tracing::error!(
+                    "{e}, holding {} events of blocks {chunk_start}..={chunk_end} for retry",
+                    chunk.len()
+                );
+                stats.events_failed += chunk.len() as u64;
+                disk_full = true;
+                for log_with_meta in chunk {
+                    retry_queue.push(log_with_meta, &e).await;
+                }
+            }
+            Err(e) => return Err(e),
         }
 
-        let conn = pool.get()?;
-        set_last_processed_block(&conn, chunk_end as i64)?;
+        if disk_full {
+            if chunk_end < to_block {
+                tokio::time::sleep(DISK_FULL_BACKOFF).await;
+            }
+        } else if checkpoint.is_some() {
+            checkpointed = chunk_end;
+            tracing::debug!("Backfill checkpoint at block {chunk_end}");
+        }
 
         stats.blocks_processed = chunk_end - from_block + 1;
 
-        let progress = (chunk_end - from_block) as f64 / (to_block - from_block).max(1) as f64 * 100.0;
-        tracing::info!(
-            "Backfill progress: {}/{} blocks ({:.1}%), {} events",
-            stats.blocks_processed,
-            to_block - from_block + 1,
-            progress,
-            stats.events_processed
-        );
+        if let Some(progress) = &progress {
+            let percent =
+                (chunk_end - from_block) as f64 / (to_block - from_block).max(1) as f64 * 100.0;
+            tracing::info!(
+                "Backfill progress: {}/{} blocks ({:.1}%), {} events",
+                stats.blocks_processed,
+                to_block - from_block + 1,
+                percent,
+                stats.events_processed
+            );
+            progress.update(from_block, to_block, percent, &stats);
+        }
     }
 
-    tracing::info!(
-        "Backfill complete: {} blocks, {} events processed, {} skipped",
-        stats.blocks_processed,
-        stats.events_processed,
-        stats.events_skipped
-    );
-
     Ok(stats)
 }
 
-async fn get_logs_with_retry(
-    provider: &RootProvider<Http<Client>>,
+/// Outcome of `reprocess_transaction`.
+#[derive(Clone, Default, Serialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct ReprocessStats {
+    /// Verbeth logs emitted by the contract in this transaction
+    pub logs: u64,
+    pub events_processed: u64,
+    /// Already indexed and left as is
+    pub events_skipped: u64,
+    pub events_failed: u64,
+}
+
+/// Run one transaction's Verbeth logs, taken from its receipt rather than `getLogs`, through
+/// the processor. Already-indexed logs are skipped unless `replace` is set, in which case
+/// their rows are deleted first. Returns `None` if the provider has no receipt for `tx_hash`.
+pub async fn reprocess_transaction(
+    config: &Config,
+    writer: &EventWriter,
+    timestamps: &BlockTimestampCache,
+    tx_hash: B256,
+    replace: bool,
+    breaker: &CircuitBreaker,
+) -> Result<Option<ReprocessStats>> {
+    let provider = http_provider(
+        &config.http_rpc_url(),
+        config.rpc_auth_header.as_deref(),
+        config.rpc_limits.timeout,
+    )?;
+    let Some(receipt) = provider.get_transaction_receipt(tx_hash).await? else {
+        return Ok(None);
+    };
+
+    let mut stats = ReprocessStats::default();
+    let mut logs = Vec::new();
+    for log in receipt.inner.logs() {
+        if log.address() != config.contract_address {
+            continue;
+        }
+        let block_number = log.block_number.unwrap_or(0);
+        let event = match decode_log(log) {
+            Ok(Some(event)) => event,
+            Ok(None) => continue,
+            Err(e) => {
+                stats.events_failed += 1;
+                tracing::warn!(block = block_number, tx = %tx_hash, "{e}, skipping");
+                continue;
+            }
+        };
+
+        let block_timestamp = match timestamps.get(block_number) {
+            Some(timestamp) => timestamp,
+            None => {
+                let timestamp = fetch_block_timestamp_with_retry(
+                    &provider,
+                    block_number,
+                    &config.rpc_limits,
+                    breaker,
+                )
+                .await?;
+                timestamps.insert(block_number, timestamp);
+                timestamp
+            }
+        };
+
+        logs.push(LogWithMeta {
+            event,
+            block_number,
+            log_index: log.log_index.unwrap_or(0),
+            block_timestamp,
+            tx_hash: log.transaction_hash,
+            block_hash: log.block_hash,
+            raw_log: config.store_raw_logs.then(|| Box::new(log.inner.clone())),
+        });
+    }
+    stats.logs = logs.len() as u64 + stats.events_failed;
+
+    if replace {
+        // Newest first, so a deleted row holding its key's latest seq gives it back for reuse
+        let keys: Vec<_> = logs
+            .iter()
+            .rev()
+            .map(|log| (log.block_number as i64, log.log_index as i64))
+            .collect();
+        writer
+            .run(move |conn| {
+                for (block_number, log_index) in keys {
+                    delete_log(conn, block_number, log_index)?;
+                }
+                Ok(())
+            })
+            .await?;
+    }
+
+    let processor = EventProcessor::new(writer.clone())
+        .with_contract(config.contract_address)
+        .with_compression(config.compress_blobs)
+        .with_raw_logs(config.store_raw_logs)
+        .with_fts(config.enable_fts)
+        .with_event_kinds(config.index_events)
+        .with_recipient_allowlist(config.recipient_allowlist.clone())
+        .with_payload_limits(config.payload_limits);
+    for log in logs {
+        match processor.process(log).await {
+            Ok(true) => stats.events_processed += 1,
+            Ok(false) => stats.events_skipped += 1,
+            Err(e) => {
+                stats.events_failed += 1;
+                tracing::error!("Failed to reprocess event in {tx_hash}: {e}");
+            }
+        }
+    }
+
+    Ok(Some(stats))
+}
+
+/// Lowercased fragments of the errors providers return when a `getLogs` query spans
+/// too many blocks or matches too many logs. Retrying these verbatim never succeeds.
+/// Pause after a chunk that hit a full disk, before fetching the next one.
+const DISK_FULL_BACKOFF: Duration = Duration::from_secs(10);
+
+const RANGE_TOO_LARGE_ERRORS: &[&str] = &[
+    "block range",
+    "range too large",
+    "range is too large",
+    "query returned more than",
+    "response size exceeded",
+    "limited to a",
+];
+
+fn is_range_too_large(error: &str) -> bool {
+    let error = error.to_lowercase();
+    RANGE_TOO_LARGE_ERRORS.iter().any(|e| error.contains(e))
+}
+
+/// Why a failed RPC call is worth retrying, or `None` if it is not.
+fn retry_reason(error: &TransportError) -> Option<&'static str> {
+    if is_timeout(error) {
+        return Some("Timed out");
+    }
+    let message = error.to_string();
+    (message.contains("429") || message.contains("exceeded") || message.contains("rate"))
+        .then_some("Rate limited")
+}
+
+pub(super) async fn get_logs_with_retry<T: Transport + Clone, P: Provider<T>>(
+    provider: &P,
     filter: &Filter,
+    limits: &RpcLimits,
+    breaker: &CircuitBreaker,
 ) -> Result<Vec<Log>> {
     let mut attempt = 0;
     loop {
+        breaker.check()?;
         match provider.get_logs(filter).await {
-            Ok(logs) => return Ok(logs),
+            Ok(logs) => {
+                breaker.record_success();
+                return Ok(logs);
+            }
+            // The provider answered; only the range needs to shrink
+            Err(e) if is_range_too_large(&e.to_string()) => {
+                breaker.record_success();
+                let (Some(from), Some(to)) = (filter.get_from_block(), filter.get_to_block())
+                else {
+                    return Err(e.into());
+                };
+                if from >= to {
+                    return Err(e.into());
+                }
+
+                // Halve the range until the provider accepts it
+                let mid = from + (to - from) / 2;
+                tracing::warn!("getLogs for blocks {from}..={to} too large ({e}), splitting");
+                let lower = filter.clone().from_block(from).to_block(mid);
+                let upper = filter.clone().from_block(mid + 1).to_block(to);
+                let mut logs =
+                    Box::pin(get_logs_with_retry(provider, &lower, limits, breaker)).await?;
+                logs.extend(
+                    Box::pin(get_logs_with_retry(provider, &upper, limits, breaker)).await?,
+                );
+                return Ok(logs);
+            }
             Err(e) => {
-                let is_rate_limit = e.to_string().contains("429")
-                    || e.to_string().contains("exceeded")
-                    || e.to_string().contains("rate");
+                breaker.record_failure();
+                let Some(reason) = retry_reason(&e) else {
+                    return Err(e.into());
+                };
 
-                if is_rate_limit && attempt < MAX_RETRIES {
+                if attempt < limits.max_retries {
                     attempt += 1;
-                    let backoff = Duration::from_millis(INITIAL_BACKOFF_MS * 2u64.pow(attempt - 1));
+                    let backoff = Duration::from_millis(limits.backoff_ms * 2u64.pow(attempt - 1));
                     tracing::warn!(
-                        "Rate limited, retrying in {:?} (attempt {}/{})",
+                        "{reason}, retrying in {:?} (attempt {}/{})",
                         backoff,
                         attempt,
-                        MAX_RETRIES
+                        limits.max_retries
                     );
                     tokio::time::sleep(backoff).await;
                 } else {
@@ -173,35 +627,45 @@ async fn get_logs_with_retry(
     }
 }
 
-async fn fetch_block_timestamp_with_retry(
-    provider: &RootProvider<Http<Client>>,
+async fn fetch_block_timestamp_with_retry<T: Transport + Clone, P: Provider<T>>(
+    provider: &P,
     block_num: u64,
+    limits: &RpcLimits,
+    breaker: &CircuitBreaker,
 ) -> Result<u64> {
     let mut attempt = 0;
     loop {
-        match provider
+        breaker.check()?;
+        let block = provider
             .get_block_by_number(
                 BlockNumberOrTag::Number(block_num),
                 BlockTransactionsKind::Hashes,
             )
-            .await
-        {
-            Ok(Some(block)) => return Ok(block.header.timestamp),
-            Ok(None) => return Err(IndexerError::BlockNotFound(block_num)),
+            .await;
+        match block {
+            Ok(Some(block)) => {
+                breaker.record_success();
+                return Ok(block.header.timestamp);
+            }
+            Ok(None) => {
+                breaker.record_success();
+                return Err(IndexerError::BlockNotFound(block_num));
+            }
             Err(e) => {
-                let is_rate_limit = e.to_string().contains("429")
-                    || e.to_string().contains("exceeded")
-                    || e.to_string().contains("rate");
+                breaker.record_failure();
+                let Some(reason) = retry_reason(&e) else {
+                    return Err(e.into());
+                };
 
-                if is_rate_limit && attempt < MAX_RETRIES {
+                if attempt < limits.max_retries {
                     attempt += 1;
-                    let backoff = Duration::from_millis(INITIAL_BACKOFF_MS * 2u64.pow(attempt - 1));
+                    let backoff = Duration::from_millis(limits.backoff_ms * 2u64.pow(attempt - 1));
                     tracing::warn!(
-                        "Rate limited fetching block {}, retrying in {:?} (attempt {}/{})",
+                        "{reason} fetching block {}, retrying in {:?} (attempt {}/{})",
                         block_num,
                         backoff,
                         attempt,
-                        MAX_RETRIES
+                        limits.max_retries
                     );
                     tokio::time::sleep(backoff).await;
                 } else {
@@ -212,7 +676,262 @@ async fn fetch_block_timestamp_with_retry(
     }
 }
 
-#[allow(dead_code)]
 pub async fn get_chain_head(provider: &RootProvider<Http<Client>>) -> Result<u64> {
     Ok(provider.get_block_number().await?)
 }
+
+#[cfg(test)]
+mod tests {
+    use alloy::primitives::{Address, LogData, U256};
+    use alloy::sol_types::SolEvent;
+
+    use super::*;
+    use crate::db::queries::{
+        get_last_processed_block, handshakes_by_recipient, hsrs_in_response_to, messages_by_topic,
+    };
+    use crate::db::{memory_pool, DbPool};
+    use crate::indexer::events::{Handshake, HandshakeResponse, MessageSent};
+    use crate::indexer::mock_rpc::{block_timestamp, chain_log, MockRpc};
+    use crate::types::{RecipientHash, TopicHash};
+
+    const CONTRACT: Address = Address::repeat_byte(0xee);
+    const TOPIC: [u8; 32] = [7; 32];
+    const RECIPIENT: [u8; 32] = [5; 32];
+    const TAG: [u8; 32] = [9; 32];
+
+    struct Harness {
+        config: Config,
+        pool: DbPool,
+        writer: EventWriter,
+        timestamps: BlockTimestampCache,
+        retry_queue: RetryQueue,
+    }
+
+    impl Harness {
+        fn new(chunk_size: u64) -> Self {
+            let config = Config::from_vars(&[
+                ("RPC_WS_URL", "wss://rpc.example.com"),
+                ("CONTRACT_ADDRESS", &CONTRACT.to_string()),
+                ("RPC_CHUNK_SIZE", &chunk_size.to_string()),
+                ("RPC_REQUESTS_PER_SECOND", "1000"),
+                ("RPC_MAX_RETRIES", "2"),
+                ("RPC_BACKOFF_MS", "0"),
+            ])
+            .unwrap();
+            let pool = memory_pool();
+            let writer = EventWriter::spawn(&pool).unwrap();
+            Self {
+                timestamps: BlockTimestampCache::new(config.block_timestamp_cache_size),
+                retry_queue: RetryQueue::new(writer.clone()),
+                config,
+                pool,
+                writer,
+            }
+        }
+
+        async fn backfill(&self, rpc: &MockRpc, from_block: u64, to_block: u64) -> BackfillStats {
+            backfill_with_provider(
+                &self.config,
+                &rpc.provider(),
+                from_block,
+                to_block,
+                &self.writer,
+                &self.timestamps,
+                &self.retry_queue,
+                &ProgressTracker::default(),
+                &CircuitBreaker::new(&self.config.rpc_limits),
+            )
+            .await
+            .unwrap()
+        }
+
+        /// `(seq, block_number, log_index, block_timestamp)` of every stored message.
+        fn messages(&self) -> Vec<(i64, i64, i64, i64)> {
+            let conn = self.pool.get().unwrap();
+            messages_by_topic(&conn, &TopicHash(TOPIC), None, None, None, false, 100)
+                .unwrap()
+                .iter()
+                .map(|m| (m.seq, m.block_number, m.log_index, m.block_timestamp))
+                .collect()
+        }
+    }
+
+    fn message(block: u64, log_index: u64) -> Log {
+        let data = MessageSent {
+            sender: Address::repeat_byte(1),
+            ciphertext: vec![0xab; 32].into(),
+            timestamp: U256::from(1_700_000_000u64),
+            topic: B256::from(TOPIC),
+            nonce: U256::from(block),
+        }
+        .encode_log_data();
+        chain_log(CONTRACT, block, log_index, data)
+    }
+
+    fn handshake(block: u64, log_index: u64) -> Log {
+        let data = Handshake {
+            recipientHash: B256::from(RECIPIENT),
+            sender: Address::repeat_byte(1),
+            pubKeys: vec![0x01; 65].into(),
+            ephemeralPubKey: vec![0x02; 32].into(),
+            plaintextPayload: b"hi".to_vec().into(),
+        }
+        .encode_log_data();
+        chain_log(CONTRACT, block, log_index, data)
+    }
+
+    fn response(block: u64, log_index: u64) -> Log {
+        let data = HandshakeResponse {
+            inResponseTo: B256::from(TAG),
+            responder: Address::repeat_byte(2),
+            responderEphemeralR: B256::repeat_byte(3),
+            ciphertext: vec![0xcd; 64].into(),
+        }
+        .encode_log_data();
+        chain_log(CONTRACT, block, log_index, data)
+    }
+
+    fn timestamp(block: i64) -> i64 {
+        block_timestamp(block as u64) as i64
+    }
+
+    #[tokio::test]
+    async fn backfill_stores_every_event_with_seq_and_timestamp() {
+        let harness = Harness::new(3);
+        // Out of order, as some providers return them, and across chunk boundaries
+        let rpc = MockRpc::new(vec![
+            message(5, 1),
+            response(7, 0),
+            message(2, 3),
+            handshake(2, 0),
+            message(5, 0),
+            response(3, 4),
+            // Not a Verbeth event
+            chain_log(
+                CONTRACT,
+                4,
+                0,
+                LogData::new_unchecked(vec![B256::ZERO], Default::default()),
+            ),
+        ]);
+
+        let stats = harness.backfill(&rpc, 1, 8).await;
+
+        assert_eq!((stats.blocks_processed, stats.events_processed), (8, 6));
+        assert_eq!(stats.events_failed, 0);
+        assert_eq!(
+            harness.messages(),
+            [
+                (0, 2, 3, timestamp(2)),
+                (1, 5, 0, timestamp(5)),
+                (2, 5, 1, timestamp(5))
+            ]
+        );
+
+        let conn = harness.pool.get().unwrap();
+        let handshakes =
+            handshakes_by_recipient(&conn, &RecipientHash(RECIPIENT), None, 10).unwrap();
+        let handshakes: Vec<_> = handshakes
+            .iter()
+            .map(|h| (h.seq, h.block_number, h.block_timestamp))
+            .collect();
+        assert_eq!(handshakes, [(0, 2, timestamp(2))]);
+
+        let responses: Vec<_> = hsrs_in_response_to(&conn, &TAG)
+            .unwrap()
+            .iter()
+            .map(|r| (r.global_seq, r.block_number, r.block_timestamp))
+            .collect();
+        assert_eq!(responses, [(0, 3, timestamp(3)), (1, 7, timestamp(7))]);
+    }
+
+    #[tokio::test]
+    async fn backfilling_a_range_again_stores_nothing_twice() {
+        let harness = Harness::new(3);
+        let rpc = MockRpc::new(vec![message(2, 0), message(4, 1), response(6, 0)]);
+
+        harness.backfill(&rpc, 1, 6).await;
+        let stored = harness.messages();
+        let stats = harness.backfill(&rpc, 1, 6).await;
+
+        assert_eq!((stats.events_processed, stats.events_skipped), (0, 3));
+        assert_eq!(harness.messages(), stored);
+        let conn = harness.pool.get().unwrap();
+        assert_eq!(hsrs_in_response_to(&conn, &TAG).unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn ranges_the_provider_rejects_are_split_until_accepted() {
+        let harness = Harness::new(20);
+        let blocks: Vec<_> = (1..=20).step_by(3).collect();
+        let rpc = MockRpc::new(blocks.iter().map(|&b| message(b, 0)).collect()).with_max_range(5);
+
+        let stats = harness.backfill(&rpc, 1, 20).await;
+
+        assert_eq!((stats.blocks_processed, stats.events_processed), (20, 7));
+        let stored: Vec<_> = harness.messages().iter().map(|m| (m.0, m.1)).collect();
+        let expected: Vec<_> = (0..).zip(blocks.iter().map(|&b| b as i64)).collect();
+        assert_eq!(stored, expected);
+    }
+
+    #[tokio::test]
+    async fn each_block_timestamp_is_fetched_once_across_chunks() {
+        let harness = Harness::new(4);
+        // Several logs per block, with blocks on both sides of every chunk boundary
+        let rpc = MockRpc::new(vec![
+            message(4, 0),
+            message(4, 1),
+            message(5, 0),
+            message(8, 0),
+            message(9, 0),
+            message(9, 2),
+            response(9, 1),
+            message(12, 0),
+        ]);
+
+        harness.backfill(&rpc, 1, 12).await;
+        assert_eq!(rpc.calls("eth_getBlockByNumber"), 5);
+
+        // A rescan of the same range is served from the shared cache
+        harness.backfill(&rpc, 1, 12).await;
+        assert_eq!(rpc.calls("eth_getBlockByNumber"), 5);
+        assert_eq!(harness.timestamps.hits(), 5);
+    }
+
+    #[tokio::test]
+    async fn full_disk_holds_the_chunk_for_retry_without_failing_the_backfill() {
+        let harness = Harness::new(10);
+        // Each ciphertext needs pages of its own
+        let large_message = |block| {
+            let data = MessageSent {
+                sender: Address::repeat_byte(1),
+                ciphertext: vec![0xab; 16 * 1024].into(),
+                timestamp: U256::from(1_700_000_000u64),
+                topic: B256::from(TOPIC),
+                nonce: U256::from(block),
+            }
+            .encode_log_data();
+            chain_log(CONTRACT, block, 0, data)
+        };
+        let rpc = MockRpc::new(vec![large_message(2), large_message(4), large_message(6)]);
+        // The database can't grow, so every insert fails with SQLITE_FULL
+        harness
+            .writer
+            .run(|conn| {
+                let pages: i64 = conn.query_row("PRAGMA page_count", [], |row| row.get(0))?;
+                conn.pragma_update(None, "max_page_count", pages)?;
+                Ok(())
+            })
+            .await
+            .unwrap();
+
+        let stats = harness.backfill(&rpc, 1, 10).await;
+
+        assert_eq!((stats.events_processed, stats.events_failed), (0, 3));
+        assert_eq!(harness.retry_queue.len().await, 3);
+        assert!(harness.retry_queue.holds_disk_full().await);
+        assert!(harness.writer.disk_full());
+        let conn = harness.pool.get().unwrap();
+        assert_eq!(get_last_processed_block(&conn).unwrap(), None);
+    }
+}
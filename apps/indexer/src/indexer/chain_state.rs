@@ -0,0 +1,139 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Label and deepest depth of each reorg depth bucket, in order.
+pub const REORG_DEPTH_BUCKETS: [(&str, u64); 5] = [
+    ("1", 1),
+    ("2-3", 3),
+    ("4-7", 7),
+    ("8-15", 15),
+    ("16+", u64::MAX),
+];
+
+/// Chain progress observed by the indexer, shared with the API for readiness checks,
+/// plus the round-trip time and subscription support of the subscriber's RPC provider.
+#[derive(Default)]
+pub struct ChainState {
+    chain_head: AtomicU64,
+    highest_seen_block: AtomicU64,
+    /// Microseconds; 0 until the first sample
+    rpc_latency_last: AtomicU64,
+    rpc_latency_avg: AtomicU64,
+    /// Why the WebSocket endpoint was abandoned for polling, if it was
+    subscription_error: Mutex<Option<String>>,
+    /// Reorgs detected, counted per `REORG_DEPTH_BUCKETS` entry
+    reorgs: [AtomicU64; REORG_DEPTH_BUCKETS.len()],
+    max_reorg_depth: AtomicU64,
+    /// Finalized logs finality verification found unindexed, and indexed events it found
+    /// no longer on chain
+    finality_missing: AtomicU64,
+    finality_orphaned: AtomicU64,
+    verified_block: AtomicU64,
+}
+
+impl ChainState {
+    pub fn chain_head(&self) -> u64 {
+        self.chain_head.load(Ordering::Relaxed)
+    }
+
+    pub fn set_chain_head(&self, block: u64) {
+        self.chain_head.fetch_max(block, Ordering::Relaxed);
+    }
+
+    /// Highest block the live subscription has observed, including blocks with no
+    /// Verbeth events. Everything up to it has been streamed while connected.
+    pub fn highest_seen_block(&self) -> u64 {
+        self.highest_seen_block.load(Ordering::Relaxed)
+    }
+
+    pub fn observe_block(&self, block: u64) {
+        self.highest_seen_block.fetch_max(block, Ordering::Relaxed);
+        self.set_chain_head(block);
+    }
+
+    /// Record one `eth_blockNumber` round trip. The average weights each new sample
+    /// by 1/8, so it follows a degrading provider within a few minutes of probes.
+    pub fn record_rpc_latency(&self, rtt: Duration) {
+        let sample = (rtt.as_micros() as u64).max(1);
+        self.rpc_latency_last.store(sample, Ordering::Relaxed);
+        let _ = self
+            .rpc_latency_avg
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |avg| {
+                Some(if avg == 0 {
+                    sample
+                } else {
+                    (avg * 7 + sample) / 8
+                })
+            });
+    }
+
+    /// Last and moving-average RPC round-trip time, or `None` before the first sample.
+    pub fn rpc_latency(&self) -> Option<(Duration, Duration)> {
+        let last = self.rpc_latency_last.load(Ordering::Relaxed);
+        let avg = self.rpc_latency_avg.load(Ordering::Relaxed);
+        (last > 0).then(|| (Duration::from_micros(last), Duration::from_micros(avg)))
+    }
+
+    /// Record that the subscriber fell back to polling because `eth_subscribe` is rejected.
+    pub fn set_subscription_error(&self, error: String) {
+        *self.subscription_error.lock().unwrap() = Some(error);
+    }
+
+    pub fn subscription_error(&self) -> Option<String> {
+        self.subscription_error.lock().unwrap().clone()
+    }
+
+    /// Count a reorg that replaced `depth` blocks.
+    pub fn record_reorg(&self, depth: u64) {
+        let bucket = REORG_DEPTH_BUCKETS
+            .iter()
+            .position(|&(_, max)| depth <= max)
+            .unwrap_or(REORG_DEPTH_BUCKETS.len() - 1);
+        self.reorgs[bucket].fetch_add(1, Ordering::Relaxed);
+        self.max_reorg_depth.fetch_max(depth, Ordering::Relaxed);
+    }
+
+    /// Reorgs detected per depth bucket, labeled as in `REORG_DEPTH_BUCKETS`.
+    pub fn reorgs_by_depth(&self) -> [(&'static str, u64); REORG_DEPTH_BUCKETS.len()] {
+        std::array::from_fn(|i| {
+            (
+                REORG_DEPTH_BUCKETS[i].0,
+                self.reorgs[i].load(Ordering::Relaxed),
+            )
+        })
+    }
+
+    pub fn reorgs_detected(&self) -> u64 {
+        self.reorgs.iter().map(|n| n.load(Ordering::Relaxed)).sum()
+    }
+
+    /// Deepest reorg detected, 0 if none.
+    pub fn max_reorg_depth(&self) -> u64 {
+        self.max_reorg_depth.load(Ordering::Relaxed)
+    }
+
+    /// Count the discrepancies of one finality check.
+    pub fn record_finality_discrepancies(&self, missing: u64, orphaned: u64) {
+        self.finality_missing.fetch_add(missing, Ordering::Relaxed);
+        self.finality_orphaned
+            .fetch_add(orphaned, Ordering::Relaxed);
+    }
+
+    /// Finality discrepancies found so far, labeled by kind.
+    pub fn finality_discrepancies(&self) -> [(&'static str, u64); 2] {
+        [
+            ("missing", self.finality_missing.load(Ordering::Relaxed)),
+            ("orphaned", self.finality_orphaned.load(Ordering::Relaxed)),
+        ]
+    }
+
+    /// Last block checked by finality verification, 0 before the first check.
+    pub fn verified_block(&self) -> u64 {
+        self.verified_block.load(Ordering::Relaxed)
+    }
+
+    pub fn set_verified_block(&self, block: u64) {
+        self.verified_block.store(block, Ordering::Relaxed);
+    }
+}
@@ -0,0 +1,152 @@
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::config::RpcLimits;
+use crate::error::{IndexerError, Result};
+
+/// Stops HTTP RPC calls for a while once the provider fails consistently, so retries
+/// from backfill, recovery and finality checks do not burn a shared quota during an
+/// outage. Shared by every task that calls the provider over HTTP.
+///
+/// Closed, it counts consecutive failures. Reaching the threshold opens it: calls fail
+/// with [`IndexerError::RpcCircuitOpen`] without reaching the provider. After the
+/// cooldown it half-opens and lets one call through; success closes it, failure opens
+/// it for another cooldown.
+pub struct CircuitBreaker {
+    threshold: u32,
+    window: Duration,
+    cooldown: Duration,
+    state: Mutex<BreakerState>,
+}
+
+#[derive(Default)]
+struct BreakerState {
+    consecutive_failures: u32,
+    last_failure: Option<Instant>,
+    /// Calls fail fast until this instant
+    open_until: Option<Instant>,
+    /// A probe is in flight; other calls keep failing until it reports back or
+    /// `open_until` passes again
+    probing: bool,
+    times_opened: u64,
+}
+
+/// Breaker position, as reported by `/health`.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum BreakerStatus {
+    Closed,
+    Open,
+    HalfOpen,
+}
+
+impl BreakerStatus {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Closed => "closed",
+            Self::Open => "open",
+            Self::HalfOpen => "half_open",
+        }
+    }
+}
+
+impl CircuitBreaker {
+    pub fn new(limits: &RpcLimits) -> Self {
+        Self {
+            threshold: limits.breaker_threshold,
+            window: limits.breaker_window,
+            cooldown: limits.breaker_cooldown,
+            state: Mutex::new(BreakerState::default()),
+        }
+    }
+
+    /// Whether a call may go to the provider now. Past the cooldown, the first caller is
+    /// let through as the probe.
+    pub fn check(&self) -> Result<()> {
+        let mut state = self.state.lock().unwrap();
+        let Some(open_until) = state.open_until else {
+            return Ok(());
+        };
+        let now = Instant::now();
+        if now < open_until {
+            return Err(IndexerError::RpcCircuitOpen(open_until - now));
+        }
+        // A probe that never reported back (e.g. its task was cancelled) is replaced
+        // after another cooldown
+        state.probing = true;
+        state.open_until = Some(now + self.cooldown);
+        tracing::info!("RPC circuit breaker half-open, probing the provider");
+        Ok(())
+    }
+
+    pub fn record_success(&self) {
+        let mut state = self.state.lock().unwrap();
+        if state.open_until.is_some() {
+            tracing::info!("RPC circuit breaker closed, provider recovered");
+        }
+        state.consecutive_failures = 0;
+        state.last_failure = None;
+        state.open_until = None;
+        state.probing = false;
+    }
+
+    pub fn record_failure(&self) {
+        if self.threshold == 0 {
+            return;
+        }
+        let mut state = self.state.lock().unwrap();
+        let now = Instant::now();
+
+        if state.probing {
+            state.probing = false;
+            state.open_until = Some(now + self.cooldown);
+            tracing::warn!(
+                "RPC circuit breaker probe failed, staying open for {:?}",
+                self.cooldown
+            );
+            return;
+        }
+        if state.open_until.is_some() {
+            return;
+        }
+
+        let within_window = state
+            .last_failure
+            .is_some_and(|last| now.duration_since(last) <= self.window);
+        state.consecutive_failures = if within_window {
+            state.consecutive_failures + 1
+        } else {
+            1
+        };
+        state.last_failure = Some(now);
+
+        if state.consecutive_failures >= self.threshold {
+            state.open_until = Some(now + self.cooldown);
+            state.times_opened += 1;
+            tracing::error!(
+                "RPC circuit breaker open after {} consecutive failures, failing calls for {:?}",
+                state.consecutive_failures,
+                self.cooldown
+            );
+        }
+    }
+
+    pub fn status(&self) -> BreakerStatus {
+        let state = self.state.lock().unwrap();
+        match state.open_until {
+            None => BreakerStatus::Closed,
+            Some(_) if state.probing => BreakerStatus::HalfOpen,
+            Some(open_until) if Instant::now() >= open_until => BreakerStatus::HalfOpen,
+            Some(_) => BreakerStatus::Open,
+        }
+    }
+
+    /// Failures counted towards the threshold since the last success.
+    pub fn consecutive_failures(&self) -> u32 {
+        self.state.lock().unwrap().consecutive_failures
+    }
+
+    /// Times the breaker has opened since startup, not counting failed probes.
+    pub fn times_opened(&self) -> u64 {
+        self.state.lock().unwrap().times_opened
+    }
+}
@@ -0,0 +1,66 @@
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+
+use alloy::primitives::Address;
+
+use crate::error::Result;
+
+use super::processor::VerbethEvent;
+
+/// Which table a stored event went to.
+#[derive(Clone, Copy)]
+pub enum EventKind {
+    Message,
+    Handshake,
+    HandshakeResponse,
+}
+
+impl EventKind {
+    pub fn of(event: &VerbethEvent) -> Self {
+        match event {
+            VerbethEvent::MessageSent { .. } => Self::Message,
+            VerbethEvent::Handshake { .. } => Self::Handshake,
+            VerbethEvent::HandshakeResponse { .. } => Self::HandshakeResponse,
+        }
+    }
+}
+
+/// What happened to one contract's events since startup.
+#[derive(Clone, Copy, Default)]
+pub struct ContractCounts {
+    pub messages: u64,
+    pub handshakes: u64,
+    pub handshake_responses: u64,
+    /// Already indexed, or left out by `INDEX_EVENTS` or `RECIPIENT_ALLOWLIST`
+    pub skipped: u64,
+    /// Failed store attempts; an event that is retried counts once per attempt
+    pub failed: u64,
+}
+
+/// Events stored per contract, for `/metrics` and `/stats`. Processors built from the
+/// config are the only ones that report here, so `contract` label values are limited to
+/// configured contracts whatever the chain emits.
+#[derive(Default)]
+pub struct ContractMetrics {
+    contracts: Mutex<BTreeMap<Address, ContractCounts>>,
+}
+
+impl ContractMetrics {
+    pub fn record(&self, contract: Address, kind: EventKind, result: &Result<bool>) {
+        let mut contracts = self.contracts.lock().unwrap();
+        let counts = contracts.entry(contract).or_default();
+        match (result, kind) {
+            (Ok(true), EventKind::Message) => counts.messages += 1,
+            (Ok(true), EventKind::Handshake) => counts.handshakes += 1,
+            (Ok(true), EventKind::HandshakeResponse) => counts.handshake_responses += 1,
+            (Ok(false), _) => counts.skipped += 1,
+            (Err(_), _) => counts.failed += 1,
+        }
+    }
+
+    /// Counts of every contract that has reported, by address.
+    pub fn snapshot(&self) -> Vec<(Address, ContractCounts)> {
+        let contracts = self.contracts.lock().unwrap();
+        contracts.iter().map(|(a, c)| (*a, *c)).collect()
+    }
+}
@@ -0,0 +1,99 @@
+use alloy::primitives::Address;
+use alloy::providers::Provider;
+use alloy::rpc::types::Filter;
+use alloy::transports::Transport;
+
+use crate::db::queries::{cache_creation_block, get_cached_creation_block};
+use crate::db::DbPool;
+use crate::error::{IndexerError, Result};
+
+/// Lowercased fragments of `getLogs` errors for a range holding too many logs. These
+/// still answer the question the search asks: the range has logs.
+const TOO_MANY_LOGS_ERRORS: &[&str] = &[
+    "query returned more than",
+    "response size exceeded",
+    "too many logs",
+    "too many results",
+];
+
+/// Backfill floor for a contract with no configured creation block: the first block with
+/// one of its logs, cached in the DB under the contract address. A contract that has no
+/// logs yet gives `head`, which is not cached, so a later start searches again.
+pub async fn detect_creation_block<T, P>(
+    provider: &P,
+    pool: &DbPool,
+    address: Address,
+    head: u64,
+) -> Result<u64>
+where
+    T: Transport + Clone,
+    P: Provider<T>,
+{
+    let key = address.to_string();
+    if let Some(block) = get_cached_creation_block(&*pool.get()?, &key)? {
+        return Ok(block);
+    }
+
+    tracing::info!("CREATION_BLOCK not set, searching for the first log of {address}");
+    let Some(block) = find_first_log_block(provider, address, head).await? else {
+        tracing::warn!("No logs from {address} up to block {head}, starting from the head");
+        return Ok(head);
+    };
+
+    cache_creation_block(&*pool.get()?, &key, block)?;
+    Ok(block)
+}
+
+/// Bisect `[0, head]` for the lowest block holding a log from `address`. Each probe asks
+/// whether the lower half of the remaining window has any log, so the search takes about
+/// log2(head) `getLogs` calls instead of a scan of every empty block.
+async fn find_first_log_block<T, P>(
+    provider: &P,
+    address: Address,
+    head: u64,
+) -> Result<Option<u64>>
+where
+    T: Transport + Clone,
+    P: Provider<T>,
+{
+    if !has_logs(provider, address, 0, head).await? {
+        return Ok(None);
+    }
+
+    // The first log is always within [low, high]
+    let (mut low, mut high) = (0, head);
+    let mut probes = 1;
+    while low < high {
+        let mid = low + (high - low) / 2;
+        probes += 1;
+        if has_logs(provider, address, low, mid).await? {
+            high = mid;
+        } else {
+            low = mid + 1;
+        }
+    }
+
+    tracing::info!("First log of {address} is in block {low} ({probes} getLogs calls)");
+    Ok(Some(low))
+}
+
+async fn has_logs<T, P>(provider: &P, address: Address, from: u64, to: u64) -> Result<bool>
+where
+    T: Transport + Clone,
+    P: Provider<T>,
+{
+    let filter = Filter::new().address(address).from_block(from).to_block(to);
+    match provider.get_logs(&filter).await {
+        Ok(logs) => Ok(!logs.is_empty()),
+        Err(e) => {
+            let message = e.to_string().to_lowercase();
+            if TOO_MANY_LOGS_ERRORS.iter().any(|f| message.contains(f)) {
+                return Ok(true);
+            }
+            Err(IndexerError::Config(format!(
+                "Finding the creation block failed on getLogs for blocks {from}..={to}: {e}. \
+                 Set CREATION_BLOCK or CREATION_TX_HASH instead"
+            )))
+        }
+    }
+}
@@ -1,5 +1,12 @@
+use std::collections::HashSet;
+use std::sync::Arc;
+
+use alloy::primitives::{Address, B256};
+use alloy::rpc::types::Filter;
 use alloy::sol;
 
+use super::processor::{VerbethEvent, EVENT_LAYOUTS};
+
 sol! {
     event MessageSent(
         address indexed sender,
@@ -24,3 +31,195 @@ sol! {
         bytes ciphertext
     );
 }
+
+/// Later layouts of the events above. Each gets its own module, since `sol!` names the
+/// generated type after the event.
+pub mod v2 {
+    use alloy::sol;
+
+    sol! {
+        /// `MessageSent` with `nonce` indexed, so a conversation's messages can be
+        /// fetched by nonce. The signature hash is the same as v1's.
+        event MessageSent(
+            address indexed sender,
+            bytes ciphertext,
+            uint256 timestamp,
+            bytes32 indexed topic,
+            uint256 indexed nonce
+        );
+    }
+}
+
+pub use v2::MessageSent as MessageSentV2;
+
+/// Which Verbeth events get indexed (`INDEX_EVENTS`).
+#[derive(Clone, Copy, Debug)]
+pub struct EventKinds {
+    pub message: bool,
+    pub handshake: bool,
+    pub hsr: bool,
+}
+
+impl EventKinds {
+    pub const ALL: Self = Self {
+        message: true,
+        handshake: true,
+        hsr: true,
+    };
+
+    /// Parse a comma-separated, non-empty subset of `message`, `handshake` and `hsr`.
+    pub fn parse(s: &str) -> Result<Self, String> {
+        let mut kinds = Self {
+            message: false,
+            handshake: false,
+            hsr: false,
+        };
+        for name in s.split(',').map(str::trim).filter(|n| !n.is_empty()) {
+            match name {
+                "message" => kinds.message = true,
+                "handshake" => kinds.handshake = true,
+                "hsr" => kinds.hsr = true,
+                other => {
+                    return Err(format!(
+                        "unknown event {other} (expected message, handshake, or hsr)"
+                    ))
+                }
+            }
+        }
+        if !(kinds.message || kinds.handshake || kinds.hsr) {
+            return Err("at least one event type is required".into());
+        }
+        Ok(kinds)
+    }
+
+    pub fn includes(&self, event: &VerbethEvent) -> bool {
+        match event {
+            VerbethEvent::MessageSent { .. } => self.message,
+            VerbethEvent::Handshake { .. } => self.handshake,
+            VerbethEvent::HandshakeResponse { .. } => self.hsr,
+        }
+    }
+
+    /// Whether the event a layout in `EVENT_LAYOUTS` decodes to is enabled.
+    fn includes_layout(&self, event: &str) -> bool {
+        match event {
+            "MessageSent" => self.message,
+            "Handshake" => self.handshake,
+            "HandshakeResponse" => self.hsr,
+            _ => false,
+        }
+    }
+
+    /// topic0 of every known layout of the enabled events, each hash once.
+    fn signature_hashes(&self) -> Vec<B256> {
+        let mut hashes = Vec::new();
+        for &(hash, event, _) in EVENT_LAYOUTS {
+            if self.includes_layout(event) && !hashes.contains(&hash) {
+                hashes.push(hash);
+            }
+        }
+        hashes
+    }
+}
+
+/// Topics and recipient hashes whose events get indexed (`RECIPIENT_ALLOWLIST`). Messages
+/// match on `topic` and handshakes on `recipient_hash`. These are separate namespaces, so
+/// an identity needs each of its values listed. Handshake responses carry neither and always
+/// pass. Unset, every event passes.
+#[derive(Clone, Debug, Default)]
+pub struct RecipientAllowlist(Option<Arc<HashSet<B256>>>);
+
+impl RecipientAllowlist {
+    /// Parse comma-separated 32-byte hex hashes. An empty list leaves the filter off.
+    pub fn parse(s: &str) -> Result<Self, String> {
+        let hashes = s
+            .split(',')
+            .map(str::trim)
+            .filter(|h| !h.is_empty())
+            .map(|h| {
+                h.parse::<B256>()
+                    .map_err(|_| format!("{h} is not a 32-byte hex hash"))
+            })
+            .collect::<Result<HashSet<_>, _>>()?;
+        Ok(Self((!hashes.is_empty()).then(|| Arc::new(hashes))))
+    }
+
+    /// Number of listed hashes, or `None` when the filter is off.
+    pub fn entries(&self) -> Option<usize> {
+        self.0.as_ref().map(|hashes| hashes.len())
+    }
+
+    pub fn includes(&self, event: &VerbethEvent) -> bool {
+        let Some(hashes) = &self.0 else {
+            return true;
+        };
+        match event {
+            VerbethEvent::MessageSent { topic, .. } => hashes.contains(topic),
+            VerbethEvent::Handshake { recipient_hash, .. } => hashes.contains(recipient_hash),
+            VerbethEvent::HandshakeResponse { .. } => true,
+        }
+    }
+}
+
+/// Log filter for the enabled Verbeth events on `contract_address`.
+///
+/// The signature hashes go straight into `event_signature`, which ORs them as a topic0
+/// set. `Filter::events` would keccak them a second time and match nothing.
+pub fn verbeth_filter(contract_address: Address, kinds: EventKinds) -> Filter {
+    Filter::new()
+        .address(contract_address)
+        .event_signature(kinds.signature_hashes())
+}
+
+#[cfg(test)]
+mod tests {
+    use alloy::primitives::keccak256;
+    use alloy::sol_types::SolEvent;
+
+    use super::*;
+
+    #[test]
+    fn every_layout_is_subscribed_to() {
+        let hashes = EventKinds::ALL.signature_hashes();
+        for &(hash, event, _) in EVENT_LAYOUTS {
+            assert!(
+                hashes.contains(&hash),
+                "{event} layout missing from the filter"
+            );
+        }
+        // v1 and v2 of MessageSent share one topic0
+        assert_eq!(hashes.len(), 3);
+    }
+
+    #[test]
+    fn filter_ors_the_three_signatures() {
+        let address = Address::repeat_byte(0xee);
+        let filter = verbeth_filter(address, EventKinds::ALL);
+
+        for hash in [
+            MessageSent::SIGNATURE_HASH,
+            Handshake::SIGNATURE_HASH,
+            HandshakeResponse::SIGNATURE_HASH,
+        ] {
+            assert!(filter.topics[0].matches(&hash));
+        }
+        for other in ["Upgraded(address)", "OwnershipTransferred(address,address)"] {
+            assert!(!filter.topics[0].matches(&keccak256(other)));
+        }
+        assert!(filter.address.matches(&address));
+        assert!(!filter.address.matches(&Address::repeat_byte(0xef)));
+
+        // Sent as one topic0 array, which the node ORs
+        let json = serde_json::to_value(&filter).unwrap();
+        assert_eq!(json["topics"][0].as_array().map(Vec::len), Some(3));
+    }
+
+    #[test]
+    fn filter_leaves_out_disabled_events() {
+        let kinds = EventKinds::parse("message").unwrap();
+        let filter = verbeth_filter(Address::ZERO, kinds);
+        assert!(filter.topics[0].matches(&MessageSent::SIGNATURE_HASH));
+        assert!(!filter.topics[0].matches(&Handshake::SIGNATURE_HASH));
+        assert!(!filter.topics[0].matches(&HandshakeResponse::SIGNATURE_HASH));
+    }
+}
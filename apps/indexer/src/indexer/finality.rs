@@ -0,0 +1,245 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::Arc;
+use std::time::Duration;
+
+use governor::{Quota, RateLimiter};
+use tokio::sync::watch;
+
+use crate::config::Config;
+use crate::db::queries::{
+    dead_lettered_logs_in_range, delete_log, get_last_processed_block, get_verified_block,
+    indexed_logs_in_range, set_verified_block,
+};
+use crate::db::DbPool;
+use crate::error::Result;
+
+use super::backfill::{get_chain_head, get_logs_with_retry, run_backfill, ProgressTracker};
+use super::chain_state::ChainState;
+use super::circuit_breaker::CircuitBreaker;
+use super::events::verbeth_filter;
+use super::processor::decode_log;
+use super::retry_queue::RetryQueue;
+use super::rpc::http_provider;
+use super::timestamp_cache::BlockTimestampCache;
+use super::writer::EventWriter;
+
+/// Time between verification passes.
+const CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Once blocks have `depth` blocks on top, compare the events indexed from them with a
+/// fresh `getLogs` of the canonical chain, and correct the index where they differ.
+///
+/// Events indexed near the tip can come from a block that is later reorged away without
+/// the subscriber noticing, e.g. across a reconnect past `REORG_CHECK_DEPTH`. Rows the
+/// chain no longer has are deleted, and logs missing from the index are re-indexed.
+/// Dead-lettered logs are left to `/admin/dead-letters`. Progress is kept in the DB, so a
+/// restart picks up after the last checked block.
+#[allow(clippy::too_many_arguments)]
+pub async fn run_finality_checks(
+    config: Arc<Config>,
+    pool: DbPool,
+    writer: EventWriter,
+    timestamps: Arc<BlockTimestampCache>,
+    retry_queue: Arc<RetryQueue>,
+    progress: ProgressTracker,
+    chain_state: Arc<ChainState>,
+    breaker: Arc<CircuitBreaker>,
+    depth: u64,
+    mut shutdown: watch::Receiver<bool>,
+) {
+    let checker = FinalityChecker {
+        config: &config,
+        pool: &pool,
+        writer: &writer,
+        timestamps: &timestamps,
+        retry_queue: &retry_queue,
+        progress: &progress,
+        chain_state: &chain_state,
+        breaker: &breaker,
+        depth,
+    };
+
+    loop {
+        tokio::select! {
+            _ = tokio::time::sleep(CHECK_INTERVAL) => {}
+            _ = shutdown.changed() => {
+                if *shutdown.borrow() {
+                    return;
+                }
+            }
+        }
+
+        // Stop mid-pass on shutdown; the next start resumes after the last checked chunk
+        tokio::select! {
+            result = checker.verify_final_blocks() => {
+                if let Err(e) = result {
+                    tracing::warn!("Finality check failed: {e}");
+                }
+            }
+            _ = shutdown.changed() => {
+                if *shutdown.borrow() {
+                    return;
+                }
+            }
+        }
+    }
+}
+
+struct FinalityChecker<'a> {
+    config: &'a Config,
+    pool: &'a DbPool,
+    writer: &'a EventWriter,
+    timestamps: &'a BlockTimestampCache,
+    retry_queue: &'a RetryQueue,
+    progress: &'a ProgressTracker,
+    chain_state: &'a ChainState,
+    breaker: &'a CircuitBreaker,
+    depth: u64,
+}
+
+impl FinalityChecker<'_> {
+    async fn verify_final_blocks(&self) -> Result<()> {
+        let config = self.config;
+        let provider = http_provider(
+            &config.http_rpc_url(),
+            config.rpc_auth_header.as_deref(),
+            config.rpc_limits.timeout,
+        )?;
+        let head = get_chain_head(&provider).await?;
+
+        let Some(last_block) = get_last_processed_block(&*self.pool.get()?)? else {
+            return Ok(());
+        };
+        let last_block = last_block as u64;
+        let to_block = head.saturating_sub(self.depth).min(last_block);
+        let from_block = match get_verified_block(&*self.pool.get()?)? {
+            Some(block) => block as u64 + 1,
+            // First run: start with what was indexed while still inside the window
+            None => (last_block + 1)
+                .saturating_sub(self.depth)
+                .max(config.creation_block),
+        };
+        if from_block > to_block {
+            return Ok(());
+        }
+
+        let limiter = RateLimiter::direct(Quota::per_second(config.rpc_limits.requests_per_second));
+        for chunk_start in (from_block..=to_block).step_by(config.rpc_chunk_size as usize) {
+            let chunk_end = (chunk_start + config.rpc_chunk_size - 1).min(to_block);
+            limiter.until_ready().await;
+
+            let filter = verbeth_filter(config.contract_address, config.index_events)
+                .from_block(chunk_start)
+                .to_block(chunk_end);
+            let logs =
+                get_logs_with_retry(&provider, &filter, &config.rpc_limits, self.breaker).await?;
+            // Keyed like the index; logs the processor would not store are left out
+            let on_chain: BTreeMap<(i64, i64), Option<[u8; 32]>> = logs
+                .iter()
+                .filter(|log| match decode_log(log) {
+                    Ok(Some(event)) => {
+                        config.index_events.includes(&event)
+                            && config.recipient_allowlist.includes(&event)
+                    }
+                    _ => false,
+                })
+                .filter_map(|log| {
+                    let key = (log.block_number? as i64, log.log_index? as i64);
+                    Some((key, log.block_hash.map(|h| h.0)))
+                })
+                .collect();
+
+            self.reconcile(chunk_start, chunk_end, &on_chain).await?;
+
+            self.writer
+                .run(move |conn| set_verified_block(conn, chunk_end as i64))
+                .await?;
+            self.chain_state.set_verified_block(chunk_end);
+        }
+        Ok(())
+    }
+
+    /// Delete indexed events missing from `on_chain` (or stored under another block hash),
+    /// then re-index the range if anything was missing or deleted.
+    async fn reconcile(
+        &self,
+        from_block: u64,
+        to_block: u64,
+        on_chain: &BTreeMap<(i64, i64), Option<[u8; 32]>>,
+    ) -> Result<()> {
+        let (orphaned, missing) = {
+            let conn = self.pool.get()?;
+            let indexed = indexed_logs_in_range(&conn, from_block as i64, to_block as i64)?;
+            let dead_lettered: BTreeSet<(i64, i64)> =
+                dead_lettered_logs_in_range(&conn, from_block as i64, to_block as i64)?
+                    .into_iter()
+                    .collect();
+
+            // Newest first, as `delete_log` needs to roll seq counters back
+            let orphaned: Vec<(i64, i64)> = indexed
+                .iter()
+                .filter(|&&(block_number, log_index, hash)| {
+                    match on_chain.get(&(block_number, log_index)) {
+                        None => true,
+                        Some(chain_hash) => {
+                            hash.is_some() && chain_hash.is_some() && hash != *chain_hash
+                        }
+                    }
+                })
+                .map(|&(block_number, log_index, _)| (block_number, log_index))
+                .collect();
+            let indexed: BTreeSet<(i64, i64)> = indexed
+                .into_iter()
+                .map(|(block_number, log_index, _)| (block_number, log_index))
+                .collect();
+            let missing = on_chain
+                .keys()
+                .filter(|key| !indexed.contains(key) && !dead_lettered.contains(key))
+                .count() as u64;
+
+            (orphaned, missing)
+        };
+
+        for &(block_number, log_index) in &orphaned {
+            tracing::warn!(
+                block = block_number,
+                log_index = log_index,
+                "Indexed event is not in the finalized chain, deleting"
+            );
+        }
+        let orphaned = self
+            .writer
+            .run(move |conn| {
+                for &(block_number, log_index) in &orphaned {
+                    delete_log(conn, block_number, log_index)?;
+                }
+                Ok(orphaned.len() as u64)
+            })
+            .await?;
+
+        if orphaned == 0 && missing == 0 {
+            return Ok(());
+        }
+        self.chain_state
+            .record_finality_discrepancies(missing, orphaned);
+        tracing::warn!(
+            "Finality check of blocks {from_block} to {to_block}: {missing} events missing, \
+             {orphaned} orphaned, re-indexing"
+        );
+
+        let config = self.config;
+        run_backfill(
+            config,
+            &config.http_rpc_url(),
+            from_block,
+            to_block,
+            self.writer,
+            self.timestamps,
+            self.retry_queue,
+            self.progress,
+            self.breaker,
+        )
+        .await?;
+        Ok(())
+    }
+}
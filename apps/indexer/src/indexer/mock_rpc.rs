@@ -0,0 +1,176 @@
+//! In-process JSON-RPC node for tests. Serves `eth_getLogs` and `eth_getBlockByNumber`
+//! from a fixed set of logs, counts every call, and can be told to fail some of them.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+
+use alloy::primitives::{Address, LogData, B256};
+use alloy::providers::RootProvider;
+use alloy::rpc::client::RpcClient;
+use alloy::rpc::json_rpc::{
+    ErrorPayload, RequestPacket, Response, ResponsePacket, ResponsePayload, SerializedRequest,
+};
+use alloy::rpc::types::{Block, Log};
+use alloy::transports::{TransportError, TransportFut};
+use serde_json::Value;
+use tower::Service;
+
+/// Timestamp the mock node reports for `block`.
+pub fn block_timestamp(block: u64) -> u64 {
+    1_700_000_000 + block * 2
+}
+
+/// Log as `eth_getLogs` returns it, emitted by `address` at a chain position.
+pub fn chain_log(address: Address, block: u64, log_index: u64, data: LogData) -> Log {
+    Log {
+        inner: alloy::primitives::Log { address, data },
+        block_hash: Some(B256::with_last_byte(block as u8)),
+        block_number: Some(block),
+        block_timestamp: None,
+        transaction_hash: Some(B256::repeat_byte(log_index as u8 + 1)),
+        transaction_index: Some(0),
+        log_index: Some(log_index),
+        removed: false,
+    }
+}
+
+#[derive(Clone, Default)]
+pub struct MockRpc(Arc<Mutex<MockState>>);
+
+#[derive(Default)]
+struct MockState {
+    logs: Vec<Log>,
+    /// Widest `eth_getLogs` range accepted, in blocks
+    max_range: Option<u64>,
+    /// Remaining rate-limit errors per block for `eth_getBlockByNumber`
+    block_failures: HashMap<u64, u32>,
+    calls: HashMap<String, usize>,
+}
+
+impl MockRpc {
+    pub fn new(logs: Vec<Log>) -> Self {
+        Self(Arc::new(Mutex::new(MockState {
+            logs,
+            ..Default::default()
+        })))
+    }
+
+    /// Reject `eth_getLogs` over more than `blocks` blocks, the way capped providers do.
+    pub fn with_max_range(self, blocks: u64) -> Self {
+        self.0.lock().unwrap().max_range = Some(blocks);
+        self
+    }
+
+    /// Answer the next `times` fetches of `block` with a rate-limit error.
+    pub fn fail_block(&self, block: u64, times: u32) {
+        self.0.lock().unwrap().block_failures.insert(block, times);
+    }
+
+    /// Calls made to `method` so far, failed ones included.
+    pub fn calls(&self, method: &str) -> usize {
+        self.0
+            .lock()
+            .unwrap()
+            .calls
+            .get(method)
+            .copied()
+            .unwrap_or(0)
+    }
+
+    pub fn provider(&self) -> RootProvider<Self> {
+        RootProvider::new(RpcClient::new(self.clone(), true))
+    }
+
+    fn respond(&self, request: &SerializedRequest) -> Response {
+        let params: Value = request
+            .params()
+            .map(|params| serde_json::from_str(params.get()).unwrap())
+            .unwrap_or_default();
+
+        let mut state = self.0.lock().unwrap();
+        *state.calls.entry(request.method().into()).or_default() += 1;
+        let result = match request.method() {
+            "eth_getLogs" => state.get_logs(&params[0]),
+            "eth_getBlockByNumber" => state.get_block(&params[0]),
+            method => Err(error(-32601, format!("method {method} not found"))),
+        };
+
+        Response {
+            id: request.id().clone(),
+            payload: match result {
+                Ok(value) => {
+                    ResponsePayload::Success(serde_json::value::to_raw_value(&value).unwrap())
+                }
+                Err(e) => ResponsePayload::Failure(e),
+            },
+        }
+    }
+}
+
+impl MockState {
+    fn get_logs(&self, filter: &Value) -> Result<Value, ErrorPayload> {
+        let from = quantity(&filter["fromBlock"]);
+        let to = quantity(&filter["toBlock"]);
+        if let Some(max) = self.max_range.filter(|&max| to - from + 1 > max) {
+            return Err(error(
+                -32005,
+                format!("block range is limited to {max} blocks"),
+            ));
+        }
+
+        let logs: Vec<_> = self
+            .logs
+            .iter()
+            .filter(|log| (from..=to).contains(&log.block_number.unwrap()))
+            .collect();
+        Ok(serde_json::to_value(logs).unwrap())
+    }
+
+    fn get_block(&mut self, number: &Value) -> Result<Value, ErrorPayload> {
+        let number = quantity(number);
+        if let Some(failures) = self.block_failures.get_mut(&number).filter(|f| **f > 0) {
+            *failures -= 1;
+            return Err(error(429, "rate limited"));
+        }
+
+        let mut block = Block::<alloy::rpc::types::Transaction>::default();
+        block.header.hash = B256::with_last_byte(number as u8);
+        block.header.inner.number = number;
+        block.header.inner.timestamp = block_timestamp(number);
+        Ok(serde_json::to_value(block).unwrap())
+    }
+}
+
+fn quantity(value: &Value) -> u64 {
+    let hex = value.as_str().expect("block number is a hex quantity");
+    u64::from_str_radix(hex.trim_start_matches("0x"), 16).unwrap()
+}
+
+fn error(code: i64, message: impl Into<String>) -> ErrorPayload {
+    ErrorPayload {
+        code,
+        message: message.into().into(),
+        data: None,
+    }
+}
+
+impl Service<RequestPacket> for MockRpc {
+    type Response = ResponsePacket;
+    type Error = TransportError;
+    type Future = TransportFut<'static>;
+
+    fn poll_ready(&mut self, _: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, request: RequestPacket) -> Self::Future {
+        let response = match request {
+            RequestPacket::Single(request) => ResponsePacket::Single(self.respond(&request)),
+            RequestPacket::Batch(requests) => {
+                ResponsePacket::Batch(requests.iter().map(|r| self.respond(r)).collect())
+            }
+        };
+        Box::pin(async move { Ok(response) })
+    }
+}
@@ -1,5 +1,16 @@
 pub mod backfill;
+pub mod chain_state;
+pub mod circuit_breaker;
+pub mod contract_metrics;
+pub mod discovery;
 pub mod events;
+pub mod finality;
+#[cfg(test)]
+pub mod mock_rpc;
 pub mod processor;
+pub mod rescan;
 pub mod retry_queue;
+pub mod rpc;
 pub mod subscriber;
+pub mod timestamp_cache;
+pub mod writer;
@@ -1,23 +1,31 @@
-use alloy::primitives::{Address, B256};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use alloy::primitives::{Address, Log as PrimitiveLog, B256};
 use alloy::rpc::types::Log;
+use alloy::sol_types::SolEvent;
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use tokio::sync::broadcast;
 
+use crate::config::PayloadLimits;
 use crate::db::models::{HandshakeRow, HsrRow, MessageRow};
 use crate::db::queries::{
-    get_and_increment_seq, insert_handshake, insert_hsr, insert_message,
+    advance_last_processed_block, get_and_increment_seq, handshakes_with_raw_log,
+    hsrs_with_raw_log, index_handshake_payload, insert_handshake, insert_hsr, insert_message,
+    is_log_indexed, messages_with_raw_log, unindex_handshake_payload, update_handshake_fields,
+    update_hsr_fields, update_message_fields,
 };
-use crate::db::DbPool;
 use crate::error::{IndexerError, Result};
+use crate::types::{EphemeralKey, RecipientHash, SenderAddress, TopicHash};
 
-use super::events::{Handshake, HandshakeResponse, MessageSent};
-
-// Payload size limits (reasonable for Verbeth protocol)
-const MAX_CIPHERTEXT_SIZE: usize = 64 * 1024;       // 64 KB per message
-const MAX_PUB_KEYS_SIZE: usize = 65;                // version + X25519 + Ed25519
-const MAX_EPHEMERAL_KEY_SIZE: usize = 1216;         // X25519 32 + ML-KEM 1184
-const MAX_PLAINTEXT_PAYLOAD_SIZE: usize = 1024;     // 1 KB metadata
-const MAX_HSR_CIPHERTEXT_SIZE: usize = 4 * 1024;    // 4 KB handshake response
+use super::contract_metrics::EventKind;
+use super::events::{
+    EventKinds, Handshake, HandshakeResponse, MessageSent, MessageSentV2, RecipientAllowlist,
+};
+use super::writer::EventWriter;
 
-#[derive(Clone)]
+#[derive(Clone, Serialize, Deserialize)]
 pub enum VerbethEvent {
     MessageSent {
         sender: Address,
@@ -41,54 +49,80 @@ pub enum VerbethEvent {
     },
 }
 
-#[derive(Clone)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct LogWithMeta {
     pub event: VerbethEvent,
     pub block_number: u64,
     pub log_index: u64,
     pub block_timestamp: u64,
+    /// Absent for pending logs, and in dead-letter payloads written before it existed
+    #[serde(default)]
+    pub tx_hash: Option<B256>,
+    /// Same as `tx_hash`; used to detect reorged blocks on recovery
+    #[serde(default)]
+    pub block_hash: Option<B256>,
+    /// The log `event` was decoded from, kept only when raw logs are stored
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub raw_log: Option<Box<PrimitiveLog>>,
+}
+
+impl LogWithMeta {
+    pub fn chain_position(&self) -> (u64, u64) {
+        (self.block_number, self.log_index)
+    }
+}
+
+/// Where a log sits on chain. Seqs are handed out in processing order, so every path
+/// that feeds [`EventProcessor::process`] sorts its logs by this first.
+pub fn chain_position(log: &Log) -> (u64, u64) {
+    (log.block_number.unwrap_or(0), log.log_index.unwrap_or(0))
 }
 
-fn validate_payload_sizes(event: &VerbethEvent) -> Result<()> {
+fn validate_payload_sizes(event: &VerbethEvent, limits: &PayloadLimits) -> Result<()> {
     match event {
         VerbethEvent::MessageSent { ciphertext, .. } => {
-            if ciphertext.len() > MAX_CIPHERTEXT_SIZE {
+            if ciphertext.len() > limits.ciphertext {
                 return Err(IndexerError::PayloadTooLarge {
                     field: "ciphertext",
                     size: ciphertext.len(),
-                    max: MAX_CIPHERTEXT_SIZE,
+                    max: limits.ciphertext,
                 });
             }
         }
-        VerbethEvent::Handshake { pub_keys, ephemeral_pub_key, plaintext_payload, .. } => {
-            if pub_keys.len() > MAX_PUB_KEYS_SIZE {
+        VerbethEvent::Handshake {
+            pub_keys,
+            ephemeral_pub_key,
+            plaintext_payload,
+            ..
+        } => {
+            if pub_keys.len() > limits.pub_keys {
                 return Err(IndexerError::PayloadTooLarge {
                     field: "pubKeys",
                     size: pub_keys.len(),
-                    max: MAX_PUB_KEYS_SIZE,
+                    max: limits.pub_keys,
                 });
             }
-            if ephemeral_pub_key.len() > MAX_EPHEMERAL_KEY_SIZE {
+            if ephemeral_pub_key.len() > limits.ephemeral_key {
                 return Err(IndexerError::PayloadTooLarge {
                     field: "ephemeralPubKey",
                     size: ephemeral_pub_key.len(),
-                    max: MAX_EPHEMERAL_KEY_SIZE,
+                    max: limits.ephemeral_key,
                 });
             }
-            if plaintext_payload.len() > MAX_PLAINTEXT_PAYLOAD_SIZE {
+            if plaintext_payload.len() > limits.plaintext_payload {
                 return Err(IndexerError::PayloadTooLarge {
                     field: "plaintextPayload",
                     size: plaintext_payload.len(),
-                    max: MAX_PLAINTEXT_PAYLOAD_SIZE,
+                    max: limits.plaintext_payload,
                 });
             }
         }
         VerbethEvent::HandshakeResponse { ciphertext, .. } => {
-            if ciphertext.len() > MAX_HSR_CIPHERTEXT_SIZE {
+            if ciphertext.len() > limits.hsr_ciphertext {
                 return Err(IndexerError::PayloadTooLarge {
                     field: "hsrCiphertext",
                     size: ciphertext.len(),
-                    max: MAX_HSR_CIPHERTEXT_SIZE,
+                    max: limits.hsr_ciphertext,
                 });
             }
         }
@@ -96,20 +130,305 @@ fn validate_payload_sizes(event: &VerbethEvent) -> Result<()> {
     Ok(())
 }
 
+/// A row that was just inserted, published to live subscribers (streams, webhooks).
+#[derive(Clone)]
+pub enum IndexedEvent {
+    Message(MessageRow),
+    Handshake(HandshakeRow),
+    HandshakeResponse(HsrRow),
+}
+
+/// How events are stored and published. Cheap to clone: each event is handed to the
+/// [`EventWriter`] together with a copy of the processor that produced it.
+#[derive(Clone)]
 pub struct EventProcessor {
-    pool: DbPool,
+    writer: EventWriter,
+    contract: Option<Address>,
+    events: Option<broadcast::Sender<IndexedEvent>>,
+    compress_blobs: bool,
+    raw_logs: bool,
+    fts: bool,
+    kinds: EventKinds,
+    allowlist: RecipientAllowlist,
+    payload_limits: PayloadLimits,
 }
 
 impl EventProcessor {
-    pub fn new(pool: DbPool) -> Self {
-        Self { pool }
+    pub fn new(writer: EventWriter) -> Self {
+        Self {
+            writer,
+            contract: None,
+            events: None,
+            compress_blobs: false,
+            raw_logs: false,
+            fts: false,
+            kinds: EventKinds::ALL,
+            allowlist: RecipientAllowlist::default(),
+            payload_limits: PayloadLimits::default(),
+        }
+    }
+
+    /// Whether the writer's last write failed because the disk is full.
+    pub fn disk_full(&self) -> bool {
+        self.writer.disk_full()
+    }
+
+    /// The writer this processor stores through, for writes that go with its events.
+    pub fn writer(&self) -> &EventWriter {
+        &self.writer
+    }
+
+    /// Count this processor's events under `contract` in the writer's
+    /// [`ContractMetrics`](super::contract_metrics::ContractMetrics). Processors without
+    /// one are not counted.
+    pub fn with_contract(mut self, contract: Address) -> Self {
+        self.contract = Some(contract);
+        self
+    }
+
+    /// Publish newly inserted rows on `events`. Sends are best-effort: having no
+    /// receivers, or receivers that lag, never fails processing.
+    pub fn with_events(mut self, events: broadcast::Sender<IndexedEvent>) -> Self {
+        self.events = Some(events);
+        self
+    }
+
+    /// zstd-compress large blobs on insert. Reads decode either format.
+    pub fn with_compression(mut self, compress_blobs: bool) -> Self {
+        self.compress_blobs = compress_blobs;
+        self
+    }
+
+    /// Store each event's `raw_log` alongside its row.
+    pub fn with_raw_logs(mut self, raw_logs: bool) -> Self {
+        self.raw_logs = raw_logs;
+        self
     }
 
-    pub fn process(&self, log: LogWithMeta) -> Result<bool> {
+    /// Whether callers should attach `raw_log` to the logs they hand over.
+    pub fn stores_raw_logs(&self) -> bool {
+        self.raw_logs
+    }
+
+    /// Mirror handshake payloads into `handshakes_fts`, which must exist.
+    pub fn with_fts(mut self, fts: bool) -> Self {
+        self.fts = fts;
+        self
+    }
+
+    /// Skip event types outside `kinds` without touching the DB.
+    pub fn with_event_kinds(mut self, kinds: EventKinds) -> Self {
+        self.kinds = kinds;
+        self
+    }
+
+    /// Skip messages and handshakes whose topic or recipient hash is not in `allowlist`.
+    pub fn with_recipient_allowlist(mut self, allowlist: RecipientAllowlist) -> Self {
+        self.allowlist = allowlist;
+        self
+    }
+
+    /// Override the default payload size limits.
+    pub fn with_payload_limits(mut self, payload_limits: PayloadLimits) -> Self {
+        self.payload_limits = payload_limits;
+        self
+    }
+
+    fn publish(&self, event: IndexedEvent) {
+        if let Some(events) = &self.events {
+            let _ = events.send(event);
+        }
+    }
+
+    /// Store one event under the next seq of its topic or recipient (or the next global
+    /// handshake-response seq) on the shared [`EventWriter`]. Seqs follow the order events
+    /// reach the writer, not block order: callers pass logs sorted by [`chain_position`],
+    /// one producer per range. Only an event retried after newer events of the same key,
+    /// or a backfill of an older gap, lands out of order, which `GET /admin/verify` reports.
+    pub async fn process(&self, log: LogWithMeta) -> Result<bool> {
+        let Some(contract) = self.contract else {
+            return self.writer.write(self.clone(), log).await;
+        };
+        let kind = EventKind::of(&log.event);
+        let result = self.writer.write(self.clone(), log).await;
+        self.writer
+            .contract_metrics()
+            .record(contract, kind, &result);
+        result
+    }
+
+    /// `process` against a caller-provided connection, bypassing the writer. This is
+    /// what the writer itself runs; elsewhere it suits e.g. an in-memory DB.
+    pub fn process_with_conn(&self, conn: &Connection, log: LogWithMeta) -> Result<bool> {
+        let Some(event) = self.store(conn, log)? else {
+            return Ok(false);
+        };
+        self.publish(event);
+        Ok(true)
+    }
+
+    /// Store a chunk of logs in one transaction, together with the `checkpoint` of the
+    /// range they came from, through the shared [`EventWriter`]. Returns one result per log.
+    pub async fn process_chunk(
+        &self,
+        logs: Vec<LogWithMeta>,
+        checkpoint: Option<u64>,
+    ) -> Result<Vec<Result<bool>>> {
+        let Some(contract) = self.contract else {
+            return self
+                .writer
+                .write_chunk(self.clone(), logs, checkpoint)
+                .await;
+        };
+        let kinds: Vec<_> = logs.iter().map(|log| EventKind::of(&log.event)).collect();
+        let results = self
+            .writer
+            .write_chunk(self.clone(), logs, checkpoint)
+            .await?;
+        for (kind, result) in kinds.into_iter().zip(&results) {
+            self.writer
+                .contract_metrics()
+                .record(contract, kind, result);
+        }
+        Ok(results)
+    }
+
+    /// `process_chunk` against a caller-provided connection. The chunk commits once, so a
+    /// dense range costs one sync instead of one per event, and a crash never leaves the
+    /// checkpoint ahead of or behind its events. Rows are published only after the commit.
+    /// If any log fails, the whole chunk rolls back and is stored log by log instead, so
+    /// one bad event only fails itself.
+    pub fn process_chunk_with_conn(
+        &self,
+        conn: &Connection,
+        logs: Vec<LogWithMeta>,
+        checkpoint: Option<u64>,
+    ) -> Result<Vec<Result<bool>>> {
+        conn.execute("BEGIN IMMEDIATE", [])?;
+
+        let stored = (|| -> Result<Vec<Option<IndexedEvent>>> {
+            let stored = logs
+                .iter()
+                .map(|log| self.store(conn, log.clone()))
+                .collect::<Result<Vec<_>>>()?;
+            if let Some(block) = checkpoint {
+                advance_last_processed_block(conn, block as i64)?;
+            }
+            conn.execute("COMMIT", [])?;
+            Ok(stored)
+        })();
+
+        match stored {
+            Ok(stored) => Ok(stored
+                .into_iter()
+                .map(|event| {
+                    let inserted = event.is_some();
+                    if let Some(event) = event {
+                        self.publish(event);
+                    }
+                    Ok(inserted)
+                })
+                .collect()),
+            // Storing log by log would fail the same way, and the checkpoint must not
+            // move past events that were never stored
+            Err(e) if e.is_disk_full() => {
+                let _ = conn.execute("ROLLBACK", []);
+                Err(e)
+            }
+            Err(e) => {
+                let _ = conn.execute("ROLLBACK", []);
+                tracing::debug!("Chunk transaction failed ({e}), storing its events one by one");
+                let results: Vec<_> = logs
+                    .into_iter()
+                    .map(|log| self.process_with_conn(conn, log))
+                    .collect();
+                // The disk may have filled partway through
+                let disk_full = results
+                    .iter()
+                    .any(|r| r.as_ref().is_err_and(IndexerError::is_disk_full));
+                if let Some(block) = checkpoint.filter(|_| !disk_full) {
+                    advance_last_processed_block(conn, block as i64)?;
+                }
+                Ok(results)
+            }
+        }
+    }
+
+    /// Insert one event, returning the row if it is new. Publishing is left to the caller,
+    /// which knows when the row is committed.
+    fn store(&self, conn: &Connection, log: LogWithMeta) -> Result<Option<IndexedEvent>> {
+        // Address-only getLogs fallbacks can still deliver disabled event types
+        if !self.kinds.includes(&log.event) {
+            return Ok(None);
+        }
+        // Never stored at all, so the DB holds nothing about identities outside the list
+        if !self.allowlist.includes(&log.event) {
+            return Ok(None);
+        }
+
         // Validate payload sizes before processing
-        validate_payload_sizes(&log.event)?;
+        if let Err(e) = validate_payload_sizes(&log.event, &self.payload_limits) {
+            tracing::warn!(
+                block = log.block_number,
+                log_index = log.log_index,
+                "Rejected event: {e}. Raise the matching MAX_*_SIZE if the protocol allows it"
+            );
+            return Err(e);
+        }
+
+        // A zero timestamp means the block fetch failed upstream; persisting it would put
+        // the row at 1970 for every time-range query
+        if log.block_timestamp == 0 {
+            return Err(IndexerError::MissingTimestamp(log.block_number));
+        }
+
+        // Replayed logs must not consume a new seq; the unique (block_number, log_index)
+        // index backs this up via INSERT OR IGNORE.
+        if is_log_indexed(conn, log.block_number as i64, log.log_index as i64)? {
+            return Ok(None);
+        }
+
+        // First-seen time, so consumers can tell live rows from backfilled ones by lag
+        let indexed_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs() as i64);
+
+        // The seq is allocated in the same transaction as the insert, so an insert that
+        // fails or is ignored gives its seq back instead of leaving a permanent gap. Inside
+        // a chunk transaction a savepoint scopes the rollback to this event.
+        let own_transaction = conn.is_autocommit();
+        conn.execute_batch(if own_transaction {
+            "BEGIN IMMEDIATE"
+        } else {
+            "SAVEPOINT store_event"
+        })?;
+        let result = self.insert_event(conn, log, indexed_at);
+        match (&result, own_transaction) {
+            (Ok(Some(_)), true) => conn.execute_batch("COMMIT")?,
+            (Ok(Some(_)), false) => conn.execute_batch("RELEASE store_event")?,
+            (_, true) => {
+                let _ = conn.execute_batch("ROLLBACK");
+            }
+            (_, false) => {
+                let _ = conn.execute_batch("ROLLBACK TO store_event; RELEASE store_event");
+            }
+        }
+        result
+    }
 
-        let conn = self.pool.get()?;
+    /// Allocate the event's seq and insert its row; [`Self::store`] owns the transaction.
+    fn insert_event(
+        &self,
+        conn: &Connection,
+        log: LogWithMeta,
+        indexed_at: i64,
+    ) -> Result<Option<IndexedEvent>> {
+        let raw_log = log
+            .raw_log
+            .as_ref()
+            .filter(|_| self.raw_logs)
+            .and_then(|raw| serde_json::to_vec(raw).ok());
+        let raw_log = raw_log.as_deref();
 
         match log.event {
             VerbethEvent::MessageSent {
@@ -119,23 +438,25 @@ impl EventProcessor {
                 topic,
                 nonce,
             } => {
-                let topic_bytes: [u8; 32] = topic.0;
-                let seq = get_and_increment_seq(&conn, "message", Some(&topic_bytes))?;
-
-                insert_message(
-                    &conn,
-                    &MessageRow {
-                        topic: topic_bytes,
-                        seq,
-                        sender: sender.0 .0,
-                        ciphertext,
-                        timestamp: timestamp as i64,
-                        nonce: nonce as i64,
-                        block_number: log.block_number as i64,
-                        log_index: log.log_index as i64,
-                        block_timestamp: log.block_timestamp as i64,
-                    },
-                )
+                let topic = TopicHash(topic.0);
+                let seq = get_and_increment_seq(conn, "message", Some(&topic.0))?;
+
+                let row = MessageRow {
+                    topic,
+                    seq,
+                    sender: SenderAddress(sender.0 .0),
+                    ciphertext,
+                    timestamp: timestamp as i64,
+                    nonce: nonce as i64,
+                    block_number: log.block_number as i64,
+                    log_index: log.log_index as i64,
+                    block_timestamp: log.block_timestamp as i64,
+                    tx_hash: log.tx_hash.map(|h| h.0),
+                    block_hash: log.block_hash.map(|h| h.0),
+                    indexed_at,
+                };
+                let inserted = insert_message(conn, &row, raw_log, self.compress_blobs)?;
+                Ok(inserted.then_some(IndexedEvent::Message(row)))
             }
             VerbethEvent::Handshake {
                 recipient_hash,
@@ -144,24 +465,32 @@ impl EventProcessor {
                 ephemeral_pub_key,
                 plaintext_payload,
             } => {
-                let recipient_hash_bytes: [u8; 32] = recipient_hash.0;
-                let seq =
-                    get_and_increment_seq(&conn, "handshake", Some(&recipient_hash_bytes))?;
-
-                insert_handshake(
-                    &conn,
-                    &HandshakeRow {
-                        recipient_hash: recipient_hash_bytes,
-                        seq,
-                        sender: sender.0 .0,
-                        pub_keys,
-                        ephemeral_pub_key,
-                        plaintext_payload,
-                        block_number: log.block_number as i64,
-                        log_index: log.log_index as i64,
-                        block_timestamp: log.block_timestamp as i64,
-                    },
-                )
+                let recipient_hash = RecipientHash(recipient_hash.0);
+                let seq = get_and_increment_seq(conn, "handshake", Some(&recipient_hash.0))?;
+
+                let row = HandshakeRow {
+                    recipient_hash,
+                    seq,
+                    sender: SenderAddress(sender.0 .0),
+                    pub_keys,
+                    ephemeral_pub_key,
+                    plaintext_payload,
+                    block_number: log.block_number as i64,
+                    log_index: log.log_index as i64,
+                    block_timestamp: log.block_timestamp as i64,
+                    tx_hash: log.tx_hash.map(|h| h.0),
+                    block_hash: log.block_hash.map(|h| h.0),
+                    indexed_at,
+                };
+                let inserted = insert_handshake(conn, &row, raw_log, self.compress_blobs)?;
+                if inserted && self.fts {
+                    // The row is stored either way; a missed entry only hides it from search
+                    let id = conn.last_insert_rowid();
+                    if let Err(e) = index_handshake_payload(conn, id, &row.plaintext_payload) {
+                        tracing::warn!(block = log.block_number, "Failed to index payload: {e}");
+                    }
+                }
+                Ok(inserted.then_some(IndexedEvent::Handshake(row)))
             }
             VerbethEvent::HandshakeResponse {
                 in_response_to,
@@ -169,67 +498,840 @@ impl EventProcessor {
                 responder_ephemeral_r,
                 ciphertext,
             } => {
-                let global_seq = get_and_increment_seq(&conn, "hsr", None)?;
+                let global_seq = get_and_increment_seq(conn, "hsr", None)?;
+
+                let row = HsrRow {
+                    global_seq,
+                    in_response_to: in_response_to.0,
+                    responder: SenderAddress(responder.0 .0),
+                    responder_ephemeral_r: EphemeralKey(responder_ephemeral_r.0),
+                    ciphertext,
+                    block_number: log.block_number as i64,
+                    log_index: log.log_index as i64,
+                    block_timestamp: log.block_timestamp as i64,
+                    tx_hash: log.tx_hash.map(|h| h.0),
+                    block_hash: log.block_hash.map(|h| h.0),
+                    indexed_at,
+                };
+                let inserted = insert_hsr(conn, &row, raw_log, self.compress_blobs)?;
+                Ok(inserted.then_some(IndexedEvent::HandshakeResponse(row)))
+            }
+        }
+    }
+}
+
+/// Outcome of a [`RedecodePass`].
+#[derive(Clone, Default, Serialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct RedecodeStats {
+    /// Rows stored with a raw log
+    pub scanned: u64,
+    /// Rows whose decoded fields came out different and were rewritten
+    pub updated: u64,
+    pub unchanged: u64,
+    /// Raw logs that no longer parse or decode; their rows are left as is
+    pub failed: u64,
+    /// Raw logs that now decode to another event type, topic or recipient. Their rows keep
+    /// the seq of the old key, so they are left for `/admin/reprocess` with `replace`.
+    pub mismatched: u64,
+}
+
+enum Redecoded {
+    Updated,
+    Unchanged,
+    Mismatched,
+}
+
+/// Rows handled per write job by [`RedecodePass`].
+const REDECODE_BATCH: i64 = 500;
+
+#[derive(Clone, Copy)]
+enum RedecodeTable {
+    Messages,
+    Handshakes,
+    HandshakeResponses,
+    Done,
+}
 
-                insert_hsr(
-                    &conn,
-                    &HsrRow {
-                        global_seq,
-                        in_response_to: in_response_to.0,
-                        responder: responder.0 .0,
-                        responder_ephemeral_r: responder_ephemeral_r.0,
-                        ciphertext,
-                        block_number: log.block_number as i64,
-                        log_index: log.log_index as i64,
-                        block_timestamp: log.block_timestamp as i64,
-                    },
-                )
+/// Run [`decode_log`] again over every row stored with its raw log (`STORE_RAW_LOGS`) and
+/// rewrite the decoded fields that changed, e.g. after a decoder fix. Seqs and chain
+/// positions stay as they are, so nothing needs refetching from the RPC.
+///
+/// The pass goes a batch at a time, so each batch can be its own writer job and live
+/// indexing is not held up until the whole pass finishes.
+pub struct RedecodePass {
+    compress: bool,
+    fts: bool,
+    table: RedecodeTable,
+    after_id: i64,
+    pub stats: RedecodeStats,
+}
+
+impl RedecodePass {
+    pub fn new(compress: bool, fts: bool) -> Self {
+        Self {
+            compress,
+            fts,
+            table: RedecodeTable::Messages,
+            after_id: 0,
+            stats: RedecodeStats::default(),
+        }
+    }
+
+    /// Re-decode the next batch of rows in one transaction. Returns false once every
+    /// table has been gone through.
+    pub fn next_batch(&mut self, conn: &Connection) -> Result<bool> {
+        let (compress, fts) = (self.compress, self.fts);
+        let stats = &mut self.stats;
+        let last_id = match self.table {
+            RedecodeTable::Messages => redecode_batch(
+                conn,
+                stats,
+                self.after_id,
+                messages_with_raw_log,
+                |id, row, event| redecode_message(conn, id, row, event, compress),
+            )?,
+            RedecodeTable::Handshakes => redecode_batch(
+                conn,
+                stats,
+                self.after_id,
+                handshakes_with_raw_log,
+                |id, row, event| redecode_handshake(conn, id, row, event, compress, fts),
+            )?,
+            RedecodeTable::HandshakeResponses => redecode_batch(
+                conn,
+                stats,
+                self.after_id,
+                hsrs_with_raw_log,
+                |id, row, event| redecode_hsr(conn, id, row, event, compress),
+            )?,
+            RedecodeTable::Done => return Ok(false),
+        };
+
+        match last_id {
+            Some(id) => self.after_id = id,
+            None => {
+                self.after_id = 0;
+                self.table = match self.table {
+                    RedecodeTable::Messages => RedecodeTable::Handshakes,
+                    RedecodeTable::Handshakes => RedecodeTable::HandshakeResponses,
+                    _ => RedecodeTable::Done,
+                };
             }
         }
+        Ok(!matches!(self.table, RedecodeTable::Done))
+    }
+}
+
+fn redecode_message(
+    conn: &Connection,
+    id: i64,
+    row: &MessageRow,
+    event: VerbethEvent,
+    compress: bool,
+) -> Result<Redecoded> {
+    let VerbethEvent::MessageSent {
+        sender,
+        ciphertext,
+        timestamp,
+        topic,
+        nonce,
+    } = event
+    else {
+        return Ok(Redecoded::Mismatched);
+    };
+    if topic.0 != row.topic.0 {
+        return Ok(Redecoded::Mismatched);
     }
+    let decoded = MessageRow {
+        sender: SenderAddress(sender.0 .0),
+        ciphertext,
+        timestamp: timestamp as i64,
+        nonce: nonce as i64,
+        ..row.clone()
+    };
+    if (
+        decoded.sender,
+        &decoded.ciphertext,
+        decoded.timestamp,
+        decoded.nonce,
+    ) == (row.sender, &row.ciphertext, row.timestamp, row.nonce)
+    {
+        return Ok(Redecoded::Unchanged);
+    }
+    update_message_fields(conn, id, &decoded, compress)?;
+    Ok(Redecoded::Updated)
 }
 
-pub fn decode_log(log: &Log) -> Option<VerbethEvent> {
-    use alloy::sol_types::SolEvent;
+fn redecode_handshake(
+    conn: &Connection,
+    id: i64,
+    row: &HandshakeRow,
+    event: VerbethEvent,
+    compress: bool,
+    fts: bool,
+) -> Result<Redecoded> {
+    let VerbethEvent::Handshake {
+        recipient_hash,
+        sender,
+        pub_keys,
+        ephemeral_pub_key,
+        plaintext_payload,
+    } = event
+    else {
+        return Ok(Redecoded::Mismatched);
+    };
+    if recipient_hash.0 != row.recipient_hash.0 {
+        return Ok(Redecoded::Mismatched);
+    }
+    let decoded = HandshakeRow {
+        sender: SenderAddress(sender.0 .0),
+        pub_keys,
+        ephemeral_pub_key,
+        plaintext_payload,
+        ..row.clone()
+    };
+    if (
+        decoded.sender,
+        &decoded.pub_keys,
+        &decoded.ephemeral_pub_key,
+        &decoded.plaintext_payload,
+    ) == (
+        row.sender,
+        &row.pub_keys,
+        &row.ephemeral_pub_key,
+        &row.plaintext_payload,
+    ) {
+        return Ok(Redecoded::Unchanged);
+    }
+    update_handshake_fields(conn, id, &decoded, compress)?;
+    if fts && decoded.plaintext_payload != row.plaintext_payload {
+        unindex_handshake_payload(conn, id)?;
+        index_handshake_payload(conn, id, &decoded.plaintext_payload)?;
+    }
+    Ok(Redecoded::Updated)
+}
 
-    let topics = log.topics();
-    if topics.is_empty() {
-        return None;
+fn redecode_hsr(
+    conn: &Connection,
+    id: i64,
+    row: &HsrRow,
+    event: VerbethEvent,
+    compress: bool,
+) -> Result<Redecoded> {
+    let VerbethEvent::HandshakeResponse {
+        in_response_to,
+        responder,
+        responder_ephemeral_r,
+        ciphertext,
+    } = event
+    else {
+        return Ok(Redecoded::Mismatched);
+    };
+    let decoded = HsrRow {
+        in_response_to: in_response_to.0,
+        responder: SenderAddress(responder.0 .0),
+        responder_ephemeral_r: EphemeralKey(responder_ephemeral_r.0),
+        ciphertext,
+        ..row.clone()
+    };
+    if (
+        decoded.in_response_to,
+        decoded.responder,
+        decoded.responder_ephemeral_r,
+        &decoded.ciphertext,
+    ) == (
+        row.in_response_to,
+        row.responder,
+        row.responder_ephemeral_r,
+        &row.ciphertext,
+    ) {
+        return Ok(Redecoded::Unchanged);
     }
+    update_hsr_fields(conn, id, &decoded, compress)?;
+    Ok(Redecoded::Updated)
+}
 
-    let sig = topics[0];
+/// Re-decode one batch of a table's rows with a raw log, after `after_id`, in a
+/// transaction, handing each freshly decoded event to `apply`. Returns the last row id
+/// handled, or `None` once the table has no rows left.
+fn redecode_batch<R>(
+    conn: &Connection,
+    stats: &mut RedecodeStats,
+    after_id: i64,
+    fetch: impl Fn(&Connection, i64, i64) -> Result<Vec<(i64, R, Vec<u8>)>>,
+    mut apply: impl FnMut(i64, &R, VerbethEvent) -> Result<Redecoded>,
+) -> Result<Option<i64>> {
+    let batch = fetch(conn, after_id, REDECODE_BATCH)?;
+    let Some(last_id) = batch.last().map(|(id, _, _)| *id) else {
+        return Ok(None);
+    };
 
-    if sig == MessageSent::SIGNATURE_HASH {
-        let decoded = MessageSent::decode_log(log.inner.as_ref(), true).ok()?;
-        return Some(VerbethEvent::MessageSent {
-            sender: decoded.sender,
-            ciphertext: decoded.ciphertext.to_vec(),
-            timestamp: decoded.timestamp.try_into().ok()?,
-            topic: decoded.topic,
-            nonce: decoded.nonce.try_into().ok()?,
-        });
+    let tx = conn.unchecked_transaction()?;
+    for (id, row, raw_log) in batch {
+        stats.scanned += 1;
+        let event = match decode_raw_log(&raw_log) {
+            Ok(event) => event,
+            Err(e) => {
+                stats.failed += 1;
+                tracing::warn!(id, "Stored raw log no longer decodes: {e}");
+                continue;
+            }
+        };
+        match apply(id, &row, event)? {
+            Redecoded::Updated => stats.updated += 1,
+            Redecoded::Unchanged => stats.unchanged += 1,
+            Redecoded::Mismatched => stats.mismatched += 1,
+        }
     }
+    tx.commit()?;
+    Ok(Some(last_id))
+}
 
-    if sig == Handshake::SIGNATURE_HASH {
-        let decoded = Handshake::decode_log(log.inner.as_ref(), true).ok()?;
-        return Some(VerbethEvent::Handshake {
-            recipient_hash: decoded.recipientHash,
-            sender: decoded.sender,
-            pub_keys: decoded.pubKeys.to_vec(),
-            ephemeral_pub_key: decoded.ephemeralPubKey.to_vec(),
-            plaintext_payload: decoded.plaintextPayload.to_vec(),
-        });
+/// Parse a stored raw log and decode it as a Verbeth event.
+fn decode_raw_log(raw_log: &[u8]) -> std::result::Result<VerbethEvent, String> {
+    let inner: PrimitiveLog = serde_json::from_slice(raw_log).map_err(|e| e.to_string())?;
+    let log = Log {
+        inner,
+        ..Default::default()
+    };
+    match decode_log(&log) {
+        Ok(Some(event)) => Ok(event),
+        Ok(None) => Err("not a Verbeth event".to_string()),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+/// A log whose topic0 is a Verbeth event signature but whose body failed to decode.
+/// Unlike logs of other events, which are skipped quietly, this points at a contract or
+/// ABI mismatch.
+#[derive(Debug, Error)]
+#[error("malformed {event} log: {reason}")]
+pub struct DecodeError {
+    pub event: &'static str,
+    pub reason: String,
+}
+
+impl DecodeError {
+    fn new(event: &'static str, reason: impl ToString) -> Self {
+        Self {
+            event,
+            reason: reason.to_string(),
+        }
+    }
+}
+
+type LayoutDecoder = fn(&PrimitiveLog) -> std::result::Result<VerbethEvent, DecodeError>;
+
+/// Every known ABI layout of the Verbeth events, as `(signature hash, event name, decoder)`.
+///
+/// A contract upgrade that changes an event adds a layout here, with its own `sol!`
+/// definition, rather than replacing the old one. Logs emitted before the upgrade then still
+/// decode. Layouts can share a signature hash when only the `indexed` markers change,
+/// because those are not part of the signature. They are tried in order. The log filter
+/// takes its topic0 set from this table, so a new signature is subscribed to as well.
+pub const EVENT_LAYOUTS: &[(B256, &str, LayoutDecoder)] = &[
+    (
+        MessageSent::SIGNATURE_HASH,
+        "MessageSent",
+        decode_message_sent,
+    ),
+    (
+        MessageSentV2::SIGNATURE_HASH,
+        "MessageSent",
+        decode_message_sent_v2,
+    ),
+    (Handshake::SIGNATURE_HASH, "Handshake", decode_handshake),
+    (
+        HandshakeResponse::SIGNATURE_HASH,
+        "HandshakeResponse",
+        decode_handshake_response,
+    ),
+];
+
+/// Decode a Verbeth event. `Ok(None)` means the log is not a Verbeth event at all.
+pub fn decode_log(log: &Log) -> std::result::Result<Option<VerbethEvent>, DecodeError> {
+    let Some(&sig) = log.topics().first() else {
+        return Ok(None);
+    };
+
+    let mut layouts = EVENT_LAYOUTS
+        .iter()
+        .filter(|(hash, _, _)| *hash == sig)
+        .peekable();
+    let Some(&(_, event, _)) = layouts.peek() else {
+        return Ok(None);
+    };
+
+    let mut errors = Vec::new();
+    for (_, _, decode) in layouts {
+        match decode(&log.inner) {
+            Ok(decoded) => return Ok(Some(decoded)),
+            Err(e) => errors.push(e.reason),
+        }
+    }
+    let reason = if errors.len() == 1 {
+        errors.remove(0)
+    } else {
+        format!(
+            "fits none of its {} known layouts ({})",
+            errors.len(),
+            errors.join("; ")
+        )
+    };
+    Err(DecodeError::new(event, reason))
+}
+
+fn decode_message_sent(log: &PrimitiveLog) -> std::result::Result<VerbethEvent, DecodeError> {
+    let decoded =
+        MessageSent::decode_log(log, true).map_err(|e| DecodeError::new("MessageSent", e))?;
+    Ok(VerbethEvent::MessageSent {
+        sender: decoded.sender,
+        ciphertext: decoded.ciphertext.to_vec(),
+        timestamp: decoded
+            .timestamp
+            .try_into()
+            .map_err(|_| DecodeError::new("MessageSent", "timestamp exceeds u64"))?,
+        topic: decoded.topic,
+        nonce: decoded
+            .nonce
+            .try_into()
+            .map_err(|_| DecodeError::new("MessageSent", "nonce exceeds u64"))?,
+    })
+}
+
+fn decode_message_sent_v2(log: &PrimitiveLog) -> std::result::Result<VerbethEvent, DecodeError> {
+    let decoded =
+        MessageSentV2::decode_log(log, true).map_err(|e| DecodeError::new("MessageSent", e))?;
+    Ok(VerbethEvent::MessageSent {
+        sender: decoded.sender,
+        ciphertext: decoded.ciphertext.to_vec(),
+        timestamp: decoded
+            .timestamp
+            .try_into()
+            .map_err(|_| DecodeError::new("MessageSent", "timestamp exceeds u64"))?,
+        topic: decoded.topic,
+        nonce: decoded
+            .nonce
+            .try_into()
+            .map_err(|_| DecodeError::new("MessageSent", "nonce exceeds u64"))?,
+    })
+}
+
+fn decode_handshake(log: &PrimitiveLog) -> std::result::Result<VerbethEvent, DecodeError> {
+    let decoded = Handshake::decode_log(log, true).map_err(|e| DecodeError::new("Handshake", e))?;
+    Ok(VerbethEvent::Handshake {
+        recipient_hash: decoded.recipientHash,
+        sender: decoded.sender,
+        pub_keys: decoded.pubKeys.to_vec(),
+        ephemeral_pub_key: decoded.ephemeralPubKey.to_vec(),
+        plaintext_payload: decoded.plaintextPayload.to_vec(),
+    })
+}
+
+fn decode_handshake_response(log: &PrimitiveLog) -> std::result::Result<VerbethEvent, DecodeError> {
+    let decoded = HandshakeResponse::decode_log(log, true)
+        .map_err(|e| DecodeError::new("HandshakeResponse", e))?;
+    Ok(VerbethEvent::HandshakeResponse {
+        in_response_to: decoded.inResponseTo,
+        responder: decoded.responder,
+        responder_ephemeral_r: decoded.responderEphemeralR,
+        ciphertext: decoded.ciphertext.to_vec(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::queries::{hsrs_in_response_to, messages_by_topic};
+    use crate::db::{memory_pool, DbPool};
+
+    const TOPIC: [u8; 32] = [7; 32];
+    const TAG: [u8; 32] = [9; 32];
+
+    fn processor(pool: &DbPool) -> EventProcessor {
+        EventProcessor::new(EventWriter::spawn(pool).unwrap())
+    }
+
+    fn at(event: VerbethEvent, block_number: u64, log_index: u64) -> LogWithMeta {
+        LogWithMeta {
+            event,
+            block_number,
+            log_index,
+            block_timestamp: 1_700_000_000 + block_number,
+            tx_hash: None,
+            block_hash: None,
+            raw_log: None,
+        }
+    }
+
+    fn message(nonce: u64) -> VerbethEvent {
+        VerbethEvent::MessageSent {
+            sender: Address::repeat_byte(1),
+            ciphertext: vec![0xab; 32],
+            timestamp: 1_700_000_000,
+            topic: B256::from(TOPIC),
+            nonce,
+        }
+    }
+
+    fn response() -> VerbethEvent {
+        VerbethEvent::HandshakeResponse {
+            in_response_to: B256::from(TAG),
+            responder: Address::repeat_byte(2),
+            responder_ephemeral_r: B256::repeat_byte(3),
+            ciphertext: vec![0xcd; 64],
+        }
+    }
+
+    fn rpc_log(data: alloy::primitives::LogData) -> Log {
+        Log {
+            inner: PrimitiveLog {
+                address: Address::repeat_byte(0xee),
+                data,
+            },
+            ..Default::default()
+        }
+    }
+
+    fn sent_message(sent: &VerbethEvent) -> (Address, Vec<u8>, u64, B256, u64) {
+        match sent {
+            VerbethEvent::MessageSent {
+                sender,
+                ciphertext,
+                timestamp,
+                topic,
+                nonce,
+            } => (*sender, ciphertext.clone(), *timestamp, *topic, *nonce),
+            _ => panic!("not a message"),
+        }
+    }
+
+    fn message_seqs(conn: &Connection) -> Vec<i64> {
+        messages_by_topic(conn, &TopicHash(TOPIC), None, None, None, false, 100)
+            .unwrap()
+            .iter()
+            .map(|m| m.seq)
+            .collect()
+    }
+
+    #[test]
+    fn handshake_responses_get_consecutive_global_seqs() {
+        let pool = memory_pool();
+        let processor = processor(&pool);
+        let conn = pool.get().unwrap();
+
+        for block in 1..=4 {
+            assert!(processor
+                .process_with_conn(&conn, at(response(), block, 0))
+                .unwrap());
+        }
+
+        let seqs: Vec<_> = hsrs_in_response_to(&conn, &TAG)
+            .unwrap()
+            .iter()
+            .map(|r| r.global_seq)
+            .collect();
+        assert_eq!(seqs, [0, 1, 2, 3]);
     }
 
-    if sig == HandshakeResponse::SIGNATURE_HASH {
-        let decoded = HandshakeResponse::decode_log(log.inner.as_ref(), true).ok()?;
-        return Some(VerbethEvent::HandshakeResponse {
-            in_response_to: decoded.inResponseTo,
-            responder: decoded.responder,
-            responder_ephemeral_r: decoded.responderEphemeralR,
-            ciphertext: decoded.ciphertext.to_vec(),
+    #[test]
+    fn failed_insert_gives_its_seq_back() {
+        let pool = memory_pool();
+        let processor = processor(&pool);
+        let conn = pool.get().unwrap();
+
+        conn.execute_batch(
+            "CREATE TEMP TRIGGER fail_insert BEFORE INSERT ON messages
+             BEGIN SELECT RAISE(ABORT, 'simulated failure'); END;",
+        )
+        .unwrap();
+        assert!(processor
+            .process_with_conn(&conn, at(message(0), 1, 0))
+            .is_err());
+        conn.execute_batch("DROP TRIGGER fail_insert").unwrap();
+
+        assert!(processor
+            .process_with_conn(&conn, at(message(0), 1, 0))
+            .unwrap());
+        assert_eq!(message_seqs(&conn), [0]);
+    }
+
+    #[test]
+    fn decodes_message_sent_v1() {
+        let log = rpc_log(
+            MessageSent {
+                sender: Address::repeat_byte(1),
+                ciphertext: vec![0xab; 40].into(),
+                timestamp: alloy::primitives::U256::from(1_700_000_000u64),
+                topic: B256::from(TOPIC),
+                nonce: alloy::primitives::U256::from(5u64),
+            }
+            .encode_log_data(),
+        );
+
+        let event = decode_log(&log).unwrap().unwrap();
+        assert_eq!(
+            sent_message(&event),
+            (
+                Address::repeat_byte(1),
+                vec![0xab; 40],
+                1_700_000_000,
+                B256::from(TOPIC),
+                5
+            )
+        );
+    }
+
+    #[test]
+    fn decodes_message_sent_v2_with_indexed_nonce() {
+        let data = MessageSentV2 {
+            sender: Address::repeat_byte(1),
+            ciphertext: vec![0xab; 40].into(),
+            timestamp: alloy::primitives::U256::from(1_700_000_000u64),
+            topic: B256::from(TOPIC),
+            nonce: alloy::primitives::U256::from(5u64),
+        }
+        .encode_log_data();
+        // Same topic0 as v1; only the extra topic tells the layouts apart
+        assert_eq!(data.topics()[0], MessageSent::SIGNATURE_HASH);
+        assert_eq!(data.topics().len(), 4);
+
+        let event = decode_log(&rpc_log(data)).unwrap().unwrap();
+        assert_eq!(
+            sent_message(&event),
+            (
+                Address::repeat_byte(1),
+                vec![0xab; 40],
+                1_700_000_000,
+                B256::from(TOPIC),
+                5
+            )
+        );
+    }
+
+    #[test]
+    fn message_sent_fitting_no_layout_names_them_all() {
+        let mut data = MessageSent {
+            sender: Address::repeat_byte(1),
+            ciphertext: vec![0xab; 40].into(),
+            timestamp: alloy::primitives::U256::from(1_700_000_000u64),
+            topic: B256::from(TOPIC),
+            nonce: alloy::primitives::U256::from(5u64),
+        }
+        .encode_log_data();
+        let topics = data.topics()[..2].to_vec();
+        data.set_topics_unchecked(topics);
+
+        let Err(error) = decode_log(&rpc_log(data)) else {
+            panic!("decoded a log with too few topics");
+        };
+        assert_eq!(error.event, "MessageSent");
+        assert!(error.reason.contains("2 known layouts"), "{}", error.reason);
+    }
+
+    #[test]
+    fn same_log_twice_is_stored_once() {
+        let pool = memory_pool();
+        let processor = processor(&pool);
+        let conn = pool.get().unwrap();
+
+        assert!(processor
+            .process_with_conn(&conn, at(message(0), 1, 0))
+            .unwrap());
+        assert!(!processor
+            .process_with_conn(&conn, at(message(0), 1, 0))
+            .unwrap());
+        assert!(!processor
+            .process_with_conn(&conn, at(response(), 1, 0))
+            .unwrap());
+        // The replay didn't consume a seq, so the next message follows straight on
+        assert!(processor
+            .process_with_conn(&conn, at(message(1), 2, 0))
+            .unwrap());
+
+        assert_eq!(message_seqs(&conn), [0, 1]);
+        assert!(hsrs_in_response_to(&conn, &TAG).unwrap().is_empty());
+    }
+
+    #[test]
+    fn message_seqs_count_per_topic() {
+        let pool = memory_pool();
+        let processor = processor(&pool);
+        let conn = pool.get().unwrap();
+        let other_topic = |nonce| match message(nonce) {
+            VerbethEvent::MessageSent {
+                sender,
+                ciphertext,
+                timestamp,
+                nonce,
+                ..
+            } => VerbethEvent::MessageSent {
+                sender,
+                ciphertext,
+                timestamp,
+                topic: B256::repeat_byte(8),
+                nonce,
+            },
+            _ => unreachable!(),
+        };
+
+        for (i, event) in [message(0), other_topic(0), message(1), message(2)]
+            .into_iter()
+            .enumerate()
+        {
+            assert!(processor
+                .process_with_conn(&conn, at(event, 1, i as u64))
+                .unwrap());
+        }
+
+        assert_eq!(message_seqs(&conn), [0, 1, 2]);
+        let other: Vec<_> =
+            messages_by_topic(&conn, &TopicHash([8; 32]), None, None, None, false, 100)
+                .unwrap()
+                .iter()
+                .map(|m| m.seq)
+                .collect();
+        assert_eq!(other, [0]);
+    }
+
+    #[test]
+    fn oversized_ciphertext_is_rejected_before_it_takes_a_seq() {
+        let pool = memory_pool();
+        let processor = processor(&pool).with_payload_limits(PayloadLimits {
+            ciphertext: 16,
+            ..PayloadLimits::default()
         });
+        let conn = pool.get().unwrap();
+
+        let result = processor.process_with_conn(&conn, at(message(0), 1, 0));
+        assert!(matches!(
+            result,
+            Err(IndexerError::PayloadTooLarge {
+                field: "ciphertext",
+                size: 32,
+                max: 16
+            })
+        ));
+
+        let small = VerbethEvent::MessageSent {
+            sender: Address::repeat_byte(1),
+            ciphertext: vec![0xab; 16],
+            timestamp: 1_700_000_000,
+            topic: B256::from(TOPIC),
+            nonce: 1,
+        };
+        assert!(processor.process_with_conn(&conn, at(small, 2, 0)).unwrap());
+        assert_eq!(message_seqs(&conn), [0]);
+    }
+
+    #[test]
+    fn corrupted_message_sent_body_is_an_error() {
+        let data = MessageSent {
+            sender: Address::repeat_byte(1),
+            ciphertext: vec![0xab; 40].into(),
+            timestamp: alloy::primitives::U256::from(1_700_000_000u64),
+            topic: B256::from(TOPIC),
+            nonce: alloy::primitives::U256::from(5u64),
+        }
+        .encode_log_data();
+        // Cut the body off in the middle of the ciphertext
+        let body = data.data[..100].to_vec();
+        let data = alloy::primitives::LogData::new_unchecked(data.topics().to_vec(), body.into());
+
+        let Err(error) = decode_log(&rpc_log(data)) else {
+            panic!("decoded a truncated body");
+        };
+        assert_eq!(error.event, "MessageSent");
+    }
+
+    #[test]
+    fn log_of_another_event_is_not_an_error() {
+        let data = alloy::primitives::LogData::new_unchecked(
+            vec![B256::repeat_byte(0x42)],
+            Default::default(),
+        );
+        assert!(matches!(decode_log(&rpc_log(data)), Ok(None)));
+    }
+
+    #[tokio::test]
+    async fn stored_events_are_counted_per_contract() {
+        let pool = memory_pool();
+        let contract = Address::repeat_byte(0xee);
+        let processor = processor(&pool)
+            .with_contract(contract)
+            .with_payload_limits(PayloadLimits {
+                hsr_ciphertext: 16,
+                ..PayloadLimits::default()
+            });
+
+        assert!(processor.process(at(message(0), 1, 0)).await.unwrap());
+        // Replayed
+        assert!(!processor.process(at(message(0), 1, 0)).await.unwrap());
+        let results = processor
+            .process_chunk(vec![at(message(1), 2, 0), at(response(), 2, 1)], Some(2))
+            .await
+            .unwrap();
+        assert!(results[1].is_err());
+        // Not built from the config, so not counted
+        let unlabeled = EventProcessor::new(processor.writer().clone());
+        assert!(unlabeled.process(at(message(2), 3, 0)).await.unwrap());
+
+        let snapshot = processor.writer().contract_metrics().snapshot();
+        let counts: Vec<_> = snapshot
+            .iter()
+            .map(|(address, c)| {
+                let stored = (c.messages, c.handshakes, c.handshake_responses);
+                (*address, stored, c.skipped, c.failed)
+            })
+            .collect();
+        assert_eq!(counts, [(contract, (2, 0, 0), 1, 1)]);
     }
 
-    None
+    #[tokio::test]
+    async fn redecode_pass_rewrites_changed_fields_one_writer_job_per_batch() {
+        let pool = memory_pool();
+        let processor = processor(&pool).with_raw_logs(true);
+        // Stored as the old decoder read it; the raw log holds a longer ciphertext
+        let raw = rpc_log(
+            MessageSent {
+                sender: Address::repeat_byte(1),
+                ciphertext: vec![0xab; 40].into(),
+                timestamp: alloy::primitives::U256::from(1_700_000_000u64),
+                topic: B256::from(TOPIC),
+                nonce: alloy::primitives::U256::ZERO,
+            }
+            .encode_log_data(),
+        );
+        let mut log = at(message(0), 1, 0);
+        log.raw_log = Some(Box::new(raw.inner));
+        assert!(processor.process(log).await.unwrap());
+
+        let mut pass = RedecodePass::new(false, false);
+        let mut jobs = 0;
+        loop {
+            let (next, more) = processor
+                .writer()
+                .run(move |conn| {
+                    let more = pass.next_batch(conn)?;
+                    Ok((pass, more))
+                })
+                .await
+                .unwrap();
+            pass = next;
+            jobs += 1;
+            if !more {
+                break;
+            }
+        }
+
+        // One batch of messages, then an empty one per table
+        assert_eq!(jobs, 4);
+        let stats = pass.stats;
+        assert_eq!((stats.scanned, stats.updated, stats.failed), (1, 1, 0));
+        let conn = pool.get().unwrap();
+        let rows =
+            messages_by_topic(&conn, &TopicHash(TOPIC), None, None, None, false, 10).unwrap();
+        assert_eq!(rows[0].ciphertext, vec![0xab; 40]);
+    }
 }
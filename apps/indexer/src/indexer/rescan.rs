@@ -0,0 +1,116 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::sync::watch;
+
+use crate::config::Config;
+use crate::db::queries::get_last_processed_block;
+use crate::db::DbPool;
+use crate::error::Result;
+
+use super::backfill::{run_backfill, ProgressTracker};
+use super::circuit_breaker::CircuitBreaker;
+use super::retry_queue::RetryQueue;
+use super::timestamp_cache::BlockTimestampCache;
+use super::writer::EventWriter;
+
+/// Every `interval`, backfill the last `RESCAN_BLOCKS` blocks behind the resume point.
+///
+/// Reconnect recovery only covers outages the subscriber noticed. A log the provider silently
+/// dropped from a live stream leaves no trace, and since blocks without events are normal,
+/// the gap can't be told apart from quiet. Re-reading recent history with `getLogs` is cheap,
+/// and already-indexed events are skipped, so the pass only stores what was missed.
+#[allow(clippy::too_many_arguments)]
+pub async fn run_rescans(
+    config: Arc<Config>,
+    pool: DbPool,
+    writer: EventWriter,
+    timestamps: Arc<BlockTimestampCache>,
+    retry_queue: Arc<RetryQueue>,
+    progress: ProgressTracker,
+    breaker: Arc<CircuitBreaker>,
+    interval: Duration,
+    mut shutdown: watch::Receiver<bool>,
+) {
+    loop {
+        tokio::select! {
+            _ = tokio::time::sleep(interval) => {}
+            _ = shutdown.changed() => {
+                if *shutdown.borrow() {
+                    return;
+                }
+            }
+        }
+
+        let rescan = rescan_recent_blocks(
+            &config,
+            &pool,
+            &writer,
+            &timestamps,
+            &retry_queue,
+            &progress,
+            &breaker,
+        );
+        // Stop mid-pass on shutdown; a later pass covers the same blocks again
+        tokio::select! {
+            result = rescan => {
+                if let Err(e) = result {
+                    tracing::warn!("Rescan failed: {e}");
+                }
+            }
+            _ = shutdown.changed() => {
+                if *shutdown.borrow() {
+                    return;
+                }
+            }
+        }
+    }
+}
+
+async fn rescan_recent_blocks(
+    config: &Config,
+    pool: &DbPool,
+    writer: &EventWriter,
+    timestamps: &BlockTimestampCache,
+    retry_queue: &RetryQueue,
+    progress: &ProgressTracker,
+    breaker: &CircuitBreaker,
+) -> Result<()> {
+    let Some(to_block) = get_last_processed_block(&*pool.get()?)? else {
+        return Ok(());
+    };
+    let to_block = to_block as u64;
+    let from_block = (to_block + 1)
+        .saturating_sub(config.rescan_blocks)
+        .max(config.creation_block);
+    if from_block > to_block {
+        return Ok(());
+    }
+
+    let stats = run_backfill(
+        config,
+        &config.http_rpc_url(),
+        from_block,
+        to_block,
+        writer,
+        timestamps,
+        retry_queue,
+        progress,
+        breaker,
+    )
+    .await?;
+
+    if stats.events_processed > 0 {
+        tracing::warn!(
+            "Rescan of blocks {from_block} to {to_block} recovered {} missed events ({} already indexed)",
+            stats.events_processed,
+            stats.events_skipped
+        );
+    } else {
+        tracing::info!(
+            "Rescan of blocks {from_block} to {to_block} found no missed events ({} already indexed)",
+            stats.events_skipped
+        );
+    }
+    Ok(())
+}
@@ -1,64 +1,201 @@
 use std::collections::VecDeque;
-use tokio::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+use alloy::primitives::B256;
+use rusqlite::ErrorCode;
+use tokio::sync::{mpsc, Mutex};
+
+use crate::db::queries::insert_dead_letter;
+use crate::error::IndexerError;
 
 use super::processor::LogWithMeta;
+use super::writer::EventWriter;
 
-const MAX_RETRIES: u32 = 3;
 const MAX_QUEUE_SIZE: usize = 1000;
 
+/// How a failed event is retried, decided from the error that made it fail.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FailureClass {
+    /// SQLite busy/locked or an exhausted pool: clears once the competing writer is done
+    Transient,
+    /// RPC errors, missing timestamps and anything not recognised
+    Default,
+    /// Payload validation: the same event fails the same way every time
+    Permanent,
+    /// The disk is full. Retried slowly until space is freed, and never dead-lettered,
+    /// since the dead-letter table is on the same disk
+    DiskFull,
+}
+
+impl FailureClass {
+    pub fn of(error: &IndexerError) -> Self {
+        if error.is_disk_full() {
+            return Self::DiskFull;
+        }
+        match error {
+            IndexerError::PayloadTooLarge { .. } | IndexerError::Decode(_) => Self::Permanent,
+            IndexerError::Pool(_) => Self::Transient,
+            IndexerError::Database(rusqlite::Error::SqliteFailure(e, _))
+                if matches!(e.code, ErrorCode::DatabaseBusy | ErrorCode::DatabaseLocked) =>
+            {
+                Self::Transient
+            }
+            _ => Self::Default,
+        }
+    }
+
+    /// Retry attempts before the event is dead-lettered.
+    fn max_retries(self) -> u32 {
+        match self {
+            Self::Transient => 10,
+            Self::Default => 3,
+            Self::Permanent => 0,
+            Self::DiskFull => u32::MAX,
+        }
+    }
+
+    /// Wait before each attempt.
+    fn retry_delay(self) -> Duration {
+        match self {
+            Self::Transient => Duration::from_secs(1),
+            Self::Default => Duration::from_secs(10),
+            Self::Permanent => Duration::ZERO,
+            Self::DiskFull => Duration::from_secs(60),
+        }
+    }
+}
+
 pub struct FailedEvent {
     pub log: LogWithMeta,
     pub retry_count: u32,
     pub last_error: String,
+    pub class: FailureClass,
+    /// Not handed out by `pop_due` before this
+    pub retry_at: Instant,
+}
+
+/// An event that was just dead-lettered, for `WEBHOOK_ALERT_ON_DEAD_LETTER`.
+pub struct DeadLetterNotice {
+    /// Row id in `dead_letters`, for `POST /admin/dead-letters/{id}/requeue`
+    pub id: i64,
+    pub block_number: u64,
+    pub log_index: u64,
+    pub tx_hash: Option<B256>,
+    pub retry_count: u32,
+    pub error: String,
+    /// Notices dropped on a full channel since the last one that was sent
+    pub dropped: u64,
 }
 
 pub struct RetryQueue {
     queue: Mutex<VecDeque<FailedEvent>>,
+    writer: EventWriter,
+    alerts: Option<mpsc::Sender<DeadLetterNotice>>,
+    dropped_alerts: AtomicU64,
 }
 
 impl RetryQueue {
-    pub fn new() -> Self {
+    pub fn new(writer: EventWriter) -> Self {
         Self {
             queue: Mutex::new(VecDeque::new()),
+            writer,
+            alerts: None,
+            dropped_alerts: AtomicU64::new(0),
         }
     }
 
-    pub async fn push(&self, log: LogWithMeta, error: String) {
-        let mut q = self.queue.lock().await;
+    /// Send a notice on `alerts` for every persisted dead letter. Sends never wait: while
+    /// the channel is full, notices are dropped and counted on the next one that fits.
+    pub fn with_dead_letter_alerts(mut self, alerts: mpsc::Sender<DeadLetterNotice>) -> Self {
+        self.alerts = Some(alerts);
+        self
+    }
 
-        // Check if we're at capacity - dead-letter oldest event
-        if q.len() >= MAX_QUEUE_SIZE {
-            if let Some(old) = q.pop_front() {
-                tracing::error!(
-                    block = old.log.block_number,
-                    log_index = old.log.log_index,
-                    retries = old.retry_count,
-                    error = %old.last_error,
-                    "Event permanently failed (dead-letter, queue full)"
-                );
-            }
+    /// Queue an event that failed for the first time, or dead-letter it straight away
+    /// if the error can't be fixed by retrying.
+    pub async fn push(&self, log: LogWithMeta, error: &IndexerError) {
+        let class = FailureClass::of(error);
+        let event = FailedEvent {
+            log,
+            retry_count: 0,
+            last_error: error.to_string(),
+            class,
+            retry_at: Instant::now() + class.retry_delay(),
+        };
+
+        if class == FailureClass::Permanent {
+            tracing::error!(
+                block = event.log.block_number,
+                log_index = event.log.log_index,
+                error = %event.last_error,
+                "Event permanently failed (dead-letter, not retryable)"
+            );
+            self.dead_letter(event).await;
+            return;
         }
 
-        q.push_back(FailedEvent {
+        self.enqueue(event).await;
+    }
+
+    /// Queue a dead-lettered event an operator sent back, with a fresh default budget
+    /// whatever it failed with, since they may have fixed the cause.
+    pub async fn requeue(&self, log: LogWithMeta, last_error: String) {
+        let class = FailureClass::Default;
+        self.enqueue(FailedEvent {
             log,
             retry_count: 0,
-            last_error: error,
-        });
+            last_error,
+            class,
+            retry_at: Instant::now() + class.retry_delay(),
+        })
+        .await;
+    }
+
+    async fn enqueue(&self, event: FailedEvent) {
+        // At capacity, dead-letter the oldest event. Not while the disk is full, though:
+        // the dead-letter insert would fail too and lose it, so the queue grows instead
+        let evicted = {
+            let mut q = self.queue.lock().await;
+            let evicted = if q.len() >= MAX_QUEUE_SIZE && !self.writer.disk_full() {
+                q.pop_front()
+            } else {
+                None
+            };
+            q.push_back(event);
+            evicted
+        };
+
+        if let Some(old) = evicted {
+            tracing::error!(
+                block = old.log.block_number,
+                log_index = old.log.log_index,
+                retries = old.retry_count,
+                error = %old.last_error,
+                "Event permanently failed (dead-letter, queue full)"
+            );
+            self.dead_letter(old).await;
+        }
     }
 
-    pub async fn push_retry(&self, mut event: FailedEvent, error: String) {
+    /// Re-queue an event whose retry failed, under the policy of the latest error.
+    pub async fn push_retry(&self, mut event: FailedEvent, error: &IndexerError) {
         event.retry_count += 1;
-        event.last_error = error;
+        event.last_error = error.to_string();
+        event.class = FailureClass::of(error);
+        event.retry_at = Instant::now() + event.class.retry_delay();
 
-        if event.retry_count >= MAX_RETRIES {
-            // Dead-letter: log and discard
+        if event.retry_count >= event.class.max_retries() {
+            // Dead-letter: log and persist for inspection/requeue
             tracing::error!(
                 block = event.log.block_number,
                 log_index = event.log.log_index,
                 retries = event.retry_count,
+                class = ?event.class,
                 error = %event.last_error,
                 "Event permanently failed (dead-letter, max retries)"
             );
+            self.dead_letter(event).await;
             return;
         }
 
@@ -66,18 +203,213 @@ impl RetryQueue {
         q.push_back(event);
     }
 
-    pub async fn pop(&self) -> Option<FailedEvent> {
-        self.queue.lock().await.pop_front()
+    /// Remove the due event that comes first on chain, so events of one topic that are
+    /// retried together get their seqs in block order.
+    pub async fn pop_due(&self) -> Option<FailedEvent> {
+        let mut q = self.queue.lock().await;
+        let now = Instant::now();
+        let (index, _) = q
+            .iter()
+            .enumerate()
+            .filter(|(_, e)| e.retry_at <= now)
+            .min_by_key(|(_, e)| e.log.chain_position())?;
+        q.remove(index)
+    }
+
+    /// Remove and return every queued event.
+    pub async fn take_all(&self) -> Vec<FailedEvent> {
+        self.queue.lock().await.drain(..).collect()
+    }
+
+    /// Persist an event that can no longer be retried in this process, e.g. on shutdown.
+    pub async fn abandon(&self, mut event: FailedEvent, error: String) {
+        event.last_error = error;
+        tracing::error!(
+            block = event.log.block_number,
+            log_index = event.log.log_index,
+            retries = event.retry_count,
+            error = %event.last_error,
+            "Event still failing at shutdown (dead-letter)"
+        );
+        self.dead_letter(event).await;
+    }
+
+    /// Whether any queued event failed on a full disk. Its block may be behind ones that
+    /// were stored since, so the resume point must not move past it.
+    pub async fn holds_disk_full(&self) -> bool {
+        self.queue
+            .lock()
+            .await
+            .iter()
+            .any(|e| e.class == FailureClass::DiskFull)
     }
 
     #[allow(dead_code)]
     pub async fn len(&self) -> usize {
         self.queue.lock().await.len()
     }
+
+    /// Persist a dead-lettered event so it can be inspected and requeued via the API.
+    /// If the disk is full, the event goes back in the queue under the disk-full policy
+    /// instead, to be dead-lettered or stored once space is freed.
+    async fn dead_letter(&self, mut event: FailedEvent) {
+        let payload = match serde_json::to_string(&event.log) {
+            Ok(payload) => payload,
+            Err(e) => {
+                tracing::error!(
+                    block = event.log.block_number,
+                    log_index = event.log.log_index,
+                    "Failed to persist dead-letter: {e}"
+                );
+                return;
+            }
+        };
+        let block_number = event.log.block_number as i64;
+        let log_index = event.log.log_index as i64;
+        let retry_count = event.retry_count as i64;
+        let error = event.last_error.clone();
+        let result = self
+            .writer
+            .run(move |conn| {
+                insert_dead_letter(conn, block_number, log_index, retry_count, &error, &payload)
+            })
+            .await;
+
+        match result {
+            Ok(id) => {
+                if let Some(alerts) = &self.alerts {
+                    let dropped = self.dropped_alerts.swap(0, Ordering::Relaxed);
+                    let notice = DeadLetterNotice {
+                        id,
+                        block_number: event.log.block_number,
+                        log_index: event.log.log_index,
+                        tx_hash: event.log.tx_hash,
+                        retry_count: event.retry_count,
+                        error: event.last_error.clone(),
+                        dropped,
+                    };
+                    if alerts.try_send(notice).is_err() {
+                        self.dropped_alerts
+                            .fetch_add(dropped + 1, Ordering::Relaxed);
+                    }
+                }
+            }
+            Err(e) if e.is_disk_full() => {
+                tracing::warn!(
+                    block = event.log.block_number,
+                    log_index = event.log.log_index,
+                    "Disk full, keeping dead-letter in the retry queue"
+                );
+                event.class = FailureClass::DiskFull;
+                event.retry_at = Instant::now() + event.class.retry_delay();
+                self.queue.lock().await.push_back(event);
+            }
+            Err(e) => {
+                tracing::error!(
+                    block = event.log.block_number,
+                    log_index = event.log.log_index,
+                    "Failed to persist dead-letter: {e}"
+                );
+            }
+        }
+    }
 }
 
-impl Default for RetryQueue {
-    fn default() -> Self {
-        Self::new()
+#[cfg(test)]
+mod tests {
+    use alloy::primitives::Address;
+
+    use super::*;
+    use crate::db::memory_pool;
+    use crate::db::queries::list_dead_letters;
+    use crate::indexer::processor::VerbethEvent;
+
+    fn log(block_number: u64) -> LogWithMeta {
+        LogWithMeta {
+            event: VerbethEvent::HandshakeResponse {
+                in_response_to: B256::repeat_byte(9),
+                responder: Address::repeat_byte(2),
+                responder_ephemeral_r: B256::repeat_byte(3),
+                ciphertext: vec![0xcd; 64],
+            },
+            block_number,
+            log_index: 0,
+            block_timestamp: 1_700_000_000,
+            tx_hash: None,
+            block_hash: None,
+            raw_log: None,
+        }
+    }
+
+    fn busy() -> IndexerError {
+        IndexerError::Database(rusqlite::Error::SqliteFailure(
+            rusqlite::ffi::Error::new(rusqlite::ffi::SQLITE_BUSY),
+            None,
+        ))
+    }
+
+    #[tokio::test]
+    async fn due_events_pop_in_chain_order() {
+        let pool = memory_pool();
+        let queue = RetryQueue::new(EventWriter::spawn(&pool).unwrap());
+        for block_number in [9, 3, 7, 1] {
+            queue
+                .enqueue(FailedEvent {
+                    log: log(block_number),
+                    retry_count: 0,
+                    last_error: busy().to_string(),
+                    class: FailureClass::Transient,
+                    retry_at: Instant::now(),
+                })
+                .await;
+        }
+
+        let mut popped = Vec::new();
+        while let Some(event) = queue.pop_due().await {
+            popped.push(event.log.block_number);
+        }
+        assert_eq!(popped, [1, 3, 7, 9]);
+    }
+
+    #[tokio::test]
+    async fn payload_too_large_dead_letters_on_first_failure() {
+        let pool = memory_pool();
+        let queue = RetryQueue::new(EventWriter::spawn(&pool).unwrap());
+        let error = IndexerError::PayloadTooLarge {
+            field: "ciphertext",
+            size: 70_000,
+            max: 65_536,
+        };
+
+        queue.push(log(1), &error).await;
+
+        assert_eq!(queue.len().await, 0);
+        let dead = list_dead_letters(&pool.get().unwrap(), 10).unwrap();
+        assert_eq!(dead.len(), 1);
+        assert_eq!((dead[0].block_number, dead[0].retry_count), (1, 0));
+    }
+
+    #[tokio::test]
+    async fn db_busy_is_retried_before_dead_lettering() {
+        let pool = memory_pool();
+        let queue = RetryQueue::new(EventWriter::spawn(&pool).unwrap());
+        assert_eq!(FailureClass::of(&busy()), FailureClass::Transient);
+
+        queue.push(log(1), &busy()).await;
+        let max_retries = FailureClass::Transient.max_retries();
+        for _ in 1..max_retries {
+            let event = queue.take_all().await.pop().expect("still queued");
+            queue.push_retry(event, &busy()).await;
+        }
+        assert_eq!(queue.len().await, 1);
+        assert!(list_dead_letters(&pool.get().unwrap(), 10)
+            .unwrap()
+            .is_empty());
+
+        let event = queue.take_all().await.pop().unwrap();
+        queue.push_retry(event, &busy()).await;
+        let dead = list_dead_letters(&pool.get().unwrap(), 10).unwrap();
+        assert_eq!(dead.len(), 1);
+        assert_eq!(dead[0].retry_count, max_retries as i64);
     }
 }
@@ -0,0 +1,63 @@
+use std::time::Duration;
+
+use alloy::providers::{ProviderBuilder, RootProvider, WsConnect};
+use alloy::pubsub::PubSubFrontend;
+use alloy::rpc::client::RpcClient;
+use alloy::transports::http::{Client, Http};
+use alloy::transports::{Authorization, RpcError, TransportError, TransportErrorKind};
+use reqwest::header::{HeaderMap, HeaderValue, AUTHORIZATION};
+
+use crate::error::{IndexerError, Result};
+
+/// Sent on every HTTP RPC request, so providers can tell the indexer's traffic apart.
+const USER_AGENT: &str = concat!("verbeth-indexer/", env!("CARGO_PKG_VERSION"));
+
+/// HTTP provider, sending `auth` as the `Authorization` header on every request if set.
+/// Requests fail after `timeout` rather than waiting on a stalled connection.
+pub fn http_provider(
+    url: &str,
+    auth: Option<&str>,
+    timeout: Duration,
+) -> Result<RootProvider<Http<Client>>> {
+    let url = url
+        .parse()
+        .map_err(|e| IndexerError::Config(format!("Invalid RPC URL: {e}")))?;
+
+    let mut headers = HeaderMap::new();
+    if let Some(auth) = auth {
+        let mut value = HeaderValue::from_str(auth)
+            .map_err(|e| IndexerError::Config(format!("Invalid RPC_AUTH_HEADER: {e}")))?;
+        value.set_sensitive(true);
+        headers.insert(AUTHORIZATION, value);
+    }
+
+    let client = Client::builder()
+        .user_agent(USER_AGENT)
+        .timeout(timeout)
+        .default_headers(headers)
+        .build()
+        .map_err(|e| IndexerError::Config(format!("Failed to build HTTP client: {e}")))?;
+    let transport = Http::with_client(client, url);
+    let is_local = transport.guess_local();
+
+    Ok(ProviderBuilder::new().on_client(RpcClient::new(transport, is_local)))
+}
+
+/// Whether `error` is an HTTP request that ran into the provider's timeout.
+pub fn is_timeout(error: &TransportError) -> bool {
+    match error {
+        RpcError::Transport(TransportErrorKind::Custom(e)) => e
+            .downcast_ref::<reqwest::Error>()
+            .is_some_and(reqwest::Error::is_timeout),
+        _ => false,
+    }
+}
+
+/// WebSocket provider, sending `auth` as the `Authorization` header on the handshake if set.
+pub async fn ws_provider(url: &str, auth: Option<&str>) -> Result<RootProvider<PubSubFrontend>> {
+    let mut ws = WsConnect::new(url);
+    if let Some(auth) = auth {
+        ws = ws.with_auth(Authorization::raw(auth));
+    }
+    Ok(ProviderBuilder::new().on_ws(ws).await?)
+}
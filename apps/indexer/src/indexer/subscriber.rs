@@ -1,43 +1,121 @@
+use std::collections::BTreeMap;
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 use alloy::eips::BlockNumberOrTag;
-use alloy::primitives::Address;
-use alloy::providers::{Provider, ProviderBuilder, RootProvider, WsConnect};
+use alloy::primitives::B256;
+use alloy::providers::{Provider, RootProvider};
 use alloy::pubsub::PubSubFrontend;
-use alloy::rpc::types::{BlockTransactionsKind, Filter};
-use alloy::sol_types::SolEvent;
-use tokio::sync::watch;
+use alloy::rpc::types::{BlockTransactionsKind, Filter, Header, Log};
+use alloy::transports::http::{Client, Http};
+use alloy::transports::{Transport, TransportError};
+use governor::{Quota, RateLimiter};
+use r2d2::PooledConnection;
+use r2d2_sqlite::SqliteConnectionManager;
+use tokio::sync::{broadcast, watch};
 
-use crate::config::{sanitize_rpc_url, Config};
-use crate::db::queries::{get_last_processed_block, set_last_processed_block};
+use crate::config::{sanitize_rpc_url, Config, SubscribeMode};
+use crate::db::queries::{
+    advance_last_processed_block, delete_from_block, delete_log, get_last_processed_block,
+    indexed_block_hashes,
+};
 use crate::db::DbPool;
-use crate::error::Result;
+use crate::error::{IndexerError, Result};
 
-use super::backfill::run_backfill;
-use super::events::{Handshake, HandshakeResponse, MessageSent};
-use super::processor::{decode_log, EventProcessor, LogWithMeta};
-use super::retry_queue::RetryQueue;
+use super::backfill::{index_blocks, run_backfill, ProgressTracker};
+use super::chain_state::ChainState;
+use super::circuit_breaker::CircuitBreaker;
+use super::events::verbeth_filter;
+use super::processor::{chain_position, decode_log, EventProcessor, IndexedEvent, LogWithMeta};
+use super::retry_queue::{FailedEvent, RetryQueue};
+use super::rpc::{http_provider, ws_provider};
+use super::timestamp_cache::BlockTimestampCache;
+use super::writer::EventWriter;
 
-const RETRY_INTERVAL_SECS: u64 = 10;
+/// How often the retry loop looks for events whose retry delay has passed.
+const RETRY_POLL_INTERVAL: Duration = Duration::from_secs(1);
+/// Cap on the reconnect delay, including jitter.
+const MAX_RECONNECT_BACKOFF: Duration = Duration::from_secs(60);
+/// How often the live subscription times an `eth_blockNumber` call for the latency gauge.
+const LATENCY_PROBE_INTERVAL: Duration = Duration::from_secs(30);
+/// A probe slower than this is logged and not recorded, so it can't stall the stream.
+const LATENCY_PROBE_TIMEOUT: Duration = Duration::from_secs(10);
+/// Blocks below the resume point whose stored hashes are re-checked on recovery.
+const REORG_CHECK_DEPTH: u64 = 64;
+/// Upper bound on the final retry pass at shutdown.
+const RETRY_DRAIN_TIMEOUT: Duration = Duration::from_secs(5);
+/// Lowercased fragments of the errors returned by endpoints that accept WebSocket
+/// connections but not subscriptions. Reconnecting never helps with these.
+const SUBSCRIBE_UNSUPPORTED_ERRORS: &[&str] = &[
+    "-32601",
+    "method not found",
+    "does not exist",
+    "not available",
+    "not supported",
+    "unsupported",
+];
+/// Extra attempts at checking out a DB connection before the error tears down the socket.
+const POOL_RETRY_ATTEMPTS: u32 = 3;
+const POOL_RETRY_BACKOFF: Duration = Duration::from_millis(100);
+/// Per-attempt checkout wait, well under r2d2's 30s default, which would stall the runtime.
+const POOL_CHECKOUT_TIMEOUT: Duration = Duration::from_secs(1);
 
+#[allow(clippy::too_many_arguments)]
 pub async fn subscribe_with_reconnect(
     config: Arc<Config>,
     pool: DbPool,
+    writer: EventWriter,
+    retry_queue: Arc<RetryQueue>,
+    chain_state: Arc<ChainState>,
+    events: broadcast::Sender<IndexedEvent>,
+    timestamps: Arc<BlockTimestampCache>,
+    progress: ProgressTracker,
+    breaker: Arc<CircuitBreaker>,
     mut shutdown: watch::Receiver<bool>,
 ) {
-    let processor = Arc::new(EventProcessor::new(pool.clone()));
-    let retry_queue = Arc::new(RetryQueue::new());
+    let processor = Arc::new(
+        EventProcessor::new(writer.clone())
+            .with_events(events)
+            .with_contract(config.contract_address)
+            .with_compression(config.compress_blobs)
+            .with_raw_logs(config.store_raw_logs)
+            .with_fts(config.enable_fts)
+            .with_event_kinds(config.index_events)
+            .with_recipient_allowlist(config.recipient_allowlist.clone())
+            .with_payload_limits(config.payload_limits),
+    );
     let mut backoff = Duration::from_secs(1);
     let mut is_first_connect = true;
+    // Switches to polling for good if the WebSocket endpoint rejects subscriptions
+    let mut subscribe_mode = config.subscribe_mode;
+
+    // Retries re-fetch block timestamps that were unavailable when the log arrived
+    let retry_provider = match http_provider(
+        &config.http_rpc_url(),
+        config.rpc_auth_header.as_deref(),
+        config.rpc_limits.timeout,
+    ) {
+        Ok(provider) => provider,
+        Err(e) => {
+            tracing::error!("Subscriber not started: {e}");
+            return;
+        }
+    };
 
     // Spawn background retry task
     let retry_processor = processor.clone();
     let retry_q = retry_queue.clone();
-    let retry_pool = pool.clone();
+    let retry_timestamps = timestamps.clone();
     let retry_shutdown = shutdown.clone();
-    tokio::spawn(async move {
-        run_retry_loop(retry_q, retry_processor, retry_pool, retry_shutdown).await;
+    let retry_handle = tokio::spawn(async move {
+        run_retry_loop(
+            retry_q,
+            retry_processor,
+            retry_timestamps,
+            retry_provider,
+            retry_shutdown,
+        )
+        .await;
     });
 
     loop {
@@ -48,61 +126,146 @@ pub async fn subscribe_with_reconnect(
 
         // Recover missed events via HTTP before (re)connecting WS
         // Skip on first connect since main.rs already does initial backfill
-        if !is_first_connect {
-            if let Err(e) = recover_missed_events(&config, &pool).await {
+        // Polling resumes from the last processed block by itself
+        if !is_first_connect && subscribe_mode == SubscribeMode::Ws {
+            let recovery = recover_missed_events(
+                &config,
+                &pool,
+                &writer,
+                &chain_state,
+                &timestamps,
+                &retry_queue,
+                &progress,
+                &breaker,
+            );
+            if let Err(e) = recovery.await {
                 tracing::warn!("Failed to recover missed events: {e}");
             }
         }
         is_first_connect = false;
 
-        match connect_and_subscribe(
-            &config.rpc_ws_url,
-            config.contract_address,
-            &processor,
-            &retry_queue,
-            &pool,
-            &mut shutdown,
-        )
-        .await
-        {
+        let result = match subscribe_mode {
+            SubscribeMode::Ws => {
+                connect_and_subscribe(
+                    &config.rpc_ws_url,
+                    config.rpc_auth_header.as_deref(),
+                    verbeth_filter(config.contract_address, config.index_events),
+                    config.confirmations,
+                    config.ws_idle_timeout,
+                    &processor,
+                    &retry_queue,
+                    &pool,
+                    &chain_state,
+                    &timestamps,
+                    &mut shutdown,
+                )
+                .await
+            }
+            SubscribeMode::Poll => {
+                poll_for_events(
+                    &config,
+                    &processor,
+                    &retry_queue,
+                    &pool,
+                    &chain_state,
+                    &timestamps,
+                    &progress,
+                    &breaker,
+                    &mut shutdown,
+                )
+                .await
+            }
+        };
+
+        match result {
             Ok(()) => {
                 tracing::info!("Subscriber shut down gracefully");
                 break;
             }
+            Err(e @ IndexerError::SubscriptionUnsupported(_)) => {
+                tracing::error!(
+                    "{e}. Falling back to polling {} every {:?}; set SUBSCRIBE_MODE=poll to \
+                     skip the WebSocket, or use a provider that supports eth_subscribe",
+                    sanitize_rpc_url(&config.http_rpc_url()),
+                    config.poll_interval
+                );
+                chain_state.set_subscription_error(e.to_string());
+                subscribe_mode = SubscribeMode::Poll;
+            }
             Err(e) => {
-                tracing::warn!("Subscriber error: {e}, reconnecting in {:?}", backoff);
+                // ±50% jitter so instances sharing a provider don't reconnect in lockstep
+                let delay = backoff
+                    .mul_f64(rand::random_range(0.5..1.5))
+                    .min(MAX_RECONNECT_BACKOFF);
+                tracing::warn!("Subscriber error: {e}, reconnecting in {:?}", delay);
                 tokio::select! {
-                    _ = tokio::time::sleep(backoff) => {}
+                    _ = tokio::time::sleep(delay) => {}
                     _ = shutdown.changed() => {
                         if *shutdown.borrow() {
                             break;
                         }
                     }
                 }
-                backoff = (backoff * 2).min(Duration::from_secs(60));
+                backoff = (backoff * 2).min(MAX_RECONNECT_BACKOFF);
             }
         }
     }
+
+    // The retry loop sees the same shutdown signal and drains its queue before exiting
+    let _ = retry_handle.await;
 }
 
-async fn recover_missed_events(config: &Config, pool: &DbPool) -> Result<()> {
+#[allow(clippy::too_many_arguments)]
+async fn recover_missed_events(
+    config: &Config,
+    pool: &DbPool,
+    writer: &EventWriter,
+    chain_state: &ChainState,
+    timestamps: &BlockTimestampCache,
+    retry_queue: &RetryQueue,
+    progress: &ProgressTracker,
+    breaker: &CircuitBreaker,
+) -> Result<()> {
+    // Fail fast while the provider is down rather than spend quota on every reconnect
+    breaker.check()?;
+
     let conn = pool.get()?;
     let last_block = get_last_processed_block(&conn)?.unwrap_or(0) as u64;
     drop(conn);
 
-    // Derive HTTP URL from WS URL if not explicitly set
-    let http_url = config.rpc_http_url.clone().unwrap_or_else(|| {
-        config
-            .rpc_ws_url
-            .replace("wss://", "https://")
-            .replace("ws://", "http://")
-    });
+    let http_url = config.http_rpc_url();
+
+    let provider = http_provider(
+        &http_url,
+        config.rpc_auth_header.as_deref(),
+        config.rpc_limits.timeout,
+    )?;
 
-    let provider = ProviderBuilder::new().on_http(http_url.parse().map_err(|e| {
-        crate::error::IndexerError::Config(format!("Invalid HTTP RPC URL: {e}"))
-    })?);
+    let last_block = rewind_reorged_blocks(
+        config,
+        &provider,
+        pool,
+        writer,
+        chain_state,
+        breaker,
+        0,
+        last_block,
+    )
+    .await?;
 
-    let chain_head = provider.get_block_number().await?;
+    let chain_head = match provider.get_block_number().await {
+        Ok(head) => {
+            breaker.record_success();
+            head
+        }
+        Err(e) => {
+            breaker.record_failure();
+            return Err(e.into());
+        }
+    };
+    chain_state.set_chain_head(chain_head);
+    // Unconfirmed blocks are left to the live subscription's pending buffer
+    let chain_head = chain_head.saturating_sub(config.confirmations);
 
     if chain_head <= last_block {
         tracing::debug!("No missed blocks to recover");
@@ -118,12 +281,15 @@ async fn recover_missed_events(config: &Config, pool: &DbPool) -> Result<()> {
     );
 
     run_backfill(
+        config,
         &http_url,
-        config.contract_address,
         last_block + 1,
         chain_head,
-        config.rpc_chunk_size,
-        pool.clone(),
+        writer,
+        timestamps,
+        retry_queue,
+        progress,
+        breaker,
     )
     .await?;
 
@@ -134,7 +300,8 @@ async fn recover_missed_events(config: &Config, pool: &DbPool) -> Result<()> {
 async fn run_retry_loop(
     queue: Arc<RetryQueue>,
     processor: Arc<EventProcessor>,
-    pool: DbPool,
+    timestamps: Arc<BlockTimestampCache>,
+    provider: RootProvider<Http<Client>>,
     mut shutdown: watch::Receiver<bool>,
 ) {
     loop {
@@ -142,31 +309,17 @@ async fn run_retry_loop(
             _ = shutdown.changed() => {
                 if *shutdown.borrow() {
                     tracing::debug!("Retry loop shutting down");
+                    drain_retry_queue(&queue, &processor, &timestamps, &provider).await;
                     return;
                 }
             }
-            _ = tokio::time::sleep(Duration::from_secs(RETRY_INTERVAL_SECS)) => {
-                while let Some(failed) = queue.pop().await {
-                    let block_number = failed.log.block_number;
-                    let log_clone = failed.log.clone();
-                    match processor.process(log_clone) {
-                        Ok(true) => {
-                            tracing::info!(
-                                block = block_number,
-                                attempt = failed.retry_count + 1,
-                                "Retry succeeded"
-                            );
-                            if let Ok(conn) = pool.get() {
-                                let _ = set_last_processed_block(&conn, block_number as i64);
-                            }
-                        }
-                        Ok(false) => {
-                            tracing::debug!(block = block_number, "Retry: duplicate event");
-                        }
-                        Err(e) => {
-                            // Re-queue for another retry attempt
-                            queue.push_retry(failed, e.to_string()).await;
-                        }
+            _ = tokio::time::sleep(RETRY_POLL_INTERVAL) => {
+                while let Some(failed) = queue.pop_due().await {
+                    let result =
+                        retry_event(&failed, &queue, &processor, &timestamps, &provider).await;
+                    if let Err(e) = result {
+                        // Re-queue for another retry attempt
+                        queue.push_retry(failed, &e).await;
                     }
                 }
             }
@@ -174,106 +327,664 @@ async fn run_retry_loop(
     }
 }
 
+async fn retry_event<T: Transport + Clone, P: Provider<T>>(
+    failed: &FailedEvent,
+    queue: &RetryQueue,
+    processor: &EventProcessor,
+    timestamps: &BlockTimestampCache,
+    provider: &P,
+) -> Result<()> {
+    let block_number = failed.log.block_number;
+    let mut log = failed.log.clone();
+    if log.block_timestamp == 0 {
+        log.block_timestamp = match timestamps.get(block_number) {
+            Some(ts) => ts,
+            None => {
+                let ts = fetch_block_timestamp(provider, block_number).await?;
+                timestamps.insert(block_number, ts);
+                ts
+            }
+        };
+    }
+    if processor.process(log).await? {
+        tracing::info!(
+            block = block_number,
+            attempt = failed.retry_count + 1,
+            "Retry succeeded"
+        );
+        if !queue.holds_disk_full().await {
+            let _ = processor
+                .writer()
+                .run(move |conn| advance_last_processed_block(conn, block_number as i64))
+                .await;
+        }
+    } else {
+        tracing::debug!(block = block_number, "Retry: duplicate event");
+    }
+    Ok(())
+}
+
+/// Give every queued event one last attempt before the process exits. The queue lives in
+/// memory, so anything that still fails (or is not reached in time) is dead-lettered
+/// rather than lost, and can be requeued after restart.
+async fn drain_retry_queue(
+    queue: &RetryQueue,
+    processor: &EventProcessor,
+    timestamps: &BlockTimestampCache,
+    provider: &RootProvider<Http<Client>>,
+) {
+    let mut queued = queue.take_all().await;
+    queued.sort_by_key(|e| e.log.chain_position());
+    if queued.is_empty() {
+        return;
+    }
+    tracing::info!("Draining {} queued retries before shutdown", queued.len());
+    let mut pending = queued.into_iter();
+
+    let drain = async {
+        for failed in pending.by_ref() {
+            if let Err(e) = retry_event(&failed, queue, processor, timestamps, provider).await {
+                queue.abandon(failed, e.to_string()).await;
+            }
+        }
+    };
+    if tokio::time::timeout(RETRY_DRAIN_TIMEOUT, drain)
+        .await
+        .is_err()
+    {
+        tracing::warn!("Retry drain timed out after {:?}", RETRY_DRAIN_TIMEOUT);
+        for failed in pending {
+            queue
+                .abandon(failed, "not retried before shutdown".into())
+                .await;
+        }
+    }
+}
+
+/// Logs held back until their block reaches the configured confirmation depth.
+struct PendingLogs {
+    confirmations: u64,
+    blocks: BTreeMap<u64, Vec<Log>>,
+}
+
+impl PendingLogs {
+    fn new(confirmations: u64) -> Self {
+        Self {
+            confirmations,
+            blocks: BTreeMap::new(),
+        }
+    }
+
+    fn push(&mut self, log: Log) {
+        let block_number = log.block_number.unwrap_or(0);
+        self.blocks.entry(block_number).or_default().push(log);
+    }
+
+    /// Drop a buffered log retracted by a reorg. Returns false if it was not buffered.
+    fn remove(&mut self, block_number: u64, log_index: u64) -> bool {
+        let Some(logs) = self.blocks.get_mut(&block_number) else {
+            return false;
+        };
+        let before = logs.len();
+        logs.retain(|l| l.log_index != Some(log_index));
+        let removed = logs.len() != before;
+        if logs.is_empty() {
+            self.blocks.remove(&block_number);
+        }
+        removed
+    }
+
+    /// Remove and return logs from blocks with at least `confirmations` blocks on top,
+    /// in chain order. Within a block they were buffered in arrival order, which the tail
+    /// fetch on connect can interleave with the stream.
+    fn drain_confirmed(&mut self, chain_head: u64) -> Vec<Log> {
+        let Some(confirmed_up_to) = chain_head.checked_sub(self.confirmations) else {
+            return Vec::new();
+        };
+        let pending = self.blocks.split_off(&(confirmed_up_to + 1));
+        std::mem::replace(&mut self.blocks, pending)
+            .into_values()
+            .flat_map(|mut logs| {
+                logs.sort_by_key(chain_position);
+                logs
+            })
+            .collect()
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
 async fn connect_and_subscribe(
     ws_url: &str,
-    contract_address: Address,
+    auth: Option<&str>,
+    filter: Filter,
+    confirmations: u64,
+    idle_timeout: Option<Duration>,
     processor: &Arc<EventProcessor>,
     retry_queue: &Arc<RetryQueue>,
     pool: &DbPool,
+    chain_state: &ChainState,
+    timestamps: &BlockTimestampCache,
     shutdown: &mut watch::Receiver<bool>,
 ) -> Result<()> {
     tracing::info!("Connecting to WebSocket: {}", sanitize_rpc_url(ws_url));
 
-    let ws = WsConnect::new(ws_url);
-    let provider = ProviderBuilder::new().on_ws(ws).await?;
+    let provider = ws_provider(ws_url, auth).await?;
 
     tracing::info!("Connected, subscribing to events...");
 
-    let filter = Filter::new().address(contract_address).events([
-        MessageSent::SIGNATURE_HASH,
-        Handshake::SIGNATURE_HASH,
-        HandshakeResponse::SIGNATURE_HASH,
-    ]);
-
-    let sub = provider.subscribe_logs(&filter).await?;
+    let sub = provider
+        .subscribe_logs(&filter)
+        .await
+        .map_err(subscription_error)?;
     let mut stream = sub.into_stream();
 
+    // New heads keep the cached chain head fresh even when the contract is quiet
+    let mut heads = provider
+        .subscribe_blocks()
+        .await
+        .map_err(subscription_error)?
+        .into_stream();
+
+    let last_block = get_last_processed_block(&*get_conn(pool).await?)?.unwrap_or(0) as u64;
+    // Whether this stream picks up right after `last_block`, so that everything up to
+    // the highest seen block has been indexed; unknown until the first head arrives.
+    let mut gap_free = None;
+    // Number and hash of the latest head, to spot heads that replace or don't extend it
+    let mut last_head: Option<(u64, B256)> = None;
+
+    let mut pending = PendingLogs::new(confirmations);
+    if confirmations > 0 {
+        // Backfill/recovery stop `confirmations` blocks short of the head, so pull the
+        // still-unconfirmed tail into the buffer now that the subscription is live.
+        gap_free = Some(true);
+        let head = provider.get_block_number().await?;
+        chain_state.set_chain_head(head);
+        if head > last_block {
+            let tail = filter.clone().from_block(last_block + 1).to_block(head);
+            for log in provider.get_logs(&tail).await? {
+                pending.push(log);
+            }
+        }
+    }
+
     tracing::info!("Subscribed to Verbeth events");
 
+    let mut latency_probe = tokio::time::interval(LATENCY_PROBE_INTERVAL);
+    latency_probe.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+    // Some providers silently drop idle WS connections, leaving a stream that never
+    // yields nor ends. New heads arrive every block, so a long silence means it is dead.
+    // Only heads and logs push the deadline back; the latency probe and shutdown don't.
+    let next_idle_deadline = || idle_timeout.map(|timeout| tokio::time::Instant::now() + timeout);
+    let mut idle_deadline = next_idle_deadline();
+
     loop {
+        let idle = async {
+            match idle_deadline {
+                Some(deadline) => tokio::time::sleep_until(deadline).await,
+                None => std::future::pending().await,
+            }
+        };
+
         tokio::select! {
             _ = shutdown.changed() => {
                 if *shutdown.borrow() {
+                    // Events held for retry on a full disk are below the highest block
+                    if gap_free == Some(true) && !processor.disk_full() {
+                        let writer = processor.writer();
+                        flush_highest_seen_block(writer, chain_state, confirmations).await;
+                    }
                     return Ok(());
                 }
             }
+            _ = idle => {
+                tracing::warn!(
+                    "No WebSocket activity for {:?}, assuming the connection is dead",
+                    idle_timeout.unwrap_or_default()
+                );
+                return Err(IndexerError::ConnectionLost("idle timeout"));
+            }
+            _ = latency_probe.tick() => {
+                probe_rpc_latency(&provider, chain_state).await;
+            }
+            header_opt = futures_lite::StreamExt::next(&mut heads) => {
+                match header_opt {
+                    Some(header) => {
+                        idle_deadline = next_idle_deadline();
+                        // Blocks mined between the last backfill and the subscription
+                        // are only covered if the backfill reached them
+                        gap_free.get_or_insert(header.number <= last_block + 1);
+                        if let Some(depth) = reorg_depth(last_head, &header) {
+                            tracing::warn!(block = header.number, depth, "Chain reorg detected");
+                            chain_state.record_reorg(depth);
+                        }
+                        last_head = Some((header.number, header.hash));
+                        chain_state.observe_block(header.number);
+                    }
+                    None => {
+                        tracing::warn!("WebSocket block stream ended");
+                        return Err(IndexerError::ConnectionLost("block stream ended"));
+                    }
+                }
+            }
             log_opt = futures_lite::StreamExt::next(&mut stream) => {
                 let log = match log_opt {
                     Some(log) => log,
                     None => {
                         tracing::warn!("WebSocket stream ended");
-                        return Err(crate::error::IndexerError::Config("Stream ended".into()));
+                        return Err(IndexerError::ConnectionLost("log stream ended"));
                     }
                 };
+                idle_deadline = next_idle_deadline();
+
+                if log.removed {
+                    handle_removed_log(&log, &mut pending, processor.writer()).await?;
+                    continue;
+                }
 
-                let block_number = log.block_number.unwrap_or(0);
-                let log_index = log.log_index.unwrap_or(0);
+                chain_state.observe_block(log.block_number.unwrap_or(0));
 
-                let Some(event) = decode_log(&log) else {
-                    tracing::debug!("Unknown event at block {}", block_number);
+                if confirmations == 0 {
+                    handle_log(&provider, log, processor, retry_queue, timestamps).await?;
                     continue;
-                };
+                }
+                pending.push(log);
+            }
+        }
 
-                let block_timestamp = match fetch_block_timestamp(&provider, block_number).await {
-                    Ok(ts) => ts,
-                    Err(e) => {
-                        tracing::warn!("Failed to fetch block timestamp: {e}");
-                        continue;
-                    }
-                };
+        for log in pending.drain_confirmed(chain_state.chain_head()) {
+            handle_log(&provider, log, processor, retry_queue, timestamps).await?;
+        }
+    }
+}
 
-                let log_with_meta = LogWithMeta {
-                    event,
-                    block_number,
-                    log_index,
-                    block_timestamp,
-                };
+/// HTTP-only stand-in for `connect_and_subscribe`: every `POLL_INTERVAL`, index the
+/// confirmed blocks after the last processed one through the backfill path, after the
+/// same reorg check recovery does.
+#[allow(clippy::too_many_arguments)]
+async fn poll_for_events(
+    config: &Config,
+    processor: &EventProcessor,
+    retry_queue: &RetryQueue,
+    pool: &DbPool,
+    chain_state: &ChainState,
+    timestamps: &BlockTimestampCache,
+    progress: &ProgressTracker,
+    breaker: &CircuitBreaker,
+    shutdown: &mut watch::Receiver<bool>,
+) -> Result<()> {
+    let http_url = config.http_rpc_url();
+    tracing::info!(
+        "Polling {} for events every {:?}",
+        sanitize_rpc_url(&http_url),
+        config.poll_interval
+    );
 
-                match processor.process(log_with_meta) {
-                    Ok(true) => {
-                        tracing::debug!("Processed event at block {}", block_number);
-                        let conn = pool.get()?;
-                        set_last_processed_block(&conn, block_number as i64)?;
-                    }
-                    Ok(false) => {
-                        tracing::debug!("Duplicate event at block {}", block_number);
-                    }
-                    Err(e) => {
-                        tracing::warn!(
-                            block = block_number,
-                            log_index = log_index,
-                            error = %e,
-                            "Failed to process event, queuing for retry"
-                        );
-                        // Re-create log_with_meta for retry (need to re-decode)
-                        if let Some(event) = decode_log(&log) {
-                            let retry_log = LogWithMeta {
-                                event,
-                                block_number,
-                                log_index,
-                                block_timestamp,
-                            };
-                            retry_queue.push(retry_log, e.to_string()).await;
-                        }
-                    }
+    let provider = http_provider(
+        &http_url,
+        config.rpc_auth_header.as_deref(),
+        config.rpc_limits.timeout,
+    )?;
+
+    if get_last_processed_block(&*get_conn(pool).await?)?.is_none() {
+        // Startup found nothing to backfill; follow on from the head it saw, as a fresh
+        // subscription would, rather than polling from block 0
+        let start = chain_state
+            .chain_head()
+            .saturating_sub(config.confirmations);
+        processor
+            .writer()
+            .run(move |conn| advance_last_processed_block(conn, start as i64))
+            .await?;
+    }
+
+    // Blocks below this were hash-checked by an earlier poll
+    let mut checked_from = 0;
+    loop {
+        let last_block = get_last_processed_block(&*get_conn(pool).await?)?.unwrap_or(0) as u64;
+        let last_block = rewind_reorged_blocks(
+            config,
+            &provider,
+            pool,
+            processor.writer(),
+            chain_state,
+            breaker,
+            checked_from,
+            last_block,
+        )
+        .await?;
+        checked_from = last_block + 1;
+
+        let started = Instant::now();
+        let head = provider.get_block_number().await?;
+        chain_state.record_rpc_latency(started.elapsed());
+        chain_state.set_chain_head(head);
+        let confirmed = head.saturating_sub(config.confirmations);
+
+        if confirmed > last_block {
+            let stats = index_blocks(
+                config,
+                &provider,
+                processor,
+                last_block + 1,
+                confirmed,
+                timestamps,
+                retry_queue,
+                progress,
+                breaker,
+            )
+            .await?;
+            tracing::debug!(
+                "Polled blocks {} to {}: {} events",
+                last_block + 1,
+                confirmed,
+                stats.events_processed
+            );
+        }
+        // Blocks past `confirmed` are picked up by a later poll, as the WS pending buffer would
+        chain_state.observe_block(head);
+
+        tokio::select! {
+            _ = tokio::time::sleep(config.poll_interval) => {}
+            _ = shutdown.changed() => {
+                if *shutdown.borrow() {
+                    return Ok(());
                 }
             }
         }
     }
 }
 
-async fn fetch_block_timestamp(
+/// Compare the block hashes stored for recently indexed events with the chain. If a block
+/// was reorged while the socket was down, delete everything indexed from that block on and
+/// return the block before it, so recovery re-indexes the canonical logs.
+///
+/// The reorg is recorded in `chain_state` with a depth counted back from `last_block`. Only
+/// blocks holding indexed events are compared, so that depth is a lower bound. Blocks below
+/// `since` were compared by an earlier call and are skipped.
+#[allow(clippy::too_many_arguments)]
+async fn rewind_reorged_blocks<T: Transport + Clone, P: Provider<T>>(
+    config: &Config,
+    provider: &P,
+    pool: &DbPool,
+    writer: &EventWriter,
+    chain_state: &ChainState,
+    breaker: &CircuitBreaker,
+    since: u64,
+    last_block: u64,
+) -> Result<u64> {
+    let from_block = last_block.saturating_sub(REORG_CHECK_DEPTH).max(since);
+    let stored = indexed_block_hashes(&*pool.get()?, from_block as i64)?;
+
+    let limiter = RateLimiter::direct(Quota::per_second(config.rpc_limits.requests_per_second));
+    let mut reorged_from = None;
+    for (block_number, stored_hash) in stored {
+        breaker.check()?;
+        limiter.until_ready().await;
+        let block = match provider
+            .get_block_by_number(
+                BlockNumberOrTag::Number(block_number as u64),
+                BlockTransactionsKind::Hashes,
+            )
+            .await
+        {
+            Ok(block) => {
+                breaker.record_success();
+                block
+            }
+            Err(e) => {
+                breaker.record_failure();
+                return Err(e.into());
+            }
+        };
+        if block.map(|b| b.header.hash.0) != Some(stored_hash) {
+            reorged_from = Some(block_number);
+            break;
+        }
+    }
+
+    let Some(reorged_from) = reorged_from else {
+        return Ok(last_block);
+    };
+
+    // One transaction, so a crash cannot leave the cursor past half-deleted blocks
+    let deleted = writer
+        .run(move |conn| delete_from_block(conn, reorged_from))
+        .await?;
+    chain_state.record_reorg((last_block + 1).saturating_sub(reorged_from as u64).max(1));
+
+    tracing::warn!(
+        block = reorged_from,
+        messages = deleted.messages,
+        handshakes = deleted.handshakes,
+        handshake_responses = deleted.handshake_responses,
+        "Block hash changed while disconnected, rewound reorged events"
+    );
+    Ok((reorged_from - 1).max(0) as u64)
+}
+
+/// Tell a provider that rejects `eth_subscribe` apart from a failed connection.
+fn subscription_error(e: TransportError) -> IndexerError {
+    let message = e.to_string().to_lowercase();
+    if SUBSCRIBE_UNSUPPORTED_ERRORS
+        .iter()
+        .any(|fragment| message.contains(fragment))
+    {
+        IndexerError::SubscriptionUnsupported(e.to_string())
+    } else {
+        e.into()
+    }
+}
+
+/// Time one `eth_blockNumber` round trip on the subscription's own connection, which
+/// separates a slow provider from slow indexing. Failures are left to the stream itself.
+async fn probe_rpc_latency(provider: &RootProvider<PubSubFrontend>, chain_state: &ChainState) {
+    let started = Instant::now();
+    match tokio::time::timeout(LATENCY_PROBE_TIMEOUT, provider.get_block_number()).await {
+        Ok(Ok(head)) => {
+            chain_state.record_rpc_latency(started.elapsed());
+            chain_state.set_chain_head(head);
+        }
+        Ok(Err(e)) => tracing::warn!("RPC latency probe failed: {e}"),
+        Err(_) => tracing::warn!(
+            "RPC latency probe got no answer within {:?}",
+            LATENCY_PROBE_TIMEOUT
+        ),
+    }
+}
+
+/// Persist the highest block the stream has seen as the resume point, so a restart
+/// after a quiet stretch recovers from there rather than from the last event.
+///
+/// Stays one block behind with no confirmations: a block's logs can arrive after
+/// its header, and anything still in the pending buffer is not indexed yet.
+async fn flush_highest_seen_block(
+    writer: &EventWriter,
+    chain_state: &ChainState,
+    confirmations: u64,
+) {
+    let Some(block) = chain_state
+        .highest_seen_block()
+        .checked_sub(confirmations.max(1))
+    else {
+        return;
+    };
+
+    let result = writer
+        .run(move |conn| advance_last_processed_block(conn, block as i64))
+        .await;
+    match result {
+        Ok(()) => tracing::info!("Saved resume point at block {block} before shutdown"),
+        Err(e) => tracing::warn!("Failed to save resume point before shutdown: {e}"),
+    }
+}
+
+/// Check out a DB connection, backing off briefly if the pool is exhausted.
+/// A burst of API or backfill traffic can hold every connection for a moment,
+/// which is no reason to drop and resubscribe the WebSocket.
+async fn get_conn(pool: &DbPool) -> Result<PooledConnection<SqliteConnectionManager>> {
+    let mut backoff = POOL_RETRY_BACKOFF;
+    let mut attempt = 0;
+    loop {
+        match pool.get_timeout(POOL_CHECKOUT_TIMEOUT) {
+            Ok(conn) => return Ok(conn),
+            Err(e) if attempt < POOL_RETRY_ATTEMPTS => {
+                attempt += 1;
+                tracing::warn!(
+                    "DB pool exhausted ({e}), retrying in {:?} (attempt {}/{})",
+                    backoff,
+                    attempt,
+                    POOL_RETRY_ATTEMPTS
+                );
+                tokio::time::sleep(backoff).await;
+                backoff *= 2;
+            }
+            Err(e) => return Err(e.into()),
+        }
+    }
+}
+
+/// Blocks replaced by `header` if it does not extend `previous`: a head at or below the
+/// previous height replaces everything from its height up, and a next head whose parent
+/// is not the previous head replaces at least that one block.
+fn reorg_depth(previous: Option<(u64, B256)>, header: &Header) -> Option<u64> {
+    let (number, hash) = previous?;
+    if header.number <= number {
+        (header.hash != hash).then(|| number - header.number + 1)
+    } else if header.number == number + 1 && header.parent_hash != hash {
+        Some(1)
+    } else {
+        None
+    }
+}
+
+/// Undo a log the provider retracted because its block was reorged out.
+/// The re-mined version, if any, arrives later as a normal log.
+async fn handle_removed_log(
+    log: &Log,
+    pending: &mut PendingLogs,
+    writer: &EventWriter,
+) -> Result<()> {
+    let block_number = log.block_number.unwrap_or(0);
+    let log_index = log.log_index.unwrap_or(0);
+
+    if pending.remove(block_number, log_index) {
+        tracing::info!(
+            block = block_number,
+            log_index,
+            "Dropped unconfirmed log removed by reorg"
+        );
+        return Ok(());
+    }
+
+    let deleted = writer
+        .run(move |conn| delete_log(conn, block_number as i64, log_index as i64))
+        .await?;
+    if deleted {
+        tracing::warn!(
+            block = block_number,
+            log_index,
+            "Deleted indexed event removed by reorg"
+        );
+    }
+    Ok(())
+}
+
+async fn handle_log(
     provider: &RootProvider<PubSubFrontend>,
+    log: Log,
+    processor: &EventProcessor,
+    retry_queue: &RetryQueue,
+    timestamps: &BlockTimestampCache,
+) -> Result<()> {
+    let block_number = log.block_number.unwrap_or(0);
+    let log_index = log.log_index.unwrap_or(0);
+
+    let event = match decode_log(&log) {
+        Ok(Some(event)) => event,
+        Ok(None) => {
+            tracing::debug!("Unknown event at block {}", block_number);
+            return Ok(());
+        }
+        Err(e) => {
+            tracing::warn!(
+                block = block_number,
+                log_index = log_index,
+                tx = ?log.transaction_hash,
+                "{e}, skipping"
+            );
+            return Ok(());
+        }
+    };
+
+    let block_timestamp = match timestamps.get(block_number) {
+        Some(ts) => Some(ts),
+        None => match fetch_block_timestamp(provider, block_number).await {
+            Ok(ts) => {
+                timestamps.insert(block_number, ts);
+                Some(ts)
+            }
+            Err(e) => {
+                tracing::warn!("Failed to fetch block timestamp: {e}");
+                None
+            }
+        },
+    };
+
+    let log_with_meta = LogWithMeta {
+        event,
+        block_number,
+        log_index,
+        block_timestamp: block_timestamp.unwrap_or(0),
+        tx_hash: log.transaction_hash,
+        block_hash: log.block_hash,
+        raw_log: processor
+            .stores_raw_logs()
+            .then(|| Box::new(log.inner.clone())),
+    };
+
+    if block_timestamp.is_none() {
+        // Never persist a zero timestamp; the retry loop fetches it again
+        let error = IndexerError::MissingTimestamp(block_number);
+        tracing::warn!(
+            block = block_number,
+            log_index = log_index,
+            "{error}, queuing for retry"
+        );
+        retry_queue.push(log_with_meta, &error).await;
+        return Ok(());
+    }
+
+    match processor.process(log_with_meta.clone()).await {
+        Ok(true) => {
+            tracing::debug!("Processed event at block {}", block_number);
+            // Events held for retry on a full disk would be skipped by a restart
+            if !retry_queue.holds_disk_full().await {
+                processor
+                    .writer()
+                    .run(move |conn| advance_last_processed_block(conn, block_number as i64))
+                    .await?;
+            }
+        }
+        Ok(false) => {
+            tracing::debug!("Duplicate event at block {}", block_number);
+        }
+        Err(e) => {
+            tracing::warn!(
+                block = block_number,
+                log_index = log_index,
+                error = %e,
+                "Failed to process event"
+            );
+            retry_queue.push(log_with_meta, &e).await;
+        }
+    }
+
+    Ok(())
+}
+
+async fn fetch_block_timestamp<T: Transport + Clone, P: Provider<T>>(
+    provider: &P,
     block_number: u64,
 ) -> Result<u64> {
     let block = provider
@@ -282,7 +993,322 @@ async fn fetch_block_timestamp(
             BlockTransactionsKind::Hashes,
         )
         .await?
-        .ok_or(crate::error::IndexerError::BlockNotFound(block_number))?;
+        .ok_or(IndexerError::BlockNotFound(block_number))?;
 
     Ok(block.header.timestamp)
 }
+
+#[cfg(test)]
+mod tests {
+    use std::ops::RangeInclusive;
+
+    use alloy::primitives::{Address, U256};
+    use alloy::sol_types::SolEvent;
+
+    use super::*;
+    use crate::db::memory_pool;
+    use crate::db::queries::{list_dead_letters, messages_by_topic};
+    use crate::indexer::events::MessageSent;
+    use crate::indexer::mock_rpc::{block_timestamp, chain_log, MockRpc};
+    use crate::indexer::processor::VerbethEvent;
+    use crate::types::TopicHash;
+
+    const TOPIC: [u8; 32] = [7; 32];
+
+    fn message(block_number: u64, log_index: u64, ciphertext: u8) -> LogWithMeta {
+        LogWithMeta {
+            event: VerbethEvent::MessageSent {
+                sender: Address::repeat_byte(1),
+                ciphertext: vec![ciphertext; 32],
+                timestamp: 1_700_000_000,
+                topic: B256::from(TOPIC),
+                nonce: 0,
+            },
+            block_number,
+            log_index,
+            block_timestamp: 1_700_000_000 + block_number,
+            tx_hash: None,
+            block_hash: Some(B256::repeat_byte(block_number as u8)),
+            raw_log: None,
+        }
+    }
+
+    fn poll_config() -> Config {
+        Config::from_vars(&[
+            ("RPC_WS_URL", "wss://rpc.example.com"),
+            ("CONTRACT_ADDRESS", &Address::repeat_byte(0xee).to_string()),
+            ("RPC_REQUESTS_PER_SECOND", "1000"),
+            ("RPC_BREAKER_THRESHOLD", "1"),
+        ])
+        .unwrap()
+    }
+
+    /// Store a message in each of `blocks` under the hash the mock node reports.
+    async fn store_canonical(processor: &EventProcessor, blocks: RangeInclusive<u64>) {
+        for block_number in blocks {
+            let mut log = message(block_number, 0, block_number as u8);
+            log.block_hash = Some(B256::with_last_byte(block_number as u8));
+            assert!(processor.process(log).await.unwrap());
+        }
+    }
+
+    fn removed(block_number: u64, log_index: u64) -> Log {
+        Log {
+            block_number: Some(block_number),
+            log_index: Some(log_index),
+            removed: true,
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn removed_log_then_remined_leaves_only_the_remined_row() {
+        let pool = memory_pool();
+        let writer = EventWriter::spawn(&pool).unwrap();
+        let processor = EventProcessor::new(writer.clone());
+        let mut pending = PendingLogs::new(0);
+
+        assert!(processor.process(message(5, 0, 0xaa)).await.unwrap());
+        handle_removed_log(&removed(5, 0), &mut pending, &writer)
+            .await
+            .unwrap();
+        // Re-mined one block later, at another position
+        assert!(processor.process(message(6, 2, 0xbb)).await.unwrap());
+
+        let conn = pool.get().unwrap();
+        let rows =
+            messages_by_topic(&conn, &TopicHash(TOPIC), None, None, None, false, 100).unwrap();
+        let rows: Vec<_> = rows
+            .iter()
+            .map(|m| (m.seq, m.block_number, m.log_index, m.ciphertext[0]))
+            .collect();
+        // The retracted row gave its seq back to the re-mined one
+        assert_eq!(rows, [(0, 6, 2, 0xbb)]);
+    }
+
+    #[tokio::test]
+    async fn shuffled_logs_get_seqs_in_chain_order() {
+        let pool = memory_pool();
+        let processor = EventProcessor::new(EventWriter::spawn(&pool).unwrap());
+        let mut pending = PendingLogs::new(0);
+        // Tail fetch and stream interleaved, blocks and indexes out of order
+        for (block_number, log_index) in [(6, 1), (5, 3), (6, 0), (5, 0), (7, 2), (5, 1)] {
+            pending.push(Log {
+                block_number: Some(block_number),
+                log_index: Some(log_index),
+                ..Default::default()
+            });
+        }
+
+        for log in pending.drain_confirmed(7) {
+            let (block_number, log_index) = chain_position(&log);
+            assert!(processor
+                .process(message(block_number, log_index, log_index as u8))
+                .await
+                .unwrap());
+        }
+
+        let conn = pool.get().unwrap();
+        let rows =
+            messages_by_topic(&conn, &TopicHash(TOPIC), None, None, None, false, 100).unwrap();
+        let rows: Vec<_> = rows
+            .iter()
+            .map(|m| (m.seq, m.block_number, m.log_index))
+            .collect();
+        assert_eq!(
+            rows,
+            [
+                (0, 5, 0),
+                (1, 5, 1),
+                (2, 5, 3),
+                (3, 6, 0),
+                (4, 6, 1),
+                (5, 7, 2)
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn removed_log_still_buffered_is_never_stored() {
+        let pool = memory_pool();
+        let writer = EventWriter::spawn(&pool).unwrap();
+        let mut pending = PendingLogs::new(3);
+        pending.push(Log {
+            block_number: Some(5),
+            log_index: Some(0),
+            ..Default::default()
+        });
+
+        handle_removed_log(&removed(5, 0), &mut pending, &writer)
+            .await
+            .unwrap();
+
+        assert!(pending.drain_confirmed(100).is_empty());
+    }
+
+    #[tokio::test]
+    async fn events_whose_timestamp_fetch_fails_are_retried_not_lost() {
+        let contract = Address::repeat_byte(0xee);
+        let config = Config::from_vars(&[
+            ("RPC_WS_URL", "wss://rpc.example.com"),
+            ("CONTRACT_ADDRESS", &contract.to_string()),
+            ("RPC_REQUESTS_PER_SECOND", "1000"),
+            ("RPC_MAX_RETRIES", "2"),
+            ("RPC_BACKOFF_MS", "0"),
+        ])
+        .unwrap();
+        let sent = |block| {
+            let data = MessageSent {
+                sender: Address::repeat_byte(1),
+                ciphertext: vec![0xab; 32].into(),
+                timestamp: U256::from(1_700_000_000u64),
+                topic: B256::from(TOPIC),
+                nonce: U256::from(block),
+            }
+            .encode_log_data();
+            chain_log(contract, block, 0, data)
+        };
+        let rpc = MockRpc::new((2..=6).map(sent).collect());
+        // Every attempt of the first pass fails, the retry's succeeds
+        rpc.fail_block(4, config.rpc_limits.max_retries + 1);
+
+        let pool = memory_pool();
+        let processor = EventProcessor::new(EventWriter::spawn(&pool).unwrap());
+        let queue = RetryQueue::new(processor.writer().clone());
+        let timestamps = BlockTimestampCache::new(config.block_timestamp_cache_size);
+        let provider = rpc.provider();
+
+        let stats = index_blocks(
+            &config,
+            &provider,
+            &processor,
+            1,
+            6,
+            &timestamps,
+            &queue,
+            &ProgressTracker::default(),
+            &CircuitBreaker::new(&config.rpc_limits),
+        )
+        .await
+        .unwrap();
+        assert_eq!((stats.events_processed, stats.events_failed), (4, 1));
+        assert_eq!(queue.len().await, 1);
+
+        for failed in queue.take_all().await {
+            retry_event(&failed, &queue, &processor, &timestamps, &provider)
+                .await
+                .unwrap();
+        }
+
+        let conn = pool.get().unwrap();
+        let mut stored: Vec<_> =
+            messages_by_topic(&conn, &TopicHash(TOPIC), None, None, None, false, 100)
+                .unwrap()
+                .iter()
+                .map(|m| (m.block_number as u64, m.block_timestamp as u64))
+                .collect();
+        stored.sort();
+        let expected: Vec<_> = (2..=6).map(|b| (b, block_timestamp(b))).collect();
+        assert_eq!(stored, expected);
+        assert!(list_dead_letters(&conn, 10).unwrap().is_empty());
+        // Storing block 4 late must not move the resume point back below block 6
+        assert_eq!(get_last_processed_block(&conn).unwrap(), Some(6));
+    }
+
+    #[tokio::test]
+    async fn reorged_blocks_are_rewound_in_one_write() {
+        let pool = memory_pool();
+        let writer = EventWriter::spawn(&pool).unwrap();
+        let processor = EventProcessor::new(writer.clone());
+        for block_number in 3..=6 {
+            let mut log = message(block_number, 0, block_number as u8);
+            // Blocks 3 and 4 match the node, 5 and 6 were mined on a dropped fork
+            if block_number < 5 {
+                log.block_hash = Some(B256::with_last_byte(block_number as u8));
+            }
+            assert!(processor.process(log).await.unwrap());
+        }
+        writer
+            .run(|conn| advance_last_processed_block(conn, 6))
+            .await
+            .unwrap();
+        let chain_state = ChainState::default();
+
+        let config = poll_config();
+
+        let resume_after = rewind_reorged_blocks(
+            &config,
+            &MockRpc::default().provider(),
+            &pool,
+            &writer,
+            &chain_state,
+            &CircuitBreaker::new(&config.rpc_limits),
+            0,
+            6,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(resume_after, 4);
+        assert_eq!(chain_state.reorgs_detected(), 1);
+        let conn = pool.get().unwrap();
+        assert_eq!(get_last_processed_block(&conn).unwrap(), Some(4));
+        let rows: Vec<_> =
+            messages_by_topic(&conn, &TopicHash(TOPIC), None, None, None, false, 100)
+                .unwrap()
+                .iter()
+                .map(|m| (m.seq, m.block_number))
+                .collect();
+        assert_eq!(rows, [(0, 3), (1, 4)]);
+        drop(conn);
+
+        // Re-mined events continue from the surviving seqs
+        assert!(processor.process(message(5, 0, 0xcc)).await.unwrap());
+        let conn = pool.get().unwrap();
+        let rows = messages_by_topic(&conn, &TopicHash(TOPIC), None, None, None, false, 100);
+        assert_eq!(rows.unwrap().last().map(|m| m.seq), Some(2));
+    }
+
+    #[tokio::test]
+    async fn polls_only_hash_check_blocks_indexed_since_the_last_poll() {
+        let config = poll_config();
+        let pool = memory_pool();
+        let processor = EventProcessor::new(EventWriter::spawn(&pool).unwrap());
+        let chain_state = ChainState::default();
+        let breaker = CircuitBreaker::new(&config.rpc_limits);
+        let rpc = MockRpc::default();
+        let provider = rpc.provider();
+        let rewind = |since, last_block| {
+            rewind_reorged_blocks(
+                &config,
+                &provider,
+                &pool,
+                processor.writer(),
+                &chain_state,
+                &breaker,
+                since,
+                last_block,
+            )
+        };
+
+        store_canonical(&processor, 1..=5).await;
+        assert_eq!(rewind(0, 5).await.unwrap(), 5);
+        assert_eq!(rpc.calls("eth_getBlockByNumber"), 5);
+
+        // The next poll indexed two more blocks; only those are fetched
+        store_canonical(&processor, 6..=7).await;
+        assert_eq!(rewind(6, 7).await.unwrap(), 7);
+        assert_eq!(rpc.calls("eth_getBlockByNumber"), 7);
+
+        // A failed fetch opens the breaker, which then fails the check without a call
+        rpc.fail_block(6, 1);
+        assert!(rewind(0, 7).await.is_err());
+        let calls = rpc.calls("eth_getBlockByNumber");
+        assert!(matches!(
+            rewind(0, 7).await,
+            Err(IndexerError::RpcCircuitOpen(_))
+        ));
+        assert_eq!(rpc.calls("eth_getBlockByNumber"), calls);
+        assert_eq!(chain_state.reorgs_detected(), 0);
+    }
+}
@@ -0,0 +1,45 @@
+use std::num::NonZeroUsize;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+use lru::LruCache;
+
+/// Bounded block number -> timestamp cache shared by backfill and the live subscriber.
+pub struct BlockTimestampCache {
+    entries: Mutex<LruCache<u64, u64>>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl BlockTimestampCache {
+    pub fn new(capacity: NonZeroUsize) -> Self {
+        Self {
+            entries: Mutex::new(LruCache::new(capacity)),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    pub fn get(&self, block_number: u64) -> Option<u64> {
+        let timestamp = self.entries.lock().unwrap().get(&block_number).copied();
+        let counter = if timestamp.is_some() {
+            &self.hits
+        } else {
+            &self.misses
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+        timestamp
+    }
+
+    pub fn insert(&self, block_number: u64, timestamp: u64) {
+        self.entries.lock().unwrap().put(block_number, timestamp);
+    }
+
+    pub fn hits(&self) -> u64 {
+        self.hits.load(Ordering::Relaxed)
+    }
+
+    pub fn misses(&self) -> u64 {
+        self.misses.load(Ordering::Relaxed)
+    }
+}
@@ -0,0 +1,179 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use rusqlite::Connection;
+use tokio::sync::{mpsc, oneshot};
+
+use crate::db::DbPool;
+use crate::error::{IndexerError, Result};
+
+use super::contract_metrics::ContractMetrics;
+use super::processor::{EventProcessor, LogWithMeta};
+
+/// Events buffered ahead of the writer before producers wait.
+const WRITE_QUEUE_CAPACITY: usize = 1024;
+
+enum WriteJob {
+    Event {
+        processor: EventProcessor,
+        log: LogWithMeta,
+        reply: oneshot::Sender<Result<bool>>,
+    },
+    Chunk {
+        processor: EventProcessor,
+        logs: Vec<LogWithMeta>,
+        checkpoint: Option<u64>,
+        reply: oneshot::Sender<Result<Vec<Result<bool>>>>,
+    },
+    /// Any other write, e.g. moving the resume point or deleting reorged rows. The
+    /// closure sends its own reply.
+    Run(Box<dyn FnOnce(&Connection) + Send>),
+}
+
+/// Handle to the one thread that stores indexed events. Backfill, the live subscriber,
+/// retries and admin jobs all enqueue here, so seqs are assigned on a single connection
+/// in arrival order and concurrent producers never race for the same key.
+#[derive(Clone)]
+pub struct EventWriter {
+    jobs: mpsc::Sender<WriteJob>,
+    /// Set while writes fail with `SQLITE_FULL`, cleared by the next successful one
+    disk_full: Arc<AtomicBool>,
+    contract_metrics: Arc<ContractMetrics>,
+}
+
+impl EventWriter {
+    /// Start the writer with a connection checked out of `pool` for its whole life.
+    /// It runs on its own OS thread rather than `spawn_blocking`, which would hold up
+    /// runtime shutdown for as long as any handle is alive.
+    pub fn spawn(pool: &DbPool) -> Result<Self> {
+        let conn = pool.get()?;
+        let (jobs, mut rx) = mpsc::channel::<WriteJob>(WRITE_QUEUE_CAPACITY);
+        let disk_full = Arc::new(AtomicBool::new(false));
+        let flag = disk_full.clone();
+        std::thread::Builder::new()
+            .name("event-writer".into())
+            .spawn(move || {
+                // The producer may have stopped waiting (e.g. a timed-out drain)
+                while let Some(job) = rx.blocking_recv() {
+                    match job {
+                        WriteJob::Event {
+                            processor,
+                            log,
+                            reply,
+                        } => {
+                            let result = processor.process_with_conn(&conn, log);
+                            track_disk_full(&flag, result.as_ref().err().as_slice());
+                            let _ = reply.send(result);
+                        }
+                        WriteJob::Chunk {
+                            processor,
+                            logs,
+                            checkpoint,
+                            reply,
+                        } => {
+                            let result = processor.process_chunk_with_conn(&conn, logs, checkpoint);
+                            let errors: Vec<_> = match &result {
+                                Ok(results) => {
+                                    results.iter().filter_map(|r| r.as_ref().err()).collect()
+                                }
+                                Err(e) => vec![e],
+                            };
+                            track_disk_full(&flag, &errors);
+                            let _ = reply.send(result);
+                        }
+                        WriteJob::Run(job) => job(&conn),
+                    }
+                }
+            })
+            .map_err(|e| IndexerError::Config(format!("Failed to start event writer: {e}")))?;
+        Ok(Self {
+            jobs,
+            disk_full,
+            contract_metrics: Arc::default(),
+        })
+    }
+
+    /// Whether the last write failed because the disk is full.
+    pub fn disk_full(&self) -> bool {
+        self.disk_full.load(Ordering::Relaxed)
+    }
+
+    /// Per-contract counts of the events stored through this writer.
+    pub fn contract_metrics(&self) -> &ContractMetrics {
+        &self.contract_metrics
+    }
+
+    /// Store `log` as configured by `processor`, waiting for the writer's result.
+    pub async fn write(&self, processor: EventProcessor, log: LogWithMeta) -> Result<bool> {
+        let (reply, result) = oneshot::channel();
+        let job = WriteJob::Event {
+            processor,
+            log,
+            reply,
+        };
+        self.jobs
+            .send(job)
+            .await
+            .map_err(|_| IndexerError::WriterStopped)?;
+        result.await.map_err(|_| IndexerError::WriterStopped)?
+    }
+
+    /// Store `logs` and move the resume point to `checkpoint` in one transaction, waiting
+    /// for the writer's per-log results.
+    pub async fn write_chunk(
+        &self,
+        processor: EventProcessor,
+        logs: Vec<LogWithMeta>,
+        checkpoint: Option<u64>,
+    ) -> Result<Vec<Result<bool>>> {
+        let (reply, result) = oneshot::channel();
+        let job = WriteJob::Chunk {
+            processor,
+            logs,
+            checkpoint,
+            reply,
+        };
+        self.jobs
+            .send(job)
+            .await
+            .map_err(|_| IndexerError::WriterStopped)?;
+        result.await.map_err(|_| IndexerError::WriterStopped)?
+    }
+
+    /// Run `write` on the writer's connection, so it is ordered with the event writes
+    /// around it and never competes with them for the database lock.
+    pub async fn run<T, F>(&self, write: F) -> Result<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&Connection) -> Result<T> + Send + 'static,
+    {
+        let (reply, result) = oneshot::channel();
+        let flag = self.disk_full.clone();
+        let job = WriteJob::Run(Box::new(move |conn| {
+            let result = write(conn);
+            track_disk_full(&flag, result.as_ref().err().as_slice());
+            let _ = reply.send(result);
+        }));
+        self.jobs
+            .send(job)
+            .await
+            .map_err(|_| IndexerError::WriterStopped)?;
+        result.await.map_err(|_| IndexerError::WriterStopped)?
+    }
+}
+
+/// Update the disk-full flag from the errors of one write job, logging once per
+/// transition. Other errors leave it as it is: they say nothing about free space.
+fn track_disk_full(flag: &AtomicBool, errors: &[&IndexerError]) {
+    if let Some(e) = errors.iter().find(|e| e.is_disk_full()) {
+        if !flag.swap(true, Ordering::Relaxed) {
+            tracing::error!(
+                "CRITICAL: database disk is full ({e}). Events that fail are held in the \
+                 retry queue, and neither the subscriber nor backfills advance the resume \
+                 point until they are stored"
+            );
+        }
+    } else if errors.is_empty() && flag.swap(false, Ordering::Relaxed) {
+        tracing::info!("Database writes succeed again after the disk was full");
+    }
+}
@@ -1,121 +1,539 @@
 use std::net::SocketAddr;
+use std::path::Path;
+use std::process::ExitCode;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
 
-use alloy::providers::{Provider, ProviderBuilder};
-use tokio::sync::watch;
+use alloy::primitives::B256;
+use alloy::providers::{Provider, RootProvider};
+use alloy::transports::http::{Client, Http};
+use axum_server::tls_rustls::RustlsConfig;
+use tokio::sync::{broadcast, mpsc, watch};
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt, EnvFilter};
 
 mod api;
 mod config;
 mod db;
 mod error;
+#[cfg(feature = "grpc")]
+mod grpc;
 mod indexer;
+mod types;
+mod webhook;
 
 use api::AppState;
-use config::Config;
-use db::{create_pool, queries};
+use config::{Config, TlsPaths};
+use db::{create_pool, create_read_pool, queries};
 use error::Result;
-use indexer::{backfill, subscriber};
+use indexer::chain_state::ChainState;
+use indexer::circuit_breaker::CircuitBreaker;
+use indexer::retry_queue::RetryQueue;
+use indexer::timestamp_cache::BlockTimestampCache;
+use indexer::writer::EventWriter;
+use indexer::{backfill, discovery, finality, rescan, rpc, subscriber};
+
+/// Live events buffered per stream subscriber before it is considered lagged
+const EVENT_CHANNEL_CAPACITY: usize = 1024;
+/// Dead-letter notices waiting for the webhook notifier; more are dropped, as the alerts
+/// are debounced anyway
+const DEAD_LETTER_ALERT_CAPACITY: usize = 16;
 
 #[tokio::main]
-async fn main() -> Result<()> {
+async fn main() -> Result<ExitCode> {
+    // Inspect the database and exit; needs neither the RPC nor the rest of the config
+    if std::env::args().any(|a| a == "--stats") {
+        dotenvy::dotenv().ok();
+        let json = std::env::args().any(|a| a == "--json");
+        db::report::print(&config::database_path_from_env(), json)?;
+        return Ok(ExitCode::SUCCESS);
+    }
+
     tracing_subscriber::registry()
         .with(EnvFilter::try_from_default_env().unwrap_or_else(|_| "info".into()))
         .with(tracing_subscriber::fmt::layer())
         .init();
 
-    let config = Config::from_env()?;
+    let mut config = Config::from_env()?;
 
-    tracing::info!(
-        "Starting Verbeth Indexer v{}",
-        env!("CARGO_PKG_VERSION")
-    );
+    tracing::info!("Starting Verbeth Indexer v{}", env!("CARGO_PKG_VERSION"));
     tracing::info!("Contract: {}", config.contract_address);
     tracing::info!("Database: {}", config.database_path);
     tracing::info!("RPC chunk size: {} blocks", config.rpc_chunk_size);
 
-    let pool = create_pool(&config.database_path, &config.sqlite_sync_mode)?;
+    // Load before the backfill so a bad cert fails startup, not the first request hours later
+    let tls = match &config.tls {
+        Some(paths) if !config.backfill_only => Some(load_tls_config(paths).await?),
+        _ => None,
+    };
+
+    let pool = create_pool(
+        &config.database_path,
+        &config.sqlite_sync_mode,
+        &config.db_integrity_check,
+    )?;
 
     let (shutdown_tx, shutdown_rx) = watch::channel(false);
 
+    if let Some(interval) = config.wal_checkpoint_interval {
+        tokio::spawn(db::wal::run_checkpoints(
+            config.database_path.clone(),
+            interval,
+            shutdown_rx.clone(),
+        ));
+    }
+
+    if config.enable_fts {
+        let conn = pool.get()?;
+        db::schema::enable_handshake_fts(&conn)?;
+        let indexed = queries::index_missing_handshake_payloads(&conn)?;
+        if indexed > 0 {
+            tracing::info!("Indexed {indexed} stored handshake payloads for full-text search");
+        }
+    }
+
     let conn = pool.get()?;
     let is_empty = queries::is_db_empty(&conn)?;
     let last_block = queries::get_last_processed_block(&conn)?;
     drop(conn);
 
-    let rpc_url = config.rpc_http_url.clone().unwrap_or_else(|| {
-        config.rpc_ws_url.replace("wss://", "https://").replace("ws://", "http://")
-    });
+    let rpc_url = config.http_rpc_url();
 
-    let http_provider = ProviderBuilder::new()
-        .on_http(rpc_url.parse().map_err(|e| {
-            error::IndexerError::Config(format!("Invalid RPC URL: {e}"))
-        })?);
+    let http_provider = rpc::http_provider(
+        &rpc_url,
+        config.rpc_auth_header.as_deref(),
+        config.rpc_limits.timeout,
+    )?;
 
-    let chain_head = http_provider.get_block_number().await?;
-    tracing::info!("Chain head: {}", chain_head);
+    if let Some(tx_hash) = config.creation_tx_hash {
+        config.creation_block =
+            resolve_creation_block(&config, &http_provider, &pool, tx_hash).await?;
+        tracing::info!("Creation block {} from tx {tx_hash}", config.creation_block);
+    }
+
+    let latest_block = http_provider.get_block_number().await?;
+    tracing::info!("Chain head: {}", latest_block);
+
+    if config.detect_creation_block && is_empty {
+        config.creation_block = discovery::detect_creation_block(
+            &http_provider,
+            &pool,
+            config.contract_address,
+            latest_block,
+        )
+        .await?;
+        tracing::info!("Creation block {} detected", config.creation_block);
+    } else if config.detect_creation_block {
+        let key = config.contract_address.to_string();
+        if let Some(block) = queries::get_cached_creation_block(&*pool.get()?, &key)? {
+            config.creation_block = block;
+        }
+    }
+    // Only backfill confirmed blocks; the subscriber picks up the unconfirmed tail
+    let chain_head = latest_block.saturating_sub(config.confirmations);
 
     let start_block = if is_empty {
         let blocks_per_day = 43200u64; // ~2s blocks on Base
         let days_back = config.backfill_days as u64;
-        chain_head.saturating_sub(blocks_per_day * days_back).max(config.creation_block)
+        let start_block = chain_head
+            .saturating_sub(blocks_per_day * days_back)
+            .max(config.creation_block);
+
+        let max_blocks = config.max_backfill_blocks;
+        let capped = (max_blocks > 0 && chain_head.saturating_sub(start_block) >= max_blocks)
+            .then(|| chain_head + 1 - max_blocks);
+        if let Some(capped) = capped {
+            tracing::warn!(
+                "Initial backfill capped at {max_blocks} blocks: history before block {capped} \
+                 (from block {start_block}) is skipped. Raise MAX_BACKFILL_BLOCKS to index it"
+            );
+        }
+        queries::set_history_start_block(&*pool.get()?, capped.map(|b| b as i64))?;
+        capped.unwrap_or(start_block)
     } else {
         (last_block.unwrap_or(config.creation_block as i64) as u64) + 1
     };
+    // Both ends are inclusive, so a resume point one block behind still has one to index
+    let needs_backfill = start_block <= chain_head;
 
-    if start_block < chain_head {
-        tracing::info!("Running backfill from block {} to {}", start_block, chain_head);
-        backfill::run_backfill(
+    let timestamps = Arc::new(BlockTimestampCache::new(config.block_timestamp_cache_size));
+    let writer = EventWriter::spawn(&pool)?;
+    let mut retry_queue = RetryQueue::new(writer.clone());
+    // Webhooks only run when serving
+    let mut dead_letter_alerts = None;
+    if config.webhook_url.is_some() && config.webhook_alert_on_dead_letter && !config.backfill_only
+    {
+        let (tx, rx) = mpsc::channel(DEAD_LETTER_ALERT_CAPACITY);
+        retry_queue = retry_queue.with_dead_letter_alerts(tx);
+        dead_letter_alerts = Some(rx);
+    }
+    let retry_queue = Arc::new(retry_queue);
+    let progress = backfill::ProgressTracker::default();
+    let rpc_breaker = Arc::new(CircuitBreaker::new(&config.rpc_limits));
+
+    if config.backfill_only {
+        let result = if needs_backfill {
+            backfill::run_backfill(
+                &config,
+                &rpc_url,
+                start_block,
+                chain_head,
+                &writer,
+                &timestamps,
+                &retry_queue,
+                &progress,
+                &rpc_breaker,
+            )
+            .await
+        } else {
+            Ok(backfill::BackfillStats::default())
+        };
+        // No retry loop runs in this mode; persist deferred events for a later requeue
+        for failed in retry_queue.take_all().await {
+            let error = failed.last_error.clone();
+            retry_queue.abandon(failed, error).await;
+        }
+
+        return Ok(match result {
+            Ok(stats) => {
+                println!("{}", serde_json::to_string(&stats).unwrap_or_default());
+                if stats.events_failed > 0 {
+                    ExitCode::FAILURE
+                } else {
+                    ExitCode::SUCCESS
+                }
+            }
+            Err(e) => {
+                tracing::error!("Backfill failed: {e}");
+                ExitCode::FAILURE
+            }
+        });
+    }
+
+    let chain_state = Arc::new(ChainState::default());
+    chain_state.set_chain_head(latest_block);
+    let (events_tx, _) = broadcast::channel(EVENT_CHANNEL_CAPACITY);
+    let read_pool = match config.read_pool_size {
+        0 => pool.clone(),
+        size => create_read_pool(&config.database_path, size)?,
+    };
+    let state = AppState::new(
+        pool.clone(),
+        read_pool,
+        writer.clone(),
+        config,
+        retry_queue.clone(),
+        chain_state.clone(),
+        events_tx.clone(),
+        timestamps.clone(),
+        progress.clone(),
+        rpc_breaker.clone(),
+    );
+
+    let addr = SocketAddr::from(([0, 0, 0, 0], state.config.server_port));
+    let listener = match &state.config.listen_uds {
+        Some(path) => bind_unix(Path::new(path))?,
+        None => {
+            let listener = tokio::net::TcpListener::bind(addr).await.map_err(|e| {
+                error::IndexerError::Config(format!("Failed to bind to {}: {}", addr, e))
+            })?;
+            if tls.is_some() {
+                tracing::info!("API server listening on {} (TLS)", addr);
+            } else {
+                tracing::info!("API server listening on {}", addr);
+            }
+            HttpListener::Tcp(listener)
+        }
+    };
+
+    let socket_path = state.config.listen_uds.clone();
+    let router = api::create_router(state.clone());
+
+    // Config rejects TLS together with LISTEN_UDS
+    let server_handle = match (listener, tls) {
+        (HttpListener::Tcp(listener), Some(tls)) => {
+            let make_service = router.into_make_service_with_connect_info::<SocketAddr>();
+            let listener = listener.into_std().map_err(|e| {
+                error::IndexerError::Config(format!("Failed to bind to {}: {}", addr, e))
+            })?;
+            let handle = axum_server::Handle::new();
+            tokio::spawn({
+                let handle = handle.clone();
+                async move {
+                    shutdown_signal(shutdown_tx).await;
+                    handle.graceful_shutdown(None);
+                }
+            });
+            tokio::spawn(async move {
+                axum_server::from_tcp_rustls(listener, tls)
+                    .handle(handle)
+                    .serve(make_service)
+                    .await
+                    .ok();
+            })
+        }
+        (HttpListener::Tcp(listener), None) => tokio::spawn(async move {
+            axum::serve(
+                listener,
+                router.into_make_service_with_connect_info::<SocketAddr>(),
+            )
+            .with_graceful_shutdown(shutdown_signal(shutdown_tx))
+            .await
+            .ok();
+        }),
+        #[cfg(unix)]
+        (HttpListener::Unix(listener), _) => tokio::spawn(async move {
+            axum::serve(listener, router.into_make_service())
+                .with_graceful_shutdown(shutdown_signal(shutdown_tx))
+                .await
+                .ok();
+            // The socket file outlives the listener; remove it so the next start binds cleanly
+            if let Some(path) = socket_path {
+                if let Err(e) = std::fs::remove_file(&path) {
+                    tracing::warn!("Failed to remove socket {path}: {e}");
+                }
+            }
+        }),
+    };
+
+    // Data routes answer 503 until this completes; health, metrics and admin routes
+    // already serve, so orchestrators can watch a long startup backfill
+    if needs_backfill {
+        tracing::info!(
+            "Running backfill from block {} to {}",
+            start_block,
+            chain_head
+        );
+        let backfill = backfill::run_backfill(
+            &state.config,
             &rpc_url,
-            config.contract_address,
             start_block,
             chain_head,
-            config.rpc_chunk_size,
-            pool.clone(),
-        )
-        .await?;
+            &writer,
+            &timestamps,
+            &retry_queue,
+            &progress,
+            &rpc_breaker,
+        );
+        let mut shutdown = shutdown_rx.clone();
+        tokio::select! {
+            result = backfill => match result {
+                Ok(_) => {}
+                // Its events wait in the retry queue; /health reports degraded meanwhile
+                Err(e) if e.is_disk_full() => {
+                    tracing::error!("Startup backfill stopped on a full disk ({e}), serving degraded");
+                }
+                Err(e) => return Err(e),
+            },
+            _ = shutdown.changed() => {
+                // Resumes from the last checkpoint on the next start
+                tracing::info!("Shutdown during the startup backfill");
+                let _ = server_handle.await;
+                return Ok(ExitCode::SUCCESS);
+            }
+        }
     } else {
         tracing::info!("No backfill needed, starting from chain head");
     }
+    state.initial_sync_complete.store(true, Ordering::Relaxed);
 
-    let state = AppState::new(pool.clone(), config);
-
-    let subscriber_handle = {
+    let mut subscriber_handle = {
         let config = state.config.clone();
         let pool = pool.clone();
         let shutdown_rx = shutdown_rx.clone();
 
         tokio::spawn(async move {
-            subscriber::subscribe_with_reconnect(config, pool, shutdown_rx).await;
+            subscriber::subscribe_with_reconnect(
+                config,
+                pool,
+                writer,
+                retry_queue,
+                chain_state,
+                events_tx,
+                timestamps,
+                progress,
+                rpc_breaker,
+                shutdown_rx,
+            )
+            .await;
         })
     };
 
-    let addr = SocketAddr::from(([0, 0, 0, 0], state.config.server_port));
-    let listener = tokio::net::TcpListener::bind(addr).await.map_err(|e| {
-        error::IndexerError::Config(format!("Failed to bind to {}: {}", addr, e))
-    })?;
+    if let Some(url) = state.config.webhook_url.clone() {
+        tracing::info!("Webhook notifications enabled");
+        let mut notifier = webhook::WebhookNotifier::new(
+            url,
+            state.config.webhook_secret.clone(),
+            state.config.webhook_max_retries,
+        );
+        if let Some(alerts) = dead_letter_alerts {
+            notifier = notifier.with_dead_letter_alerts(alerts);
+        }
+        tokio::spawn(notifier.run(state.events.subscribe(), shutdown_rx.clone()));
+    }
 
-    tracing::info!("API server listening on {}", addr);
+    if let Some(interval) = state.config.rescan_interval {
+        tokio::spawn(rescan::run_rescans(
+            state.config.clone(),
+            pool.clone(),
+            state.writer.clone(),
+            state.timestamps.clone(),
+            state.retry_queue.clone(),
+            state.backfill_progress.clone(),
+            state.rpc_breaker.clone(),
+            interval,
+            shutdown_rx.clone(),
+        ));
+    }
 
-    let router = api::create_router(state);
+    if let Some(depth) = state.config.verify_finality {
+        tokio::spawn(finality::run_finality_checks(
+            state.config.clone(),
+            pool.clone(),
+            state.writer.clone(),
+            state.timestamps.clone(),
+            state.retry_queue.clone(),
+            state.backfill_progress.clone(),
+            state.chain_state.clone(),
+            state.rpc_breaker.clone(),
+            depth,
+            shutdown_rx.clone(),
+        ));
+    }
 
-    let server_handle = tokio::spawn(async move {
-        axum::serve(listener, router)
-            .with_graceful_shutdown(shutdown_signal(shutdown_tx))
-            .await
-            .ok();
-    });
+    if let Some(grpc_port) = state.config.grpc_port {
+        spawn_grpc_server(grpc_port, &state, shutdown_rx.clone());
+    }
 
     tokio::select! {
-        _ = subscriber_handle => {
+        _ = &mut subscriber_handle => {
             tracing::info!("Subscriber task finished");
         }
         _ = server_handle => {
             tracing::info!("Server task finished");
+            // On a signal the subscriber is still draining its retry queue; let it finish
+            if *shutdown_rx.borrow() {
+                let _ = subscriber_handle.await;
+            }
         }
     }
 
     tracing::info!("Shutdown complete");
-    Ok(())
+    Ok(ExitCode::SUCCESS)
+}
+
+enum HttpListener {
+    Tcp(tokio::net::TcpListener),
+    #[cfg(unix)]
+    Unix(tokio::net::UnixListener),
+}
+
+/// Bind `LISTEN_UDS`, replacing a socket file left behind by an unclean exit. A socket
+/// that still accepts connections belongs to a running instance and is left alone.
+#[cfg(unix)]
+fn bind_unix(path: &Path) -> Result<HttpListener> {
+    use std::os::unix::fs::FileTypeExt;
+
+    let bind_error = |e: std::io::Error| {
+        error::IndexerError::Config(format!("Failed to bind to {}: {e}", path.display()))
+    };
+
+    if let Ok(meta) = std::fs::symlink_metadata(path) {
+        if !meta.file_type().is_socket() {
+            return Err(error::IndexerError::Config(format!(
+                "LISTEN_UDS {} exists and is not a socket",
+                path.display()
+            )));
+        }
+        if std::os::unix::net::UnixStream::connect(path).is_ok() {
+            return Err(error::IndexerError::Config(format!(
+                "LISTEN_UDS {} is in use by another process",
+                path.display()
+            )));
+        }
+        std::fs::remove_file(path).map_err(bind_error)?;
+        tracing::info!("Removed stale socket {}", path.display());
+    }
+    let listener = tokio::net::UnixListener::bind(path).map_err(bind_error)?;
+    tracing::info!("API server listening on {}", path.display());
+    Ok(HttpListener::Unix(listener))
+}
+
+#[cfg(not(unix))]
+fn bind_unix(_path: &Path) -> Result<HttpListener> {
+    Err(error::IndexerError::Config(
+        "LISTEN_UDS is only supported on Unix".into(),
+    ))
+}
+
+/// Block of the contract's deployment transaction, from the DB after the first lookup.
+async fn resolve_creation_block(
+    config: &Config,
+    provider: &RootProvider<Http<Client>>,
+    pool: &db::DbPool,
+    tx_hash: B256,
+) -> Result<u64> {
+    let key = tx_hash.to_string();
+    if let Some(block) = queries::get_cached_creation_block(&*pool.get()?, &key)? {
+        return Ok(block);
+    }
+
+    let receipt = provider
+        .get_transaction_receipt(tx_hash)
+        .await?
+        .ok_or_else(|| {
+            error::IndexerError::Config(format!(
+                "CREATION_TX_HASH {tx_hash} not found by the RPC provider"
+            ))
+        })?;
+    let block = receipt.block_number.ok_or_else(|| {
+        error::IndexerError::Config(format!("CREATION_TX_HASH {tx_hash} is still pending"))
+    })?;
+    // Factory deployments leave contract_address unset, so only a different address is suspect
+    if let Some(deployed) = receipt.contract_address {
+        if deployed != config.contract_address {
+            tracing::warn!(
+                "CREATION_TX_HASH deployed {deployed}, not CONTRACT_ADDRESS {}",
+                config.contract_address
+            );
+        }
+    }
+
+    queries::cache_creation_block(&*pool.get()?, &key, block)?;
+    Ok(block)
+}
+
+async fn load_tls_config(paths: &TlsPaths) -> Result<RustlsConfig> {
+    // reqwest links rustls with ring too; install it as the process default so the
+    // server config builder has a single provider to pick
+    let _ = rustls::crypto::ring::default_provider().install_default();
+
+    RustlsConfig::from_pem_file(&paths.cert_path, &paths.key_path)
+        .await
+        .map_err(|e| {
+            error::IndexerError::Config(format!(
+                "Failed to load TLS cert {} / key {}: {e}",
+                paths.cert_path, paths.key_path
+            ))
+        })
+}
+
+#[cfg(feature = "grpc")]
+fn spawn_grpc_server(port: u16, state: &AppState, shutdown_rx: watch::Receiver<bool>) {
+    let addr = SocketAddr::from(([0, 0, 0, 0], port));
+    let service = grpc::IndexerService::new(state.read_pool.clone(), state.events.clone());
+
+    tracing::info!("gRPC server listening on {}", addr);
+
+    tokio::spawn(async move {
+        if let Err(e) = grpc::serve(addr, service, shutdown_rx).await {
+            tracing::error!("gRPC server error: {e}");
+        }
+    });
+}
+
+#[cfg(not(feature = "grpc"))]
+fn spawn_grpc_server(_port: u16, _state: &AppState, _shutdown_rx: watch::Receiver<bool>) {
+    tracing::warn!("GRPC_PORT is set but the indexer was built without the `grpc` feature");
 }
 
 async fn shutdown_signal(shutdown_tx: watch::Sender<bool>) {
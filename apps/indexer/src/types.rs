@@ -0,0 +1,80 @@
+use std::fmt;
+use std::str::FromStr;
+
+use rusqlite::types::{FromSql, FromSqlResult, ToSql, ToSqlOutput, ValueRef};
+
+/// Fixed-size byte identifier stored as a BLOB and shown as 0x-prefixed lowercase hex.
+/// Each kind gets its own type so a topic can't be passed where a sender is expected.
+macro_rules! hex_newtype {
+    ($(#[$meta:meta])* $name:ident, $len:literal) => {
+        $(#[$meta])*
+        #[derive(Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+        pub struct $name(pub [u8; $len]);
+
+        impl From<[u8; $len]> for $name {
+            fn from(bytes: [u8; $len]) -> Self {
+                Self(bytes)
+            }
+        }
+
+        impl fmt::Display for $name {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                write!(f, "0x{}", hex::encode(self.0))
+            }
+        }
+
+        impl fmt::Debug for $name {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                write!(f, "{}({self})", stringify!($name))
+            }
+        }
+
+        /// Accepts hex with or without a 0x prefix.
+        impl FromStr for $name {
+            type Err = hex::FromHexError;
+
+            fn from_str(s: &str) -> Result<Self, Self::Err> {
+                let s = s.strip_prefix("0x").unwrap_or(s);
+                let mut out = [0u8; $len];
+                hex::decode_to_slice(s, &mut out)?;
+                Ok(Self(out))
+            }
+        }
+
+        impl ToSql for $name {
+            fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
+                Ok(ToSqlOutput::Borrowed(ValueRef::Blob(&self.0)))
+            }
+        }
+
+        impl FromSql for $name {
+            fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+                <[u8; $len]>::column_result(value).map(Self)
+            }
+        }
+    };
+}
+
+hex_newtype!(
+    /// Conversation topic of a `MessageSent` event.
+    TopicHash,
+    32
+);
+
+hex_newtype!(
+    /// Recipient identifier a `Handshake` is addressed to.
+    RecipientHash,
+    32
+);
+
+hex_newtype!(
+    /// Address that emitted an event: a message sender, handshake initiator or responder.
+    SenderAddress,
+    20
+);
+
+hex_newtype!(
+    /// Responder's ephemeral public key from a `HandshakeResponse`.
+    EphemeralKey,
+    32
+);
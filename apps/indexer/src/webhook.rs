@@ -0,0 +1,212 @@
+use std::time::Duration;
+
+use hmac::{Hmac, Mac};
+use serde::Serialize;
+use sha2::Sha256;
+use tokio::sync::{broadcast, mpsc, watch};
+use tokio::time::Instant;
+
+use crate::api::encoding::to_hex;
+use crate::api::handshakes::{HandshakeJson, HsrJson};
+use crate::api::messages::MessageJson;
+use crate::indexer::processor::IndexedEvent;
+use crate::indexer::retry_queue::DeadLetterNotice;
+
+const SIGNATURE_HEADER: &str = "X-Verbeth-Signature";
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+const INITIAL_BACKOFF: Duration = Duration::from_millis(500);
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+/// At most one dead-letter alert is sent per interval
+const ALERT_INTERVAL: Duration = Duration::from_secs(60);
+
+#[derive(Serialize)]
+#[serde(tag = "event_type", rename_all = "snake_case")]
+enum WebhookPayload {
+    Message(MessageJson),
+    Handshake(HandshakeJson),
+    HandshakeResponse(HsrJson),
+    DeadLetter(DeadLetterAlert),
+}
+
+#[derive(Serialize)]
+struct DeadLetterAlert {
+    id: i64,
+    block_number: u64,
+    log_index: u64,
+    tx_hash: Option<String>,
+    retry_count: u32,
+    error: String,
+    /// Other events dead-lettered since the previous alert, not alerted on their own
+    suppressed: u64,
+}
+
+impl DeadLetterAlert {
+    fn new(notice: DeadLetterNotice, suppressed: u64) -> Self {
+        Self {
+            id: notice.id,
+            block_number: notice.block_number,
+            log_index: notice.log_index,
+            tx_hash: notice.tx_hash.map(|h| to_hex(h.as_slice())),
+            retry_count: notice.retry_count,
+            error: notice.error,
+            suppressed,
+        }
+    }
+}
+
+impl From<IndexedEvent> for WebhookPayload {
+    fn from(event: IndexedEvent) -> Self {
+        match event {
+            IndexedEvent::Message(row) => Self::Message(row.into()),
+            IndexedEvent::Handshake(row) => Self::Handshake(row.into()),
+            IndexedEvent::HandshakeResponse(row) => Self::HandshakeResponse(row.into()),
+        }
+    }
+}
+
+pub struct WebhookNotifier {
+    client: reqwest::Client,
+    url: String,
+    secret: Option<String>,
+    max_retries: u32,
+    alerts: Option<mpsc::Receiver<DeadLetterNotice>>,
+}
+
+impl WebhookNotifier {
+    pub fn new(url: String, secret: Option<String>, max_retries: u32) -> Self {
+        let client = reqwest::Client::builder()
+            .timeout(REQUEST_TIMEOUT)
+            .build()
+            .expect("webhook HTTP client");
+
+        Self {
+            client,
+            url,
+            secret,
+            max_retries,
+            alerts: None,
+        }
+    }
+
+    /// Also deliver a `dead_letter` alert for notices from `alerts`. When several arrive
+    /// within [`ALERT_INTERVAL`], only the newest is sent, once the interval is over, with
+    /// the number of the others.
+    pub fn with_dead_letter_alerts(mut self, alerts: mpsc::Receiver<DeadLetterNotice>) -> Self {
+        self.alerts = Some(alerts);
+        self
+    }
+
+    /// Deliver events from `events` one at a time, in indexing order, until shutdown.
+    pub async fn run(
+        mut self,
+        mut events: broadcast::Receiver<IndexedEvent>,
+        mut shutdown: watch::Receiver<bool>,
+    ) {
+        let mut alerts = self.alerts.take();
+        let mut last_alert: Option<Instant> = None;
+        // Newest notice held back by the debounce, and how many it replaced
+        let mut held: Option<DeadLetterNotice> = None;
+        let mut suppressed = 0;
+
+        loop {
+            let next_alert = last_alert.map_or_else(Instant::now, |at| at + ALERT_INTERVAL);
+            tokio::select! {
+                notice = async { alerts.as_mut()?.recv().await }, if alerts.is_some() => {
+                    let Some(notice) = notice else {
+                        alerts = None;
+                        continue;
+                    };
+                    suppressed += notice.dropped;
+                    if held.replace(notice).is_some() {
+                        suppressed += 1;
+                    }
+                }
+                _ = tokio::time::sleep_until(next_alert), if held.is_some() => {
+                    let notice = held.take().expect("guarded by held.is_some()");
+                    self.deliver(&WebhookPayload::DeadLetter(DeadLetterAlert::new(notice, suppressed)))
+                        .await;
+                    suppressed = 0;
+                    last_alert = Some(Instant::now());
+                }
+                _ = shutdown.changed() => {
+                    if *shutdown.borrow() {
+                        tracing::debug!("Webhook notifier shutting down");
+                        return;
+                    }
+                }
+                event = events.recv() => match event {
+                    Ok(event) => self.deliver(&WebhookPayload::from(event)).await,
+                    Err(broadcast::error::RecvError::Lagged(n)) => {
+                        tracing::warn!(dropped = n, "Webhook notifier lagged, events dropped");
+                    }
+                    Err(broadcast::error::RecvError::Closed) => return,
+                },
+            }
+        }
+    }
+
+    async fn deliver(&self, payload: &WebhookPayload) {
+        let body = match serde_json::to_vec(payload) {
+            Ok(body) => body,
+            Err(e) => {
+                tracing::error!("Failed to serialize webhook payload: {e}");
+                return;
+            }
+        };
+
+        let mut backoff = INITIAL_BACKOFF;
+        let mut attempt = 0;
+        loop {
+            let error = match self.post(&body).await {
+                Ok(()) => return,
+                Err(e) => e,
+            };
+
+            if attempt >= self.max_retries {
+                tracing::error!(
+                    attempts = attempt + 1,
+                    "Webhook delivery failed, dropping event: {error}"
+                );
+                return;
+            }
+
+            attempt += 1;
+            tracing::warn!(
+                "Webhook delivery failed ({error}), retrying in {:?} (attempt {}/{})",
+                backoff,
+                attempt,
+                self.max_retries
+            );
+            tokio::time::sleep(backoff).await;
+            backoff = (backoff * 2).min(MAX_BACKOFF);
+        }
+    }
+
+    async fn post(&self, body: &[u8]) -> Result<(), String> {
+        let mut request = self
+            .client
+            .post(&self.url)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .body(body.to_vec());
+
+        if let Some(secret) = &self.secret {
+            request = request.header(SIGNATURE_HEADER, sign(secret, body));
+        }
+
+        let response = request.send().await.map_err(|e| e.to_string())?;
+        let status = response.status();
+        if status.is_success() {
+            Ok(())
+        } else {
+            Err(format!("HTTP {status}"))
+        }
+    }
+}
+
+/// `sha256=<hex HMAC-SHA256 of the raw body>`, the same scheme GitHub webhooks use.
+fn sign(secret: &str, body: &[u8]) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts any key length");
+    mac.update(body);
+    format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+}